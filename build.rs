@@ -24,6 +24,8 @@ fn main() {
         .write_all(commit_info().as_bytes())
         .unwrap();
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=ELAN_INSTALL_METHOD");
+    println!("cargo:rerun-if-env-changed=ELAN_PACKAGE_NAME");
 }
 
 // Try to get hash and date of the last commit on a best effort basis. If anything goes wrong
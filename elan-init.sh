@@ -29,13 +29,20 @@ USAGE:
 FLAGS:
     -v, --verbose           Enable verbose output
     -y                      Disable confirmation prompt.
+    -q, --quiet             Disable prompts and progress output and print the result as JSON
         --no-modify-path    Don't configure the PATH environment variable
+        --repair            Fix an existing installation, keeping its settings and toolchains
+        --system            Install elan and toolchains for all users; requires root
     -h, --help              Prints help information
     -V, --version           Prints version information
 
 OPTIONS:
-        --default-toolchain <default-toolchain>    Choose a default toolchain
+        --default-toolchain <default-toolchain>    Choose a default toolchain [env: ELAN_DEFAULT_TOOLCHAIN=]
         --default-toolchain none                   Do not set a default toolchain
+    -c, --component <component>...                 Component to install into the default toolchain right away; may be repeated
+        --prefix <prefix>                          Installation directory for --system [default: /opt/elan]
+        --proxy-auth <method>                      Authenticate with the HTTP proxy using the given method, e.g. ntlm
+                                                   [possible values: basic, digest, ntlm, negotiate, any]
 EOF
 }
 
@@ -76,6 +83,7 @@ main() {
 
     # check if we have to use /dev/tty to prompt the user
     local need_tty=yes
+    local quiet=no
     for arg in "$@"; do
         case "$arg" in
             -h|--help)
@@ -86,12 +94,19 @@ main() {
                 # user wants to skip the prompt -- we don't need /dev/tty
                 need_tty=no
                 ;;
+            -q|--quiet)
+                # implies -y and only the result is printed
+                need_tty=no
+                quiet=yes
+                ;;
             *)
                 ;;
         esac
     done
 
-    if $_ansi_escapes_are_valid; then
+    if [ "$quiet" = "yes" ]; then
+        :
+    elif $_ansi_escapes_are_valid; then
         printf "\33[1minfo:\33[0m downloading installer\n" 1>&2
     else
         printf '%s\n' 'info: downloading installer' 1>&2
@@ -0,0 +1,28 @@
+//! Parsing toolchain specifications must not panic, and toolchains resolved
+//! without network access must have a safe installation directory
+#![no_main]
+
+use elan::{lookup_unresolved_toolchain_desc, Cfg};
+use libfuzzer_sys::fuzz_target;
+use std::sync::Arc;
+
+thread_local! {
+    static CFG: (tempfile::TempDir, Cfg) = {
+        let home = tempfile::tempdir().unwrap();
+        std::env::set_var("ELAN_HOME", home.path());
+        std::env::remove_var("ELAN_TOOLCHAIN");
+        let cfg = Cfg::from_env(Arc::new(|_| {})).unwrap();
+        (home, cfg)
+    };
+}
+
+fuzz_target!(|name: &str| {
+    CFG.with(|(_, cfg)| {
+        if let Ok(desc) = lookup_unresolved_toolchain_desc(cfg, name) {
+            if desc.0.validate().is_ok() {
+                let dir = desc.0.to_toolchain_dir();
+                assert!(!dir.contains(['/', '\\']) && !dir.starts_with('.'));
+            }
+        }
+    })
+});
@@ -0,0 +1,13 @@
+//! Parsing `settings.toml` must not panic, and settings that parse must
+//! survive being written back
+#![no_main]
+
+use elan::settings::Settings;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &str| {
+    if let Ok(settings) = Settings::parse(data) {
+        let reparsed = Settings::parse(&settings.clone().stringify()).unwrap();
+        assert_eq!(reparsed, settings);
+    }
+});
@@ -0,0 +1,18 @@
+//! Toolchain names must map to a safe installation directory and back
+#![no_main]
+
+use elan_dist::dist::ToolchainDesc;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|name: &str| {
+    if let Ok(desc) = ToolchainDesc::from_resolved_str(name) {
+        assert_eq!(desc.to_string(), name);
+        let dir = desc.to_toolchain_dir();
+        assert!(!dir.is_empty() && !dir.starts_with('.'));
+        assert!(!dir.contains(['/', '\\', ':', '\0']));
+        assert_eq!(ToolchainDesc::from_toolchain_dir(&dir).unwrap(), desc);
+    }
+    if let Ok(desc) = ToolchainDesc::from_toolchain_dir(name) {
+        assert_eq!(desc.to_toolchain_dir(), name);
+    }
+});
@@ -0,0 +1,15 @@
+//! Unpacking arbitrary archives must not panic or write outside of the
+//! extraction directory
+#![no_main]
+
+use elan_dist::TarGzPackage;
+use elan_fuzz::{assert_only_child, budget};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let root = tempfile::tempdir().unwrap();
+    let into = root.path().join("into");
+    std::fs::create_dir(&into).unwrap();
+    let _ = TarGzPackage::unpack(data, &into, &budget());
+    assert_only_child(root.path(), &into);
+});
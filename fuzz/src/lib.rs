@@ -0,0 +1,20 @@
+//! Helpers shared by the fuzz targets
+
+use elan_dist::{ExtractionBudget, ExtractionLimits};
+use std::path::Path;
+
+/// Small limits to keep decompression bombs from filling the disk
+pub fn budget() -> ExtractionBudget {
+    ExtractionBudget::new(ExtractionLimits {
+        max_size: 1 << 20,
+        max_files: 1000,
+    })
+}
+
+/// Asserts that `child` is the only entry of `root`, i.e. that unpacking into
+/// `child` did not write anywhere else
+pub fn assert_only_child(root: &Path, child: &Path) {
+    for entry in std::fs::read_dir(root).unwrap() {
+        assert_eq!(entry.unwrap().path(), child);
+    }
+}
@@ -13,9 +13,50 @@ error_chain! {
             description("http request returned an unsuccessful status code")
             display("http request returned an unsuccessful status code: {}", e)
         }
+        RateLimited(status: u32, remaining: Option<u64>, reset_in: Option<u64>, retry_after: Option<u64>) {
+            description("GitHub rate limit exceeded")
+            display("{}", {
+                let mut msg = format!("GitHub rate limit exceeded (HTTP {})", status);
+                if let Some(remaining) = remaining {
+                    msg += &format!(", {} requests remaining", remaining);
+                }
+                if let Some(reset_in) = reset_in {
+                    msg += &format!(", limit resets in {} minutes", reset_in.div_ceil(60));
+                }
+                if let Some(retry_after) = retry_after {
+                    msg += &format!(", retry after {} seconds", retry_after);
+                }
+                msg + "; set `ELAN_GITHUB_TOKEN` to a GitHub access token to raise the limit"
+            })
+        }
+        TruncatedDownload(got: u64, expected: u64) {
+            description("truncated download")
+            display("truncated download (got {} of {} bytes)", got, expected)
+        }
+        NotModified {
+            description("resource not modified since it was cached")
+        }
         FileNotFound {
             description("file not found")
         }
+        Interrupted {
+            description("download interrupted")
+        }
+        TimedOut {
+            description("download timed out")
+        }
+        Stalled(secs: u64) {
+            description("download stalled")
+            display("download stalled: no data received for {} seconds", secs)
+        }
+        TooLarge(limit: u64) {
+            description("response too large")
+            display("response exceeds the limit of {} bytes", limit)
+        }
+        InvalidProxyAuth(method: String) {
+            description("invalid proxy authentication method")
+            display("invalid proxy authentication method '{}'; expected one of basic, digest, ntlm, negotiate, or any", method)
+        }
         BackendUnavailable(be: &'static str) {
             description("download backend unavailable")
             display("download backend '{}' unavailable", be)
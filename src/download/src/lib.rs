@@ -1,12 +1,17 @@
 //! Easy file downloading
 #![deny(rust_2018_idioms)]
 
+use std::future::Future;
 use std::path::Path;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
 use url::Url;
 
 mod errors;
 pub use crate::errors::*;
 
+pub mod transfer;
+
 #[derive(Debug, Copy, Clone)]
 pub enum Backend {
     Curl,
@@ -18,73 +23,345 @@ pub enum Event<'a> {
     DownloadContentLengthReceived(u64),
     /// Received some data.
     DownloadDataReceived(&'a [u8]),
+    /// Received the cache validators of the response, after all its data.
+    ValidatorsReceived(&'a Validators),
+}
+
+/// Receives the events of a download
+pub type Callback<'a> = &'a dyn Fn(Event<'_>) -> Result<()>;
+
+/// Cache validators of a response. Sent back with a later request for the
+/// same URL, they make the server answer with 304 Not Modified, reported as
+/// `ErrorKind::NotModified`, if the cached copy is still current.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// Value of the `ETag` header
+    pub etag: Option<String>,
+    /// Value of the `Last-Modified` header
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Runs `future` to completion on the runtime shared by all transfers
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME
+        .get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .thread_name("download")
+                .enable_time()
+                .build()
+                .expect("failed to start the download runtime")
+        })
+        .block_on(future)
 }
 
-fn download_with_backend(
+fn download_blocking(
     backend: Backend,
     url: &Url,
+    validators: Option<&Validators>,
     callback: &dyn Fn(Event<'_>) -> Result<()>,
 ) -> Result<()> {
     match backend {
-        Backend::Curl => curl::download(url, callback),
+        Backend::Curl => curl::download(url, validators, callback),
     }
 }
 
+pub fn download_with_backend(
+    backend: Backend,
+    url: &Url,
+    callback: &dyn Fn(Event<'_>) -> Result<()>,
+) -> Result<()> {
+    block_on(transfer::download(
+        backend,
+        url,
+        &transfer::Options::default(),
+        callback,
+    ))
+}
+
 pub fn download_to_path_with_backend(
     backend: Backend,
     url: &Url,
     path: &Path,
-    callback: Option<&dyn Fn(Event<'_>) -> Result<()>>,
+    callback: Option<Callback<'_>>,
 ) -> Result<()> {
-    use std::cell::RefCell;
-    use std::fs::OpenOptions;
-    use std::io::Write;
-
-    || -> Result<()> {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path)
-            .chain_err(|| "error creating file for download")?;
-
-        let file = RefCell::new(file);
-
-        download_with_backend(backend, url, &|event| {
-            if let Event::DownloadDataReceived(data) = event {
-                file.borrow_mut()
-                    .write_all(data)
-                    .chain_err(|| "unable to write download to disk")?;
-            }
-            match callback {
-                Some(cb) => cb(event),
-                None => Ok(()),
-            }
-        })?;
-
-        file.borrow_mut()
-            .sync_data()
-            .chain_err(|| "unable to sync download to disk")?;
+    block_on(transfer::download_to_path(
+        backend,
+        url,
+        path,
+        &transfer::Options::default(),
+        callback,
+    ))
+}
 
-        Ok(())
-    }()
+/// Size of the resource at `url` according to a HEAD request, or `None` if
+/// the server does not report one
+pub fn content_length_with_backend(backend: Backend, url: &Url) -> Result<Option<u64>> {
+    match backend {
+        Backend::Curl => curl::content_length(url),
+    }
 }
 
+/// Methods accepted for authenticating with HTTP proxies; only supported by
+/// the curl backend
+pub const PROXY_AUTH_METHODS: &[&str] = &["basic", "digest", "ntlm", "negotiate", "any"];
+
 /// Download via libcurl; encrypt with the native (or OpenSSl) TLS
 /// stack via libcurl
 #[cfg(feature = "curl-backend")]
 pub mod curl {
 
-    use super::Event;
+    use super::{Event, Validators, PROXY_AUTH_METHODS};
     use crate::errors::*;
-    use curl::easy::Easy;
-    use std::cell::RefCell;
+    use curl::easy::{Auth, Easy, List};
+    use std::cell::{Cell, RefCell};
+    use std::env;
     use std::str;
-    use std::time::Duration;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
     use url::Url;
 
     thread_local!(pub static EASY: RefCell<Easy> = RefCell::new(Easy::new()));
 
-    pub fn download(url: &Url, callback: &dyn Fn(Event<'_>) -> Result<()>) -> Result<()> {
+    static PROXY_AUTH: Mutex<Option<String>> = Mutex::new(None);
+
+    static DEADLINE: Mutex<Option<Instant>> = Mutex::new(None);
+
+    static USER_AGENT: Mutex<Option<String>> = Mutex::new(None);
+
+    /// Longest time a transfer may go without receiving any data
+    pub const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Sets the `User-Agent` header sent with all requests, which defaults to
+    /// "elan"; the GitHub API rejects requests without one
+    pub fn set_user_agent(user_agent: &str) {
+        *USER_AGENT.lock().unwrap() = Some(user_agent.to_owned());
+    }
+
+    /// Makes downloads on any thread fail with `ErrorKind::TimedOut` once
+    /// `deadline` has passed, or lets them run for as long as they make
+    /// progress again if it is `None`
+    pub fn set_deadline(deadline: Option<Instant>) {
+        *DEADLINE.lock().unwrap() = deadline;
+    }
+
+    /// Sets the method used to authenticate with the proxy configured by the
+    /// usual environment variables such as `https_proxy`
+    pub fn set_proxy_auth(method: &str) -> Result<()> {
+        if !PROXY_AUTH_METHODS.contains(&method) {
+            return Err(ErrorKind::InvalidProxyAuth(method.to_owned()).into());
+        }
+        *PROXY_AUTH.lock().unwrap() = Some(method.to_owned());
+        Ok(())
+    }
+
+    /// Limits the transfer of `handle` to the deadline set by `set_deadline`,
+    /// returning the deadline, or fails if it has already passed
+    pub fn configure_deadline(handle: &mut Easy) -> Result<Option<Instant>> {
+        let deadline = *DEADLINE.lock().unwrap();
+        // Handles are reused, so a zero timeout has to reset any previous
+        // deadline
+        let timeout = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => return Err(ErrorKind::TimedOut.into()),
+            },
+            None => Duration::ZERO,
+        };
+        handle
+            .timeout(timeout)
+            .chain_err(|| "failed to set timeout")?;
+        Ok(deadline)
+    }
+
+    /// Applies the proxy authentication method set by `set_proxy_auth`, if
+    /// any, to `handle`
+    pub fn configure_proxy(handle: &mut Easy) -> Result<()> {
+        let method = match PROXY_AUTH.lock().unwrap().clone() {
+            Some(method) => method,
+            None => return Ok(()),
+        };
+        let mut auth = Auth::new();
+        match &*method {
+            "basic" => auth.basic(true),
+            "digest" => auth.digest(true),
+            "ntlm" => auth.ntlm(true),
+            "negotiate" => auth.gssnegotiate(true),
+            _ => auth.auto(true),
+        };
+        handle
+            .proxy_auth(&auth)
+            .chain_err(|| "failed to set proxy authentication")?;
+
+        // Like `curl --proxy-ntlm -U :`, authenticate as the current user
+        // unless the proxy URL contains credentials
+        let has_credentials = [
+            "https_proxy",
+            "HTTPS_PROXY",
+            "http_proxy",
+            "all_proxy",
+            "ALL_PROXY",
+        ]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .any(|url| url.contains('@'));
+        if !has_credentials && method != "basic" && method != "digest" {
+            handle
+                .proxy_username("")
+                .and_then(|_| handle.proxy_password(""))
+                .chain_err(|| "failed to set proxy authentication")?;
+        }
+        Ok(())
+    }
+
+    /// Sends the token in `ELAN_GITHUB_TOKEN`, if any, with requests to
+    /// GitHub, and makes the request conditional on `validators`, if any.
+    /// Always resets the request headers as `handle` may be reused.
+    pub fn configure_headers(
+        handle: &mut Easy,
+        url: &str,
+        validators: Option<&Validators>,
+    ) -> Result<()> {
+        let mut headers = List::new();
+        let conditions = validators.into_iter().flat_map(|v| {
+            let etag = v.etag.as_ref().map(|e| format!("If-None-Match: {}", e));
+            let modified = v
+                .last_modified
+                .as_ref()
+                .map(|m| format!("If-Modified-Since: {}", m));
+            etag.into_iter().chain(modified)
+        });
+        for header in conditions {
+            headers
+                .append(&header)
+                .chain_err(|| "failed to set request headers")?;
+        }
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|h| h.to_owned()));
+        if let (Some("github.com" | "api.github.com"), Ok(token)) =
+            (host.as_deref(), env::var("ELAN_GITHUB_TOKEN"))
+        {
+            if !token.is_empty() {
+                headers
+                    .append(&format!("Authorization: Bearer {}", token))
+                    .chain_err(|| "failed to set request headers")?;
+            }
+        }
+        handle
+            .http_headers(headers)
+            .chain_err(|| "failed to set request headers")
+    }
+
+    /// Rate limit information collected from response headers
+    #[derive(Default)]
+    pub struct RateLimit {
+        remaining: Cell<Option<u64>>,
+        reset: Cell<Option<u64>>,
+        retry_after: Cell<Option<u64>>,
+    }
+
+    impl RateLimit {
+        /// Records the header line `header` if it is rate limit related
+        pub fn observe_header(&self, header: &str) {
+            // A new response after a redirect
+            if header.starts_with("HTTP/") {
+                self.remaining.set(None);
+                self.reset.set(None);
+                self.retry_after.set(None);
+            }
+            let (name, value) = match header.split_once(':') {
+                Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+                None => return,
+            };
+            let value = value.parse::<u64>().ok();
+            match &*name {
+                "x-ratelimit-remaining" => self.remaining.set(value),
+                "x-ratelimit-reset" => self.reset.set(value),
+                // HTTP dates are not used by GitHub and thus not supported
+                "retry-after" => self.retry_after.set(value),
+                _ => {}
+            }
+        }
+
+        /// Returns an error if the response status `code` signals that the
+        /// rate limit was exceeded
+        pub fn check(&self, code: u32) -> Result<()> {
+            let limited = code == 429
+                || (code == 403
+                    && (self.remaining.get() == Some(0) || self.retry_after.get().is_some()));
+            if !limited {
+                return Ok(());
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let reset_in = self.reset.get().map(|reset| reset.saturating_sub(now));
+            Err(ErrorKind::RateLimited(
+                code,
+                self.remaining.get(),
+                reset_in,
+                self.retry_after.get(),
+            )
+            .into())
+        }
+    }
+
+    /// Requests only the headers of `url`, following redirects, and returns
+    /// the `Content-Length` of the final response. Uses a handle of its own
+    /// so that downloads never inherit the HEAD method.
+    pub fn content_length(url: &Url) -> Result<Option<u64>> {
+        let mut handle = Easy::new();
+        handle.url(url.as_ref()).chain_err(|| "failed to set url")?;
+        handle
+            .follow_location(true)
+            .and_then(|_| handle.nobody(true))
+            .and_then(|_| handle.connect_timeout(Duration::new(30, 0)))
+            .chain_err(|| "failed to set request options")?;
+        let user_agent = USER_AGENT.lock().unwrap().clone();
+        handle
+            .useragent(user_agent.as_deref().unwrap_or("elan"))
+            .chain_err(|| "failed to set user agent")?;
+        let deadline = configure_deadline(&mut handle)?;
+        configure_proxy(&mut handle)?;
+        configure_headers(&mut handle, url.as_ref(), None)?;
+
+        handle.perform().or_else(|e| {
+            if e.is_file_couldnt_read_file() {
+                Err(e).chain_err(|| ErrorKind::FileNotFound)
+            } else if deadline.is_some_and(|d| Instant::now() >= d) {
+                Err(e).chain_err(|| ErrorKind::TimedOut)
+            } else {
+                Err(e).chain_err(|| "error during request")
+            }
+        })?;
+        let code = handle
+            .response_code()
+            .chain_err(|| "failed to get response code")?;
+        match code {
+            0 | 200..=299 => {}
+            _ => return Err(ErrorKind::HttpStatus(code).into()),
+        }
+        // Negative if unknown
+        let len = handle
+            .content_length_download()
+            .chain_err(|| "failed to get content length")?;
+        Ok(if len >= 0.0 { Some(len as u64) } else { None })
+    }
+
+    pub fn download(
+        url: &Url,
+        validators: Option<&Validators>,
+        callback: &dyn Fn(Event<'_>) -> Result<()>,
+    ) -> Result<()> {
         // Fetch either a cached libcurl handle (which will preserve open
         // connections) or create a new one if it isn't listed.
         //
@@ -102,7 +379,25 @@ pub mod curl {
             handle
                 .connect_timeout(Duration::new(30, 0))
                 .chain_err(|| "failed to set connect timeout")?;
+            // Abort when the server stops sending data instead of hanging
+            handle
+                .low_speed_limit(1)
+                .and_then(|_| handle.low_speed_time(STALL_TIMEOUT))
+                .chain_err(|| "failed to set stall timeout")?;
+            let user_agent = USER_AGENT.lock().unwrap().clone();
+            handle
+                .useragent(user_agent.as_deref().unwrap_or("elan"))
+                .chain_err(|| "failed to set user agent")?;
+            let deadline = configure_deadline(&mut handle)?;
+            configure_proxy(&mut handle)?;
+            configure_headers(&mut handle, url.as_ref(), validators)?;
 
+            let rate_limit = RateLimit::default();
+            let received_validators = RefCell::new(Validators::default());
+            // Checked after the transfer as proxies and captive portals may
+            // cut downloads short without curl noticing
+            let expected_len = Cell::new(None);
+            let received_len = Cell::new(0u64);
             {
                 let cberr = RefCell::new(None);
                 let mut transfer = handle.transfer();
@@ -111,11 +406,14 @@ pub mod curl {
                 // downloaded. We just feed it into our hasher and also write it out
                 // to disk.
                 transfer
-                    .write_function(|data| match callback(Event::DownloadDataReceived(data)) {
-                        Ok(()) => Ok(data.len()),
-                        Err(e) => {
-                            *cberr.borrow_mut() = Some(e);
-                            Ok(0)
+                    .write_function(|data| {
+                        received_len.set(received_len.get() + data.len() as u64);
+                        match callback(Event::DownloadDataReceived(data)) {
+                            Ok(()) => Ok(data.len()),
+                            Err(e) => {
+                                *cberr.borrow_mut() = Some(e);
+                                Ok(0)
+                            }
                         }
                     })
                     .chain_err(|| "failed to set write")?;
@@ -125,9 +423,28 @@ pub mod curl {
                 transfer
                     .header_function(|header| {
                         if let Ok(data) = str::from_utf8(header) {
-                            let prefix = "Content-Length: ";
-                            if data.starts_with(prefix) {
+                            rate_limit.observe_header(data);
+                            // A new response after a redirect
+                            if data.starts_with("HTTP/") {
+                                expected_len.set(None);
+                                *received_validators.borrow_mut() = Validators::default();
+                            }
+                            if let Some((name, value)) = data.split_once(':') {
+                                let value = Some(value.trim().to_owned());
+                                let mut received = received_validators.borrow_mut();
+                                if name.eq_ignore_ascii_case("etag") {
+                                    received.etag = value;
+                                } else if name.eq_ignore_ascii_case("last-modified") {
+                                    received.last_modified = value;
+                                }
+                            }
+                            // HTTP/2 header names are lowercase
+                            let prefix = "content-length:";
+                            if data.len() > prefix.len()
+                                && data[..prefix.len()].eq_ignore_ascii_case(prefix)
+                            {
                                 if let Ok(s) = data[prefix.len()..].trim().parse::<u64>() {
+                                    expected_len.set(Some(s));
                                     let msg = Event::DownloadContentLengthReceived(s);
                                     match callback(msg) {
                                         Ok(()) => (),
@@ -154,6 +471,10 @@ pub mod curl {
                             // Otherwise, return the error from curl
                             if e.is_file_couldnt_read_file() {
                                 Err(e).chain_err(|| ErrorKind::FileNotFound)
+                            } else if deadline.is_some_and(|d| Instant::now() >= d) {
+                                Err(e).chain_err(|| ErrorKind::TimedOut)
+                            } else if e.is_operation_timedout() {
+                                Err(e).chain_err(|| ErrorKind::Stalled(STALL_TIMEOUT.as_secs()))
                             } else {
                                 Err(e).chain_err(|| "error during download")
                             }
@@ -166,13 +487,26 @@ pub mod curl {
             let code = handle
                 .response_code()
                 .chain_err(|| "failed to get response code")?;
+            rate_limit.check(code)?;
             match code {
                 0 | 200..=299 => {}
+                304 if validators.is_some() => return Err(ErrorKind::NotModified.into()),
                 _ => {
                     return Err(ErrorKind::HttpStatus(code).into());
                 }
             };
 
+            if let Some(expected) = expected_len.get() {
+                if received_len.get() < expected {
+                    return Err(ErrorKind::TruncatedDownload(received_len.get(), expected).into());
+                }
+            }
+
+            let received_validators = received_validators.into_inner();
+            if !received_validators.is_empty() {
+                callback(Event::ValidatorsReceived(&received_validators))?;
+            }
+
             Ok(())
         })
     }
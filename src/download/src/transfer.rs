@@ -0,0 +1,318 @@
+//! Async core of the download crate
+//!
+//! A transfer runs the blocking backend on the runtime's blocking thread pool
+//! and streams its events back over a bounded channel. The future awaiting
+//! the transfer delivers them to the callback, so callbacks need not be
+//! `Send`, and it can impose a timeout, react to cancellation even while the
+//! server sends nothing, and limit the bandwidth of the transfer. Several
+//! transfers can be in flight at once by awaiting them concurrently, each
+//! blocking thread using its own curl handle.
+
+use super::{Backend, Callback, Event, Validators};
+use crate::errors::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use url::Url;
+
+/// Number of events buffered between the backend and the callback; a full
+/// buffer stalls the backend, which is what makes throttling effective
+const CHANNEL_CAPACITY: usize = 16;
+
+/// How often a transfer that receives no data checks for cancellation
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancels the transfers it is passed to, failing them with
+/// `ErrorKind::Interrupted`
+#[derive(Clone, Default)]
+pub struct Cancel {
+    flag: Arc<AtomicBool>,
+    condition: Option<fn() -> bool>,
+}
+
+impl Cancel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that is also cancelled once `condition` returns true, e.g.
+    /// when a signal has been received
+    pub fn when(condition: fn() -> bool) -> Self {
+        Cancel {
+            condition: Some(condition),
+            ..Self::default()
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::SeqCst) || self.condition.is_some_and(|c| c())
+    }
+}
+
+/// Limits the combined throughput of the transfers sharing it
+pub struct Throttle {
+    bytes_per_sec: u64,
+    /// When the bytes consumed so far will have been transferred at the
+    /// permitted rate
+    next: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Throttle {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Waits until `bytes` more bytes may be passed on
+    async fn consume(&self, bytes: usize) {
+        let until = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let start = next.map_or(now, |next| next.max(now));
+            let until = start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+            *next = Some(until);
+            until
+        };
+        time::sleep_until(until).await;
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Options {
+    pub cancel: Option<Cancel>,
+    /// Longest time the whole transfer may take
+    pub timeout: Option<Duration>,
+    pub throttle: Option<Arc<Throttle>>,
+    /// Validators of a cached copy, making the transfer fail with
+    /// `ErrorKind::NotModified` if it is still current
+    pub validators: Option<Validators>,
+}
+
+enum Message {
+    ContentLength(u64),
+    Data(Vec<u8>),
+    Validators(Validators),
+}
+
+pub async fn download(
+    backend: Backend,
+    url: &Url,
+    options: &Options,
+    callback: &dyn Fn(Event<'_>) -> Result<()>,
+) -> Result<()> {
+    let (sender, mut receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let cancel = options.cancel.clone().unwrap_or_default();
+    // Stops the backend once this future has given up on the transfer
+    let stop = Cancel::new();
+
+    let worker = {
+        let url = url.clone();
+        let validators = options.validators.clone();
+        let (cancel, stop) = (cancel.clone(), stop.clone());
+        tokio::task::spawn_blocking(move || {
+            super::download_blocking(backend, &url, validators.as_ref(), &|event| {
+                if cancel.is_cancelled() || stop.is_cancelled() {
+                    return Err(ErrorKind::Interrupted.into());
+                }
+                let message = match event {
+                    Event::DownloadContentLengthReceived(len) => Message::ContentLength(len),
+                    Event::DownloadDataReceived(data) => Message::Data(data.to_vec()),
+                    Event::ValidatorsReceived(validators) => {
+                        Message::Validators(validators.clone())
+                    }
+                };
+                sender
+                    .blocking_send(message)
+                    .map_err(|_| ErrorKind::Interrupted.into())
+            })
+        })
+    };
+
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let result = loop {
+        let message = match time::timeout(CANCEL_POLL_INTERVAL, receiver.recv()).await {
+            Ok(Some(message)) => message,
+            // The backend has finished
+            Ok(None) => break Ok(()),
+            Err(_) => {
+                if cancel.is_cancelled() {
+                    break Err(ErrorKind::Interrupted.into());
+                }
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    break Err(ErrorKind::TimedOut.into());
+                }
+                continue;
+            }
+        };
+        let result = match message {
+            Message::ContentLength(len) => callback(Event::DownloadContentLengthReceived(len)),
+            Message::Data(data) => {
+                if let Some(throttle) = &options.throttle {
+                    throttle.consume(data.len()).await;
+                }
+                callback(Event::DownloadDataReceived(&data))
+            }
+            Message::Validators(validators) => callback(Event::ValidatorsReceived(&validators)),
+        };
+        if let Err(e) = result {
+            break Err(e);
+        }
+        if cancel.is_cancelled() {
+            break Err(ErrorKind::Interrupted.into());
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break Err(ErrorKind::TimedOut.into());
+        }
+    };
+
+    match result {
+        Ok(()) => worker
+            .await
+            .unwrap_or_else(|_| Err("download backend panicked".into())),
+        Err(e) => {
+            // The backend may be waiting for data that never arrives, so it
+            // is left to notice on its own rather than awaited
+            stop.cancel();
+            drop(receiver);
+            Err(e)
+        }
+    }
+}
+
+pub async fn download_to_path(
+    backend: Backend,
+    url: &Url,
+    path: &Path,
+    options: &Options,
+    callback: Option<Callback<'_>>,
+) -> Result<()> {
+    use std::cell::RefCell;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .chain_err(|| "error creating file for download")?;
+
+    let file = RefCell::new(file);
+
+    download(backend, url, options, &|event| {
+        if let Event::DownloadDataReceived(data) = event {
+            file.borrow_mut()
+                .write_all(data)
+                .chain_err(|| "unable to write download to disk")?;
+        }
+        match callback {
+            Some(cb) => cb(event),
+            None => Ok(()),
+        }
+    })
+    .await?;
+
+    file.borrow_mut()
+        .sync_data()
+        .chain_err(|| "unable to sync download to disk")?;
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "curl-backend"))]
+mod tests {
+    use super::*;
+    use crate::block_on;
+    use std::cell::Cell;
+
+    fn file_url(contents: &[u8]) -> (tempfile_dir::Dir, Url) {
+        let dir = tempfile_dir::Dir::new();
+        let path = dir.0.join("data");
+        std::fs::write(&path, contents).unwrap();
+        (dir, Url::from_file_path(&path).unwrap())
+    }
+
+    /// A directory removed on drop; the crate has no tempfile dependency
+    mod tempfile_dir {
+        use std::path::PathBuf;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        pub struct Dir(pub PathBuf);
+
+        impl Dir {
+            pub fn new() -> Self {
+                static COUNTER: AtomicUsize = AtomicUsize::new(0);
+                let path = std::env::temp_dir().join(format!(
+                    "download-test-{}-{}",
+                    std::process::id(),
+                    COUNTER.fetch_add(1, Ordering::SeqCst)
+                ));
+                std::fs::create_dir_all(&path).unwrap();
+                Dir(path)
+            }
+        }
+
+        impl Drop for Dir {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_download() {
+        let contents = vec![7u8; 100_000];
+        let (_dir, url) = file_url(&contents);
+        let received = Cell::new(0);
+        block_on(download(
+            Backend::Curl,
+            &url,
+            &Options::default(),
+            &|event| {
+                if let Event::DownloadDataReceived(data) = event {
+                    received.set(received.get() + data.len());
+                }
+                Ok(())
+            },
+        ))
+        .unwrap();
+        assert_eq!(received.get(), contents.len());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (_dir, url) = file_url(&[0; 100_000]);
+        let options = Options {
+            cancel: Some(Cancel::new()),
+            ..Options::default()
+        };
+        let result = block_on(download(Backend::Curl, &url, &options, &|_| {
+            options.cancel.as_ref().unwrap().cancel();
+            Ok(())
+        }));
+        match result {
+            Err(Error(ErrorKind::Interrupted, _)) => {}
+            _ => panic!("transfer was not cancelled"),
+        }
+    }
+
+    #[test]
+    fn test_throttle() {
+        let throttle = Throttle::new(1000);
+        let start = std::time::Instant::now();
+        block_on(async {
+            throttle.consume(100).await;
+            throttle.consume(100).await;
+        });
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,105 @@
+//! Output for CI systems
+//!
+//! With `ELAN_CI=github`, the default when running in GitHub Actions, elan
+//! formats its messages as workflow commands of GitHub Actions: the log of
+//! each toolchain installation is folded into a group, errors and warnings
+//! become annotations that are also shown on the summary of the run, and
+//! credentials in URLs are masked, as are the values of secret environment
+//! variables should any tool print them. The runner picks up workflow
+//! commands from stderr as well, which is where they go so that they never
+//! mix with output meant for other programs.
+
+use elan::env_var::{self, ENV_VARS};
+use elan_utils::utils;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    None,
+    Github,
+}
+
+/// The CI system to format output for, from `ELAN_CI` or else detected from
+/// the environment
+pub fn mode() -> Mode {
+    static MODE: OnceLock<Mode> = OnceLock::new();
+    *MODE.get_or_init(|| match env::var(env_var::ELAN_CI) {
+        Ok(ci) if ci.eq_ignore_ascii_case("github") => Mode::Github,
+        Ok(ci) if !ci.is_empty() => Mode::None,
+        _ if env::var("GITHUB_ACTIONS").as_deref() == Ok("true") => Mode::Github,
+        _ => Mode::None,
+    })
+}
+
+pub fn is_github() -> bool {
+    mode() == Mode::Github
+}
+
+/// Whether a group has been started and not ended yet
+static GROUP_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Escapes the parameter of a workflow command, which must fit on one line
+fn escape(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn command(name: &str, value: &str) {
+    eprintln!("::{}::{}", name, escape(value));
+}
+
+/// Asks the runner to mask the values of the secret environment variables
+/// that are set, for all output of the job
+pub fn mask_secrets() {
+    if !is_github() {
+        return;
+    }
+    for var in ENV_VARS.iter().filter(|var| var.secret) {
+        if let Ok(value) = env::var(var.name) {
+            if !value.is_empty() {
+                command("add-mask", &value);
+            }
+        }
+    }
+}
+
+/// The message as it should be printed, with credentials masked in CI
+pub fn mask(message: String) -> String {
+    if is_github() {
+        utils::mask_credentials(&message)
+    } else {
+        message
+    }
+}
+
+/// Folds the following messages into a group titled `title`, ending the
+/// current group if any
+pub fn start_group(title: &str) {
+    if is_github() {
+        end_group();
+        command("group", &mask(title.to_owned()));
+        GROUP_OPEN.store(true, Ordering::Relaxed);
+    }
+}
+
+pub fn end_group() {
+    if GROUP_OPEN.swap(false, Ordering::Relaxed) {
+        eprintln!("::endgroup::");
+    }
+}
+
+/// Writes `message` as an annotation of the kind `error` or `warning`,
+/// outside of any group so that it is not folded away. Returns false if not
+/// in CI, in which case the message is to be printed as usual.
+pub fn annotate(kind: &str, message: &str) -> bool {
+    if !is_github() {
+        return false;
+    }
+    end_group();
+    command(kind, &utils::mask_credentials(message));
+    true
+}
@@ -2,10 +2,11 @@
 
 use crate::errors::*;
 use crate::term2;
+use clap::Args;
 use elan::{Cfg, Notification, Toolchain};
 use elan_dist::dist::ToolchainDesc;
 use elan_utils::notify::NotificationLevel;
-use elan_utils::utils;
+use elan_utils::{tty, utils};
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use std::process::{Command, Stdio};
@@ -30,6 +31,31 @@ pub fn confirm(question: &str, default: bool) -> Result<bool> {
     Ok(r)
 }
 
+/// The `-y`/`--yes` flag shared by all commands that remove toolchains,
+/// overrides, or elan itself
+#[derive(Args, Debug)]
+pub struct YesArg {
+    /// Do not ask for confirmation
+    #[arg(short, long, alias = "no-confirm")]
+    pub yes: bool,
+}
+
+/// Asks `question` before a destructive action, unless `--yes` was passed
+/// or stdin is not a terminal so that scripts keep working unattended. The
+/// question goes to stderr so that it does not mix with JSON output.
+pub fn confirm_destructive(yes: &YesArg, question: &str) -> Result<bool> {
+    if yes.yes || !tty::stdin_isatty() {
+        return Ok(true);
+    }
+    eprint!("{} (y/N) ", question);
+    let _ = std::io::stderr().flush();
+    let confirmed = matches!(&*read_line()?, "y" | "Y" | "yes");
+    if !confirmed {
+        info!("aborting; pass `--yes` to skip this question");
+    }
+    Ok(confirmed)
+}
+
 pub enum Confirm {
     Yes,
     No,
@@ -101,15 +127,18 @@ pub fn read_line() -> Result<String> {
 
 pub fn set_globals(verbose: bool) -> Result<Cfg> {
     use crate::download_tracker::DownloadTracker;
-    use std::cell::RefCell;
+    use std::sync::Mutex;
 
-    let download_tracker = RefCell::new(DownloadTracker::new());
+    let download_tracker = Mutex::new(DownloadTracker::new());
 
     Ok(Cfg::from_env(Arc::new(move |n: Notification<'_>| {
-        if download_tracker.borrow_mut().handle_notification(&n) {
+        if download_tracker.lock().unwrap().handle_notification(&n) {
             return;
         }
 
+        if let Notification::InstallingToolchain(_) = n {
+            crate::ci::start_group(&n.to_string());
+        }
         match n.level() {
             NotificationLevel::Verbose => {
                 if verbose {
@@ -126,6 +155,9 @@ pub fn set_globals(verbose: bool) -> Result<Cfg> {
                 err!("{}", n);
             }
         }
+        if let Notification::InstalledToolchain(_) = n {
+            crate::ci::end_group();
+        }
     }))?)
 }
 
@@ -204,6 +236,7 @@ pub fn lean_version(toolchain: &Toolchain<'_>) -> String {
 
 pub fn list_overrides(cfg: &Cfg) -> Result<()> {
     let overrides = cfg.settings_file.with(|s| Ok(s.overrides.clone()))?;
+    let dangling = cfg.dangling_overrides()?;
 
     if overrides.is_empty() {
         println!("no overrides");
@@ -214,24 +247,58 @@ pub fn list_overrides(cfg: &Cfg) -> Result<()> {
             if !dir_exists {
                 any_not_exist = true;
             }
+            let toolchain_missing = dangling.iter().any(|(d, _)| *d == k);
             println!(
                 "{:<40}\t{:<20}",
                 utils::format_path_for_display(&k)
                     + if dir_exists { "" } else { " (not a directory)" },
-                v
+                v.to_string()
+                    + if toolchain_missing {
+                        " (linked toolchain missing)"
+                    } else {
+                        ""
+                    }
             )
         }
-        if any_not_exist {
+        if any_not_exist || !dangling.is_empty() {
             println!();
+        }
+        if any_not_exist {
             info!(
                 "you may remove overrides for non-existent directories with
 `elan override unset --nonexistent`"
             );
         }
+        if !dangling.is_empty() {
+            info!(
+                "you may remove or reassign overrides whose linked toolchain no longer exists
+with `elan override clean`"
+            );
+        }
     }
     Ok(())
 }
 
+/// Levenshtein distance between `a` and `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(cur).min(row[j])
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
 pub fn version() -> &'static str {
     concat!(
         env!("CARGO_PKG_VERSION"),
@@ -240,10 +307,19 @@ pub fn version() -> &'static str {
 }
 
 pub fn report_error(e: &Error) {
-    err!("{}", e);
+    if crate::ci::is_github() {
+        // The causes are part of the annotation rather than separate messages
+        let mut message = e.to_string();
+        for e in e.iter().skip(1) {
+            message += &format!("\ncaused by: {}", e);
+        }
+        err!("{}", message);
+    } else {
+        err!("{}", e);
 
-    for e in e.iter().skip(1) {
-        info!("caused by: {}", e);
+        for e in e.iter().skip(1) {
+            info!("caused by: {}", e);
+        }
     }
 
     if show_backtrace() {
@@ -0,0 +1,307 @@
+//! `elan daemon`: a long-running JSON-RPC 2.0 server for editors
+//!
+//! Editors otherwise have to spawn elan for every toolchain resolution or
+//! binary lookup. The daemon instead reads newline-delimited JSON-RPC
+//! requests from stdin (or, on Unix, from connections to a socket given by
+//! `--socket`, each served by its own thread) and writes one response line
+//! per request. While a request is
+//! being processed, installation progress is streamed as `progress`
+//! notifications.
+//!
+//! With `--cache`, query results are memoized. All files a result may depend
+//! on are watched, and any change to them drops the entire cache. As channels
+//! such as `stable` move on without any local file changing, results also
+//! expire after `RESULT_TTL`. Proxies need no such invalidation as they
+//! resolve the toolchain anew on every run.
+
+use crate::errors::*;
+use crate::watch::Watcher;
+use elan::api::{dir_param, dispatch, ProgressEncoder, INVALID_REQUEST, PARSE_ERROR};
+use elan::{gc, utils, Cfg, Notification};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a memoized result may be served, bounding how long a channel
+/// keeps resolving to its previous release
+const RESULT_TTL: Duration = Duration::from_secs(60);
+
+/// Messages for the client, written in order by a dedicated thread so that
+/// notifications can be sent from any thread doing work for a request
+type Output = mpsc::Sender<Value>;
+
+pub fn main(socket: Option<&str>, use_cache: bool) -> Result<()> {
+    match socket {
+        None => {
+            let stdin = io::stdin();
+            serve(
+                stdin.lock(),
+                Box::new(io::stdout()),
+                use_cache,
+                &RwLock::new(()),
+            )?;
+            Ok(())
+        }
+        Some(path) => serve_socket(Path::new(path), use_cache),
+    }
+}
+
+/// Memoized query results together with the files they were derived from
+struct Cache {
+    enabled: bool,
+    watcher: Watcher,
+    /// Results by request, with when they were computed
+    results: HashMap<String, (Instant, Value)>,
+}
+
+impl Cache {
+    fn new(cfg: &Cfg, enabled: bool) -> Result<Self> {
+        let mut watcher = Watcher::new();
+        // `Cfg` itself caches the settings file, so we need to watch it even
+        // when result caching is disabled
+        watcher.watch(cfg.settings_file.path());
+        if enabled {
+            watcher.watch(&cfg.toolchains_dir);
+            for root in gc::get_roots(cfg)? {
+                if !root.is_empty() {
+                    watcher.watch(&Path::new(&root).join("lean-toolchain"));
+                }
+            }
+        }
+        Ok(Cache {
+            enabled,
+            watcher,
+            results: HashMap::new(),
+        })
+    }
+
+    /// Watches every file that the toolchain resolution for `dir` may depend on
+    fn watch_dir(&mut self, dir: &Path) {
+        for d in dir.ancestors() {
+            self.watcher.watch(&d.join("lean-toolchain"));
+            self.watcher.watch(&d.join("leanpkg.toml"));
+        }
+    }
+}
+
+/// Most connections to `--socket` served at once; further clients get an
+/// error response
+#[cfg(unix)]
+const MAX_CONNECTIONS: usize = 16;
+
+/// Connections to `--socket` without a request for this long are closed, so
+/// that clients that went away without hanging up do not hold on to one of
+/// the `MAX_CONNECTIONS`
+#[cfg(unix)]
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[cfg(unix)]
+fn serve_socket(path: &Path, use_cache: bool) -> Result<()> {
+    use elan::api::SERVER_ERROR;
+    use std::io::BufReader;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    if path.exists() {
+        utils::remove_file("socket", path)?;
+    }
+    let listener = UnixListener::bind(path)
+        .chain_err(|| format!("could not bind to socket '{}'", path.display()))?;
+    info!("listening on '{}'", path.display());
+
+    let active = Arc::new(AtomicUsize::new(0));
+    let stopping = Arc::new(AtomicBool::new(false));
+    let in_flight = Arc::new(RwLock::new(()));
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::SeqCst) {
+            break;
+        }
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            let busy = error_response(Value::Null, SERVER_ERROR, "too many connections");
+            let _ = writeln!(stream, "{}", busy);
+            continue;
+        }
+        let path = path.to_owned();
+        let active = active.clone();
+        let stopping = stopping.clone();
+        let in_flight = in_flight.clone();
+        thread::spawn(move || {
+            let res = (|| {
+                stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+                let reader = BufReader::new(stream.try_clone()?);
+                serve(reader, Box::new(stream), use_cache, &in_flight)
+            })();
+            // Clients that hang up or stay idle are of no concern
+            if let Ok(true) = res {
+                stopping.store(true, Ordering::SeqCst);
+                // Wake up the listener so that it notices
+                let _ = UnixStream::connect(&path);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    // Let requests of other clients finish before exiting
+    let _done = in_flight.write().unwrap();
+    let _ = std::fs::remove_file(path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_socket(_: &Path, _: bool) -> Result<()> {
+    Err("`--socket` is only supported on Unix; use stdio instead".into())
+}
+
+/// Serves requests until end of input. Returns whether a `shutdown` request
+/// was received. `in_flight` is read-locked while a request is processed.
+fn serve<R: BufRead>(
+    input: R,
+    output: Box<dyn Write + Send>,
+    use_cache: bool,
+    in_flight: &RwLock<()>,
+) -> Result<bool> {
+    let (out, messages) = mpsc::channel::<Value>();
+    let writer = thread::spawn(move || {
+        let mut output = output;
+        for msg in messages {
+            let _ = writeln!(output, "{}", msg);
+            let _ = output.flush();
+        }
+    });
+    let result = serve_requests(input, &out, use_cache, in_flight);
+    // Let the writer finish once every message has been written
+    drop(out);
+    let _ = writer.join();
+    result
+}
+
+fn serve_requests<R: BufRead>(
+    input: R,
+    out: &Output,
+    use_cache: bool,
+    in_flight: &RwLock<()>,
+) -> Result<bool> {
+    let mut cfg = new_cfg(out)?;
+    let mut cache = Cache::new(&cfg, use_cache)?;
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let _processing = in_flight.read().unwrap();
+
+        // Start over if anything was changed behind our back
+        if cache.watcher.changed() {
+            cfg = new_cfg(out)?;
+            cache = Cache::new(&cfg, use_cache)?;
+        }
+
+        let (response, shutdown) = handle_line(&cfg, &mut cache, &line);
+        if let Some(response) = response {
+            send(out, response);
+        }
+        if shutdown {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn send(out: &Output, msg: Value) {
+    // Only fails if the writer thread has panicked
+    let _ = out.send(msg);
+}
+
+/// Creates a configuration whose notifications are forwarded to the client as
+/// `progress` notifications.
+fn new_cfg(out: &Output) -> Result<Cfg> {
+    let out = out.clone();
+    let progress = ProgressEncoder::default();
+
+    Ok(Cfg::from_env(Arc::new(move |n: Notification<'_>| {
+        if let Some(params) = progress.encode(&n) {
+            send(
+                &out,
+                json!({ "jsonrpc": "2.0", "method": "progress", "params": params }),
+            );
+        }
+    }))?)
+}
+
+fn respond(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Returns the response, if any, and whether the daemon should shut down.
+fn handle_line(cfg: &Cfg, cache: &mut Cache, line: &str) -> (Option<Value>, bool) {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(r) => r,
+        Err(e) => {
+            return (
+                Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+                false,
+            )
+        }
+    };
+    // Requests without an id are notifications and must not be answered
+    let id = request.get("id").cloned();
+    let method = match request.get("method").and_then(Value::as_str) {
+        Some(m) => m,
+        None => {
+            return (
+                id.map(|id| error_response(id, INVALID_REQUEST, "missing method")),
+                false,
+            )
+        }
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    let shutdown = method == "shutdown";
+    let cacheable = cache.enabled && matches!(method, "resolve" | "which" | "list");
+    let key = format!("{} {}", method, params);
+    let result = match method {
+        "shutdown" => Ok(Value::Null),
+        _ => match cache.results.get(&key) {
+            Some((at, result)) if cacheable && at.elapsed() < RESULT_TTL => Ok(result.clone()),
+            _ => dispatch(cfg, method, &params),
+        },
+    };
+    if let (true, Ok(result)) = (cacheable, &result) {
+        if method != "list" && params.get("toolchain").is_none() {
+            match dir_param(&params) {
+                Ok(dir) => cache.watch_dir(&dir),
+                // Don't cache what we can't invalidate
+                Err(_) => return (id.map(|id| respond(id, result.clone())), shutdown),
+            }
+        }
+        cache.results.insert(key, (Instant::now(), result.clone()));
+    }
+    let response = id.map(|id| match result {
+        Ok(result) => respond(id, result),
+        Err(e) => {
+            let (code, msg) = e.describe();
+            error_response(id, code, &msg)
+        }
+    });
+    (response, shutdown)
+}
@@ -1,3 +1,4 @@
+use crate::log;
 use elan::Notification;
 use elan_dist::Notification as In;
 use elan_utils::tty;
@@ -9,6 +10,9 @@ use time::OffsetDateTime;
 /// Keep track of this many past download amounts
 const DOWNLOAD_TRACK_COUNT: usize = 5;
 
+/// Seconds between progress lines when stdout is not a terminal
+const LOG_INTERVAL: f64 = 30.0;
+
 /// Tracks download progress and displays information about it to a terminal.
 pub struct DownloadTracker {
     /// Content-Length of the to-be downloaded object.
@@ -35,6 +39,9 @@ pub struct DownloadTracker {
     /// If we have displayed progress, this is the number of characters we
     /// rendered, so we can erase it cleanly.
     displayed_charcount: Option<usize>,
+    /// Time stamp of the last progress line logged when stdout is not a
+    /// terminal, such as when an editor runs a binary of a missing toolchain
+    last_logged: Option<f64>,
 }
 
 impl DownloadTracker {
@@ -49,6 +56,7 @@ impl DownloadTracker {
             last_sec: None,
             term: term::stdout(),
             displayed_charcount: None,
+            last_logged: None,
         }
     }
 
@@ -60,8 +68,10 @@ impl DownloadTracker {
                 true
             }
             Notification::Install(In::Utils(Un::DownloadDataReceived(data))) => {
-                if tty::stdout_isatty() && self.term.is_some() {
+                if tty::stdout_isatty() && self.term.is_some() && !log::is_quiet() {
                     self.data_received(data.len());
+                } else {
+                    self.log_progress(data.len());
                 }
                 true
             }
@@ -104,6 +114,34 @@ impl DownloadTracker {
             }
         }
     }
+    /// Periodically logs the progress of the download to stderr so that logs
+    /// show that a long download is still alive.
+    fn log_progress(&mut self, len: usize) {
+        self.total_downloaded += len;
+        let current_time: f64 =
+            (OffsetDateTime::now_utc() - OffsetDateTime::UNIX_EPOCH).as_seconds_f64();
+        match self.last_logged {
+            None => self.last_logged = Some(current_time),
+            Some(last) if current_time - last >= LOG_INTERVAL => {
+                let total_h = HumanReadable(self.total_downloaded as f64).to_string();
+                match self.content_len {
+                    Some(content_len) => {
+                        let percent = (self.total_downloaded as f64 / content_len as f64) * 100.;
+                        let content_len_h = HumanReadable(content_len as f64).to_string();
+                        info!(
+                            "still downloading: {:.0}% ({} of {})",
+                            percent,
+                            total_h.trim(),
+                            content_len_h.trim()
+                        );
+                    }
+                    None => info!("still downloading: {}", total_h.trim()),
+                }
+                self.last_logged = Some(current_time);
+            }
+            Some(_) => {}
+        }
+    }
     /// Notifies self that the download has finished.
     pub fn download_finished(&mut self) {
         if self.displayed_charcount.is_some() {
@@ -122,6 +160,7 @@ impl DownloadTracker {
         self.seconds_elapsed = 0;
         self.last_sec = None;
         self.displayed_charcount = None;
+        self.last_logged = None;
     }
     /// Display the tracked download information to the terminal.
     fn display(&mut self) {
@@ -134,6 +173,11 @@ impl DownloadTracker {
         let speed = if len > 0 { sum / len as f64 } else { 0. };
         let speed_h = HumanReadable(speed);
 
+        // The line must never wrap, as then `\r` cannot return to its start.
+        // The last column is left empty as some consoles wrap when it is
+        // written to.
+        let max_width = tty::stdout_width().unwrap_or(80).saturating_sub(1);
+
         // First, move to the start of the current line and clear it.
         let _ = write!(self.term.as_mut().unwrap(), "\r");
         // We'd prefer to use delete_line() but on Windows it seems to
@@ -141,30 +185,48 @@ impl DownloadTracker {
         // let _ = self.term.as_mut().unwrap().delete_line();
         // So instead we do:
         if let Some(n) = self.displayed_charcount {
-            // This is not ideal as very narrow terminals might mess up,
-            // but it is more likely to succeed until term's windows console
-            // fixes whatever's up with delete_line().
-            let _ = write!(self.term.as_mut().unwrap(), "{}", " ".repeat(n));
+            // The terminal may have been resized in the meantime
+            let _ = write!(
+                self.term.as_mut().unwrap(),
+                "{}",
+                " ".repeat(n.min(max_width))
+            );
             let _ = self.term.as_mut().unwrap().flush();
             let _ = write!(self.term.as_mut().unwrap(), "\r");
         }
 
-        let output: String = match self.content_len {
+        // From most to least detailed
+        let candidates: Vec<String> = match self.content_len {
             Some(content_len) => {
                 let content_len = content_len as f64;
                 let percent = (self.total_downloaded as f64 / content_len) * 100.;
                 let content_len_h = HumanReadable(content_len);
                 let remaining = content_len - self.total_downloaded as f64;
                 let eta_h = HumanReadable(remaining / speed);
-                format!(
-                    "{} / {} ({:3.0} %) {}/s ETA: {:#}",
-                    total_h, content_len_h, percent, speed_h, eta_h
-                )
-            }
-            None => {
-                format!("Total: {} Speed: {}/s", total_h, speed_h)
+                vec![
+                    format!(
+                        "{} / {} ({:3.0} %) {}/s ETA: {:#}",
+                        total_h, content_len_h, percent, speed_h, eta_h
+                    ),
+                    format!(
+                        "{} / {} ({:3.0} %) {}/s",
+                        total_h, content_len_h, percent, speed_h
+                    ),
+                    format!("{:3.0} %", percent),
+                ]
             }
+            None => vec![
+                format!("Total: {} Speed: {}/s", total_h, speed_h),
+                format!("{}", total_h),
+            ],
         };
+        let output = candidates
+            .iter()
+            .find(|c| c.chars().count() <= max_width)
+            .unwrap_or_else(|| candidates.last().unwrap())
+            .chars()
+            .take(max_width)
+            .collect::<String>();
 
         let _ = write!(self.term.as_mut().unwrap(), "{output}");
         // Since stdout is typically line-buffered and we don't print a newline, we manually flush.
@@ -1,256 +1,764 @@
 use crate::common;
+use crate::common::YesArg;
+use crate::daemon;
 use crate::errors::*;
 use crate::help::*;
+use crate::job;
+use crate::man;
+use crate::proxy_completions;
+use crate::proxy_mode;
 use crate::self_update;
 use crate::term2;
-use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
-use elan::{command, gc, lookup_toolchain_desc, lookup_unresolved_toolchain_desc, Cfg, Toolchain};
+use clap::builder::PossibleValuesParser;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use elan::api;
+use elan::{
+    command,
+    env_var::{self, ENV_VARS},
+    gc,
+    history::{self, Entry, Operation},
+    lookup_toolchain_desc, lookup_unresolved_toolchain_desc,
+    read_unresolved_toolchain_desc_from_file, resolve_toolchain_desc_ext, Cfg, OverrideReason,
+    OverrideSource, Toolchain, UnresolvedToolchainDesc,
+};
 use elan_dist::dist::ToolchainDesc;
-use elan_utils::utils;
+use elan_dist::manifest::Component;
+use elan_utils::{tty, utils};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::error::Error;
-use std::io::{self, Write};
-use std::path::Path;
+use std::ffi::OsString;
+use std::io::{self, BufRead, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
+use time::OffsetDateTime;
 
 use serde_derive::Serialize;
 
 use crate::json_dump;
 
 pub fn main() -> Result<()> {
-    crate::self_update::cleanup_self_updater()?;
-
-    let matches = &cli().get_matches();
-    let verbose = matches.is_present("verbose");
+    crate::ci::mask_secrets();
+    let opts = Cli::parse();
+    let verbose = opts.verbose;
+    if opts.quiet || verbose {
+        crate::log::set_quiet(!verbose);
+    }
+    if opts.read_only {
+        // Also applies to any proxies run by us
+        env::set_var(env_var::ELAN_READ_ONLY, "1");
+    }
+    if !utils::elan_read_only() {
+        crate::self_update::cleanup_self_updater()?;
+    }
     let cfg = &(common::set_globals(verbose)?);
 
-    match matches.subcommand() {
-        ("show", Some(_)) => show(cfg)?,
-        ("install", Some(m)) => install(cfg, m)?,
-        ("uninstall", Some(m)) => toolchain_remove(cfg, m)?,
-        ("default", Some(m)) => default_(cfg, m)?,
-        ("toolchain", Some(c)) => match c.subcommand() {
-            ("install", Some(m)) => install(cfg, m)?,
-            ("list", Some(_)) => list_toolchains(cfg)?,
-            ("link", Some(m)) => toolchain_link(cfg, m)?,
-            ("uninstall", Some(m)) => toolchain_remove(cfg, m)?,
-            ("gc", Some(m)) => toolchain_gc(cfg, m)?,
-            (_, _) => unreachable!(),
+    // Clean up after installations interrupted e.g. by Ctrl-C
+    if let Err(e) = cfg.recover_interrupted_installs() {
+        warn!("{}", e);
+    }
+    match self_update::heal_proxies() {
+        Ok(relinked) if !relinked.is_empty() => {
+            info!(
+                "restored missing or outdated tools: {}",
+                relinked.join(", ")
+            )
+        }
+        Ok(_) => {}
+        Err(e) => warn!("could not restore missing tools: {}", e),
+    }
+
+    match &opts.command {
+        Some(ElanCommand::Show { quiet, verbose }) => show(cfg, *quiet, *verbose)?,
+        Some(ElanCommand::Install(args)) => install(cfg, args)?,
+        Some(ElanCommand::Uninstall(args)) => toolchain_remove(cfg, args)?,
+        Some(ElanCommand::Default { toolchain }) => default_(cfg, toolchain)?,
+        Some(ElanCommand::Toolchain { command }) => match command {
+            ToolchainCommand::Install(args) => install(cfg, args)?,
+            ToolchainCommand::List { quiet } => list_toolchains(cfg, *quiet, verbose)?,
+            ToolchainCommand::Link { toolchain, path } => toolchain_link(cfg, toolchain, path)?,
+            ToolchainCommand::Rename { old, new } => toolchain_rename(cfg, old, new)?,
+            ToolchainCommand::Uninstall(args) => toolchain_remove(cfg, args)?,
+            ToolchainCommand::Gc(args) => toolchain_gc(cfg, args)?,
+            ToolchainCommand::Freeze(args) => toolchain_freeze(cfg, &args.toolchain, true)?,
+            ToolchainCommand::Unfreeze(args) => toolchain_freeze(cfg, &args.toolchain, false)?,
+            ToolchainCommand::Strip {
+                toolchains,
+                pattern,
+                dry_run,
+            } => toolchain_strip(cfg, &toolchains.toolchain, pattern, *dry_run)?,
+            ToolchainCommand::DefaultFor { dir, json, no_net } => {
+                toolchain_default_for(cfg, dir, *json, *no_net)?
+            }
+            ToolchainCommand::RunAll(args) => toolchain_run_all(cfg, args)?,
         },
-        ("override", Some(c)) => match c.subcommand() {
-            ("list", Some(_)) => common::list_overrides(cfg)?,
-            ("set", Some(m)) => override_add(cfg, m)?,
-            ("unset", Some(m)) => override_remove(cfg, m)?,
-            (_, _) => unreachable!(),
+        Some(ElanCommand::Component { command }) => match command {
+            ComponentCommand::List { toolchain } => component_list(cfg, toolchain.as_deref())?,
+            ComponentCommand::Add(args) => component_add(cfg, args)?,
+            ComponentCommand::Remove(args) => component_remove(cfg, args)?,
         },
-        ("run", Some(m)) => run(cfg, m)?,
-        ("which", Some(m)) => which(cfg, m)?,
-        ("doc", Some(m)) => doc(cfg, m)?,
-        ("man", Some(m)) => man(cfg, m)?,
-        ("self", Some(c)) => match c.subcommand() {
-            ("update", Some(_)) => self_update::update()?,
-            ("uninstall", Some(m)) => self_uninstall(m)?,
-            (_, _) => unreachable!(),
+        Some(ElanCommand::Override { command }) => match command {
+            OverrideCommand::List => common::list_overrides(cfg)?,
+            OverrideCommand::Set { toolchain } => override_add(cfg, toolchain)?,
+            OverrideCommand::Unset {
+                path,
+                nonexistent,
+                yes,
+            } => override_remove(cfg, path.as_deref(), *nonexistent, yes)?,
+            OverrideCommand::Clean { reassign, yes } => {
+                override_clean(cfg, reassign.as_deref(), yes)?
+            }
         },
-        ("completions", Some(c)) => {
-            if let Some(shell) = c.value_of("shell") {
-                cli().gen_completions_to(
-                    "elan",
-                    shell.parse::<Shell>().unwrap(),
-                    &mut io::stdout(),
-                );
+        Some(ElanCommand::Run {
+            install,
+            toolchain,
+            command,
+        }) => run(cfg, toolchain, *install, command)?,
+        Some(ElanCommand::InitProject {
+            template,
+            dir,
+            toolchain,
+        }) => init_project(cfg, template, dir.as_deref(), toolchain.as_deref())?,
+        Some(ElanCommand::RunProxy { tool, args }) => proxy_mode::run(tool, args)?,
+        Some(ElanCommand::Which { command }) => which(cfg, command)?,
+        Some(ElanCommand::Man {
+            command,
+            toolchain,
+            generate,
+        }) => man(cfg, command, toolchain.as_deref(), generate.as_deref())?,
+        Some(ElanCommand::Installation { command })
+            if cfg.read_only && !matches!(command, SelfCommand::WhichInstallation { .. }) =>
+        {
+            return Err(elan::Error::from(elan::ErrorKind::ReadOnly).into())
+        }
+        Some(ElanCommand::Installation { command }) => match command {
+            SelfCommand::Update { yes } => self_update::update(yes)?,
+            SelfCommand::Clean => self_clean(cfg)?,
+            SelfCommand::NormalizeSettings => self_normalize_settings(cfg)?,
+            SelfCommand::RepairProxies => self_repair_proxies()?,
+            SelfCommand::WhichInstallation { json } => self_update::which_installation(*json)?,
+            SelfCommand::TrustRoot { command } => match command {
+                TrustRootCommand::Add { url, pubkey } => trust_root_add(cfg, url, pubkey)?,
+                TrustRootCommand::Remove { url } => trust_root_remove(cfg, url)?,
+                TrustRootCommand::List => trust_root_list(cfg)?,
+            },
+            SelfCommand::Uninstall { yes, dry_run, json } => {
+                self_update::uninstall(yes.yes, *dry_run, *json)?
             }
+        },
+        Some(ElanCommand::Completions {
+            shell,
+            proxies: true,
+        }) => proxy_completions::generate(*shell, &mut io::stdout())?,
+        Some(ElanCommand::Completions { shell, .. }) => {
+            // Written at once so that a closed pipe is an error, not a panic
+            let mut script = vec![];
+            clap_complete::generate(*shell, &mut cli(), "elan", &mut script);
+            io::stdout().write_all(&script)?;
         }
-        ("dump-state", Some(m)) => dump_state(cfg, m)?,
-        (_, _) => unreachable!(),
-    }
-
-    Ok(())
-}
-
-pub fn cli() -> App<'static, 'static> {
-    let app = App::new("elan")
-        .version(common::version())
-        .about("The Lean toolchain installer")
-        .after_help(ELAN_HELP)
-        .setting(AppSettings::VersionlessSubcommands)
-        .setting(AppSettings::DeriveDisplayOrder)
-        .setting(AppSettings::SubcommandRequiredElseHelp)
-        .arg(Arg::with_name("verbose")
-            .help("Enable verbose output")
-            .short("v")
-            .long("verbose"))
-        .subcommand(SubCommand::with_name("show")
-            .about("Show the active and installed toolchains")
-            .after_help(SHOW_HELP))
-        .subcommand(SubCommand::with_name("install")
-            .about("Install Lean toolchain")
-            .after_help(INSTALL_HELP)
-            .setting(AppSettings::Hidden) // synonym for 'toolchain install'
-            .arg(Arg::with_name("toolchain")
-                .help(TOOLCHAIN_ARG_HELP)
-                .required(true)
-                .multiple(true)))
-        .subcommand(SubCommand::with_name("uninstall")
-            .about("Uninstall Lean toolchains")
-            .setting(AppSettings::Hidden) // synonym for 'toolchain uninstall'
-            .arg(Arg::with_name("toolchain")
-                .help(TOOLCHAIN_ARG_HELP)
-                .required(true)
-                .multiple(true)))
-        .subcommand(SubCommand::with_name("default")
-            .about("Set the default toolchain")
-            .after_help(DEFAULT_HELP)
-            .arg(Arg::with_name("toolchain")
-                .help(TOOLCHAIN_ARG_HELP)
-                .required(true)))
-        .subcommand(SubCommand::with_name("toolchain")
-            .about("Modify or query the installed toolchains")
-            .after_help(TOOLCHAIN_HELP)
-            .setting(AppSettings::VersionlessSubcommands)
-            .setting(AppSettings::DeriveDisplayOrder)
-            .setting(AppSettings::SubcommandRequiredElseHelp)
-            .subcommand(SubCommand::with_name("list")
-                .about("List installed toolchains"))
-            .subcommand(SubCommand::with_name("install")
-                .about("Install a given toolchain")
-                .arg(Arg::with_name("toolchain")
-                     .help(TOOLCHAIN_ARG_HELP)
-                     .required(true)
-                     .multiple(true)))
-            .subcommand(SubCommand::with_name("uninstall")
-                .about("Uninstall a toolchain")
-                .alias("remove")
-                .arg(Arg::with_name("toolchain")
-                     .help(TOOLCHAIN_ARG_HELP)
-                     .required(true)
-                     .multiple(true)))
-            .subcommand(SubCommand::with_name("link")
-                .about("Create a custom toolchain by symlinking to a directory")
-                .after_help(TOOLCHAIN_LINK_HELP)
-                .arg(Arg::with_name("toolchain")
-                    .help(TOOLCHAIN_ARG_HELP)
-                    .required(true))
-                .arg(Arg::with_name("path")
-                    .required(true)))
-            .subcommand(SubCommand::with_name("gc")
-                .about("Garbage-collect toolchains not used by any known project")
-                .after_help(TOOLCHAIN_GC_HELP)
-                .arg(Arg::with_name("delete")
-                    .long("delete")
-                    .help("Delete collected toolchains instead of only reporting them"))
-                .arg(Arg::with_name("json")
-                    .long("json")
-                    .help("Format output as JSON"))))
-        .subcommand(SubCommand::with_name("override")
-            .about("Modify directory toolchain overrides")
-            .after_help(OVERRIDE_HELP)
-            .setting(AppSettings::VersionlessSubcommands)
-            .setting(AppSettings::DeriveDisplayOrder)
-            .setting(AppSettings::SubcommandRequiredElseHelp)
-            .subcommand(SubCommand::with_name("list")
-                .about("List directory toolchain overrides"))
-            .subcommand(SubCommand::with_name("set")
-                .about("Set the override toolchain for a directory")
-                .alias("add")
-                .arg(Arg::with_name("toolchain")
-                     .help(TOOLCHAIN_ARG_HELP)
-                     .required(true)))
-            .subcommand(SubCommand::with_name("unset")
-                .about("Remove the override toolchain for a directory")
-                .after_help(OVERRIDE_UNSET_HELP)
-                .alias("remove")
-                .arg(Arg::with_name("path")
-                    .long("path")
-                    .takes_value(true)
-                    .help("Path to the directory"))
-                .arg(Arg::with_name("nonexistent")
-                    .long("nonexistent")
-                    .takes_value(false)
-                    .help("Remove override toolchain for all nonexistent directories"))))
-        .subcommand(SubCommand::with_name("run")
-            .about("Run a command with an environment configured for a given toolchain")
-            .after_help(RUN_HELP)
-            .setting(AppSettings::TrailingVarArg)
-            .arg(Arg::with_name("install")
-                .help("Install the requested toolchain if needed")
-                .long("install"))
-            .arg(Arg::with_name("toolchain")
-                .help(TOOLCHAIN_ARG_HELP)
-                .required(true))
-            .arg(Arg::with_name("command")
-                .required(true).multiple(true).use_delimiter(false)))
-        .subcommand(SubCommand::with_name("which")
-            .about("Display which binary will be run for a given command")
-            .arg(Arg::with_name("command")
-                .required(true)))
-        .subcommand(SubCommand::with_name("dump-state")
-            .setting(AppSettings::Hidden)
-            .arg(Arg::with_name("no-net")
-                .help("Make network operations for resolving channels fail immediately")
-                .long("no-net")))
-        /*.subcommand(SubCommand::with_name("doc")
-            .alias("docs")
-            .about("Open the documentation for the current toolchain")
-            .after_help(DOC_HELP)
-            .arg(Arg::with_name("book")
-                 .long("book")
-                 .help("The Rust Programming Language book"))
-            .arg(Arg::with_name("std")
-                 .long("std")
-                 .help("Standard library API documentation"))
-            .group(ArgGroup::with_name("page")
-                 .args(&["book", "std"])))*/;
-
-    /*if cfg!(not(target_os = "windows")) {
-        app = app
-            .subcommand(SubCommand::with_name("man")
-                    .about("View the man page for a given command")
-                    .arg(Arg::with_name("command")
-                         .required(true))
-                    .arg(Arg::with_name("toolchain")
-                         .help(TOOLCHAIN_ARG_HELP)
-                         .long("toolchain")
-                         .takes_value(true)));
-    }*/
-
-    app.subcommand(
-        SubCommand::with_name("self")
-            .about("Modify the elan installation")
-            .setting(AppSettings::VersionlessSubcommands)
-            .setting(AppSettings::DeriveDisplayOrder)
-            .setting(AppSettings::SubcommandRequiredElseHelp)
-            .subcommand(
-                SubCommand::with_name("update").about("Download and install updates to elan"),
-            )
-            .subcommand(
-                SubCommand::with_name("uninstall")
-                    .about("Uninstall elan.")
-                    .arg(Arg::with_name("no-prompt").short("y")),
-            ),
-    )
-    /*.subcommand(SubCommand::with_name("telemetry")
-    .about("elan telemetry commands")
-    .setting(AppSettings::Hidden)
-    .setting(AppSettings::VersionlessSubcommands)
-    .setting(AppSettings::DeriveDisplayOrder)
-    .setting(AppSettings::SubcommandRequiredElseHelp)
-    .subcommand(SubCommand::with_name("enable")
-                    .about("Enable elan telemetry"))
-    .subcommand(SubCommand::with_name("disable")
-                    .about("Disable elan telemetry"))
-    .subcommand(SubCommand::with_name("analyze")
-                    .about("Analyze stored telemetry")))*/
-    .subcommand(
-        SubCommand::with_name("completions")
-            .about("Generate completion scripts for your shell")
-            .after_help(COMPLETIONS_HELP)
-            .setting(AppSettings::ArgRequiredElseHelp)
-            .arg(Arg::with_name("shell").possible_values(&Shell::variants())),
-    )
+        Some(ElanCommand::CompleteToolchains) => proxy_completions::complete_toolchains(cfg)?,
+        Some(ElanCommand::DumpState { no_net }) => dump_state(cfg, *no_net)?,
+        Some(ElanCommand::History { limit, json }) => show_history(cfg, *limit, *json)?,
+        Some(ElanCommand::Undo { dry_run }) => undo(cfg, *dry_run)?,
+        Some(ElanCommand::Resolve {
+            toolchain,
+            stdin,
+            no_net,
+        }) => resolve(cfg, toolchain, *stdin, *no_net)?,
+        Some(ElanCommand::Env { list }) => env_vars(*list),
+        Some(ElanCommand::Config { command }) => match command {
+            ConfigCommand::Get { key } => config_get(cfg, key)?,
+            ConfigCommand::Set { key, value } => config_set(cfg, key, value)?,
+            ConfigCommand::Unset { key } => config_unset(cfg, key)?,
+            ConfigCommand::List => config_list(cfg)?,
+        },
+        Some(ElanCommand::Cache { command }) => match command {
+            CacheCommand::Key(workspace) => cache_key(cfg, workspace)?,
+            CacheCommand::Export { archive, workspace } => cache_export(cfg, archive, workspace)?,
+            CacheCommand::Import { archive } => cache_import(cfg, archive)?,
+            CacheCommand::Serve { port } => cache_serve(cfg, port.unwrap_or(0))?,
+            CacheCommand::Fetch(workspace) => cache_fetch(cfg, workspace)?,
+        },
+        Some(ElanCommand::Daemon { socket, cache }) => daemon::main(socket.as_deref(), *cache)?,
+        Some(ElanCommand::Api {
+            method,
+            params,
+            api_version,
+        }) => api(cfg, method, params.as_deref(), api_version.as_deref())?,
+        Some(ElanCommand::Target(_)) => rustup_verb(
+            "target",
+            "Lean toolchains are installed for a single platform; use \
+             `elan toolchain install --target <triple>` to install the build for another one",
+        )?,
+        Some(ElanCommand::Update(_)) => rustup_verb(
+            "update",
+            "channels such as `stable` are resolved to their newest release whenever they are \
+             used; use `elan self update` to update elan itself",
+        )?,
+        Some(ElanCommand::Check(_)) => rustup_verb(
+            "check",
+            "use `elan resolve stable` to see the release a channel currently resolves to",
+        )?,
+        Some(ElanCommand::Set(_)) => {
+            rustup_verb("set", "settings are changed with `elan config set`")?
+        }
+        Some(ElanCommand::Doc(_)) => rustup_verb(
+            "doc",
+            "use `elan man` to view the manual of elan or of a tool such as `lean`",
+        )?,
+        None => status(cfg)?,
+    }
+
+    Ok(())
+}
+
+/// The Lean toolchain installer
+#[derive(Parser, Debug)]
+#[command(name = "elan", version = common::version(), after_help = ELAN_HELP)]
+struct Cli {
+    /// Enable verbose output
+    #[arg(short, long)]
+    verbose: bool,
+    /// Suppress info messages and download progress; the default in containers without a terminal unless ELAN_QUIET=0
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Use installed toolchains without modifying any toolchains or settings; also enabled by setting ELAN_READ_ONLY=1
+    #[arg(long)]
+    read_only: bool,
+    #[command(subcommand)]
+    command: Option<ElanCommand>,
+}
+
+/// The command line interface of elan, for generating completions and man
+/// pages
+pub fn cli() -> clap::Command {
+    Cli::command()
+}
+
+#[derive(Subcommand, Debug)]
+enum ElanCommand {
+    /// Show the active and installed toolchains
+    #[command(after_help = SHOW_HELP)]
+    Show {
+        /// List installed toolchains by name only
+        #[arg(short, long)]
+        quiet: bool,
+        /// Also show where the active toolchain is looked for, in order of precedence
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Install Lean toolchain
+    // synonym for 'toolchain install'
+    #[command(after_help = INSTALL_HELP, hide = true)]
+    Install(InstallArgs),
+    /// Uninstall Lean toolchains
+    // synonym for 'toolchain uninstall'
+    #[command(hide = true)]
+    Uninstall(UninstallArgs),
+    /// Set the default toolchain
+    #[command(after_help = DEFAULT_HELP)]
+    Default {
+        #[arg(help = TOOLCHAIN_ARG_HELP)]
+        toolchain: String,
+    },
+    /// Modify or query the installed toolchains
+    #[command(after_help = TOOLCHAIN_HELP, arg_required_else_help = true)]
+    Toolchain {
+        #[command(subcommand)]
+        command: ToolchainCommand,
+    },
+    /// Modify a toolchain's installed components
+    #[command(after_help = COMPONENT_HELP, arg_required_else_help = true)]
+    Component {
+        #[command(subcommand)]
+        command: ComponentCommand,
+    },
+    /// Modify directory toolchain overrides
+    #[command(after_help = OVERRIDE_HELP, arg_required_else_help = true)]
+    Override {
+        #[command(subcommand)]
+        command: OverrideCommand,
+    },
+    /// Run a command with an environment configured for a given toolchain
+    #[command(after_help = RUN_HELP)]
+    Run {
+        /// Install the requested toolchain if needed
+        #[arg(long)]
+        install: bool,
+        #[arg(help = TOOLCHAIN_ARG_HELP)]
+        toolchain: String,
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<OsString>,
+    },
+    /// Create a Lean project with a suitable toolchain
+    #[command(after_help = INIT_PROJECT_HELP)]
+    InitProject {
+        /// Lake template of the project, e.g. `std`, `exe`, `lib`, or `math`
+        template: String,
+        /// Directory of the project, which is created if needed [default: the current directory]
+        dir: Option<PathBuf>,
+        /// Toolchain of the project [default: the one of Mathlib for `math` templates, otherwise `stable`]
+        #[arg(long)]
+        toolchain: Option<String>,
+    },
+    /// Run a proxied tool such as lean or lake, regardless of the name elan was invoked by
+    #[command(after_help = RUN_PROXY_HELP)]
+    RunProxy {
+        tool: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<OsString>,
+    },
+    /// Display which binary will be run for a given command
+    Which { command: String },
+    /// Show the environment variables elan reads
+    #[command(after_help = env_help())]
+    Env {
+        /// List all environment variables known to elan instead of those currently set
+        #[arg(long)]
+        list: bool,
+    },
+    /// Query and modify settings
+    #[command(after_help = CONFIG_HELP, arg_required_else_help = true)]
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Resolve toolchain names or `lean-toolchain` files, printing JSON lines
+    #[command(after_help = RESOLVE_HELP)]
+    Resolve {
+        /// Toolchain name or path to a `lean-toolchain` file
+        #[arg(required_unless_present = "stdin")]
+        toolchain: Vec<String>,
+        /// Read toolchain names or paths from stdin, one per line
+        #[arg(long)]
+        stdin: bool,
+        #[arg(long, alias = "offline", help = NO_NET_ARG_HELP)]
+        no_net: bool,
+    },
+    /// Print elan's state in a machine-readable format
+    #[command(after_help = DUMP_STATE_HELP)]
+    DumpState {
+        #[arg(long, alias = "offline", help = NO_NET_ARG_HELP)]
+        no_net: bool,
+    },
+    /// Show the operations that changed the installed or selected toolchains
+    #[command(after_help = HISTORY_HELP)]
+    History {
+        /// Show only the most recent operations
+        #[arg(short = 'n', long, value_name = "COUNT")]
+        limit: Option<usize>,
+        /// Print the operations as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Revert the most recent uninstallation, garbage collection, or default or override change
+    #[command(after_help = UNDO_HELP)]
+    Undo {
+        /// Show what would be restored without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Cache and share toolchains and release archives
+    #[command(after_help = CACHE_HELP, arg_required_else_help = true)]
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Serve toolchain queries over JSON-RPC for editor integration
+    #[command(after_help = DAEMON_HELP)]
+    Daemon {
+        /// Listen on the given Unix socket instead of stdio
+        #[arg(long)]
+        socket: Option<String>,
+        /// Memoize query results for up to a minute, or until a file they depend on changes
+        #[arg(long)]
+        cache: bool,
+    },
+    /// Perform a single operation with a stable JSON interface for automation
+    #[command(after_help = API_HELP)]
+    Api {
+        #[arg(value_parser = PossibleValuesParser::new(api::METHODS))]
+        method: String,
+        /// Parameters as a JSON object, or '-' to read them from stdin
+        params: Option<String>,
+        /// Fail unless this elan implements the given API version
+        #[arg(long)]
+        api_version: Option<String>,
+    },
+    /// View the man page of elan, one of its commands, or a tool of the toolchain
+    #[command(after_help = MAN_HELP)]
+    Man {
+        /// Command such as `toolchain install` or `elan-toolchain-install`, or a tool such as `lean` [default: elan]
+        #[arg(conflicts_with = "generate")]
+        command: Vec<String>,
+        #[arg(long, help = TOOLCHAIN_ARG_HELP)]
+        toolchain: Option<String>,
+        /// Write the man pages of elan and all its commands to this directory instead
+        #[arg(long, value_name = "DIR")]
+        generate: Option<PathBuf>,
+    },
+    /// Modify the elan installation
+    #[command(name = "self", arg_required_else_help = true)]
+    Installation {
+        #[command(subcommand)]
+        command: SelfCommand,
+    },
+    /// Generate completion scripts for your shell
+    #[command(after_help = COMPLETIONS_HELP, arg_required_else_help = true)]
+    Completions {
+        shell: Shell,
+        /// Generate completions of `+toolchain` for lean, lake, and other proxies
+        #[arg(long)]
+        proxies: bool,
+    },
+    // Used by the scripts of `elan completions --proxies`
+    #[command(hide = true)]
+    CompleteToolchains,
+    // Commands of rustup that have no counterpart in elan, which point to
+    // what to use instead
+    #[command(hide = true)]
+    Target(RustupVerbArgs),
+    #[command(hide = true)]
+    Update(RustupVerbArgs),
+    #[command(hide = true)]
+    Check(RustupVerbArgs),
+    #[command(hide = true)]
+    Set(RustupVerbArgs),
+    #[command(hide = true)]
+    Doc(RustupVerbArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum ToolchainCommand {
+    /// List installed toolchains
+    #[command(after_help = TOOLCHAIN_LIST_HELP)]
+    List {
+        /// List toolchains by name only
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Install a given toolchain
+    #[command(alias = "add")]
+    Install(InstallArgs),
+    /// Uninstall a toolchain
+    #[command(after_help = TOOLCHAIN_UNINSTALL_HELP, aliases = ["remove", "rm"])]
+    Uninstall(UninstallArgs),
+    /// Create a custom toolchain by symlinking to a directory
+    #[command(after_help = TOOLCHAIN_LINK_HELP)]
+    Link {
+        #[arg(help = TOOLCHAIN_ARG_HELP)]
+        toolchain: String,
+        path: PathBuf,
+    },
+    /// Rename a linked toolchain, updating the settings that refer to it
+    #[command(after_help = TOOLCHAIN_RENAME_HELP)]
+    Rename {
+        /// Current name of the linked toolchain
+        old: String,
+        /// New name of the linked toolchain
+        new: String,
+    },
+    /// Protect toolchains from removal without `--force`
+    #[command(after_help = TOOLCHAIN_FREEZE_HELP)]
+    Freeze(ToolchainsArg),
+    /// Remove the protection added by `elan toolchain freeze`
+    Unfreeze(ToolchainsArg),
+    /// Remove optional files such as documentation from installed toolchains
+    #[command(after_help = TOOLCHAIN_STRIP_HELP)]
+    Strip {
+        #[command(flatten)]
+        toolchains: ToolchainsArg,
+        /// Remove the paths matching this glob instead of those selected by the `strip_patterns` setting
+        #[arg(long)]
+        pattern: Vec<String>,
+        /// Print the paths that would be removed and their sizes without removing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Garbage-collect toolchains not used by any known project
+    #[command(after_help = TOOLCHAIN_GC_HELP)]
+    Gc(GcArgs),
+    /// Print the toolchain that would be used in the given directories
+    #[command(after_help = TOOLCHAIN_DEFAULT_FOR_HELP)]
+    DefaultFor {
+        #[arg(required = true)]
+        dir: Vec<String>,
+        /// Format output as JSON
+        #[arg(long)]
+        json: bool,
+        #[arg(long, alias = "offline", help = NO_NET_ARG_HELP)]
+        no_net: bool,
+    },
+    /// Run a command under each of several toolchains
+    #[command(after_help = TOOLCHAIN_RUN_ALL_HELP)]
+    RunAll(RunAllArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum ComponentCommand {
+    /// List installed and available components
+    List {
+        #[arg(long, help = TOOLCHAIN_ARG_HELP)]
+        toolchain: Option<String>,
+    },
+    /// Add components to a Lean toolchain
+    Add(ComponentArgs),
+    /// Remove components from a Lean toolchain
+    #[command(aliases = ["uninstall", "rm"])]
+    Remove(ComponentArgs),
+}
+
+#[derive(Subcommand, Debug)]
+enum OverrideCommand {
+    /// List directory toolchain overrides
+    List,
+    /// Set the override toolchain for a directory
+    #[command(alias = "add")]
+    Set {
+        #[arg(help = TOOLCHAIN_ARG_HELP)]
+        toolchain: String,
+    },
+    /// Remove the override toolchain for a directory
+    #[command(after_help = OVERRIDE_UNSET_HELP, alias = "remove")]
+    Unset {
+        /// Path to the directory
+        #[arg(long)]
+        path: Option<String>,
+        /// Remove override toolchain for all nonexistent directories
+        #[arg(long)]
+        nonexistent: bool,
+        #[command(flatten)]
+        yes: YesArg,
+    },
+    /// Remove or reassign overrides whose linked toolchain no longer exists
+    #[command(after_help = OVERRIDE_CLEAN_HELP)]
+    Clean {
+        /// Reassign the overrides to this toolchain instead of removing them
+        #[arg(long, value_name = "TOOLCHAIN")]
+        reassign: Option<String>,
+        #[command(flatten)]
+        yes: YesArg,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the value of a setting
+    Get { key: String },
+    /// Change the value of a setting
+    Set { key: String, value: String },
+    /// Reset a setting to its default
+    Unset { key: String },
+    /// List all settings that are not at their defaults
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Print the cache key for the toolchains of a workspace
+    Key(WorkspaceArg),
+    /// Write the toolchains of a workspace to a cache archive
+    Export {
+        /// Path of the `.tar.zst` archive to write
+        archive: PathBuf,
+        #[command(flatten)]
+        workspace: WorkspaceArg,
+    },
+    /// Install the toolchains in a cache archive
+    Import {
+        /// Path of an archive written by `elan cache export`
+        archive: PathBuf,
+    },
+    /// Serve the download cache to elan instances on the local network
+    #[command(after_help = CACHE_SERVE_HELP)]
+    Serve {
+        /// Listen on this port instead of any free one
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Fetch the artifact caches a workspace declares, such as prebuilt Mathlib
+    #[command(after_help = CACHE_FETCH_HELP)]
+    Fetch(WorkspaceArg),
+}
+
+#[derive(Subcommand, Debug)]
+enum SelfCommand {
+    /// Download and install updates to elan
+    #[command(after_help = SELF_UPDATE_HELP)]
+    Update {
+        #[command(flatten)]
+        yes: YesArg,
+    },
+    /// Recover from interrupted installations and remove temporary files
+    #[command(after_help = SELF_CLEAN_HELP)]
+    Clean,
+    /// Show how and from where elan was installed
+    #[command(after_help = SELF_WHICH_INSTALLATION_HELP)]
+    WhichInstallation {
+        /// Print the information as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Relink the tools in `ELAN_HOME/bin` to elan
+    #[command(after_help = SELF_REPAIR_PROXIES_HELP)]
+    RepairProxies,
+    /// Rewrite toolchain names in the settings to their canonical form
+    #[command(after_help = SELF_NORMALIZE_SETTINGS_HELP)]
+    NormalizeSettings,
+    /// Manage the keys that self-updates from alternative update roots are verified with
+    #[command(after_help = SELF_TRUST_ROOT_HELP, arg_required_else_help = true)]
+    TrustRoot {
+        #[command(subcommand)]
+        command: TrustRootCommand,
+    },
+    /// Uninstall elan.
+    #[command(after_help = SELF_UNINSTALL_HELP)]
+    Uninstall {
+        #[command(flatten)]
+        yes: YesArg,
+        /// Only report the files, shell configuration lines, and registry entries that would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Format the report of `--dry-run` as JSON
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TrustRootCommand {
+    /// Require self-updates from an update root to be signed with a key
+    // PEM keys start with dashes
+    #[command(allow_hyphen_values = true)]
+    Add { url: String, pubkey: String },
+    /// Stop verifying self-updates from an update root
+    Remove { url: String },
+    /// List update roots and their keys
+    List,
+}
+
+#[derive(Args, Debug)]
+struct InstallArgs {
+    #[arg(required = true, help = TOOLCHAIN_ARG_HELP)]
+    toolchain: Vec<String>,
+    #[arg(short, long, help = COMPONENT_ARG_HELP)]
+    component: Vec<String>,
+    #[arg(long, help = NO_EMULATION_ARG_HELP)]
+    no_emulation: bool,
+    #[arg(long, help = TARGET_ARG_HELP)]
+    target: Option<String>,
+    #[arg(long, help = REINSTALL_ARG_HELP)]
+    reinstall: bool,
+    #[arg(long, help = FORCE_INSTALL_ARG_HELP)]
+    force: bool,
+    #[arg(long, help = DRY_RUN_INSTALL_ARG_HELP)]
+    dry_run: bool,
+    #[arg(long, help = MINIMIZE_ARG_HELP)]
+    minimize: bool,
+    // Only available when elan is built with source build support
+    #[cfg(feature = "build-from-source")]
+    #[arg(long, help = BUILD_ARG_HELP, conflicts_with_all = ["component", "target", "dry_run"])]
+    build: bool,
+}
+
+#[derive(Args, Debug)]
+struct UninstallArgs {
+    #[arg(required_unless_present = "all", help = TOOLCHAIN_ARG_HELP)]
+    toolchain: Vec<String>,
+    #[arg(long, help = FORCE_UNINSTALL_ARG_HELP)]
+    force: bool,
+    /// Uninstall all toolchains
+    #[arg(long, conflicts_with = "toolchain")]
+    all: bool,
+    /// Toolchain to keep when using `--all`; may be repeated
+    #[arg(long, requires = "all")]
+    except: Vec<String>,
+    #[command(flatten)]
+    yes: YesArg,
+}
+
+#[derive(Args, Debug)]
+struct ToolchainsArg {
+    #[arg(required = true, help = TOOLCHAIN_ARG_HELP)]
+    toolchain: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct GcArgs {
+    /// Delete collected toolchains instead of only reporting them
+    #[arg(long)]
+    delete: bool,
+    /// Also delete unused toolchains that are frozen
+    #[arg(long, requires = "delete")]
+    force: bool,
+    /// Only report what `--delete` would delete, which is the default
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    yes: YesArg,
+    /// Format output as JSON
+    #[arg(long)]
+    json: bool,
+    /// Forget known projects whose directory or `lean-toolchain` file no longer exists
+    #[arg(long)]
+    prune_roots: bool,
+    /// List known projects instead of collecting toolchains
+    #[arg(long, conflicts_with_all = ["delete", "prune_roots", "forget_root"])]
+    list_roots: bool,
+    /// Forget a known project; may be repeated
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["delete", "prune_roots"])]
+    forget_root: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct RunAllArgs {
+    /// Toolchain to run under instead of all installed ones; may be repeated
+    #[arg(long)]
+    toolchain: Vec<String>,
+    /// Install the requested toolchains if needed
+    #[arg(long)]
+    install: bool,
+    /// Number of toolchains to run the command under in parallel
+    #[arg(short, long, default_value = "1")]
+    jobs: NonZeroUsize,
+    /// Write a JSON report of the results to the given file
+    #[arg(long)]
+    json: Option<PathBuf>,
+    #[arg(required = true, trailing_var_arg = true)]
+    command: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct ComponentArgs {
+    #[arg(required = true)]
+    component: Vec<String>,
+    #[arg(long, help = TOOLCHAIN_ARG_HELP)]
+    toolchain: Option<String>,
+}
+
+/// Whatever is passed to a command of rustup that elan does not have
+#[derive(Args, Debug)]
+struct RustupVerbArgs {
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct WorkspaceArg {
+    /// Workspace directories [default: the current directory]
+    dir: Vec<PathBuf>,
+}
+
+/// Fails for a command of rustup that elan has no counterpart for
+fn rustup_verb(command: &str, hint: &str) -> Result<()> {
+    Err(ErrorKind::UnsupportedCommand(command.to_owned(), hint.to_owned()).into())
 }
 
-fn default_(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let name = m.value_of("toolchain").expect("");
+fn default_(cfg: &Cfg, name: &str) -> Result<()> {
     // sanity-check
     let _ = lookup_unresolved_toolchain_desc(cfg, name)?;
 
@@ -258,35 +766,191 @@ fn default_(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
     Ok(())
 }
 
-fn install(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let names = m.values_of("toolchain").expect("");
-    for name in names {
+#[cfg(feature = "build-from-source")]
+fn build_toolchains(cfg: &Cfg, args: &InstallArgs) -> Result<()> {
+    for name in &args.toolchain {
+        let desc = match lookup_unresolved_toolchain_desc(cfg, name)?.0 {
+            // Build commits as given instead of looking for their release
+            ToolchainDesc::Remote {
+                origin, release, ..
+            } if elan::is_commit_hash(&release) => ToolchainDesc::Remote {
+                origin,
+                release,
+                from_channel: None,
+            },
+            _ => lookup_toolchain_desc(cfg, name)?,
+        };
+        cfg.get_toolchain(&desc, false)?.install_from_source()?;
+    }
+    Ok(())
+}
+
+fn install(cfg: &Cfg, args: &InstallArgs) -> Result<()> {
+    #[cfg(feature = "build-from-source")]
+    if args.build {
+        return build_toolchains(cfg, args);
+    }
+    let components = args
+        .component
+        .iter()
+        .map(|c| Component { pkg: c.to_owned() })
+        .collect::<Vec<_>>();
+    for name in &args.toolchain {
         let desc = lookup_toolchain_desc(cfg, name)?;
         let toolchain = cfg.get_toolchain(&desc, false)?;
 
-        if !toolchain.exists() || !toolchain.is_custom() {
-            toolchain.install_from_dist()?;
-            println!();
-            common::show_channel_update(cfg, &toolchain.desc)?;
+        if args.dry_run {
+            show_install_plan(&toolchain, &components, args)?;
+            continue;
         }
+        if toolchain.exists() && toolchain.is_custom() {
+            if !args.force || matches!(desc, ToolchainDesc::Local { .. }) {
+                info!("'{}' is a linked toolchain; skipping", desc);
+                continue;
+            }
+            toolchain.remove_as(Operation::Replace)?;
+        }
+        if toolchain.exists() && !args.reinstall && !components.is_empty() {
+            toolchain.add_missing_components(&components)?;
+        } else {
+            toolchain.install_from_dist_ext(
+                &components,
+                !args.no_emulation,
+                args.target.as_deref(),
+                args.reinstall,
+            )?;
+        }
+        if args.minimize {
+            let freed = toolchain.strip(&cfg.strip_patterns()?)?;
+            info!("minimized '{}', freeing {}", desc, format_size(freed));
+        }
+        println!();
+        common::show_channel_update(cfg, &toolchain.desc)?;
     }
 
     Ok(())
 }
 
-fn run(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let toolchain = m.value_of("toolchain").expect("");
-    let args = m.values_of("command").unwrap();
-    let args: Vec<_> = args.collect();
+/// Prints what installing `toolchain` would download and where to, without
+/// downloading or changing anything
+fn show_install_plan(
+    toolchain: &Toolchain<'_>,
+    components: &[Component],
+    args: &InstallArgs,
+) -> Result<()> {
+    let linked = toolchain.exists() && toolchain.is_custom();
+    println!("toolchain: {}", toolchain.desc);
+    if linked && (!args.force || matches!(toolchain.desc, ToolchainDesc::Local { .. })) {
+        println!("installed: yes, linked; skipped");
+        return Ok(());
+    }
+    let plan =
+        toolchain.plan_install_from_dist(components, !args.no_emulation, args.target.as_deref())?;
+    let emulated = if plan.emulated { ", emulated" } else { "" };
+    println!("asset: {} ({}{})", plan.url, plan.target, emulated);
+    println!("size: {}", asset_size(&plan.url));
+    for (component, url) in &plan.components {
+        println!("component {}: {}", component.name(), url);
+        println!("  size: {}", asset_size(url));
+    }
+    println!("destination: {}", toolchain.path().display());
+    let installed = if linked {
+        "yes, linked; replaced due to --force"
+    } else if !toolchain.exists() {
+        "no"
+    } else if args.reinstall {
+        "yes; replaced due to --reinstall"
+    } else {
+        "yes"
+    };
+    println!("installed: {}", installed);
+    Ok(())
+}
+
+/// Size of a release asset as reported by the server, if it reports one
+fn asset_size(url: &str) -> String {
+    match utils::parse_url(url).and_then(|url| utils::fetch_content_length(&url)) {
+        Ok(Some(len)) => format!("{} ({} bytes)", format_size(len), len),
+        Ok(None) => "unknown".to_owned(),
+        Err(e) => format!("unknown ({})", e),
+    }
+}
+
+/// Runs `args`, which are passed on exactly as given even if they are not
+/// UTF-8
+fn run(cfg: &Cfg, toolchain: &str, install: bool, args: &[OsString]) -> Result<()> {
     let desc = lookup_toolchain_desc(cfg, toolchain)?;
-    let cmd = cfg.create_command_for_toolchain(&desc, m.is_present("install"), args[0])?;
+    let cmd = cfg.create_command_for_toolchain(&desc, install, &args[0])?;
 
-    Ok(command::run_command_for_dir(cmd, args[0], &args[1..])?)
+    let code = {
+        let _setup = job::setup();
+        command::run_command_for_dir(cmd, &args[0], &args[1..])?
+    };
+    // Only reached on Windows, see `proxy_mode::run`
+    std::process::exit(code.0)
 }
 
-fn which(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let binary = m.value_of("command").expect("");
+/// Toolchain of projects depending on Mathlib, which must match Mathlib's
+const MATHLIB_TOOLCHAIN: &str = "leanprover-community/mathlib4:lean-toolchain";
+
+fn init_project(
+    cfg: &Cfg,
+    template: &str,
+    dir: Option<&Path>,
+    toolchain: Option<&str>,
+) -> Result<()> {
+    if cfg.read_only {
+        return Err(elan::Error::from(elan::ErrorKind::ReadOnly).into());
+    }
+    let dir = match dir {
+        Some(dir) => utils::to_absolute(dir)?,
+        None => utils::current_dir()?,
+    };
+    let name = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("cannot name a project after '{}'", dir.display()))?;
+    let toolchain = toolchain.unwrap_or(if template.starts_with("math") {
+        MATHLIB_TOOLCHAIN
+    } else {
+        "stable"
+    });
+    let desc = lookup_toolchain_desc(cfg, toolchain)?;
+
+    // `lake new` creates the directory, `lake init` fills an existing one
+    let mut cmd = cfg.create_command_for_toolchain(&desc, true, "lake")?;
+    let subcommand = if dir.exists() {
+        cmd.current_dir(&dir);
+        "init"
+    } else {
+        let parent = dir.parent().expect("");
+        utils::ensure_dir_exists("project", parent, &|n| (cfg.notify_handler)(n.into()))?;
+        cmd.current_dir(parent);
+        "new"
+    };
+    cmd.args([subcommand, name, template]);
+    let status = cmd
+        .status()
+        .chain_err(|| ErrorKind::ProjectSetupFailed(format!("lake {}", subcommand)))?;
+    if !status.success() {
+        return Err(ErrorKind::ProjectSetupFailed(format!(
+            "lake {} {} {}",
+            subcommand, name, template
+        ))
+        .into());
+    }
+
+    // Lake writes the toolchain it runs with, which need not be fixed yet
+    utils::write_file(
+        "toolchain",
+        &dir.join("lean-toolchain"),
+        &format!("{}\n", desc),
+    )?;
+    info!("created project '{}' using '{}'", dir.display(), desc);
+    Ok(())
+}
 
+fn which(cfg: &Cfg, binary: &str) -> Result<()> {
     let binary_path = cfg
         .which_binary(&utils::current_dir()?, binary)?
         .expect("binary not found");
@@ -319,32 +983,216 @@ pub fn mk_toolchain_label(
     }
 }
 
-pub fn list_toolchains(cfg: &Cfg) -> Result<()> {
+/// Whether to print toolchains as a table rather than a plain list, which
+/// scripts expect
+fn use_table(quiet: bool) -> bool {
+    !quiet && tty::stdout_isatty()
+}
+
+fn format_size(bytes: u64) -> String {
+    const KIB: f64 = 1024.0;
+    const MIB: f64 = KIB * KIB;
+    const GIB: f64 = MIB * KIB;
+    let bytes = bytes as f64;
+    if bytes >= GIB {
+        format!("{:.1} GiB", bytes / GIB)
+    } else if bytes >= MIB {
+        format!("{:.0} MiB", bytes / MIB)
+    } else {
+        format!("{:.0} KiB", bytes / KIB)
+    }
+}
+
+/// Prints the table described in `TOOLCHAIN_LIST_HELP`. Sizes not recorded
+/// at installation are only measured with `measure`, as that walks the whole
+/// toolchain directory.
+fn print_toolchain_table(
+    cfg: &Cfg,
+    toolchains: &[ToolchainDesc],
+    default_tc: &Option<String>,
+    resolved_default_tc: &Option<ToolchainDesc>,
+    measure: bool,
+) {
+    let rows: Vec<Vec<String>> = toolchains
+        .iter()
+        .map(|desc| {
+            let toolchain = Toolchain::from(cfg, desc);
+            let custom = toolchain.is_custom();
+            let (origin, release) = match desc {
+                ToolchainDesc::Local { name } => ("-".to_owned(), name.clone()),
+                ToolchainDesc::Remote {
+                    origin, release, ..
+                } => (origin.clone(), release.clone()),
+            };
+            let channel = match desc {
+                ToolchainDesc::Remote { release, .. } if !custom => {
+                    elan::release_channel(release).unwrap_or("-")
+                }
+                _ => "-",
+            };
+            let mut release = release;
+            if resolved_default_tc.as_ref() == Some(desc) {
+                match default_tc {
+                    Some(d) if d != &desc.to_string() => release += &format!(" (default: {})", d),
+                    _ => release += " (default)",
+                }
+            }
+            if toolchain.is_frozen() {
+                release += " (frozen)";
+            }
+            if toolchain.problem().is_some() {
+                release += " (broken)";
+            }
+            // Linked toolchains may be arbitrarily large directories
+            // Linked toolchains may be arbitrarily large directories
+            let size = if custom {
+                "-".to_owned()
+            } else {
+                toolchain
+                    .installed_size()
+                    .or_else(|| measure.then(|| utils::dir_size(toolchain.path()).ok())?)
+                    .map(format_size)
+                    .unwrap_or_else(|| "?".to_owned())
+            };
+            let last_used = toolchain
+                .last_used()
+                .map(|t| OffsetDateTime::from(t).date().to_string())
+                .unwrap_or_else(|| "-".to_owned());
+            vec![
+                origin,
+                release,
+                channel.to_owned(),
+                if custom { "linked" } else { "remote" }.to_owned(),
+                size,
+                last_used,
+            ]
+        })
+        .collect();
+    term2::stdout().table(
+        &["ORIGIN", "RELEASE", "CHANNEL", "TYPE", "SIZE", "LAST USED"],
+        &rows,
+    );
+}
+
+/// Warns about toolchains that cannot be run, which would otherwise only
+/// surface as errors when running one of their tools
+fn warn_broken_toolchains(cfg: &Cfg, toolchains: &[ToolchainDesc]) {
+    for desc in toolchains {
+        let toolchain = Toolchain::from(cfg, desc);
+        if let Some(problem) = toolchain.problem() {
+            warn!(
+                "toolchain '{}' is broken: {}; {}",
+                desc,
+                problem,
+                toolchain.repair_hint()
+            );
+        }
+    }
+}
+
+pub fn list_toolchains(cfg: &Cfg, quiet: bool, verbose: bool) -> Result<()> {
     let toolchains = cfg.list_toolchains()?;
 
     if toolchains.is_empty() {
         println!("no installed toolchains");
+    } else if use_table(quiet) {
+        let default_tc = cfg.get_default()?;
+        // Resolution errors must not prevent listing
+        let resolved_default_tc = cfg.resolve_default().ok().flatten();
+        print_toolchain_table(cfg, &toolchains, &default_tc, &resolved_default_tc, verbose);
     } else {
-        for tc in toolchains {
+        for tc in &toolchains {
             println!("{}", tc);
         }
     }
+    warn_broken_toolchains(cfg, &toolchains);
+    Ok(())
+}
+
+/// Whether the directory of the proxies is in `PATH`
+fn proxies_in_path() -> bool {
+    let bin = match utils::elan_system_home().map_or_else(utils::elan_home, Ok) {
+        Ok(home) => home.join("bin"),
+        Err(_) => return false,
+    };
+    env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path)
+            .any(|dir| dir == bin || same_file::is_same_file(&dir, &bin).unwrap_or(false))
+    })
+}
+
+/// Prints a short overview and the commands most likely to be of use next,
+/// for `elan` without a subcommand
+fn status(cfg: &Cfg) -> Result<()> {
+    let cwd = utils::current_dir()?;
+    let default = cfg.get_default()?;
+    // Not resolved, which may need the network
+    let active = cfg.find_override(&cwd)?;
+
+    println!("elan {}", common::version());
+    println!(
+        "default toolchain: {}",
+        default.as_deref().unwrap_or("none")
+    );
+    match (&active, &default) {
+        (Some((desc, reason)), _) => println!("active toolchain:  {} ({})", desc.0, reason),
+        (None, Some(default)) => println!("active toolchain:  {} (default)", default),
+        (None, None) => println!("active toolchain:  none"),
+    }
+    if proxies_in_path() {
+        println!("PATH:              set up");
+    } else {
+        println!("PATH:              `lean` and `lake` are not in PATH; restart your shell or see `elan help`");
+    }
+
+    let in_project = matches!(active, Some((_, OverrideReason::ToolchainFile(_))));
+    let hints: [(&str, &str); 3] = if in_project {
+        [
+            ("lake build", "build the project in this directory"),
+            ("elan show", "show the installed toolchains"),
+            ("elan help", "list all commands"),
+        ]
+    } else if default.is_none() {
+        [
+            (
+                "elan default stable",
+                "use the latest stable Lean release by default",
+            ),
+            ("elan init-project std <dir>", "create a Lean project"),
+            ("elan help", "list all commands"),
+        ]
+    } else {
+        [
+            ("elan init-project std <dir>", "create a Lean project"),
+            ("elan show", "show the installed toolchains"),
+            ("elan help", "list all commands"),
+        ]
+    };
+    println!();
+    for (command, description) in hints {
+        println!("    {:<29} {}", command, description);
+    }
     Ok(())
 }
 
-fn show(cfg: &Cfg) -> Result<()> {
+fn show(cfg: &Cfg, quiet: bool, verbose: bool) -> Result<()> {
     let cwd = &(utils::current_dir()?);
     let installed_toolchains = cfg.list_toolchains()?;
     let active_toolchain = cfg.find_override_toolchain_or_default(cwd);
 
     let show_installed_toolchains = installed_toolchains.len() > 1;
     let show_active_toolchain = true;
+    let show_precedence = verbose;
 
     // Only need to display headers if we have multiple sections
-    let show_headers = [show_installed_toolchains, show_active_toolchain]
-        .iter()
-        .filter(|x| **x)
-        .count()
+    let show_headers = [
+        show_installed_toolchains,
+        show_active_toolchain,
+        show_precedence,
+    ]
+    .iter()
+    .filter(|x| **x)
+    .count()
         > 1;
 
     let default_tc = cfg.get_default()?;
@@ -356,34 +1204,52 @@ fn show(cfg: &Cfg) -> Result<()> {
         if show_headers {
             print_header("installed toolchains")
         }
-        for t in installed_toolchains {
-            println!(
-                "{}",
-                mk_toolchain_label(&t, &default_tc, &resolved_default_tc)
+        if use_table(quiet) {
+            print_toolchain_table(
+                cfg,
+                &installed_toolchains,
+                &default_tc,
+                &resolved_default_tc,
+                verbose,
             );
+        } else {
+            for t in &installed_toolchains {
+                let mut label = mk_toolchain_label(t, &default_tc, &resolved_default_tc);
+                if Toolchain::from(cfg, t).problem().is_some() {
+                    label += " (broken)";
+                }
+                println!("{}", label);
+            }
         }
+        warn_broken_toolchains(cfg, &installed_toolchains);
         if show_headers {
             println!()
         };
     }
 
+    let active_source = match active_toolchain {
+        Ok(Some((_, Some(ref reason)))) => Some(reason.source()),
+        Ok(Some((_, None))) => Some(OverrideSource::Default),
+        _ => None,
+    };
+
     if show_active_toolchain {
         if show_headers {
             print_header("active toolchain")
         }
 
-        match active_toolchain {
+        match &active_toolchain {
             Ok(atc) => match atc {
                 Some((ref toolchain, Some(ref reason))) => {
                     println!("{} ({})", toolchain.name(), reason);
-                    println!("{}", common::lean_version(toolchain));
+                    print_active_toolchain_status(toolchain);
                 }
                 Some((ref toolchain, None)) => {
                     println!(
                         "{}",
                         mk_toolchain_label(&toolchain.desc, &default_tc, &resolved_default_tc)
                     );
-                    println!("{}", common::lean_version(toolchain));
+                    print_active_toolchain_status(toolchain);
                 }
                 None => {
                     println!("no active toolchain");
@@ -397,7 +1263,39 @@ fn show(cfg: &Cfg) -> Result<()> {
                 }
             }
         }
+        // Otherwise already reported with the installed toolchains
+        if let (Ok(Some((toolchain, _))), false) = (&active_toolchain, show_installed_toolchains) {
+            warn_broken_toolchains(cfg, std::slice::from_ref(&toolchain.desc));
+        }
+
+        if show_headers {
+            println!()
+        };
+    }
 
+    if show_precedence {
+        if show_headers {
+            print_header("override precedence")
+        }
+        let order = cfg.override_precedence.effective_order();
+        for (i, source) in order.iter().enumerate() {
+            let mut line = format!("{}. {:<16}{}", i + 1, source.name(), source.description());
+            if *source == OverrideSource::Environment && cfg.env_override.is_none() {
+                line.push_str(" (not set)");
+            }
+            if Some(*source) == active_source {
+                line.push_str(" (active)");
+            }
+            println!("{}", line);
+        }
+        let disabled: Vec<_> = OverrideSource::ALL
+            .iter()
+            .filter(|s| !order.contains(s))
+            .map(|s| s.name())
+            .collect();
+        if !disabled.is_empty() {
+            println!("disabled: {}", disabled.join(", "));
+        }
         if show_headers {
             println!()
         };
@@ -412,11 +1310,17 @@ fn show(cfg: &Cfg) -> Result<()> {
         let _ = t.reset();
     }
 
+    fn print_active_toolchain_status(toolchain: &Toolchain<'_>) {
+        match toolchain.problem() {
+            Some(problem) => println!("(broken: {})", problem),
+            None => println!("{}", common::lean_version(toolchain)),
+        }
+    }
+
     Ok(())
 }
 
-fn explicit_or_dir_toolchain<'a>(cfg: &'a Cfg, m: &ArgMatches<'_>) -> Result<Toolchain<'a>> {
-    let toolchain = m.value_of("toolchain");
+fn explicit_or_dir_toolchain<'a>(cfg: &'a Cfg, toolchain: Option<&str>) -> Result<Toolchain<'a>> {
     if let Some(toolchain) = toolchain {
         let desc = lookup_toolchain_desc(cfg, toolchain)?;
         let toolchain = cfg.get_toolchain(&desc, false)?;
@@ -429,26 +1333,231 @@ fn explicit_or_dir_toolchain<'a>(cfg: &'a Cfg, m: &ArgMatches<'_>) -> Result<Too
     Ok(toolchain)
 }
 
-fn toolchain_link(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let toolchain = &m.value_of("toolchain").expect("");
-    let path = &m.value_of("path").expect("");
+fn component_list(cfg: &Cfg, toolchain: Option<&str>) -> Result<()> {
+    let toolchain = explicit_or_dir_toolchain(cfg, toolchain)?;
+    let components = toolchain.list_components()?;
+    if components.is_empty() {
+        println!("no components available for '{}'", toolchain.name());
+    }
+    for c in components {
+        if c.installed {
+            println!("{} (installed)", c.component.name());
+        } else {
+            println!("{}", c.component.name());
+        }
+    }
+    Ok(())
+}
+
+fn component_add(cfg: &Cfg, args: &ComponentArgs) -> Result<()> {
+    let toolchain = explicit_or_dir_toolchain(cfg, args.toolchain.as_deref())?;
+    for component in &args.component {
+        toolchain.add_component(Component {
+            pkg: component.to_owned(),
+        })?;
+    }
+    Ok(())
+}
+
+fn component_remove(cfg: &Cfg, args: &ComponentArgs) -> Result<()> {
+    let toolchain = explicit_or_dir_toolchain(cfg, args.toolchain.as_deref())?;
+    for component in &args.component {
+        toolchain.remove_component(Component {
+            pkg: component.to_owned(),
+        })?;
+    }
+    Ok(())
+}
+
+fn toolchain_link(cfg: &Cfg, toolchain: &str, path: &Path) -> Result<()> {
     let desc = ToolchainDesc::Local {
         name: toolchain.to_string(),
     };
     let toolchain = cfg.get_toolchain(&desc, true)?;
 
-    Ok(toolchain.install_from_dir(Path::new(path), true)?)
+    Ok(toolchain.install_from_dir(path, true)?)
+}
+
+fn toolchain_rename(cfg: &Cfg, name: &str, new: &str) -> Result<()> {
+    let old = lookup_unresolved_toolchain_desc(cfg, name)?.0;
+    if let ToolchainDesc::Remote { .. } = old {
+        if !cfg.get_toolchain(&old, false)?.exists() {
+            return Err(format!("no linked toolchain named '{}'", name).into());
+        }
+    }
+    let new = ToolchainDesc::Local {
+        name: new.to_owned(),
+    };
+    for key in cfg.rename_linked_toolchain(&old, &new)? {
+        info!("updated override for '{}'", key);
+    }
+    info!("renamed toolchain '{}' to '{}'", old, new);
+
+    // Project files are not ours to rewrite
+    for root in gc::get_roots(cfg)? {
+        let toolchain_file = Path::new(&root).join("lean-toolchain");
+        // The old name no longer resolves to a linked toolchain, so compare
+        // the file's contents instead
+        if let Ok(contents) = utils::read_file("toolchain file", &toolchain_file) {
+            if contents.trim() == old.to_string() {
+                warn!("'{}' still refers to '{}'", toolchain_file.display(), old);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn toolchain_freeze(cfg: &Cfg, toolchains: &[String], frozen: bool) -> Result<()> {
+    for toolchain in toolchains {
+        let desc = lookup_toolchain_desc(cfg, toolchain)?;
+        let toolchain = cfg.get_toolchain(&desc, false)?;
+        toolchain.set_frozen(frozen)?;
+        if frozen {
+            info!("froze toolchain '{}'", desc);
+        } else {
+            info!("unfroze toolchain '{}'", desc);
+        }
+    }
+    Ok(())
+}
+
+fn toolchain_strip(
+    cfg: &Cfg,
+    toolchains: &[String],
+    patterns: &[String],
+    dry_run: bool,
+) -> Result<()> {
+    let patterns = if patterns.is_empty() {
+        cfg.strip_patterns()?
+    } else {
+        patterns.to_vec()
+    };
+    for toolchain in toolchains {
+        let desc = lookup_toolchain_desc(cfg, toolchain)?;
+        let toolchain = cfg.get_toolchain(&desc, false)?;
+        if !toolchain.exists() {
+            return Err(format!("toolchain '{}' is not installed", desc).into());
+        }
+        if dry_run {
+            println!("toolchain: {}", desc);
+            for (path, size) in toolchain.strippable_paths(&patterns)? {
+                println!("  {} ({})", path, format_size(size));
+            }
+            continue;
+        }
+        let freed = toolchain.strip(&patterns)?;
+        info!("stripped '{}', freeing {}", desc, format_size(freed));
+    }
+    Ok(())
 }
 
-fn toolchain_remove(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    for toolchain in m.values_of("toolchain").expect("") {
+fn toolchain_remove(cfg: &Cfg, args: &UninstallArgs) -> Result<()> {
+    if args.all {
+        return toolchain_remove_all(cfg, args);
+    }
+    let mut toolchains = vec![];
+    // Only uninstalling toolchains that are protected otherwise needs
+    // confirmation
+    let mut forced = vec![];
+    for toolchain in &args.toolchain {
         let desc = lookup_toolchain_desc(cfg, toolchain)?;
         let toolchain = cfg.get_toolchain(&desc, false)?;
+        if !args.force {
+            toolchain.check_removable()?;
+        } else if toolchain.exists() {
+            let reason = if toolchain.is_frozen() {
+                Some("frozen".to_owned())
+            } else {
+                toolchain.in_use_reason()?
+            };
+            if let Some(reason) = reason {
+                forced.push(format!("'{}' ({})", toolchain.desc, reason));
+            }
+        }
+        toolchains.push(toolchain);
+    }
+    let question = match &forced[..] {
+        [] => None,
+        [forced] => Some(format!("Uninstall toolchain {} anyway?", forced)),
+        forced => Some(format!(
+            "The following toolchains will be uninstalled anyway:\n- {}\nContinue?",
+            forced.join("\n- ")
+        )),
+    };
+    if let Some(question) = question {
+        if !common::confirm_destructive(&args.yes, &question)? {
+            return Ok(());
+        }
+    }
+    for toolchain in toolchains {
         toolchain.remove()?;
     }
     Ok(())
 }
 
+fn toolchain_remove_all(cfg: &Cfg, args: &UninstallArgs) -> Result<()> {
+    let except = args
+        .except
+        .iter()
+        .map(|name| lookup_toolchain_desc(cfg, name))
+        .collect::<elan::Result<Vec<_>>>()?;
+    let (frozen, toolchains): (Vec<_>, Vec<_>) = cfg
+        .list_toolchains()?
+        .into_iter()
+        .filter(|t| !except.contains(t))
+        .partition(|t| !args.force && Toolchain::from(cfg, t).is_frozen());
+    if !frozen.is_empty() {
+        println!("The following frozen toolchains will be kept; pass `--force` to uninstall them as well:");
+        for t in &frozen {
+            println!("- {}", t);
+        }
+    }
+    if toolchains.is_empty() {
+        println!("No toolchains to uninstall");
+        return Ok(());
+    }
+
+    let (_, used_toolchains) = gc::analyze_toolchains(cfg)?;
+    let mut any_used = false;
+    println!("The following toolchains will be uninstalled:");
+    for t in &toolchains {
+        let users = used_toolchains
+            .iter()
+            .filter(|(_, tc)| tc == t)
+            .map(|(user, _)| user.as_str())
+            .collect::<Vec<_>>();
+        if users.is_empty() {
+            println!("- {}", t);
+        } else {
+            any_used = true;
+            println!("- {} (used by {})", t, users.join(", "));
+        }
+    }
+    if any_used {
+        warn!("toolchains still in use will be reinstalled on their next use");
+    }
+
+    if !args.force {
+        // Check all of them before removing any
+        for t in &toolchains {
+            if let Some(process) = Toolchain::from(cfg, t).running_process() {
+                return Err(elan::Error::from(elan::ErrorKind::ToolchainInUse(
+                    t.clone(),
+                    format!("in use by {}", process),
+                ))
+                .into());
+            }
+        }
+    }
+    if !common::confirm_destructive(&args.yes, "\nContinue?")? {
+        return Ok(());
+    }
+    for t in toolchains {
+        cfg.get_toolchain(&t, false)?.remove()?;
+    }
+    Ok(())
+}
+
 #[derive(Serialize)]
 struct UsedToolchain {
     // project root or "default toolchain"
@@ -459,28 +1568,166 @@ struct UsedToolchain {
 #[derive(Serialize)]
 struct GCResult {
     unused_toolchains: Vec<String>,
+    /// Unused toolchains kept because they are frozen
+    frozen_toolchains: Vec<String>,
     used_toolchains: Vec<UsedToolchain>,
+    /// Files and directories of the unused toolchains that are or would be deleted
+    removed_paths: Vec<PathBuf>,
+    deleted: bool,
 }
 
-fn toolchain_gc(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let (unused_toolchains, used_toolchains) = gc::analyze_toolchains(cfg)?;
-    let delete = m.is_present("delete");
-    let json = m.is_present("json");
-    if json {
-        let result = GCResult {
-            unused_toolchains: unused_toolchains
-                .iter()
-                .map(|t| t.desc.to_string())
-                .collect(),
-            used_toolchains: used_toolchains
-                .iter()
-                .map(|(root, tc)| UsedToolchain {
-                    user: root.clone(),
-                    toolchain: tc.to_string(),
-                })
-                .collect(),
+#[derive(Serialize)]
+struct DirToolchain {
+    /// Directory as given on the command line
+    dir: String,
+    /// Not necessarily resolved name, e.g. `stable`; `None` if neither an override nor a
+    /// default toolchain applies
+    unresolved: Option<UnresolvedToolchainDesc>,
+    /// Fully resolved name, or `Err` if resolution failed
+    resolved: Option<std::result::Result<String, String>>,
+    /// Whether the resolved toolchain is installed
+    installed: bool,
+    /// `None` if the default toolchain is used
+    reason: Option<OverrideReason>,
+}
+
+fn toolchain_default_for(cfg: &Cfg, dirs: &[String], json: bool, no_net: bool) -> Result<()> {
+    let mut results = vec![];
+    for dir in dirs {
+        let path = Path::new(dir);
+        utils::assert_is_directory(path)?;
+        let path = utils::to_absolute(path)?;
+        let (unresolved, reason) = match cfg.find_override(&path)? {
+            Some((unresolved, reason)) => (Some(unresolved), Some(reason)),
+            None => match cfg
+                .get_default()?
+                .filter(|_| cfg.override_precedence.contains(OverrideSource::Default))
+            {
+                Some(name) => (Some(lookup_unresolved_toolchain_desc(cfg, &name)?), None),
+                None => (None, None),
+            },
         };
-        println!(
+        let resolved = unresolved.as_ref().map(|unresolved| {
+            resolve_toolchain_desc_ext(cfg, unresolved, no_net, true).map_err(|e| e.to_string())
+        });
+        let installed = match resolved {
+            Some(Ok(ref desc)) => Toolchain::from(cfg, desc).exists(),
+            _ => false,
+        };
+        results.push(DirToolchain {
+            dir: dir.to_owned(),
+            unresolved,
+            resolved: resolved.map(|r| r.map(|desc| desc.to_string())),
+            installed,
+            reason,
+        });
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&results).chain_err(|| "failed to print JSON")?
+        );
+        return Ok(());
+    }
+    for r in results {
+        let toolchain = match r.resolved {
+            None => "no toolchain".to_owned(),
+            Some(Ok(name)) if r.installed => name,
+            Some(Ok(name)) => format!("{}, not installed", name),
+            Some(Err(e)) => format!("unresolved: {}", e),
+        };
+        let reason = match r.reason {
+            Some(reason) => reason.to_string(),
+            None if r.unresolved.is_some() => "default toolchain".to_owned(),
+            None => "no default toolchain configured".to_owned(),
+        };
+        println!("{}: {} ({})", r.dir, toolchain, reason);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct KnownProject {
+    path: String,
+    added: u64,
+    last_used: u64,
+    exists: bool,
+}
+
+fn toolchain_gc(cfg: &Cfg, args: &GcArgs) -> Result<()> {
+    let json = args.json;
+    if args.list_roots {
+        return list_roots(cfg, json);
+    }
+    if !args.forget_root.is_empty() {
+        for path in &args.forget_root {
+            let path = utils::canonicalize_path(path, &|_| {});
+            if gc::forget_root(cfg, &path)? {
+                info!("forgot known project '{}'", path.display());
+            } else {
+                warn!("'{}' is not a known project", path.display());
+            }
+        }
+        return Ok(());
+    }
+    if args.prune_roots {
+        for root in gc::prune_roots(cfg)? {
+            info!("forgot missing project '{}'", root.path);
+        }
+    }
+
+    let (unused_toolchains, used_toolchains) = gc::analyze_toolchains(cfg)?;
+    let mut delete = args.delete && !args.dry_run;
+    let (frozen_toolchains, unused_toolchains): (Vec<_>, Vec<_>) = unused_toolchains
+        .into_iter()
+        .partition(|t| !args.force && t.is_frozen());
+    if delete && !unused_toolchains.is_empty() {
+        let question = format!(
+            "The following toolchains are not used by any known project and will be deleted:\n- {}\nContinue?",
+            unused_toolchains
+                .iter()
+                .map(|t| t.desc.to_string())
+                .collect::<Vec<_>>()
+                .join("\n- ")
+        );
+        if !common::confirm_destructive(&args.yes, &question)? {
+            delete = false;
+            if !json {
+                return Ok(());
+            }
+        }
+    }
+    if json {
+        let removed_paths = unused_toolchains
+            .iter()
+            .flat_map(|t| t.removed_paths())
+            .collect();
+        if delete {
+            for t in &unused_toolchains {
+                t.remove_unused()?;
+            }
+        }
+        let result = GCResult {
+            unused_toolchains: unused_toolchains
+                .iter()
+                .map(|t| t.desc.to_string())
+                .collect(),
+            frozen_toolchains: frozen_toolchains
+                .iter()
+                .map(|t| t.desc.to_string())
+                .collect(),
+            used_toolchains: used_toolchains
+                .iter()
+                .map(|(root, tc)| UsedToolchain {
+                    user: root.clone(),
+                    toolchain: tc.to_string(),
+                })
+                .collect(),
+            removed_paths,
+            deleted: delete,
+        };
+        println!(
             "{}",
             serde_json::to_string_pretty(&result).chain_err(|| "failed to print JSON")?
         );
@@ -490,17 +1737,32 @@ fn toolchain_gc(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
     if unused_toolchains.is_empty() {
         println!("No unused toolchains found");
     } else {
-        if !delete {
+        if args.delete && !delete {
+            println!(
+                "The following toolchains are not used by any known project and would be deleted:"
+            );
+        } else if !delete {
             println!("The following toolchains are not used by any known project; rerun with `--delete` to delete them:");
         }
         for t in unused_toolchains.into_iter() {
             if delete {
-                t.remove()?;
+                t.remove_unused()?;
+            } else if args.delete {
+                println!("- {}", t.desc);
+                for path in t.removed_paths() {
+                    println!("  - {}", path.display());
+                }
             } else {
                 println!("- {}", t.desc);
             }
         }
     }
+    if !frozen_toolchains.is_empty() {
+        println!("The following unused toolchains are frozen and will be kept; pass `--force` along with `--delete` to delete them as well:");
+        for t in &frozen_toolchains {
+            println!("- {}", t.desc);
+        }
+    }
     if !delete {
         println!("Known projects:");
         for (root, tc) in used_toolchains.into_iter() {
@@ -510,16 +1772,155 @@ fn toolchain_gc(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
     Ok(())
 }
 
-fn override_add(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let toolchain = m.value_of("toolchain").expect("");
+fn list_roots(cfg: &Cfg, json: bool) -> Result<()> {
+    let roots = gc::read_roots(cfg)?;
+    if json {
+        let roots = roots
+            .into_iter()
+            .map(|r| KnownProject {
+                exists: r.exists(),
+                path: r.path,
+                added: r.added,
+                last_used: r.last_used,
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&roots).chain_err(|| "failed to print JSON")?
+        );
+        return Ok(());
+    }
+
+    if roots.is_empty() {
+        println!("No known projects");
+        return Ok(());
+    }
+    let date = |secs: u64| {
+        OffsetDateTime::from_unix_timestamp(secs as i64)
+            .map(|t| t.date().to_string())
+            .unwrap_or_default()
+    };
+    for root in roots {
+        let missing = if root.exists() { "" } else { " (missing)" };
+        println!(
+            "{}{} (added {}, last used {})",
+            root.path,
+            missing,
+            date(root.added),
+            date(root.last_used)
+        );
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct RunAllResult {
+    toolchain: String,
+    /// `None` if the command could not be started or was terminated by a signal
+    exit_code: Option<i32>,
+    success: bool,
+    /// Error starting the command, if any
+    error: Option<String>,
+}
+
+fn toolchain_run_all(cfg: &Cfg, run_all: &RunAllArgs) -> Result<()> {
+    let args = &run_all.command;
+    let jobs = run_all.jobs.get();
+    let install = run_all.install;
+    let toolchains = if run_all.toolchain.is_empty() {
+        cfg.list_toolchains()?
+    } else {
+        run_all
+            .toolchain
+            .iter()
+            .map(|name| lookup_toolchain_desc(cfg, name))
+            .collect::<elan::Result<Vec<_>>>()?
+    };
+    if toolchains.is_empty() {
+        return Err("no toolchains to run the command under".into());
+    }
+
+    let mut results = Vec::new();
+    for chunk in toolchains.chunks(jobs) {
+        let mut children = Vec::new();
+        for desc in chunk {
+            info!(
+                "running `{}` under '{}'",
+                args.iter()
+                    .map(|a| a.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                desc
+            );
+            let child = cfg
+                .create_command_for_toolchain(desc, install, &args[0])
+                .map_err(crate::errors::Error::from)
+                .and_then(|mut cmd| Ok(cmd.args(&args[1..]).spawn()?));
+            children.push((desc, child));
+        }
+        for (desc, child) in children {
+            let status = child.and_then(|mut child| Ok(child.wait()?));
+            results.push(match status {
+                Ok(status) => RunAllResult {
+                    toolchain: desc.to_string(),
+                    exit_code: status.code(),
+                    success: status.success(),
+                    error: None,
+                },
+                Err(e) => RunAllResult {
+                    toolchain: desc.to_string(),
+                    exit_code: None,
+                    success: false,
+                    error: Some(e.to_string()),
+                },
+            });
+        }
+    }
+
+    println!();
+    let width = results.iter().map(|r| r.toolchain.len()).max().unwrap_or(0);
+    for r in &results {
+        let outcome = match (&r.error, r.exit_code) {
+            (Some(e), _) => format!("error: {}", e),
+            (None, Some(code)) => format!("exit code {}", code),
+            (None, None) => "terminated by signal".to_string(),
+        };
+        let mark = if r.success { "ok" } else { "FAILED" };
+        println!(
+            "{:<width$}  {:<6}  {}",
+            r.toolchain,
+            mark,
+            outcome,
+            width = width
+        );
+    }
+
+    if let Some(report) = &run_all.json {
+        let json = serde_json::to_string_pretty(&results).chain_err(|| "failed to print JSON")?;
+        utils::write_file("report", report, &json)?;
+    }
+
+    let failed = results.iter().filter(|r| !r.success).count();
+    if failed > 0 {
+        return Err(format!(
+            "command failed under {} of {} toolchains",
+            failed,
+            results.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn override_add(cfg: &Cfg, toolchain: &str) -> Result<()> {
     let desc = lookup_toolchain_desc(cfg, toolchain)?;
     let toolchain = cfg.get_toolchain(&desc, false)?;
     toolchain.make_override(&utils::current_dir()?)?;
     Ok(())
 }
 
-fn override_remove(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let paths = if m.is_present("nonexistent") {
+fn override_remove(cfg: &Cfg, path: Option<&str>, nonexistent: bool, yes: &YesArg) -> Result<()> {
+    let paths = if nonexistent {
         let list: Vec<_> = cfg.settings_file.with(|s| {
             Ok(s.overrides
                 .iter()
@@ -536,21 +1937,46 @@ fn override_remove(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
             info!("no nonexistent paths detected");
         }
         list
-    } else if m.is_present("path") {
-        vec![m.value_of("path").unwrap().to_string()]
+    } else if let Some(path) = path {
+        vec![path.to_owned()]
     } else {
         vec![utils::current_dir()?.to_str().unwrap().to_string()]
     };
 
-    for path in paths {
-        if cfg
-            .settings_file
-            .with_mut(|s| Ok(s.remove_override(Path::new(&path), cfg.notify_handler.as_ref())))?
-        {
+    let overrides = cfg.settings_file.with(|s| {
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let toolchain = s.dir_override(Path::new(&path), cfg.notify_handler.as_ref());
+                (path, toolchain)
+            })
+            .collect::<Vec<_>>())
+    })?;
+    let removed = overrides
+        .iter()
+        .filter_map(|(path, toolchain)| toolchain.as_ref().map(|t| format!("'{}' ({})", path, t)))
+        .collect::<Vec<_>>();
+    let question = match &removed[..] {
+        [] => None,
+        [removed] => Some(format!("Remove the override toolchain for {}?", removed)),
+        removed => Some(format!(
+            "The override toolchains for the following directories will be removed:\n- {}\nContinue?",
+            removed.join("\n- ")
+        )),
+    };
+    if let Some(question) = question {
+        if !common::confirm_destructive(yes, &question)? {
+            return Ok(());
+        }
+    }
+
+    let explicit = path.is_some() || nonexistent;
+    for (path, _) in overrides {
+        if cfg.remove_override(Path::new(&path))? {
             info!("override toolchain for '{}' removed", path);
         } else {
             info!("no override toolchain for '{}'", path);
-            if !m.is_present("path") && !m.is_present("nonexistent") {
+            if !explicit {
                 info!(
                     "you may use `--path <path>` option to remove override toolchain \
                        for a specific path"
@@ -561,42 +1987,742 @@ fn override_remove(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
     Ok(())
 }
 
-fn doc(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let doc_url = if m.is_present("book") {
-        "book/index.html"
-    } else if m.is_present("std") {
-        "std/index.html"
-    } else {
-        "index.html"
+fn override_clean(cfg: &Cfg, reassign: Option<&str>, yes: &YesArg) -> Result<()> {
+    let dangling = cfg.dangling_overrides()?;
+    if dangling.is_empty() {
+        info!("no overrides refer to missing linked toolchains");
+        return Ok(());
+    }
+    let reassign = match reassign {
+        Some(toolchain) => Some(lookup_toolchain_desc(cfg, toolchain)?),
+        None => None,
     };
+    let interactive = reassign.is_none() && !yes.yes && tty::stdin_isatty();
+    if interactive {
+        let linked = cfg
+            .list_toolchains()?
+            .into_iter()
+            .filter(|t| matches!(t, ToolchainDesc::Local { .. }))
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+        if !linked.is_empty() {
+            info!("linked toolchains: {}", linked.join(", "));
+        }
+    }
 
-    Ok(cfg.open_docs_for_dir(&utils::current_dir()?, doc_url)?)
+    for (path, toolchain) in dangling {
+        let replacement = if let Some(desc) = &reassign {
+            Some(desc.clone())
+        } else if interactive {
+            loop {
+                eprint!(
+                    "override for '{}' refers to missing linked toolchain '{}'; \
+                     toolchain to use instead (empty to remove): ",
+                    path, toolchain
+                );
+                let _ = io::stderr().flush();
+                let input = common::read_line()?;
+                if input.trim().is_empty() {
+                    break None;
+                }
+                match lookup_toolchain_desc(cfg, input.trim()) {
+                    Ok(desc) => break Some(desc),
+                    Err(e) => err!("{}", e),
+                }
+            }
+        } else {
+            None
+        };
+        cfg.settings_file.with_mut(|s| {
+            match &replacement {
+                Some(desc) => {
+                    s.overrides.insert(path.clone(), desc.clone());
+                }
+                None => {
+                    s.overrides.remove(&path);
+                }
+            }
+            Ok(())
+        })?;
+        let operation = match replacement {
+            Some(_) => Operation::OverrideSet,
+            None => Operation::OverrideUnset,
+        };
+        history::record(
+            cfg,
+            Entry {
+                toolchain: replacement.as_ref().map(|desc| desc.to_string()),
+                previous: Some(toolchain.to_string()),
+                path: Some(path.clone()),
+                ..Entry::new(operation)
+            },
+        );
+        match replacement {
+            Some(desc) => info!("override for '{}' reassigned to '{}'", path, desc),
+            None => info!("override toolchain for '{}' removed", path),
+        }
+    }
+    Ok(())
 }
 
-fn man(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let manpage = m.value_of("command").expect("");
-    let toolchain = explicit_or_dir_toolchain(cfg, m)?;
-    let mut man_path = toolchain.path().to_path_buf();
-    man_path.push("share");
-    man_path.push("man");
-    man_path.push("man1");
-    man_path.push(manpage.to_owned() + ".1");
-    utils::assert_is_file(&man_path)?;
+fn man(
+    cfg: &Cfg,
+    command: &[String],
+    toolchain: Option<&str>,
+    generate: Option<&Path>,
+) -> Result<()> {
+    if let Some(dir) = generate {
+        utils::ensure_dir_exists("man pages", dir, &|_| {})?;
+        let pages = man::pages();
+        for (name, page) in &pages {
+            utils::write_file("man page", &dir.join(format!("{}.1", name)), page)?;
+        }
+        info!("wrote {} man pages to '{}'", pages.len(), dir.display());
+        return Ok(());
+    }
+
+    let command = if command.is_empty() {
+        "elan".to_owned()
+    } else {
+        command.join("-")
+    };
+    let page = man::pages()
+        .into_iter()
+        .find(|(name, _)| *name == command || *name == format!("elan-{}", command));
+    let page_file = match page {
+        Some((name, page)) => {
+            let file = cfg
+                .temp_cfg
+                .new_file_with_ext(&format!("{}-", name), ".1")?;
+            utils::write_file("man page", &file, &page)?;
+            Some(file)
+        }
+        None => None,
+    };
+    let man_path = match &page_file {
+        Some(file) => file.to_path_buf(),
+        None => {
+            let toolchain = explicit_or_dir_toolchain(cfg, toolchain)?;
+            let mut man_path = toolchain.path().to_path_buf();
+            man_path.push("share");
+            man_path.push("man");
+            man_path.push("man1");
+            man_path.push(command + ".1");
+            utils::assert_is_file(&man_path)?;
+            man_path
+        }
+    };
     Command::new("man")
         .arg(man_path)
         .status()
-        .expect("failed to open man page");
+        .chain_err(|| "could not run `man`")?;
+    Ok(())
+}
+
+fn self_clean(cfg: &Cfg) -> Result<()> {
+    cfg.recover_interrupted_installs()?;
+    // Leave files of concurrently running elan processes alone
+    cfg.clean_temp_dir(Duration::from_secs(60 * 60))?;
+    cfg.clean_caches()?;
+    Ok(())
+}
+
+/// The `lean-toolchain` files of the given workspace directories
+fn workspace_toolchain_files(workspace: &WorkspaceArg) -> Result<Vec<PathBuf>> {
+    let dirs = if workspace.dir.is_empty() {
+        vec![utils::current_dir()?]
+    } else {
+        workspace.dir.clone()
+    };
+    Ok(elan::cache::toolchain_files(&dirs)?)
+}
+
+fn cache_key(cfg: &Cfg, workspace: &WorkspaceArg) -> Result<()> {
+    let files = workspace_toolchain_files(workspace)?;
+    println!("{}", elan::cache::cache_key(cfg, &files)?);
     Ok(())
 }
 
-fn self_uninstall(m: &ArgMatches<'_>) -> Result<()> {
-    let no_prompt = m.is_present("no-prompt");
+fn cache_export(cfg: &Cfg, archive: &Path, workspace: &WorkspaceArg) -> Result<()> {
+    let files = workspace_toolchain_files(workspace)?;
+    for toolchain in elan::cache::export(cfg, &files, archive)? {
+        info!("exported '{}'", toolchain);
+    }
+    println!("{}", elan::cache::cache_key(cfg, &files)?);
+    Ok(())
+}
 
-    self_update::uninstall(no_prompt)
+fn cache_import(cfg: &Cfg, archive: &Path) -> Result<()> {
+    if cfg.read_only {
+        return Err(elan::Error::from(elan::ErrorKind::ReadOnly).into());
+    }
+    for (toolchain, installed) in elan::cache::import(cfg, archive)? {
+        if installed {
+            info!("restored '{}'", toolchain);
+        } else {
+            info!("'{}' is already installed", toolchain);
+        }
+    }
+    Ok(())
 }
 
-fn dump_state(cfg: &Cfg, m: &ArgMatches<'_>) -> Result<()> {
-    let no_net = m.is_present("no-net");
+fn cache_serve(cfg: &Cfg, port: u16) -> Result<()> {
+    elan_dist::peer_cache::serve(&cfg.download_cache_dir(), port, &|n| {
+        (cfg.notify_handler)(n.into())
+    })?;
+    Ok(())
+}
 
+fn cache_fetch(cfg: &Cfg, workspace: &WorkspaceArg) -> Result<()> {
+    if cfg.read_only {
+        return Err(elan::Error::from(elan::ErrorKind::ReadOnly).into());
+    }
+    for file in workspace_toolchain_files(workspace)? {
+        let caches = elan::artifact_cache::declared_caches(&file)?;
+        if caches.is_empty() {
+            info!(
+                "'{}' declares no artifact caches",
+                file.parent().unwrap_or(&file).display()
+            );
+            continue;
+        }
+        let desc = elan::read_toolchain_desc_from_file(cfg, &file)?;
+        let toolchain = Toolchain::from(cfg, &desc);
+        for cache in caches.iter().filter(|cache| cache.applies_to(&desc)) {
+            let fetched = elan::artifact_cache::fetch(cfg, &toolchain, cache)?;
+            info!(
+                "fetched '{}': {} files, {} downloaded ({})",
+                cache.name,
+                fetched.files,
+                fetched.downloaded,
+                format_size(fetched.downloaded_bytes)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn self_repair_proxies() -> Result<()> {
+    let relinked = self_update::install_proxies()?;
+    if relinked.is_empty() {
+        info!("all tools are already linked to elan");
+    } else {
+        info!("relinked {}", relinked.join(", "));
+    }
+    Ok(())
+}
+
+fn self_normalize_settings(cfg: &Cfg) -> Result<()> {
+    let changes = cfg.normalize_settings()?;
+    if changes.is_empty() {
+        info!("settings are already normalized");
+    }
+    for change in changes {
+        info!("{}", change);
+    }
+    Ok(())
+}
+
+fn trust_root_add(cfg: &Cfg, url: &str, key: &str) -> Result<()> {
+    let url = url.trim_end_matches('/');
+    utils::parse_url(url)?;
+    utils::parse_public_key(key)?;
+    // Store PEM keys on a single line
+    let key: String = key
+        .lines()
+        .filter(|l| !l.starts_with("-----"))
+        .flat_map(|l| l.split_whitespace())
+        .collect();
+    cfg.settings_file.with_mut(|s| {
+        s.trust_roots.insert(url.to_owned(), key.to_owned());
+        Ok(())
+    })?;
+    info!(
+        "self-updates from '{}' must now be signed with the given key",
+        url
+    );
+    Ok(())
+}
+
+fn trust_root_remove(cfg: &Cfg, url: &str) -> Result<()> {
+    let url = url.trim_end_matches('/');
+    let removed = cfg
+        .settings_file
+        .with_mut(|s| Ok(s.trust_roots.remove(url).is_some()))?;
+    if !removed {
+        return Err(format!("no trust root configured for '{}'", url).into());
+    }
+    info!("removed trust root for '{}'", url);
+    Ok(())
+}
+
+fn trust_root_list(cfg: &Cfg) -> Result<()> {
+    let trust_roots = cfg.settings_file.with(|s| Ok(s.trust_roots.clone()))?;
+    if trust_roots.is_empty() {
+        println!("no trust roots");
+    }
+    for (url, key) in trust_roots {
+        println!("{} {}", url, key);
+    }
+    Ok(())
+}
+
+/// Greedily wraps `text` at `width` columns, indenting every line by `indent`
+fn wrap_text(text: &str, indent: usize, width: usize) -> String {
+    let mut result = String::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && indent + line.len() + 1 + word.len() > width {
+            result += &format!("{}{}\n", " ".repeat(indent), line);
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        result += &format!("{}{}\n", " ".repeat(indent), line);
+    }
+    result
+}
+
+fn describe_env_var(var: &env_var::EnvVar) -> String {
+    let when = if var.build_time {
+        ", at build time"
+    } else {
+        ""
+    };
+    format!(
+        "    {} ({}{})\n{}",
+        var.name,
+        var.ty.describe(),
+        when,
+        wrap_text(var.description, 8, 72)
+    )
+}
+
+/// The help of `elan env`, generated from `env_var::ENV_VARS`
+fn env_help() -> String {
+    let mut help = String::from(
+        "DISCUSSION:\n    Without arguments, prints the environment variables known to elan\n    \
+         that are currently set. elan knows the following variables:\n\n",
+    );
+    for var in ENV_VARS {
+        help += &describe_env_var(var);
+    }
+    help
+}
+
+fn env_vars(list: bool) {
+    if list {
+        for var in ENV_VARS {
+            print!("{}", describe_env_var(var));
+        }
+        return;
+    }
+    for var in ENV_VARS.iter().filter(|v| !v.build_time) {
+        if let Some(value) = env::var_os(var.name) {
+            if var.secret {
+                println!("{}=<hidden>", var.name);
+            } else {
+                println!("{}={}", var.name, value.to_string_lossy());
+            }
+        }
+    }
+}
+
+fn config_get(cfg: &Cfg, key: &str) -> Result<()> {
+    match cfg.settings_file.with(|s| s.get_key(key))? {
+        Some(value) => println!("{}", value),
+        None => return Err(format!("setting '{}' is not set", key).into()),
+    }
+    Ok(())
+}
+
+fn config_set(cfg: &Cfg, key: &str, value: &str) -> Result<()> {
+    let (setting, _) = elan::settings::lookup_setting_key(key)?;
+    if setting.ty == elan::settings::SettingType::Toolchain {
+        // sanity-check
+        let _ = lookup_unresolved_toolchain_desc(cfg, value)?;
+    }
+    let previous = cfg.settings_file.with_mut(|s| {
+        let previous = s.get_key(key)?;
+        s.set_key(key, Some(value))?;
+        Ok(previous)
+    })?;
+    record_default_change(cfg, setting.name, previous, Some(value));
+    Ok(())
+}
+
+fn config_unset(cfg: &Cfg, key: &str) -> Result<()> {
+    let previous = cfg.settings_file.with_mut(|s| {
+        let previous = s.get_key(key)?;
+        s.set_key(key, None)?;
+        Ok(previous)
+    })?;
+    let (setting, _) = elan::settings::lookup_setting_key(key)?;
+    record_default_change(cfg, setting.name, previous, None);
+    Ok(())
+}
+
+/// Records changes of the default toolchain made with `elan config` in the
+/// history, like `elan default` does
+fn record_default_change(
+    cfg: &Cfg,
+    setting: &str,
+    previous: Option<String>,
+    toolchain: Option<&str>,
+) {
+    if setting == "default_toolchain" && previous.as_deref() != toolchain {
+        history::record(
+            cfg,
+            Entry {
+                toolchain: toolchain.map(|t| t.to_owned()),
+                previous,
+                ..Entry::new(Operation::Default)
+            },
+        );
+    }
+}
+
+fn config_list(cfg: &Cfg) -> Result<()> {
+    for (key, value) in cfg.settings_file.with(|s| Ok(s.list_keys()))? {
+        println!("{} = {}", key, value);
+    }
+    Ok(())
+}
+
+fn resolve(cfg: &Cfg, toolchains: &[String], stdin: bool, no_net: bool) -> Result<()> {
+    let mut inputs = toolchains.to_vec();
+    if stdin {
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() {
+                inputs.push(line.to_owned());
+            }
+        }
+    }
+
+    // Channels are usually shared by many inputs, so only resolve them once
+    let mut cache: HashMap<String, std::result::Result<ToolchainDesc, String>> = HashMap::new();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for input in inputs {
+        let path = Path::new(&input);
+        let unresolved = if utils::is_file(path) {
+            read_unresolved_toolchain_desc_from_file(cfg, path)
+        } else {
+            lookup_unresolved_toolchain_desc(cfg, &input)
+        };
+        let resolved = unresolved
+            .map_err(|e| e.to_string())
+            .and_then(|unresolved| {
+                cache
+                    .entry(format!("{:?}", unresolved))
+                    .or_insert_with(|| {
+                        resolve_toolchain_desc_ext(cfg, &unresolved, no_net, true)
+                            .map_err(|e| e.to_string())
+                    })
+                    .clone()
+            });
+        let line = match resolved {
+            Ok(desc) => json!({
+                "input": input,
+                "resolved": desc.to_string(),
+                "installed": Toolchain::from(cfg, &desc).exists(),
+            }),
+            Err(e) => json!({ "input": input, "error": e }),
+        };
+        writeln!(stdout, "{}", line)?;
+        // Let consumers process results as they arrive
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct HistoryEntry<'a> {
+    #[serde(flatten)]
+    entry: &'a Entry,
+    /// The command reverting the operation, unless `elan undo` did already
+    undo: Option<String>,
+}
+
+fn show_history(cfg: &Cfg, limit: Option<usize>, json: bool) -> Result<()> {
+    let all_entries = history::read(cfg)?;
+    let entries = &all_entries[all_entries
+        .len()
+        .saturating_sub(limit.unwrap_or(usize::MAX))..];
+    let undone: HashSet<usize> = all_entries
+        .iter()
+        .flat_map(|e| e.undone.iter().copied())
+        .collect();
+    let undo_hint = |entry: &Entry| {
+        if undone.contains(&entry.line) {
+            None
+        } else {
+            entry.undo_hint()
+        }
+    };
+    if json {
+        let entries = entries
+            .iter()
+            .map(|entry| HistoryEntry {
+                undo: undo_hint(entry),
+                entry,
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&entries).chain_err(|| "failed to print JSON")?
+        );
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No operations recorded");
+        return Ok(());
+    }
+    let unknown = "(unknown)".to_owned();
+    for entry in entries {
+        let time = format_time(entry.time);
+        let toolchain = entry.toolchain.as_ref().unwrap_or(&unknown);
+        let path = entry.path.as_ref().unwrap_or(&unknown);
+        let mut description = match entry.operation {
+            Operation::Default => match &entry.toolchain {
+                Some(toolchain) => format!("set the default toolchain to '{}'", toolchain),
+                None => "unset the default toolchain".to_owned(),
+            },
+            Operation::OverrideSet => {
+                format!("set the override for '{}' to '{}'", path, toolchain)
+            }
+            Operation::OverrideUnset => format!("removed the override for '{}'", path),
+            Operation::Install => format!("installed '{}'", toolchain),
+            Operation::Uninstall => format!("uninstalled '{}'", toolchain),
+            Operation::Gc => format!("deleted unused '{}'", toolchain),
+            Operation::Replace => format!("replaced the linked '{}'", toolchain),
+            // The operations undone at once were caused by the same command
+            Operation::Undo => match all_entries.iter().find(|e| entry.undone.contains(&e.line)) {
+                Some(undone) => format!("undid `{}`", undone.command),
+                None => "undid earlier operations".to_owned(),
+            },
+        };
+        if let Some(previous) = &entry.previous {
+            description += &format!(" (was '{}')", previous);
+        }
+        println!("{}  {}", time, description);
+        if !entry.command.is_empty() {
+            println!("    by `{}`", entry.command);
+        }
+        if undone.contains(&entry.line) {
+            println!("    undone by `elan undo`");
+        } else if let Some(undo) = entry.undo_hint() {
+            println!("    undo: `{}`", undo);
+        }
+    }
+    Ok(())
+}
+
+fn format_time(secs: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(secs as i64)
+        .map(|t| format!("{} {:02}:{:02} UTC", t.date(), t.hour(), t.minute()))
+        .unwrap_or_default()
+}
+
+/// A step of `elan undo`
+enum Restore {
+    Default(Option<String>),
+    Override(String, Option<ToolchainDesc>),
+    Reinstall(ToolchainDesc),
+}
+
+fn undo(cfg: &Cfg, dry_run: bool) -> Result<()> {
+    if cfg.read_only && !dry_run {
+        return Err(elan::Error::from(elan::ErrorKind::ReadOnly).into());
+    }
+    let entries = history::read(cfg)?;
+    let group = history::last_undoable(&entries);
+    let last = match group.last() {
+        Some(last) => last,
+        None => {
+            info!("nothing to undo");
+            return Ok(());
+        }
+    };
+    println!(
+        "{} `{}` from {}:",
+        if dry_run {
+            "Undoing would revert"
+        } else {
+            "Reverting"
+        },
+        last.command,
+        format_time(last.time)
+    );
+
+    // Decide what to do first, so that nothing is changed if the history
+    // cannot be understood
+    let parse = |name: &str| -> Result<ToolchainDesc> {
+        ToolchainDesc::from_resolved_str(name)
+            .chain_err(|| format!("invalid toolchain '{}' in the history", name))
+    };
+    let mut steps = vec![];
+    let mut reinstalled = false;
+    for entry in &group {
+        match entry.operation {
+            Operation::Default => {
+                match &entry.previous {
+                    Some(previous) => println!("- restore the default toolchain '{}'", previous),
+                    None => println!("- unset the default toolchain"),
+                }
+                steps.push(Restore::Default(entry.previous.clone()));
+            }
+            Operation::OverrideSet | Operation::OverrideUnset => {
+                let path = entry.path.clone().unwrap_or_default();
+                let previous = entry.previous.as_deref().map(parse).transpose()?;
+                match &previous {
+                    Some(previous) => {
+                        println!("- restore the override for '{}' to '{}'", path, previous)
+                    }
+                    None => println!("- remove the override for '{}'", path),
+                }
+                steps.push(Restore::Override(path, previous));
+            }
+            Operation::Uninstall | Operation::Gc => {
+                let desc = parse(entry.toolchain.as_deref().unwrap_or_default())?;
+                match &desc {
+                    ToolchainDesc::Local { name } => println!(
+                        "- cannot restore the linked toolchain '{}'; link it again with \
+                         `elan toolchain link {} <path>`",
+                        name, name
+                    ),
+                    _ if Toolchain::from(cfg, &desc).exists() => {
+                        println!("- '{}' is installed already", desc)
+                    }
+                    ToolchainDesc::Remote {
+                        origin, release, ..
+                    } => {
+                        if elan_dist::peer_cache::is_cached(origin, release) {
+                            println!("- reinstall '{}' from the download cache", desc);
+                        } else {
+                            println!(
+                                "- reinstall '{}' by downloading it again, as it is not in the \
+                                 download cache",
+                                desc
+                            );
+                        }
+                        reinstalled = true;
+                        steps.push(Restore::Reinstall(desc));
+                    }
+                }
+            }
+            _ => unreachable!("not undoable: {:?}", entry.operation),
+        }
+    }
+    if reinstalled {
+        println!(
+            "Optional components of reinstalled toolchains and whether they were frozen are not \
+             restored."
+        );
+    }
+    if dry_run {
+        return Ok(());
+    }
+
+    let mut failed = false;
+    for step in steps {
+        let result = match step {
+            Restore::Default(toolchain) => cfg.settings_file.with_mut(|s| {
+                s.default_toolchain = toolchain;
+                Ok(())
+            }),
+            Restore::Override(path, toolchain) => cfg.settings_file.with_mut(|s| {
+                let path = Path::new(&path);
+                match toolchain {
+                    Some(toolchain) => s.add_override(path, toolchain, &*cfg.notify_handler),
+                    None => {
+                        s.remove_override(path, &*cfg.notify_handler);
+                    }
+                }
+                Ok(())
+            }),
+            Restore::Reinstall(desc) => Toolchain::from(cfg, &desc).install_from_dist(),
+        };
+        if let Err(e) = result {
+            err!("{}", e);
+            failed = true;
+        }
+    }
+    if failed {
+        // Left in the history so that undoing can be retried
+        return Err("could not restore everything; run `elan undo` again to retry".into());
+    }
+    history::record(
+        cfg,
+        Entry {
+            undone: group.iter().map(|e| e.line).collect(),
+            ..Entry::new(Operation::Undo)
+        },
+    );
+    info!("undone");
+    Ok(())
+}
+
+fn dump_state(cfg: &Cfg, no_net: bool) -> Result<()> {
     Ok(json_dump::StateDump::new(cfg, no_net)?.print()?)
 }
+
+fn api(cfg: &Cfg, method: &str, params: Option<&str>, api_version: Option<&str>) -> Result<()> {
+    let result = api_call(cfg, method, params, api_version);
+    let response = match result {
+        Ok(ref result) => json!({ "api_version": api::API_VERSION, "result": result }),
+        Err((code, ref message)) => json!({
+            "api_version": api::API_VERSION,
+            "error": { "code": code, "message": message },
+        }),
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&response).chain_err(|| "failed to print JSON")?
+    );
+    if result.is_err() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn api_call(
+    cfg: &Cfg,
+    method: &str,
+    params: Option<&str>,
+    api_version: Option<&str>,
+) -> std::result::Result<serde_json::Value, (i64, String)> {
+    if let Some(version) = api_version {
+        if version.parse() != Ok(api::API_VERSION) {
+            return Err((
+                api::INVALID_REQUEST,
+                format!(
+                    "unsupported API version '{}'; this elan implements version {}",
+                    version,
+                    api::API_VERSION
+                ),
+            ));
+        }
+    }
+    let params = match params {
+        Some("-") => {
+            let mut params = String::new();
+            io::stdin()
+                .read_to_string(&mut params)
+                .map_err(|e| (api::INVALID_PARAMS, format!("could not read stdin: {}", e)))?;
+            params
+        }
+        Some(params) => params.to_owned(),
+        None => "{}".to_owned(),
+    };
+    let params = serde_json::from_str(&params)
+        .map_err(|e| (api::INVALID_PARAMS, format!("invalid parameters: {}", e)))?;
+    api::dispatch(cfg, method, &params).map_err(|e| e.describe())
+}
@@ -22,6 +22,57 @@ error_chain! {
         PermissionDenied {
             description("permission denied")
         }
+        SystemInstallNeedsRoot {
+            description("system-wide installation requires root")
+            display("installing or uninstalling elan system-wide requires root; try again with `sudo`")
+        }
+        ForeignSystemProfile(p: PathBuf) {
+            description("shell configuration file not written by elan")
+            display("'{}' exists but was not written by elan-init; remove it or rerun with \
+                     `--no-modify-path`", p.display())
+        }
+        PathModificationDenied(p: PathBuf) {
+            description("not permitted to modify PATH")
+            display("not permitted to modify the PATH variable; rerun with `--no-modify-path` \
+                     and add '{}' to PATH manually", p.display())
+        }
+        InvalidToolchainSpec(spec: String, suggestions: Vec<String>) {
+            description("invalid or unavailable toolchain")
+            display("toolchain '+{}' could not be resolved{}; toolchains are given as a channel \
+                     (`stable`, `beta`, `nightly`), a release such as `v4.9.0`, \
+                     `<owner>/<repo>:<release>`, or the name of a linked toolchain",
+                    spec,
+                    if suggestions.is_empty() {
+                        String::new()
+                    } else {
+                        let s: Vec<_> = suggestions.iter().map(|s| format!("`+{}`", s)).collect();
+                        format!(" (did you mean {}?)", s.join(" or "))
+                    })
+        }
+        InvalidDefaultToolchain(spec: String, suggestion: Option<String>) {
+            description("invalid default toolchain")
+            display("'{}' is not a valid default toolchain{}; toolchains are given as a channel \
+                     (`stable`, `beta`, `nightly`, or spelled out as e.g. \
+                     `latest-stable-release`), a release such as `v4.9.0`, \
+                     `<owner>/<repo>:<release>`, or `none`",
+                    spec,
+                    match suggestion {
+                        Some(s) => format!(" (did you mean `{}`?)", s),
+                        None => String::new(),
+                    })
+        }
+        ComponentsWithoutDefaultToolchain {
+            description("components require a default toolchain")
+            display("`--component` requires a default toolchain to install the components into")
+        }
+        ProjectSetupFailed(command: String) {
+            description("could not set up project")
+            display("`{}` failed; the project was not set up", command)
+        }
+        UnsupportedCommand(command: String, hint: String) {
+            description("command not supported by elan")
+            display("elan has no `{}` command: {}", command, hint)
+        }
         InfiniteRecursion {
             description("infinite recursion detected")
         }
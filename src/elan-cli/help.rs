@@ -4,13 +4,44 @@ pub static ELAN_HELP: &str = r"DISCUSSION:
     select and, if necessary, download the Lean version described in your
     project's `lean-toolchain` file. You can also install, select, run,
     and uninstall Lean versions manually using the commands of the `elan`
-    executable.";
+    executable. Without a command, `elan` gives an overview of the
+    current setup and suggests what to do next.";
 
 pub static SHOW_HELP: &str = r"DISCUSSION:
     Shows the name of the active toolchain and the version of `lean`.
 
     If there are multiple toolchains installed then all installed
-    toolchains are listed as well.";
+    toolchains are listed as well. When writing to a terminal, they
+    are shown as a table as described in `elan help toolchain list`;
+    `--quiet` lists them by name only.
+
+    The active toolchain is determined by consulting the following
+    sources, highest precedence first:
+
+        env               the ELAN_TOOLCHAIN environment variable
+        override          directory overrides set by `elan override`
+        toolchain-file    `lean-toolchain` files
+        leanpkg           `leanpkg.toml` files
+        toolchain-dir     being inside a toolchain directory
+        default           the default toolchain
+
+    Directory sources are looked for in the current directory first,
+    then in each parent directory in turn. The `override_precedence`
+    setting reorders these sources or, by omitting them, disables them;
+    the setting of a system-wide installation takes priority over the
+    user's. `--verbose` shows the sources in effect and which one
+    determined the active toolchain.";
+
+pub static TOOLCHAIN_LIST_HELP: &str = r"DISCUSSION:
+    When writing to a terminal, installed toolchains are shown as a
+    table with their origin, release, the channel the release belongs
+    to, whether they are linked or were downloaded, their size on disk,
+    and when they were last run. Otherwise, or with `--quiet`, only
+    their names are listed, one per line, for use in scripts.
+
+    The size is the one recorded whenever elan installs or changes a
+    toolchain. For toolchains installed by older versions of elan, it is
+    shown as `?` unless `--verbose` is given, which measures it.";
 
 pub static INSTALL_HELP: &str = r"DISCUSSION:
     Installs a specific lean toolchain.
@@ -20,7 +51,176 @@ pub static INSTALL_HELP: &str = r"DISCUSSION:
 pub static DEFAULT_HELP: &str = r"DISCUSSION:
     Sets the default toolchain to the one specified.";
 
-pub static TOOLCHAIN_HELP: &str = r"DISCUSSION:
+pub static SELF_CLEAN_HELP: &str = r"DISCUSSION:
+    Toolchains are unpacked into a temporary directory and only moved
+    into place when complete, so an interrupted installation never
+    leaves a broken toolchain behind. Interrupted installations are
+    completed or rolled back automatically the next time `elan` runs,
+    which also removes lock files left behind by killed installations;
+    this command does so explicitly and additionally deletes temporary
+    files older than an hour as well as cached release metadata and
+    archives.";
+
+pub static SELF_WHICH_INSTALLATION_HELP: &str = r"DISCUSSION:
+    Prints the path of the running elan executable, the elan home it
+    belongs to, and whether that home's `bin/elan` is this executable,
+    followed by how elan was installed (by `elan-init`, an MSI package,
+    or a package manager), its version and commit, and where
+    self-updates are downloaded from. Please include this output when
+    reporting a problem with elan.";
+
+pub static SELF_REPAIR_PROXIES_HELP: &str = r"DISCUSSION:
+    Tools such as `lean` and `lake` in `ELAN_HOME/bin` are links to, or
+    copies of, the elan executable. Every invocation of elan quickly
+    checks that none of them is missing or outdated and recreates them if
+    needed; this command relinks all tools that are not linked as the
+    `proxy_link_mode` setting asks for, comparing their contents with
+    elan's.";
+
+pub static SELF_NORMALIZE_SETTINGS_HELP: &str = r"DISCUSSION:
+    Toolchains can be named in several ways, e.g. `4.9.0`, `v4.9.0`, and
+    `leanprover/lean4:v4.9.0` all name the same release. New settings
+    are always stored under the canonical name, but settings files
+    written by older versions of elan or by hand may contain other
+    spellings, as well as several overrides for the same directory.
+    This command rewrites the default toolchain and the overrides in
+    `ELAN_HOME/settings.toml` to canonical names, merges duplicate
+    overrides, and lists each change it made.
+
+    Origins are case-insensitive like GitHub repository names and are
+    stored in lowercase. Toolchains that older versions of elan
+    installed under a differently cased origin such as
+    `LeanProver/lean4` are moved to the lowercase name, or removed if
+    that toolchain is installed as well.";
+
+pub static CONFIG_HELP: &str = r"DISCUSSION:
+    Manages the settings stored in `ELAN_HOME/settings.toml`. Values
+    are checked before they are saved, so the file stays readable by
+    elan. The following settings are supported:
+
+        default_toolchain         the toolchain used outside of projects
+        default_target            the target triple to install
+                                  toolchains for instead of the host's
+        override_precedence       comma-separated sources of the active
+                                  toolchain, highest precedence first;
+                                  see `elan help show`
+        max_extracted_size        the maximum total size of the files
+                                  extracted for a toolchain installation,
+                                  e.g. 16G
+        max_extracted_files       the maximum number of files extracted
+                                  for a toolchain installation
+        implicit_install_timeout  the number of seconds after which an
+                                  installation triggered by running a
+                                  binary of a missing toolchain, e.g.
+                                  by an editor, is aborted
+        asset_targets.<origin>    comma-separated release asset targets
+                                  to accept for an origin, most
+                                  preferred first
+        release_index.<origin>    the URL of the release index to look
+                                  up the latest releases of an origin
+                                  in, or `none` to use GitHub only
+        github_url                the GitHub web site, or a mirror of
+                                  it, to download releases from; several
+                                  comma-separated URLs are tried in turn
+        github_api_url            the GitHub API to look up releases
+                                  and tags in
+        github_raw_url            the host serving raw files of GitHub
+                                  repositories
+        update_root               the base URL to download releases of
+                                  elan from
+        ipfs_gateway              the IPFS gateway to download release
+                                  archives with `ipfs://` URLs through,
+                                  if built with the `ipfs` feature
+        size_probe                whether to ask servers for the size
+                                  of a download before starting it:
+                                  true (default) or false
+        peer_cache                whether to keep downloaded release
+                                  archives and look for them at peers
+                                  on the local network first, see
+                                  `elan cache serve --help`: true or
+                                  false (default)
+        strip_patterns            comma-separated paths to remove from
+                                  toolchains by `elan toolchain strip`
+                                  and `--minimize`, relative to the
+                                  toolchain directory; `*` and `**`
+                                  match any name and any number of
+                                  directories (default:
+                                  doc,docs,share/doc,share/man,src,
+                                  **/tests)
+        proxy_auth                the authentication method for HTTP
+                                  proxies: basic, digest, ntlm,
+                                  negotiate, or any
+        proxy_link_mode           how the tools in `ELAN_HOME/bin` are
+                                  linked to elan: auto, hardlink,
+                                  symlink, or copy
+        self_delete_strategy      how elan replaces or removes itself
+                                  on Windows: auto, delete-on-close, or
+                                  script
+        telemetry                 true or false
+
+    Unlike `elan default`, setting `default_toolchain` does not install
+    the toolchain.
+
+    The extraction limits guard against decompression bombs and default
+    to 16G and 1000000 files. If the settings of a system-wide
+    installation also set a limit, the lower one applies.
+
+    `implicit_install_timeout` defaults to 1800 seconds so that editors
+    starting `lean` in a project do not hang on a stalled network; the
+    user's setting takes precedence over a system-wide one.
+
+    `proxy_link_mode` defaults to `auto`, which tries a hard link, then a
+    symbolic link, and finally a copy of the elan executable, e.g. on
+    file systems without hard links where policy forbids symbolic links.
+    Symbolic links are never used on Windows. Run `elan self
+    repair-proxies` to relink the tools after changing it.";
+
+pub static SELF_TRUST_ROOT_HELP: &str = r"DISCUSSION:
+    When `ELAN_UPDATE_ROOT` points to an alternative source of elan
+    releases such as an internal mirror, self-updates from it can be
+    required to be signed. With a trust root configured for the active
+    update root, `elan self update` also downloads `<archive>.sig`, the
+    base64-encoded Ed25519 signature of the release archive, and
+    refuses to install the update unless it is valid for the configured
+    key.
+
+    Keys are given in base64, either as the raw 32-byte public key or as
+    printed by `openssl pkey -pubout`. Trust roots are stored in
+    `ELAN_HOME/settings.toml`.";
+
+pub static SELF_UPDATE_HELP: &str = r#"DISCUSSION:
+    Before replacing itself with an older version, e.g. when a
+    development build is newer than the latest release, elan asks for
+    confirmation if run in a terminal; pass `-y`/`--yes` to skip the
+    question.
+
+    On Windows, a running executable cannot be deleted or overwritten,
+    so elan finishes updating or uninstalling itself in a helper process
+    after it exits. By default, it uses a temporary copy of itself that is
+    deleted by the system when it exits, and falls back to a batch script
+    if that copy is blocked, e.g. by antivirus software. The method can be
+    fixed by setting `self_delete_strategy` in `ELAN_HOME/settings.toml`
+    to "delete-on-close" or "script"."#;
+
+pub static SELF_UNINSTALL_HELP: &str = r#"DISCUSSION:
+    Removes `ELAN_HOME` with all toolchains and settings, and reverts the
+    changes made to `PATH` at installation. With `--dry-run`, nothing is
+    removed; instead, the files and directories that would be deleted,
+    the lines that would be removed from shell configuration files, and,
+    on Windows, the registry keys and values that would be changed are
+    listed, with `--json` in a form meant for configuration-management
+    tools.
+
+    Unlike other destructive commands, this one always asks for
+    confirmation unless `-y`/`--yes` is passed, even if stdin is not a
+    terminal.
+
+    On Windows, a running executable cannot be deleted, so elan finishes
+    uninstalling itself in a helper process after it exits; see
+    `elan help self update` for how to configure this with the
+    `self_delete_strategy` setting."#;
+
+pub static TOOLCHAIN_HELP: &str = r#"DISCUSSION:
     Many `elan` commands deal with *toolchains*, a single
     installation of the Lean theorem prover. `elan` supports multiple
     types of toolchains. The most basic track the official release
@@ -42,9 +242,24 @@ pub static TOOLCHAIN_HELP: &str = r"DISCUSSION:
     the default is 'leanprover/lean4'. For nightly versions, '-nightly'
     is appended to the value of 'origin'.
 
+    Release archives are chosen by the target at the end of their file
+    name, such as 'lean-4.9.0-x86_64-unknown-linux-gnu.tar.zst' or
+    'lean-4.9.0-linux.tar.zst'. If a custom origin names its archives
+    differently, list the accepted targets in `settings.toml`, most
+    preferred first:
+
+        [asset_targets]
+        "myorg/lean4" = ["linux-x64", "linux"]
+
+    To install builds for a different platform, e.g. to populate a
+    shared toolchain directory for other machines, pass `--target
+    <triple>` to `elan toolchain install` or set `default_target` in
+    `settings.toml`. The installed target is recorded in the
+    toolchain's `elan-install.toml`.
+
     elan can also manage symlinked local toolchain builds, which are
     often used to for developing Lean itself. For more information see
-    `elan toolchain help link`.";
+    `elan toolchain help link`."#;
 
 pub static TOOLCHAIN_LINK_HELP: &str = r"DISCUSSION:
     'toolchain' is the custom name to be assigned to the new toolchain.
@@ -61,6 +276,15 @@ pub static TOOLCHAIN_LINK_HELP: &str = r"DISCUSSION:
     If you now compile a crate in the current directory, the custom
     toolchain 'master' will be used.";
 
+pub static TOOLCHAIN_RENAME_HELP: &str = r"DISCUSSION:
+    Renames a toolchain created with `elan toolchain link` without
+    changing the directory it links to. The default toolchain and all
+    directory overrides naming the toolchain are updated to the new name
+    as well.
+
+    `lean-toolchain` files are not modified; elan warns about those of
+    known projects that still refer to the old name.";
+
 pub static TOOLCHAIN_GC_HELP: &str = r"DISCUSSION:
     Experimental. A toolchain is classified as 'in use' if
     * it is the default toolchain,
@@ -70,7 +294,109 @@ pub static TOOLCHAIN_GC_HELP: &str = r"DISCUSSION:
 
     For safety reasons, the command currently requires passing `--delete`
     to actually remove toolchains but this may be relaxed in the future
-    when the implementation is deemed stable.";
+    when the implementation is deemed stable. Toolchains protected with
+    `elan toolchain freeze` are kept unless `--force` is passed as well.
+    When run in a terminal, it asks for confirmation before deleting
+    anything unless `-y`/`--yes` is passed as well. Adding `--dry-run`
+    to such a command lists the files and directories
+    it would delete instead; with `--json`, they are reported in
+    `removed_paths`.
+
+    Known projects are recorded in `ELAN_HOME/known-projects.toml` and are
+    never forgotten automatically. Use `--list-roots` to show them,
+    `--prune-roots` to forget projects that have been deleted or no longer
+    contain a `lean-toolchain` file, and `--forget-root <path>` to forget
+    a specific project.";
+
+pub static RESOLVE_HELP: &str = r#"DISCUSSION:
+    Resolves each given toolchain name, or the toolchain named in each
+    given `lean-toolchain` file, to a fixed `<owner>/<repo>:<release>`
+    without installing it. With `--stdin`, names and paths are read
+    from standard input, one per line, which is much faster than running
+    elan for each of them as channels are only resolved once. With
+    `--no-net`, also spelled `--offline`, channels such as `stable`
+    resolve to the newest installed release of the channel instead.
+
+    One JSON object is printed per line of input, in order:
+
+        {"input":"stable","installed":true,"resolved":"leanprover/lean4:v4.9.0"}
+        {"error":"invalid toolchain name: 'x!'","input":"x!"}"#;
+
+pub static TOOLCHAIN_DEFAULT_FOR_HELP: &str = r"DISCUSSION:
+    Prints the toolchain that elan would use when running a command in
+    each of the given directories, and why: the `ELAN_TOOLCHAIN`
+    environment variable, a directory override, a `lean-toolchain`
+    file, or the default toolchain. Channels such as `stable` are
+    resolved, but nothing is installed.
+
+    With `--json`, an array with one object per directory is printed
+    instead, containing the unresolved and resolved toolchain names,
+    whether the toolchain is installed, and the override reason.";
+
+pub static TOOLCHAIN_UNINSTALL_HELP: &str = r"DISCUSSION:
+    Toolchains that are the default, active in the current directory,
+    used by a running process, or frozen via `elan toolchain freeze` are
+    only uninstalled with `--force`, in which case elan asks for
+    confirmation when run in a terminal; pass `-y`/`--yes` to skip the
+    question.
+
+    With `--all`, all installed toolchains except those passed via
+    `--except` and, unless `--force` is given, frozen toolchains are
+    uninstalled after showing a summary that points out
+    toolchains still used by known projects, the default toolchain, or
+    overrides, and asking for confirmation:
+
+        $ elan toolchain uninstall --all --except stable
+
+    Without `--force`, nothing is uninstalled if any of these
+    toolchains is used by a running process.";
+
+pub static TOOLCHAIN_FREEZE_HELP: &str = r"DISCUSSION:
+    Marks the given installed toolchains as frozen so that
+    `elan toolchain uninstall`, including `--all`, and
+    `elan toolchain gc --delete` refuse to remove them unless `--force`
+    is passed. This safeguards toolchains that must stay available, such
+    as the one used for a thesis or a production deployment:
+
+        $ elan toolchain freeze leanprover/lean4:v4.9.0
+
+    Frozen toolchains are marked in the table printed by
+    `elan toolchain list`. Use
+    `elan toolchain unfreeze` to remove the protection again. Uninstalling
+    a frozen toolchain with `--force` also removes its protection.";
+
+pub static TOOLCHAIN_RUN_ALL_HELP: &str = r"DISCUSSION:
+    Runs the given command once under each installed toolchain, or
+    under each toolchain passed via `--toolchain`, and prints a summary
+    of the exit codes. This is useful for testing a library against
+    multiple Lean versions:
+
+        $ elan toolchain run-all --toolchain stable --toolchain nightly -- lake build
+
+    With `--jobs N`, up to N toolchains are run in parallel. With
+    `--json <file>`, the results are additionally written to the given
+    file as JSON. The command fails if the command failed under any
+    toolchain.";
+
+pub static COMPONENT_HELP: &str = r"DISCUSSION:
+    Releases may publish optional components, such as documentation or
+    debug symbols, next to the main toolchain archive. `elan component
+    list` shows the components available for a toolchain, and `elan
+    component add` installs them into the toolchain directory.
+
+    The files of each installed component are recorded in the
+    toolchain, so `elan component remove` deletes exactly those files.
+    Uninstalling the toolchain removes all of its components.
+
+    Components can also be selected when installing a toolchain with
+    `elan toolchain install <toolchain> --component <name>`. All archives
+    are then unpacked into the new toolchain before it is moved into
+    place, and the installation fails if two of them contain the same
+    file. If the toolchain is installed already, the components it lacks
+    are added to it instead.
+
+    By default, the commands operate on the toolchain of the current
+    directory; use `--toolchain` to select another one.";
 
 pub static OVERRIDE_HELP: &str = r"DISCUSSION:
     Overrides configure elan to use a specific toolchain when
@@ -100,7 +426,47 @@ pub static OVERRIDE_UNSET_HELP: &str = r"DISCUSSION:
     for the specified directory. If `--nonexistent` argument is
     present, removes the override toolchain for all nonexistent
     directories. Otherwise, removes the override toolchain for the
-    current directory.";
+    current directory. When run in a terminal, elan asks for
+    confirmation before removing any override; pass `-y`/`--yes` to skip
+    the question.";
+
+pub static OVERRIDE_CLEAN_HELP: &str = r"DISCUSSION:
+    Overrides refer to linked toolchains by name, so they keep working
+    when a toolchain is removed and linked again under the same name.
+    If it is not linked again, `elan override list` marks the override
+    as '(linked toolchain missing)'.
+
+    This command goes through all such overrides. When run in a
+    terminal, elan asks for a toolchain to use instead of each missing
+    one, removing the override if none is given. Otherwise, or with
+    `-y`/`--yes`, the overrides are removed, and with `--reassign`,
+    they are all set to the given toolchain.";
+
+pub static RUN_PROXY_HELP: &str = r"DISCUSSION:
+    Behaves exactly like invoking the proxy for the given tool, e.g.
+    `lean` or `lake`, including selecting a toolchain with a leading
+    `+toolchain` argument. Use this from wrappers that rewrite arg0,
+    which makes elan unable to tell which tool to run:
+
+        $ elan run-proxy lake -- +nightly build
+
+    Alternatively, set `ELAN_PROXY_NAME` to the tool's name when
+    invoking a proxy through such a wrapper. The variable is removed
+    from the environment of the tool itself.";
+
+pub static INIT_PROJECT_HELP: &str = r"DISCUSSION:
+    Sets up a new Lean project in one step: resolves and installs the
+    toolchain, runs `lake new` or, in an existing directory, `lake
+    init` of that toolchain with the given template, and pins the
+    resolved toolchain in the project's `lean-toolchain` file.
+
+        $ elan init-project exe hello
+        $ elan init-project math my-formalization
+
+    Projects of the `math` templates get the toolchain Mathlib is
+    currently built with, so that its prebuilt files can be used;
+    others get the latest stable release unless `--toolchain` is
+    given. Run `lake build` in the project's directory afterwards.";
 
 pub static RUN_HELP: &str = r"DISCUSSION:
     Configures an environment to use the given toolchain and then runs
@@ -115,7 +481,199 @@ pub static RUN_HELP: &str = r"DISCUSSION:
 
         $ lake +nightly build
 
-        $ elan run --install nightly lake build";
+        $ elan run --install nightly lake build
+
+    All arguments after the program name are passed on unchanged. Use
+    `--` before the program name if it starts with `-`, or to make sure
+    no following argument is interpreted by elan:
+
+        $ elan run nightly -- lean --run script.lean";
+
+pub static DUMP_STATE_HELP: &str = r"DISCUSSION:
+    Prints installed toolchains, the default and active toolchain, and
+    the elan version as JSON, for use by editors and other tools.
+
+    The output contains a `schema_version` field. New fields may be
+    added at any time, but existing fields are only removed or changed
+    in meaning together with an increase of `schema_version`.";
+
+pub static HISTORY_HELP: &str = r"DISCUSSION:
+    elan records each change of the default toolchain, each override
+    set or removed, and each toolchain installed, uninstalled, deleted
+    by `elan toolchain gc`, or replaced by `elan toolchain install
+    --force` in `ELAN_HOME/history.jsonl`, one JSON object per line.
+    `elan history` lists these operations, oldest first, with the
+    command that caused each of them and, where elan can tell, the
+    command that reverts it:
+
+        $ elan history -n 1
+        2024-05-02 09:14 UTC  deleted unused 'leanprover/lean4:v4.8.0'
+            by `elan toolchain gc --delete`
+            undo: `elan toolchain install leanprover/lean4:v4.8.0`
+
+    Toolchains installed because a tool of a missing toolchain was run
+    are recorded with the command that ran it, e.g. `lake build`.";
+
+pub static UNDO_HELP: &str = r"DISCUSSION:
+    Reverts the most recent operation recorded by `elan history` that
+    can be undone and has not been yet: a change of the default
+    toolchain, the overrides set or removed by one run of a command, or
+    the toolchains uninstalled or deleted by `elan toolchain gc` in one
+    run. Running `elan undo` again reverts the operation before.
+
+    Removed toolchains are reinstalled from the download cache if the
+    `peer_cache` setting kept their archives there, and downloaded
+    again otherwise. Linked toolchains cannot be restored, and neither
+    can the optional components or the frozen state of a reinstalled
+    toolchain; `elan undo` says so before it starts. Use `--dry-run` to
+    see what would be restored.";
+
+pub static CACHE_HELP: &str = r"DISCUSSION:
+    `elan cache export` and `elan cache import` save and restore the
+    toolchains a workspace needs, e.g. between CI runs, instead of
+    caching all of `ELAN_HOME`. The archive written by `export` holds
+    just the toolchains named by the nearest `lean-toolchain` file of
+    each given directory, which must be installed, and can be imported
+    into any elan home. `elan cache key` prints a key that changes
+    exactly when those `lean-toolchain` files or the target do, for
+    use with caches such as GitHub's `actions/cache`:
+
+        $ elan cache key
+        elan-x86_64-unknown-linux-gnu-3f2a...
+        $ elan cache import elan-cache.tar.zst   # on a cache hit
+        $ elan cache export elan-cache.tar.zst   # on a cache miss
+
+    Note that the key of a workspace whose `lean-toolchain` names a
+    channel such as `stable` does not change with new releases.
+
+    `elan cache serve` shares downloaded release archives with other
+    machines on the local network instead, see its help. `elan cache
+    fetch` fetches supplementary caches that workspaces declare, such
+    as prebuilt Mathlib, see its help.";
+
+pub static CACHE_FETCH_HELP: &str = r#"DISCUSSION:
+    Fetches the artifact caches declared in the `elan-artifacts.toml`
+    file next to the `lean-toolchain` file of each given workspace
+    into `ELAN_HOME/artifact-cache/<name>`:
+
+        [[cache]]
+        name = "mathlib"
+        index = "https://example.org/mathlib/v4.9.0.json"
+        toolchains = ["leanprover/lean4:v4.9.0"]
+        env = "MATHLIB_CACHE_DIR"
+
+    A cache applies only to the toolchains listed in `toolchains`, if
+    given. Its index, a URL or a path relative to the declaration,
+    lists the files of the cache as JSON:
+
+        {"files": [{"name": "a.ltar", "sha256": "...", "url": "..."}]}
+
+    where `url` is relative to the index and defaults to the name.
+    Files are stored once per content hash and shared between all
+    caches and workspaces listing them, so they are downloaded only
+    once and must not be modified.
+
+    Tools run by elan find the artifact caches in `ELAN_ARTIFACT_CACHE`
+    and each cache that applies to the workspace in the variable named
+    by its `env` key, unless that is set already. This way, e.g.,
+    `lake exe cache get` of Mathlib shares its downloads between
+    workspaces."#;
+
+pub static CACHE_SERVE_HELP: &str = r"DISCUSSION:
+    Serves the release archives in `ELAN_HOME/cache/downloads` over
+    HTTP and announces them on the local network via multicast DNS
+    until interrupted. elan instances on the network with the
+    `peer_cache` setting enabled find such servers and download
+    archives from them before trying the internet, which helps
+    workshops and CI farms behind slow uplinks:
+
+        $ elan config set peer_cache true
+
+    Archives are only kept in the cache if the `peer_cache` setting is
+    enabled on this machine as well. Peers are trusted to serve the
+    archives they claim to, so only use this on networks whose
+    machines trust each other. `elan self clean` empties the cache.";
+
+pub static DAEMON_HELP: &str = r#"DISCUSSION:
+    Runs elan as a long-lived JSON-RPC 2.0 server so that editors do
+    not have to spawn a new process for every query. Each request and
+    response is a single line of JSON. The following methods are
+    supported, see `elan help api` for their results:
+
+        resolve   {"toolchain"?: string, "dir"?: string}
+        which     {"command": string, "dir"?: string}
+        list      {}
+        install   {"toolchain": string}
+        uninstall {"toolchain": string, "force"?: bool}
+        gc        {"delete"?: bool, "force"?: bool}
+        shutdown  {}
+
+    While a request is processed, installation progress is sent as
+    `progress` notifications. Changes to `settings.toml` are picked up
+    automatically. With `--socket`, up to 16 clients are served at
+    once, and connections without a request for ten minutes are
+    closed.
+
+    With `--cache`, results of `resolve`, `which`, and `list` are
+    memoized. elan watches `settings.toml`, the toolchains directory,
+    and the `lean-toolchain` files of all known projects and of all
+    directories queried, and drops the cache whenever one of them
+    changes. As channels such as `stable` move on to new releases
+    without any local file changing, results are also dropped after a
+    minute. The `lean`, `lake`, and other proxies are not affected, as
+    they resolve the toolchain anew on every run."#;
+
+pub static API_HELP: &str = r#"DISCUSSION:
+    Performs a single operation and prints the outcome as JSON, for
+    scripts and infrastructure that orchestrate elan. Unlike the output
+    of other commands, the parameters and results are a stable
+    interface: within an API version, fields are only ever added.
+    The output is either
+
+        {"api_version": 1, "result": ...}
+
+    or, with exit code 1,
+
+        {"api_version": 1, "error": {"code": number, "message": string}}
+
+    with the error codes of `elan daemon`. Pass `--api-version 1` to fail
+    early if elan ever stops implementing the expected version. Methods
+    take these parameters and return these results:
+
+        resolve    {"toolchain"?: string, "dir"?: string}
+                   -> {"toolchain": string | null, "reason": string | null}
+        which      {"command": string, "dir"?: string}
+                   -> {"path": string | null}
+        list       {} -> {"toolchains": [{"name": string, "path": string}]}
+        install    {"toolchain": string}
+                   -> {"toolchain": string, "installed": bool}
+        uninstall  {"toolchain": string, "force"?: bool}
+                   -> {"toolchain": string, "uninstalled": bool}
+        gc         {"delete"?: bool, "force"?: bool}
+                   -> {"unused_toolchains": [string],
+                       "frozen_toolchains": [string],
+                       "used_toolchains": [{"user": string, "toolchain": string}],
+                       "deleted": bool}
+
+    For example:
+
+        $ elan api resolve '{"toolchain": "stable"}'
+
+    Progress and diagnostics are printed to stderr as usual."#;
+
+pub static MAN_HELP: &str = r"DISCUSSION:
+    Shows the man page of elan or of one of its commands, which are
+    rendered from the help text of the running elan:
+
+        $ elan man toolchain install
+
+    Pages of other tools, such as `lean`, are looked up in the active
+    toolchain or the one given by `--toolchain`.
+
+    Packagers can install the pages of all commands along with elan,
+    e.g. into `/usr/share/man/man1`:
+
+        $ elan man --generate /usr/share/man/man1";
 
 pub static _DOC_HELP: &str = r"DISCUSSION:
     Opens the documentation for the currently active toolchain with
@@ -193,6 +751,20 @@ pub static COMPLETIONS_HELP: &str = r"DISCUSSION:
 
     for the new completions to take affect.
 
+    PROXIES:
+
+    With `--proxies`, a separate script is generated that completes
+    `+toolchain` as the first argument of `lean`, `lake`, and the
+    other proxies with the channels and installed toolchains. It is
+    supported for bash, fish, and zsh and installed the same way, e.g.
+
+        $ elan completions bash --proxies > /etc/bash_completion.d/elan-proxies.bash-completion
+        $ elan completions fish --proxies > ~/.config/fish/completions/lake.fish
+        $ elan completions zsh --proxies > ~/.zfunc/_elan_proxies
+
+    For fish, which loads completions by command name, link the file
+    to the names of the other proxies, such as `lean.fish`, as well.
+
     CUSTOM LOCATIONS:
 
     Alternatively, you could save these files to the place of your
@@ -225,6 +797,61 @@ pub static COMPLETIONS_HELP: &str = r"DISCUSSION:
 
         PS C:\> elan completions powershell >> %USERPROFILE%\Documents\WindowsPowerShell\Microsoft.PowerShell_profile.ps1";
 
+pub static COMPONENT_ARG_HELP: &str = "Optional component to install along with the \
+     toolchain; may be repeated. For more information see `elan \
+     help component`";
+
+pub static NO_EMULATION_ARG_HELP: &str = "Fail instead of installing an x86_64 build to run \
+     under Rosetta 2 if a release has no native Apple silicon build";
+
+pub static TARGET_ARG_HELP: &str = "Install the build for the given target triple, such as \
+     'x86_64-unknown-linux-gnu', instead of the host's";
+
+#[cfg(feature = "build-from-source")]
+pub static BUILD_ARG_HELP: &str = "Build the toolchain from source at the given release, \
+     branch, or full commit hash instead of downloading it; requires git, cmake, make, and a C++ \
+     compiler";
+
+pub static REINSTALL_ARG_HELP: &str = "Replace an installed toolchain with a fresh \
+     installation, keeping the old one if installing fails";
+
+pub static FORCE_INSTALL_ARG_HELP: &str = "Replace a linked toolchain that has the name of \
+     the release to install; the link is removed even if installing fails";
+
+pub static DRY_RUN_INSTALL_ARG_HELP: &str = "Resolve the toolchain and select its release \
+     archives, then print their URLs and sizes, the installation directory, and whether the \
+     toolchain is already installed instead of installing it";
+
+pub static TOOLCHAIN_STRIP_HELP: &str = r"DISCUSSION:
+    Removes the files and directories matching the `strip_patterns`
+    setting, or the `--pattern` options, from the given installed
+    toolchains to reclaim disk space. By default, documentation, tests,
+    and the sources of the core library are removed, which only editors
+    use to navigate to definitions:
+
+        $ elan toolchain strip leanprover/lean4:v4.9.0
+
+    Patterns are relative to the toolchain directory; `*` matches any
+    part of a name and `**` any number of directories. For example,
+    static libraries are only needed to link executables:
+
+        $ elan toolchain strip --pattern '**/*.a' leanprover/lean4:v4.9.0
+
+    The removed paths are recorded in the toolchain's
+    `elan-install.toml` so that they are not mistaken for missing files.
+    Reinstall the toolchain to restore them. `--dry-run` lists what
+    would be removed. Linked toolchains are never modified.";
+
+pub static MINIMIZE_ARG_HELP: &str = "Remove documentation, tests, and sources not needed \
+     to build projects from the installed toolchain, e.g. to keep container images small; the \
+     `strip_patterns` setting selects what is removed";
+
+pub static FORCE_UNINSTALL_ARG_HELP: &str = "Uninstall even if the toolchain is the default, \
+     active in the current directory, used by a running process, or frozen";
+
 pub static TOOLCHAIN_ARG_HELP: &str = "Toolchain name, such as 'stable', 'nightly', \
      or '3.3.0'. For more information see `elan \
      help toolchain`";
+
+pub static NO_NET_ARG_HELP: &str = "Resolve channels to the newest installed release \
+     instead of accessing the network";
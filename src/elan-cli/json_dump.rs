@@ -1,8 +1,15 @@
+//! Machine-readable dump of elan's state, used e.g. by the Lean VS Code extension.
+//!
+//! Compatibility: fields are only ever added to this format in a backwards-compatible way. Any
+//! change that removes or changes the meaning of an existing field bumps `SCHEMA_VERSION`, so
+//! consumers should check that field before interpreting the rest of the output.
+
 use elan::{
     lookup_unresolved_toolchain_desc, resolve_toolchain_desc_ext,
     utils::{self, fetch_latest_release_tag},
     Cfg, Toolchain, UnresolvedToolchainDesc,
 };
+use elan_dist::dist::ToolchainDesc;
 use std::{io, path::PathBuf};
 
 use serde_derive::Serialize;
@@ -11,6 +18,9 @@ use elan::OverrideReason;
 
 type Result<T> = std::result::Result<T, String>;
 
+/// Version of the output format, see module documentation
+const SCHEMA_VERSION: u32 = 1;
+
 #[derive(Serialize)]
 struct Version {
     current: String,
@@ -24,6 +34,45 @@ struct InstalledToolchain {
     resolved_name: String,
     /// Absolute path to toolchain root
     path: PathBuf,
+    /// GitHub repository the toolchain was downloaded from; `None` for linked toolchains
+    origin: Option<String>,
+    /// Release name, usually a Git tag; `None` for linked toolchains
+    release: Option<String>,
+    /// Whether this is a toolchain linked via `elan toolchain link`
+    linked: bool,
+    /// Size on disk in bytes as recorded by elan; `None` for linked toolchains and toolchains
+    /// installed by versions of elan that did not record it
+    size: Option<u64>,
+    /// Installation time as a Unix timestamp in seconds, if recorded at installation
+    installed_at: Option<u64>,
+    /// SHA-256 hash of the toolchain archive in lowercase hex, if recorded at installation
+    checksum: Option<String>,
+    /// Target of the installed build, if recorded at installation
+    target: Option<String>,
+}
+
+impl InstalledToolchain {
+    fn new(cfg: &Cfg, desc: &ToolchainDesc) -> Self {
+        let toolchain = Toolchain::from(cfg, desc);
+        let linked = toolchain.exists() && toolchain.is_custom();
+        let (origin, release) = match desc {
+            ToolchainDesc::Remote {
+                origin, release, ..
+            } => (Some(origin.clone()), Some(release.clone())),
+            ToolchainDesc::Local { .. } => (None, None),
+        };
+        InstalledToolchain {
+            resolved_name: desc.to_string(),
+            path: toolchain.path().to_owned(),
+            origin,
+            release,
+            linked,
+            size: toolchain.installed_size(),
+            installed_at: toolchain.installed_at(),
+            checksum: toolchain.installed_checksum(),
+            target: toolchain.installed_target(),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -61,6 +110,8 @@ struct Toolchains {
 
 #[derive(Serialize)]
 pub struct StateDump {
+    /// See `SCHEMA_VERSION`
+    schema_version: u32,
     elan_version: Version,
     toolchains: Toolchains,
 }
@@ -82,7 +133,7 @@ fn mk_toolchain_resolution(
 
 impl StateDump {
     pub fn new(cfg: &Cfg, no_net: bool) -> crate::Result<StateDump> {
-        let newest = fetch_latest_release_tag("leanprover/elan", no_net);
+        let newest = fetch_latest_release_tag("leanprover/elan", "stable", no_net, &|_| {});
         let cwd = &(utils::current_dir()?);
         let active_override = cfg.find_override(cwd)?;
         let default = match cfg.get_default()? {
@@ -90,6 +141,7 @@ impl StateDump {
             Some(d) => Some(lookup_unresolved_toolchain_desc(cfg, &d)?),
         };
         Ok(StateDump {
+            schema_version: SCHEMA_VERSION,
             elan_version: Version {
                 current: env!("CARGO_PKG_VERSION").to_string(),
                 newest: newest
@@ -100,10 +152,7 @@ impl StateDump {
                 installed: cfg
                     .list_toolchains()?
                     .into_iter()
-                    .map(|t| InstalledToolchain {
-                        resolved_name: t.to_string(),
-                        path: Toolchain::from(cfg, &t).path().to_owned(),
-                    })
+                    .map(|t| InstalledToolchain::new(cfg, &t))
                     .collect(),
                 default: default.as_ref().map(|default| DefaultToolchain {
                     unresolved: default.clone(),
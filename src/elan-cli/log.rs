@@ -1,6 +1,31 @@
-use crate::term2;
+use crate::{ci, term2};
+use elan::env_var;
+use elan_utils::{tty, utils};
+use std::env;
 use std::fmt;
 use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppresses info and verbose messages, e.g. for `elan-init --quiet`
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Whether messages are suppressed without `--quiet`: as `ELAN_QUIET` says
+/// if set, or else in containers without a terminal, e.g. in `docker build`,
+/// where they would only bloat the logs
+pub fn quiet_by_default() -> bool {
+    match env::var(env_var::ELAN_QUIET) {
+        Ok(quiet) if !quiet.is_empty() => quiet != "0",
+        _ => !tty::stderr_isatty() && utils::in_container() && !ci::is_github(),
+    }
+}
 
 macro_rules! warn {
     ( $ ( $ arg : tt ) * ) => ( $crate::log::warn_fmt ( format_args ! ( $ ( $ arg ) * ) ) )
@@ -17,6 +42,9 @@ macro_rules! verbose {
 }
 
 pub fn warn_fmt(args: fmt::Arguments<'_>) {
+    if ci::annotate("warning", &args.to_string()) {
+        return;
+    }
     let mut t = term2::stderr();
     let _ = t.fg(term2::color::BRIGHT_YELLOW);
     let _ = t.attr(term2::Attr::Bold);
@@ -27,6 +55,9 @@ pub fn warn_fmt(args: fmt::Arguments<'_>) {
 }
 
 pub fn err_fmt(args: fmt::Arguments<'_>) {
+    if ci::annotate("error", &args.to_string()) {
+        return;
+    }
     let mut t = term2::stderr();
     let _ = t.fg(term2::color::BRIGHT_RED);
     let _ = t.attr(term2::Attr::Bold);
@@ -37,20 +68,26 @@ pub fn err_fmt(args: fmt::Arguments<'_>) {
 }
 
 pub fn info_fmt(args: fmt::Arguments<'_>) {
+    if is_quiet() {
+        return;
+    }
     let mut t = term2::stderr();
     let _ = t.attr(term2::Attr::Bold);
     let _ = write!(t, "info: ");
     let _ = t.reset();
-    let _ = t.write_fmt(args);
+    let _ = t.write_all(ci::mask(args.to_string()).as_bytes());
     let _ = writeln!(t);
 }
 
 pub fn verbose_fmt(args: fmt::Arguments<'_>) {
+    if is_quiet() {
+        return;
+    }
     let mut t = term2::stderr();
     let _ = t.fg(term2::color::BRIGHT_MAGENTA);
     let _ = t.attr(term2::Attr::Bold);
     let _ = write!(t, "verbose: ");
     let _ = t.reset();
-    let _ = t.write_fmt(args);
+    let _ = t.write_all(ci::mask(args.to_string()).as_bytes());
     let _ = writeln!(t);
 }
@@ -11,32 +11,49 @@
 //! This scheme is further used to distingush the elan installer,
 //! called 'elan-init' which is again just the elan binary under a
 //! different name.
+//!
+//! For wrappers that rewrite arg0, `ELAN_PROXY_NAME` selects the proxied
+//! tool explicitly, as does `elan run-proxy <tool>`.
 
 #![recursion_limit = "1024"]
 #![deny(rust_2018_idioms)]
 
 #[macro_use]
 mod log;
+mod ci;
 mod common;
+mod daemon;
 mod download_tracker;
 mod elan_mode;
 mod errors;
 mod help;
 mod job;
 mod json_dump;
+mod man;
+mod proxy_completions;
 mod proxy_mode;
 mod self_update;
 mod setup_mode;
 mod term2;
+mod watch;
 
-use elan::env_var::LEAN_RECURSION_COUNT_MAX;
+use elan::env_var::{self, LEAN_RECURSION_COUNT_MAX};
+use elan_utils::utils;
 use errors::*;
 use std::env;
 use std::path::PathBuf;
 
 fn main() {
-    if let Err(ref e) = run_elan() {
+    #[cfg(windows)]
+    let code_page = elan_utils::tty::setup_console();
+    let result = run_elan();
+    #[cfg(windows)]
+    elan_utils::tty::restore_console(code_page);
+    if let Err(ref e) = result {
         common::report_error(e);
+        // Exit by the signal that aborted us, if any, so that our parent sees
+        // the same status as if we had not caught it
+        elan_utils::interrupt::reraise();
         std::process::exit(1);
     }
 }
@@ -53,6 +70,12 @@ fn run_elan() -> Result<()> {
         .and_then(|a| a.file_stem())
         .and_then(|a| a.to_str());
 
+    log::set_quiet(log::quiet_by_default());
+
+    if utils::elan_proxy_name().is_some() {
+        return proxy_mode::main();
+    }
+
     match name {
         Some("elan") => elan_mode::main(),
         Some(n) if n.starts_with("elan-setup") || n.starts_with("elan-init") => {
@@ -76,7 +99,7 @@ fn run_elan() -> Result<()> {
 }
 
 fn do_recursion_guard() -> Result<()> {
-    let recursion_count = env::var("LEAN_RECURSION_COUNT")
+    let recursion_count = env::var(env_var::LEAN_RECURSION_COUNT)
         .ok()
         .and_then(|s| s.parse().ok())
         .unwrap_or(0);
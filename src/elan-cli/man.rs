@@ -0,0 +1,245 @@
+//! Man pages of elan and its subcommands
+//!
+//! The pages are rendered from the same definitions `--help` is, so they
+//! never go out of date: one page `elan-<subcommand>...(1)` per command,
+//! listing its arguments and subcommands, followed by the discussion of the
+//! command if it has one. `elan man --generate <dir>` writes all of them for
+//! packagers to install along with the binary.
+
+use crate::elan_mode::cli;
+use clap::{Arg, Command};
+
+/// A section of help text and its lines, with indentation
+struct Section {
+    title: String,
+    lines: Vec<String>,
+}
+
+/// The sections of the text shown after the arguments of a command, such as
+/// `DISCUSSION`
+fn parse_sections(text: &str) -> Vec<Section> {
+    let mut sections: Vec<Section> = vec![];
+    for line in text.lines() {
+        let is_title = !line.starts_with(' ')
+            && line.ends_with(':')
+            && line[..line.len() - 1]
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c == ' ');
+        if is_title {
+            sections.push(Section {
+                title: line[..line.len() - 1].to_owned(),
+                lines: vec![],
+            });
+        } else if let Some(section) = sections.last_mut() {
+            section.lines.push(line.trim_end().to_owned());
+        }
+    }
+    for section in &mut sections {
+        while section.lines.last().is_some_and(|l| l.is_empty()) {
+            section.lines.pop();
+        }
+    }
+    sections
+}
+
+/// How an argument is listed, e.g. `-j, --jobs <JOBS>` or `<TOOLCHAIN>...`
+fn arg_item(arg: &Arg) -> String {
+    let value = match arg.get_value_names() {
+        Some(names) => names
+            .iter()
+            .map(|name| format!("<{}>", name))
+            .collect::<Vec<_>>()
+            .join(" "),
+        None => format!("<{}>", arg.get_id().as_str().to_uppercase()),
+    };
+    if arg.is_positional() {
+        let multiple = arg.get_num_args().is_some_and(|n| n.max_values() > 1);
+        return format!("{}{}", value, if multiple { "..." } else { "" });
+    }
+    let mut names = vec![];
+    if let Some(short) = arg.get_short() {
+        names.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("--{}", long));
+    }
+    let mut item = names.join(", ");
+    if arg.get_action().takes_values() {
+        item = format!("{} {}", item, value);
+    }
+    item
+}
+
+/// The help of an argument, with its default and possible values
+fn arg_description(arg: &Arg) -> String {
+    let mut description = arg.get_help().map(|h| h.to_string()).unwrap_or_default();
+    let defaults = arg.get_default_values();
+    if !defaults.is_empty() && arg.get_action().takes_values() {
+        let defaults: Vec<_> = defaults.iter().map(|d| d.to_string_lossy()).collect();
+        description += &format!(" [default: {}]", defaults.join(", "));
+    }
+    let values = arg.get_possible_values();
+    if !values.is_empty() && arg.get_action().takes_values() {
+        let values: Vec<_> = values
+            .iter()
+            .filter(|v| !v.is_hide_set())
+            .map(|v| v.get_name())
+            .collect();
+        description += &format!(" [possible values: {}]", values.join(", "));
+    }
+    description.trim().to_owned()
+}
+
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Renders discussion text: lines indented by four spaces form paragraphs,
+/// and lines indented further, such as examples, are kept as they are
+fn render_text(lines: &[String], page: &mut String) {
+    let mut preformatted = false;
+    let mut paragraph_open = false;
+    for line in lines {
+        if line.is_empty() {
+            if preformatted {
+                page.push('\n');
+            } else {
+                paragraph_open = false;
+            }
+            continue;
+        }
+        if indentation(line) > 4 {
+            if !preformatted {
+                page.push_str(".RS 4\n.nf\n");
+                preformatted = true;
+            }
+            page.push_str(&escape(&line[indentation(line).min(8)..]));
+        } else {
+            if preformatted {
+                while page.ends_with("\n\n") {
+                    page.pop();
+                }
+                page.push_str(".fi\n.RE\n");
+                preformatted = false;
+                paragraph_open = false;
+            }
+            if !paragraph_open {
+                page.push_str(".PP\n");
+                paragraph_open = true;
+            }
+            page.push_str(&escape(line.trim_start()));
+        }
+        page.push('\n');
+    }
+    if preformatted {
+        while page.ends_with("\n\n") {
+            page.pop();
+        }
+        page.push_str(".fi\n.RE\n");
+    }
+}
+
+fn render(path: &[String], cmd: &Command) -> String {
+    let name = path.join("-");
+    let about = cmd.get_about().map(|a| a.to_string()).unwrap_or_default();
+    let mut page = format!(
+        ".TH \"{}\" \"1\" \"\" \"elan {}\" \"elan Manual\"\n.SH NAME\n{} \\- {}\n",
+        escape(&name.to_uppercase()),
+        env!("CARGO_PKG_VERSION"),
+        escape(&name),
+        escape(&about)
+    );
+
+    let usage = cmd.clone().render_usage().to_string();
+    page.push_str(".SH SYNOPSIS\n.nf\n");
+    for line in usage.trim_start_matches("Usage:").lines() {
+        page.push_str(&escape(line.trim()));
+        page.push('\n');
+    }
+    page.push_str(".fi\n");
+
+    let args: Vec<_> = cmd.get_arguments().filter(|a| !a.is_hide_set()).collect();
+    for (title, positional) in [("ARGUMENTS", true), ("OPTIONS", false)] {
+        let items: Vec<_> = args
+            .iter()
+            .filter(|a| a.is_positional() == positional)
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        page.push_str(&format!(".SH {}\n", title));
+        for arg in items {
+            page.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(&arg_item(arg))));
+            let description = arg_description(arg);
+            if !description.is_empty() {
+                page.push_str(&escape(&description));
+                page.push('\n');
+            }
+        }
+    }
+
+    let subcommands: Vec<_> = subcommands(cmd).collect();
+    if !subcommands.is_empty() {
+        page.push_str(".SH SUBCOMMANDS\n");
+        for sub in subcommands {
+            page.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(sub.get_name())));
+            let about = sub.get_about().map(|a| a.to_string()).unwrap_or_default();
+            page.push_str(&format!(
+                "{}; see \\fB{}\\-{}\\fR(1)\n",
+                escape(&about),
+                escape(&name),
+                escape(sub.get_name())
+            ));
+        }
+    }
+
+    let after_help = cmd
+        .get_after_help()
+        .map(|h| h.to_string())
+        .unwrap_or_default();
+    for section in parse_sections(&after_help) {
+        page.push_str(&format!(".SH {}\n", section.title));
+        render_text(&section.lines, &mut page);
+    }
+    if path.len() > 1 {
+        page.push_str(&format!(
+            ".SH \"SEE ALSO\"\n\\fB{}\\fR(1)\n",
+            escape(&path[..path.len() - 1].join("-"))
+        ));
+    }
+    page
+}
+
+/// The subcommands of `cmd` that have pages
+fn subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+}
+
+fn add_pages(path: &mut Vec<String>, cmd: &Command, pages: &mut Vec<(String, String)>) {
+    pages.push((path.join("-"), render(path, cmd)));
+    for sub in subcommands(cmd) {
+        path.push(sub.get_name().to_owned());
+        add_pages(path, sub, pages);
+        path.pop();
+    }
+}
+
+/// The name and roff source of the man page of each command, such as
+/// `elan-toolchain-install`, parents first
+pub fn pages() -> Vec<(String, String)> {
+    let mut cmd = cli().bin_name("elan");
+    cmd.build();
+    let mut pages = vec![];
+    add_pages(&mut vec!["elan".to_owned()], &cmd, &mut pages);
+    pages
+}
@@ -0,0 +1,71 @@
+//! Completion of `+toolchain` arguments to proxies, which are not part of
+//! the clap-generated completions for `elan` itself
+
+use crate::errors::*;
+use crate::self_update::TOOLS;
+use clap_complete::Shell;
+use elan::Cfg;
+use std::io::Write;
+
+/// Prints the toolchain names `+toolchain` completes to, one per line
+pub fn complete_toolchains(cfg: &Cfg) -> Result<()> {
+    let mut names: Vec<String> = ["stable", "beta", "nightly"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    for desc in cfg.list_toolchains()? {
+        let name = desc.to_string();
+        if let Some(release) = name.strip_prefix("leanprover/lean4:") {
+            names.push(release.to_owned());
+        }
+        names.push(name);
+    }
+    for name in names {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+static BASH: &str = r#"_elan_proxy() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ $COMP_CWORD -eq 1 && $cur == +* ]]; then
+        COMPREPLY=($(compgen -P + -W "$(elan complete-toolchains 2>/dev/null)" -- "${cur#+}"))
+    fi
+}
+complete -o default -F _elan_proxy {tools}
+"#;
+
+static ZSH: &str = r#"#compdef {tools}
+
+if (( CURRENT == 2 )) && compset -P '+'; then
+    local -a toolchains
+    toolchains=(${(f)"$(elan complete-toolchains 2>/dev/null)"})
+    compadd -a toolchains
+else
+    _files
+fi
+"#;
+
+static FISH: &str = r#"for tool in {tools}
+    complete -c $tool -n 'test (count (commandline -opc)) -eq 1; and string match -q -- "+*" (commandline -ct)' \
+        -a '(elan complete-toolchains 2>/dev/null | string replace -r "^" "+")'
+end
+"#;
+
+/// Writes the completion script for proxies for the given shell
+pub fn generate(shell: Shell, out: &mut dyn Write) -> Result<()> {
+    let script = match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+        _ => {
+            return Err(format!(
+                "completions for proxies are not available for {}; use bash, fish, or zsh",
+                shell
+            )
+            .into())
+        }
+    };
+    write!(out, "{}", script.replace("{tools}", &TOOLS.join(" ")))?;
+    Ok(())
+}
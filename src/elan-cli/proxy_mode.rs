@@ -1,57 +1,111 @@
-use crate::common::set_globals;
+use crate::common::{edit_distance, set_globals};
 use crate::errors::*;
 use crate::job;
-use elan::command::run_command_for_dir;
+use elan::command::{run_command_for_dir, ExitCode};
 use elan::{lookup_toolchain_desc, Cfg};
 use elan_utils::utils;
+use std::collections::HashSet;
 use std::env;
 use std::ffi::OsString;
 use std::path::PathBuf;
+use std::process;
 
 pub fn main() -> Result<()> {
-    crate::self_update::cleanup_self_updater()?;
+    let mut args = env::args_os();
 
-    let _setup = job::setup();
+    let arg0 = args.next().map(PathBuf::from);
+    let arg0 = match utils::elan_proxy_name() {
+        Some(name) => name,
+        None => arg0
+            .as_ref()
+            .and_then(|a| a.file_name())
+            .and_then(|a| a.to_str())
+            .ok_or(ErrorKind::NoExeName)?
+            .to_owned(),
+    };
 
-    let mut args = env::args();
+    run(&arg0, &args.collect::<Vec<_>>())
+}
 
-    let arg0 = args.next().map(PathBuf::from);
-    let arg0 = arg0
-        .as_ref()
-        .and_then(|a| a.file_name())
-        .and_then(|a| a.to_str());
-    let arg0 = arg0.ok_or(ErrorKind::NoExeName)?;
+/// Runs `tool` with the given arguments from the toolchain selected by a
+/// leading `+toolchain` argument or, by default, the active toolchain
+pub fn run(tool: &str, args: &[OsString]) -> Result<()> {
+    if !utils::elan_read_only() {
+        crate::self_update::cleanup_self_updater()?;
+    }
+    // Quietly, as the output of tools may be parsed
+    let _ = crate::self_update::heal_proxies();
 
     // Check for a toolchain specifier.
-    let arg1 = args.next();
-    let toolchain = arg1.as_ref().and_then(|arg1| {
-        if arg1.starts_with('+') {
-            Some(&arg1[1..])
-        } else {
-            None
-        }
-    });
+    let toolchain = args
+        .first()
+        .and_then(|arg1| arg1.to_str())
+        .and_then(|arg1| arg1.strip_prefix('+'));
 
     // Build command args now while we know whether or not to skip arg 1.
-    let cmd_args: Vec<_> = if toolchain.is_none() {
-        env::args_os().skip(1).collect()
+    let cmd_args = if toolchain.is_none() {
+        args
     } else {
-        env::args_os().skip(2).collect()
+        &args[1..]
     };
 
     let cfg = set_globals(false)?;
-    direct_proxy(&cfg, arg0, toolchain, &cmd_args)?;
+    let code = {
+        let _setup = job::setup();
+        direct_proxy(&cfg, tool, toolchain, cmd_args)?
+    };
 
-    Ok(())
+    // Only reached on Windows, once the processes left behind in our job
+    // object have been torn down at the end of the block above, which
+    // `process::exit` would skip
+    process::exit(code.0)
 }
 
-fn direct_proxy(cfg: &Cfg, arg0: &str, toolchain: Option<&str>, args: &[OsString]) -> Result<()> {
+fn direct_proxy(
+    cfg: &Cfg,
+    arg0: &str,
+    toolchain: Option<&str>,
+    args: &[OsString],
+) -> Result<ExitCode> {
     let cmd = match toolchain {
         None => cfg.create_command_for_dir(&utils::current_dir()?, arg0)?,
-        Some(tc) => {
-            let desc = lookup_toolchain_desc(cfg, tc)?;
-            cfg.create_command_for_toolchain(&desc, true, arg0)?
-        }
+        Some(tc) => lookup_toolchain_desc(cfg, tc)
+            .and_then(|desc| cfg.create_command_for_toolchain(&desc, true, arg0))
+            .or_else(|e| toolchain_spec_error(cfg, tc, e))?,
     };
     Ok(run_command_for_dir(cmd, arg0, args)?)
 }
+
+/// Points out likely typos in a `+toolchain` argument that could not be used
+fn toolchain_spec_error<T>(cfg: &Cfg, spec: &str, e: elan::Error) -> Result<T> {
+    let invalid = matches!(e.kind(), elan::ErrorKind::InvalidToolchainName(_));
+    let suggestions = similar_toolchains(cfg, spec);
+    if !invalid && suggestions.is_empty() {
+        return Err(e.into());
+    }
+    Err(e).chain_err(|| ErrorKind::InvalidToolchainSpec(spec.to_owned(), suggestions))
+}
+
+/// Channels and installed toolchains with names close to `spec`
+fn similar_toolchains(cfg: &Cfg, spec: &str) -> Vec<String> {
+    let mut candidates: Vec<String> = ["stable", "beta", "nightly"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+    for desc in cfg.list_toolchains().unwrap_or_default() {
+        let name = desc.to_string();
+        // Also offer releases of the default origin in their short form
+        if let Some(release) = name.strip_prefix("leanprover/lean4:") {
+            candidates.push(release.to_owned());
+        }
+        candidates.push(name);
+    }
+    let max_distance = if spec.len() < 4 { 1 } else { 2 };
+    // A linked toolchain may be named like a release of the default origin
+    let mut seen = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|c| c != spec && edit_distance(c, spec) <= max_distance)
+        .filter(|c| seen.insert(c.clone()))
+        .collect()
+}
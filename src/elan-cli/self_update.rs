@@ -30,19 +30,26 @@
 //! Deleting the running binary during uninstall is tricky
 //! and racy on Windows.
 
-use crate::common::{self, Confirm};
+use crate::common::{self, edit_distance, Confirm, YesArg};
 use crate::errors::*;
 use crate::term2;
+use elan::env_var;
 use elan::install;
 use elan::lookup_toolchain_desc;
 use elan::lookup_unresolved_toolchain_desc;
 use elan::Notification;
 use elan::Toolchain;
+use elan::{is_commit_hash, CHANNEL_ALIASES};
 use elan_dist::dist;
 use elan_dist::dist::ToolchainDesc;
-use elan_utils::utils;
+use elan_dist::manifest;
+use elan_utils::endpoints;
+use elan_utils::raw;
+use elan_utils::utils::{self, LinkMode};
 use regex::Regex;
 use same_file::Handle;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
 use std::env;
 use std::env::consts::EXE_SUFFIX;
 use std::fs;
@@ -54,6 +61,10 @@ use tempfile::tempdir;
 pub struct InstallOpts {
     pub default_toolchain: String,
     pub no_modify_path: bool,
+    /// Proxy authentication method to remember in the settings
+    pub proxy_auth: Option<String>,
+    /// Components to install into the default toolchain right away
+    pub components: Vec<String>,
 }
 
 // The big installation messages. These are macros because the first
@@ -160,6 +171,23 @@ environment variable. This has not been done automatically.
     };
 }
 
+macro_rules! pre_install_msg_system {
+    () => {
+        r"
+# Welcome to Lean!
+
+This will install Elan, a tool for managing different Lean versions, for all
+users of this system. Elan and its Lean toolchains will be installed to:
+
+    {prefix}
+
+Users can pick toolchains and overrides as usual, which are stored in their
+own home directories, but only root can install or uninstall toolchains.
+{path_msg}
+"
+    };
+}
+
 macro_rules! pre_uninstall_msg {
     () => {
         r"This will uninstall all Lean toolchains and data, and remove
@@ -169,7 +197,7 @@ macro_rules! pre_uninstall_msg {
     };
 }
 
-static TOOLS: &[&str] = &[
+pub static TOOLS: &[&str] = &[
     "lean",
     "leanpkg",
     "leanchecker",
@@ -178,12 +206,20 @@ static TOOLS: &[&str] = &[
     "lake",
 ];
 
-static UPDATE_ROOT: &str = "https://github.com/leanprover/elan/releases/download";
+/// The directory containing elan's `bin` directory: the root of the
+/// system-wide installation if elan is installed system-wide, otherwise
+/// `ELAN_HOME`
+pub fn install_home() -> Result<PathBuf> {
+    match utils::elan_system_home() {
+        Some(home) => Ok(home),
+        None => Ok(utils::elan_home()?),
+    }
+}
 
 /// `ELAN_HOME` suitable for display, possibly with $HOME
 /// substituted for the directory prefix
 fn canonical_elan_home() -> Result<String> {
-    let path = utils::elan_home()?;
+    let path = install_home()?;
     let mut path_str = path.to_string_lossy().to_string();
 
     let default_elan_home = utils::home_dir()
@@ -218,6 +254,10 @@ fn clean_up_old_state() -> Result<()> {
             }
         }
         let t = Toolchain::from(cfg, &tc);
+        if t.is_frozen() {
+            (cfg.notify_handler)(Notification::KeepingFrozenObsoleteToolchain(t.path()));
+            continue;
+        }
         (cfg.notify_handler)(Notification::UninstallingObsoleteToolchain(t.path()));
         install::uninstall(t.path(), &|n| (cfg.notify_handler)(n.into()))?;
     }
@@ -226,9 +266,9 @@ fn clean_up_old_state() -> Result<()> {
 
 /// Installing is a simple matter of coping the running binary to
 /// `ELAN_HOME`/bin, hardlinking the various Lean tools to it,
-/// and adding `ELAN_HOME`/bin to PATH.
-pub fn install(no_prompt: bool, verbose: bool, mut opts: InstallOpts) -> Result<()> {
-    check_existence_of_lean_in_path(no_prompt)?;
+/// and adding `ELAN_HOME`/bin to PATH. Returns whether PATH was modified.
+pub fn install(no_prompt: bool, verbose: bool, mut opts: InstallOpts) -> Result<bool> {
+    let system_lean = check_existence_of_lean_in_path(no_prompt)?;
     do_anti_sudo_check(no_prompt)?;
 
     if !no_prompt {
@@ -241,7 +281,7 @@ pub fn install(no_prompt: bool, verbose: bool, mut opts: InstallOpts) -> Result<
             match common::confirm_advanced()? {
                 Confirm::No => {
                     info!("aborting installation");
-                    return Ok(());
+                    return Ok(false);
                 }
                 Confirm::Yes => {
                     break;
@@ -255,14 +295,31 @@ pub fn install(no_prompt: bool, verbose: bool, mut opts: InstallOpts) -> Result<
 
     let install_res: Result<()> = (|| {
         install_bins()?;
+        record_proxy_auth(opts.proxy_auth.as_deref())?;
+        // Not essential, so don't let a group policy stop the installation
+        if let Err(e) = do_add_uninstall_entry() {
+            warn!(
+                "could not register elan in the list of installed programs: {}",
+                e
+            );
+        }
         if !opts.no_modify_path {
-            do_add_to_path(&get_add_path_methods())?;
+            match add_to_path_or_ask(no_prompt)? {
+                Some(changes) => record_path_changes(changes)?,
+                None => opts.no_modify_path = true,
+            }
         }
         if opts.default_toolchain != "none" {
             let cfg = &(common::set_globals(verbose)?);
             // sanity-check reference
-            let _ = lookup_toolchain_desc(cfg, &opts.default_toolchain)?;
+            let desc = lookup_toolchain_desc(cfg, &opts.default_toolchain)?;
             cfg.set_default(&opts.default_toolchain)?;
+            if !opts.components.is_empty() {
+                install_components(&cfg.get_toolchain(&desc, false)?, &opts.components)?;
+            }
+        }
+        if let Some(ref bin_path) = system_lean {
+            register_system_lean(bin_path)?;
         }
 
         if cfg!(unix) {
@@ -303,7 +360,56 @@ pub fn install(no_prompt: bool, verbose: bool, mut opts: InstallOpts) -> Result<
         term2::stdout().md(msg);
     }
 
-    Ok(())
+    Ok(!opts.no_modify_path)
+}
+
+/// Installs `components` into `toolchain`, installing the toolchain itself
+/// first if necessary
+fn install_components(toolchain: &Toolchain<'_>, components: &[String]) -> Result<()> {
+    let components: Vec<_> = components
+        .iter()
+        .map(|c| manifest::Component { pkg: c.to_owned() })
+        .collect();
+    if !toolchain.exists() {
+        toolchain.install_from_dist_ext(&components, true, None, false)?;
+        return Ok(());
+    }
+    Ok(toolchain.add_missing_components(&components)?)
+}
+
+/// Adds ELAN_HOME/bin to PATH. If that is not permitted, e.g. because of a
+/// group policy on Windows, offers to retry with administrator rights or to
+/// continue without modifying PATH, in which case `None` is returned.
+fn add_to_path_or_ask(no_prompt: bool) -> Result<Option<Vec<PathChange>>> {
+    match do_add_to_path(&get_add_path_methods()) {
+        Err(ref e) if matches!(e.kind(), ErrorKind::PermissionDenied) => {}
+        res => return res.map(Some),
+    }
+
+    let bin_path = utils::elan_home()?.join("bin");
+    warn!("elan is not permitted to modify the PATH variable, possibly because of a group policy");
+    if no_prompt {
+        return Err(ErrorKind::PathModificationDenied(bin_path).into());
+    }
+    if cfg!(windows)
+        && common::confirm(
+            "Retry with administrator rights? This requires an administrator account. (y/N)",
+            false,
+        )?
+    {
+        match do_add_to_path_elevated() {
+            Ok(changes) => return Ok(Some(changes)),
+            Err(ref e) => common::report_error(e),
+        }
+    }
+    if common::confirm("Continue without modifying PATH? (Y/n)", true)? {
+        info!(
+            "add '{}' to your PATH variable manually to use elan",
+            bin_path.display()
+        );
+        return Ok(None);
+    }
+    Err(ErrorKind::PathModificationDenied(bin_path).into())
 }
 
 fn lean_exists_in_path() -> Result<()> {
@@ -328,24 +434,70 @@ fn lean_exists_in_path() -> Result<()> {
     Ok(())
 }
 
-fn check_existence_of_lean_in_path(no_prompt: bool) -> Result<()> {
+/// Name of the linked toolchain an existing Lean installation is registered as
+const SYSTEM_TOOLCHAIN: &str = "system";
+
+/// Asks what to do about a Lean installation already in PATH, returning its
+/// `bin` directory if it should be registered as a toolchain after installation
+fn check_existence_of_lean_in_path(no_prompt: bool) -> Result<Option<PathBuf>> {
     // Only the test runner should set this
-    let skip_check = env::var_os("ELAN_INIT_SKIP_PATH_CHECK");
+    let skip_check = env::var_os(env_var::ELAN_INIT_SKIP_PATH_CHECK);
 
     // Ignore this check if called with no prompt (-y) or if the environment variable is set
     if no_prompt || skip_check == Some("yes".into()) {
-        return Ok(());
+        return Ok(None);
     }
 
-    if let Err(path) = lean_exists_in_path() {
-        err!("it looks like you have an existing installation of Lean at:");
-        err!("{}", path);
-        err!("elan cannot be installed alongside Lean. Please uninstall first");
-        err!("if this is what you want, restart the installation with `-y'");
-        Err("cannot install while Lean is installed".into())
-    } else {
-        Ok(())
+    let path = match lean_exists_in_path() {
+        Ok(()) => return Ok(None),
+        Err(path) => path.to_string(),
+    };
+    warn!("it looks like you have an existing installation of Lean at:");
+    warn!("{}", path);
+    println!();
+    println!("1) Continue; elan's toolchains will take precedence over it (default)");
+    println!(
+        "2) Continue and make it available as the `{}` toolchain",
+        SYSTEM_TOOLCHAIN
+    );
+    println!("3) Cancel installation");
+
+    let _ = io::Write::flush(&mut io::stdout());
+    let input = common::read_line()?;
+    println!();
+
+    match &*input {
+        "1" | "" => Ok(None),
+        "2" => Ok(Some(PathBuf::from(path))),
+        _ => Err("cannot install while Lean is installed".into()),
+    }
+}
+
+/// Registers the Lean installation with executables in `bin_path` as a
+/// linked toolchain
+fn register_system_lean(bin_path: &Path) -> Result<()> {
+    let cfg = &(common::set_globals(false)?);
+    let desc = ToolchainDesc::Local {
+        name: SYSTEM_TOOLCHAIN.to_owned(),
+    };
+    let toolchain = cfg.get_toolchain(&desc, true)?;
+    if toolchain.exists() {
+        warn!(
+            "a toolchain named `{}` already exists, not registering '{}'",
+            SYSTEM_TOOLCHAIN,
+            bin_path.display()
+        );
+        return Ok(());
     }
+    let prefix = bin_path.parent().unwrap_or(bin_path);
+    toolchain.install_from_prefix(prefix, TOOLS)?;
+    info!(
+        "the Lean installation in '{}' is available as the `{}` toolchain, e.g. via `elan default {}`",
+        prefix.display(),
+        SYSTEM_TOOLCHAIN,
+        SYSTEM_TOOLCHAIN
+    );
+    Ok(())
 }
 
 // If the user is trying to install with sudo, on some systems this will
@@ -363,7 +515,7 @@ fn do_anti_sudo_check(no_prompt: bool) -> Result<()> {
         use std::ptr;
 
         // test runner should set this, nothing else
-        if env::var("ELAN_INIT_SKIP_SUDO_CHECK")
+        if env::var(env_var::ELAN_INIT_SKIP_SUDO_CHECK)
             .as_ref()
             .map(Deref::deref)
             .ok()
@@ -486,10 +638,19 @@ fn customize_install(mut opts: InstallOpts) -> Result<InstallOpts> {
 
     println!();
 
-    opts.default_toolchain = common::question_str(
-        "Default toolchain? (stable/nightly/<specific version>/none)",
-        &opts.default_toolchain,
-    )?;
+    loop {
+        let toolchain = common::question_str(
+            "Default toolchain? (stable/nightly/<specific version>/none)",
+            &opts.default_toolchain,
+        )?;
+        match check_default_toolchain(&toolchain) {
+            Ok(()) => {
+                opts.default_toolchain = toolchain;
+                break;
+            }
+            Err(e) => err!("{}", e),
+        }
+    }
 
     opts.no_modify_path =
         !common::question_bool("Modify PATH variable? (y/n)", !opts.no_modify_path)?;
@@ -497,8 +658,62 @@ fn customize_install(mut opts: InstallOpts) -> Result<InstallOpts> {
     Ok(opts)
 }
 
+/// Rejects a default toolchain that cannot name a release before anything is
+/// installed, suggesting the closest channel or version. Releases of other
+/// origins may be arbitrary tags, so only near misses of a channel are
+/// rejected for them.
+pub fn check_default_toolchain(spec: &str) -> Result<()> {
+    if spec == "none" || spec.starts_with("git+") {
+        return Ok(());
+    }
+    let (origin, release) = match spec.split_once(':') {
+        Some((origin, release)) => (Some(origin), release),
+        None => (None, spec),
+    };
+    let invalid = |suggestion: Option<String>| {
+        Err(ErrorKind::InvalidDefaultToolchain(spec.to_owned(), suggestion).into())
+    };
+    let with_release = |release: &str| match origin {
+        Some(origin) => format!("{}:{}", origin, release),
+        None => release.to_owned(),
+    };
+
+    let channels = ["stable", "beta", "nightly", "lean-toolchain"]
+        .iter()
+        .copied()
+        .chain(CHANNEL_ALIASES.iter().map(|(alias, _)| *alias));
+    if channels.clone().any(|c| c == release)
+        || release.starts_with("nightly-")
+        || is_commit_hash(release)
+    {
+        return Ok(());
+    }
+    let version = release.strip_prefix('v').unwrap_or(release);
+    if version.starts_with(|c: char| c.is_ascii_digit()) {
+        if semver::Version::parse(version).is_ok() {
+            return Ok(());
+        }
+        // Complete abbreviated versions such as `4.9`
+        let suggestion = [".0", ".0.0"]
+            .iter()
+            .map(|suffix| format!("{}{}", version, suffix))
+            .find(|v| semver::Version::parse(v).is_ok())
+            .map(|v| with_release(&format!("v{}", v)));
+        return invalid(suggestion);
+    }
+    let suggestion = channels
+        .map(|c| (edit_distance(c, release), c))
+        .filter(|&(d, _)| d <= 2)
+        .min()
+        .map(|(_, c)| with_release(c));
+    if suggestion.is_some() || origin.is_none() {
+        return invalid(suggestion);
+    }
+    Ok(())
+}
+
 fn install_bins() -> Result<()> {
-    let bin_path = &utils::elan_home()?.join("bin");
+    let bin_path = &install_home()?.join("bin");
     let this_exe_path = &(utils::current_exe()?);
     let elan_path = &bin_path.join(format!("elan{}", EXE_SUFFIX));
 
@@ -510,20 +725,26 @@ fn install_bins() -> Result<()> {
     }
     utils::copy_file(this_exe_path, elan_path)?;
     utils::make_executable(elan_path)?;
-    install_proxies()
+    install_proxies()?;
+    Ok(())
 }
 
-pub fn install_proxies() -> Result<()> {
-    let bin_path = &utils::elan_home()?.join("bin");
+/// Links all tools to the elan executable, returning the names of the tools
+/// that were missing or not linked correctly
+pub fn install_proxies() -> Result<Vec<&'static str>> {
+    let bin_path = &install_home()?.join("bin");
     let elan_path = &bin_path.join(format!("elan{}", EXE_SUFFIX));
 
     let elan = Handle::from_path(elan_path)?;
+    let mode = proxy_link_mode()?;
 
     let mut tool_handles = Vec::new();
     let mut link_afterwards = Vec::new();
 
     // Try to hardlink all the Lean exes to the elan exe. Some systems,
-    // like Android, does not support hardlinks, so we fallback to symlinks.
+    // like Android, does not support hardlinks, so we fallback to symlinks,
+    // and where those are forbidden as well, to copies. The
+    // `proxy_link_mode` setting can restrict this to one method.
     //
     // Note that this function may not be running in the context of a fresh
     // self update but rather as part of a normal update to fill in missing
@@ -545,31 +766,536 @@ pub fn install_proxies() -> Result<()> {
     // overwrite all the previous hard links with new ones.
     for tool in TOOLS {
         let tool_path = bin_path.join(format!("{}{}", tool, EXE_SUFFIX));
+        let mut linked = false;
         if let Ok(handle) = Handle::from_path(&tool_path) {
             tool_handles.push(handle);
-            if elan == *tool_handles.last().unwrap() {
-                continue;
-            }
+            linked = elan == *tool_handles.last().unwrap();
+        }
+        let is_symlink = fs::symlink_metadata(&tool_path)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        // Tools linked differently than `mode` asks for are relinked, and
+        // copies are only replaced when elan itself changes
+        let up_to_date = match mode {
+            LinkMode::Auto => linked || same_contents(elan_path, &tool_path),
+            LinkMode::Hardlink => linked && !is_symlink,
+            LinkMode::Symlink => linked && is_symlink,
+            LinkMode::Copy => !linked && same_contents(elan_path, &tool_path),
+        };
+        if up_to_date {
+            continue;
         }
-        link_afterwards.push(tool_path);
+        link_afterwards.push((*tool, tool_path));
     }
 
     drop(tool_handles);
-    for path in link_afterwards {
-        utils::hard_or_symlink_file(elan_path, &path)?;
+    for (_, path) in &link_afterwards {
+        utils::link_file(elan_path, path, mode)?;
+    }
+
+    Ok(link_afterwards.into_iter().map(|(tool, _)| tool).collect())
+}
+
+/// Recreates the proxies in `ELAN_HOME/bin` if any of them look missing or
+/// outdated, e.g. because the user deleted one, returning the names of the
+/// tools that were relinked. Cheap enough to run on every invocation.
+pub fn heal_proxies() -> Result<Vec<&'static str>> {
+    if utils::elan_read_only() {
+        return Ok(Vec::new());
+    }
+    let bin_path = install_home()?.join("bin");
+    if !proxies_look_broken(&bin_path) || !raw::is_writable_dir(&bin_path) {
+        return Ok(Vec::new());
+    }
+    install_proxies()
+}
+
+/// Whether any proxy in `bin_path` is missing or differs from the elan
+/// executable in size, inode, or modification time. Hard links share the
+/// metadata of elan and symbolic links are followed, so only proxies that
+/// were removed, replaced, or left behind by an update of elan are flagged;
+/// copies are newer than elan or, on Windows, have the same time.
+fn proxies_look_broken(bin_path: &Path) -> bool {
+    let elan = match fs::metadata(bin_path.join(format!("elan{}", EXE_SUFFIX))) {
+        Ok(elan) => elan,
+        // Not installed here
+        Err(_) => return false,
+    };
+    TOOLS.iter().any(|tool| {
+        let tool = match fs::metadata(bin_path.join(format!("{}{}", tool, EXE_SUFFIX))) {
+            Ok(tool) => tool,
+            Err(_) => return true,
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            if (tool.dev(), tool.ino()) == (elan.dev(), elan.ino()) {
+                return false;
+            }
+        }
+        tool.len() != elan.len() || tool.modified().ok() < elan.modified().ok()
+    })
+}
+
+/// How proxies are linked, configured by the `proxy_link_mode` setting
+fn proxy_link_mode() -> Result<LinkMode> {
+    let cfg = &(common::set_globals(false)?);
+    let mode = cfg.settings_file.with(|s| Ok(s.proxy_link_mode.clone()))?;
+    match mode.as_deref() {
+        None => Ok(LinkMode::Auto),
+        Some(name) => LinkMode::from_name(name).ok_or_else(|| {
+            format!(
+                "invalid `proxy_link_mode` setting '{}'; expected 'auto', 'hardlink', 'symlink', or 'copy'",
+                name
+            )
+            .into()
+        }),
+    }
+}
+
+fn same_contents(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(ma), Ok(mb)) if ma.len() == mb.len() => {}
+        _ => return false,
+    }
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether elan has been installed to `ELAN_HOME` before, even if other
+/// parts of the installation are missing
+pub fn is_installed() -> Result<bool> {
+    let elan_path = install_home()?
+        .join("bin")
+        .join(format!("elan{}", EXE_SUFFIX));
+    Ok(elan_path.exists())
+}
+
+/// Fixes an existing installation in place: replaces the elan executable
+/// with this one and relinks its proxies, re-adds PATH entries that went
+/// missing, and finishes interrupted toolchain installations. Settings and
+/// toolchains are kept;
+/// the default toolchain is only changed if `default_toolchain` is given.
+/// `components` are installed into the resulting default toolchain. Returns
+/// whether PATH was modified.
+pub fn repair(
+    no_prompt: bool,
+    verbose: bool,
+    no_modify_path: bool,
+    default_toolchain: Option<&str>,
+    proxy_auth: Option<&str>,
+    components: &[String],
+) -> Result<bool> {
+    let mut fixed = vec![];
+    let mut path_modified = false;
+    record_proxy_auth(proxy_auth)?;
+
+    let bin_path = install_home()?.join("bin");
+    let elan_path = bin_path.join(format!("elan{}", EXE_SUFFIX));
+    if !elan_path.exists() {
+        install_bins()?;
+        fixed.push(format!("restored '{}'", elan_path.display()));
+    } else if !same_contents(&utils::current_exe()?, &elan_path) {
+        // Re-running the installer is a common way to upgrade elan
+        install_bins()?;
+        info!(
+            "replaced '{}' with this version of elan",
+            elan_path.display()
+        );
+    } else {
+        let relinked = install_proxies()?;
+        if !relinked.is_empty() {
+            fixed.push(format!("relinked {}", relinked.join(", ")));
+        }
+    }
+
+    if !no_modify_path && utils::elan_system_home().is_none() && !path_configured()? {
+        if let Some(changes) = add_to_path_or_ask(no_prompt)? {
+            record_path_changes(changes)?;
+            path_modified = true;
+            fixed.push(format!("added '{}' to PATH", bin_path.display()));
+        }
+    }
+
+    if cfg!(unix) {
+        let env_file = &utils::elan_home()?.join("env");
+        if !env_file.exists() {
+            let env_str = &format!("{}\n", shell_export_string()?);
+            utils::write_file("env", env_file, env_str)?;
+            fixed.push(format!("restored '{}'", env_file.display()));
+        }
     }
 
+    // Keep the entry up to date with the installed version, but don't count
+    // this as a fix
+    if let Err(e) = do_add_uninstall_entry() {
+        warn!(
+            "could not register elan in the list of installed programs: {}",
+            e
+        );
+    }
+
+    let cfg = &(common::set_globals(verbose)?);
+    cfg.recover_interrupted_installs()?;
+    if let Some(toolchain) = default_toolchain {
+        if toolchain != "none" {
+            // sanity-check reference
+            let _ = lookup_toolchain_desc(cfg, toolchain)?;
+            cfg.set_default(toolchain)?;
+        }
+    }
+    if !components.is_empty() {
+        let desc = cfg
+            .resolve_default()?
+            .ok_or(ErrorKind::ComponentsWithoutDefaultToolchain)?;
+        install_components(&cfg.get_toolchain(&desc, false)?, components)?;
+    }
+
+    if fixed.is_empty() {
+        info!("nothing to repair");
+    } else {
+        for f in &fixed {
+            info!("{}", f);
+        }
+        info!("elan installation repaired");
+    }
+
+    Ok(path_modified)
+}
+
+/// Whether ELAN_HOME/bin is on PATH through the changes recorded at
+/// installation or, for installations that predate recording, through any
+/// of the changes elan would make now
+fn path_configured() -> Result<bool> {
+    let cfg = &(common::set_globals(false)?);
+    let recorded: Vec<PathChange> = cfg.settings_file.with(|s| {
+        Ok(s.path_changes
+            .iter()
+            .flatten()
+            .map(|(k, v)| (PathUpdateMethod::from_settings_key(k), v.clone()))
+            .collect())
+    })?;
+    if !recorded.is_empty() {
+        for (method, inserted) in &recorded {
+            if !path_change_in_effect(method, inserted)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+
+    let inserted = if cfg!(windows) {
+        utils::elan_home()?
+            .join("bin")
+            .to_string_lossy()
+            .to_string()
+    } else {
+        shell_export_string()?
+    };
+    for method in get_add_path_methods() {
+        if path_change_in_effect(&method, &inserted)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn path_change_in_effect(method: &PathUpdateMethod, inserted: &str) -> Result<bool> {
+    match *method {
+        PathUpdateMethod::RcFile(ref rcpath) => {
+            Ok(rcpath.is_file() && utils::read_file("rcfile", rcpath)?.contains(inserted))
+        }
+        PathUpdateMethod::Windows => windows_path_contains(inserted),
+    }
+}
+
+#[cfg(windows)]
+fn windows_path_contains(path: &str) -> Result<bool> {
+    // Unreadable values are left alone, so don't try to fix them either
+    match get_windows_path_var()? {
+        Some(old_path) => Ok(path_var_contains(&old_path, path)),
+        None => Ok(true),
+    }
+}
+
+#[cfg(unix)]
+fn windows_path_contains(_path: &str) -> Result<bool> {
+    unreachable!()
+}
+
+/// Whether `entry` is one of the entries of the `;`-separated `path_var`
+#[cfg(windows)]
+fn path_var_contains(path_var: &str, entry: &str) -> bool {
+    path_var.split(';').any(|p| p == entry)
+}
+
+/// Shell configuration written by `elan-init --system`
+const SYSTEM_PROFILE_SNIPPET: &str = "/etc/profile.d/elan.sh";
+/// First line of `SYSTEM_PROFILE_SNIPPET`, telling it apart from a file of
+/// the same name written by someone else
+const SYSTEM_PROFILE_MARKER: &str = "# Added by elan-init --system";
+
+/// Whether `SYSTEM_PROFILE_SNIPPET` exists and was written by elan
+fn system_profile_is_ours() -> bool {
+    fs::read_to_string(SYSTEM_PROFILE_SNIPPET)
+        .is_ok_and(|s| s.lines().next() == Some(SYSTEM_PROFILE_MARKER))
+}
+
+/// Installs elan and its toolchains to `prefix` for all users of the system.
+/// Returns whether PATH was modified.
+#[cfg(unix)]
+pub fn install_system(
+    no_prompt: bool,
+    verbose: bool,
+    prefix: &Path,
+    opts: InstallOpts,
+) -> Result<bool> {
+    if unsafe { libc::geteuid() } != 0 {
+        return Err(ErrorKind::SystemInstallNeedsRoot.into());
+    }
+    let profile = Path::new(SYSTEM_PROFILE_SNIPPET);
+    if !opts.no_modify_path && profile.exists() && !system_profile_is_ours() {
+        return Err(ErrorKind::ForeignSystemProfile(profile.to_owned()).into());
+    }
+    let system_lean = check_existence_of_lean_in_path(no_prompt)?;
+    let prefix = env::current_dir()?.join(prefix);
+
+    if !no_prompt {
+        let path_msg = if opts.no_modify_path {
+            format!(
+                "\n`{}/bin` needs to be in the `PATH` environment variable of all\nusers, but will not be added automatically.",
+                prefix.display()
+            )
+        } else {
+            format!(
+                "\n`{}/bin` will be added to the `PATH` environment variable of all\nusers by creating `{}`.",
+                prefix.display(),
+                SYSTEM_PROFILE_SNIPPET
+            )
+        };
+        term2::stdout().md(format!(
+            pre_install_msg_system!(),
+            prefix = prefix.display(),
+            path_msg = path_msg
+        ));
+        term2::stdout().md(current_install_opts(&opts));
+        if !common::confirm("\nContinue? (Y/n)", true)? {
+            info!("aborting installation");
+            return Ok(false);
+        }
+    }
+
+    // Direct all of the following steps to the prefix
+    env::set_var(env_var::ELAN_SYSTEM_HOME, &prefix);
+
+    let install_res: Result<()> = (|| {
+        utils::ensure_dir_exists("prefix", &prefix, &|_| {})?;
+        utils::write_file(
+            "system install marker",
+            &prefix.join(utils::SYSTEM_INSTALL_MARKER),
+            "# This directory contains a system-wide elan installation\n",
+        )?;
+        install_bins()?;
+        record_proxy_auth(opts.proxy_auth.as_deref())?;
+        if !opts.no_modify_path {
+            let snippet = format!(
+                "{}\nexport PATH=\"{}/bin:$PATH\"\n",
+                SYSTEM_PROFILE_MARKER,
+                prefix.display()
+            );
+            utils::write_file("profile", profile, &snippet)?;
+        }
+        if opts.default_toolchain != "none" {
+            let cfg = &(common::set_globals(verbose)?);
+            // Users cannot install toolchains themselves, so install the
+            // default one right away and pin it to the resolved release
+            let desc = lookup_toolchain_desc(cfg, &opts.default_toolchain)?;
+            let toolchain = cfg.get_toolchain(&desc, false)?;
+            if opts.components.is_empty() {
+                toolchain.install_from_dist_if_not_installed()?;
+            } else {
+                install_components(&toolchain, &opts.components)?;
+            }
+            let system_settings = cfg
+                .system_settings_file
+                .as_ref()
+                .expect("system installation without settings");
+            system_settings.with_mut(|s| {
+                s.default_toolchain = Some(desc.to_string());
+                Ok(())
+            })?;
+        }
+        if let Some(ref bin_path) = system_lean {
+            register_system_lean(bin_path)?;
+        }
+        Ok(())
+    })();
+
+    if let Err(ref e) = install_res {
+        common::report_error(e);
+
+        process::exit(1);
+    }
+
+    info!("elan is installed for all users at '{}'", prefix.display());
+    if !opts.no_modify_path {
+        info!("users need to log in again for the PATH change to take effect");
+    }
+
+    Ok(!opts.no_modify_path)
+}
+
+#[cfg(windows)]
+pub fn install_system(_: bool, _: bool, _: &Path, _: InstallOpts) -> Result<bool> {
+    Err("`--system` is only supported on Unix".into())
+}
+
+fn package_manager_hint(action: &str) -> String {
+    match elan::install::INSTALL_METHOD {
+        Some(method) => format!(
+            "elan was installed using {}; use it to {} elan",
+            method, action
+        ),
+        None => format!(
+            "you should probably use your system package manager to {} elan",
+            action
+        ),
+    }
+}
+
+/// How and from where the running elan was installed, as reported by
+/// `elan self which-installation`
+#[derive(Serialize)]
+struct Installation {
+    executable: Option<PathBuf>,
+    elan_home: PathBuf,
+    /// Whether `executable` is `elan_home/bin/elan`
+    in_elan_home: bool,
+    install_method: String,
+    version: &'static str,
+    /// Short hash and date of the commit elan was built from, if known
+    commit: Option<String>,
+    update_root: String,
+    /// Where `update_root` comes from: `environment`, `settings`, or
+    /// `default`
+    update_root_source: &'static str,
+    self_update: bool,
+}
+
+impl Installation {
+    fn detect() -> Result<Self> {
+        let elan_home = install_home()?;
+        let executable = env::current_exe().ok();
+        let installed = elan_home.join(format!("bin/elan{}", EXE_SUFFIX));
+        let in_elan_home = executable
+            .as_ref()
+            .is_some_and(|exe| same_file::is_same_file(exe, &installed).unwrap_or(false));
+        let install_method = if let Some(method) = install::INSTALL_METHOD {
+            format!("package manager ({})", method)
+        } else if cfg!(feature = "msi-installed") {
+            "MSI package".to_owned()
+        } else if install::NEVER_SELF_UPDATE {
+            "custom build without self-update".to_owned()
+        } else if utils::elan_system_home().is_some() {
+            "elan-init (system-wide)".to_owned()
+        } else {
+            "elan-init".to_owned()
+        };
+        let commit = common::version()
+            .strip_prefix(env!("CARGO_PKG_VERSION"))
+            .map(|info| info.trim().trim_start_matches('(').trim_end_matches(')'))
+            .filter(|info| !info.is_empty())
+            .map(str::to_owned);
+        let (update_root, source) = endpoints::UPDATE_ROOT.resolve();
+        Ok(Installation {
+            executable,
+            elan_home,
+            in_elan_home,
+            install_method,
+            version: env!("CARGO_PKG_VERSION"),
+            commit,
+            update_root,
+            update_root_source: match source {
+                endpoints::Source::Env => "environment",
+                endpoints::Source::Setting => "settings",
+                endpoints::Source::Default => "default",
+            },
+            self_update: !install::NEVER_SELF_UPDATE,
+        })
+    }
+}
+
+/// Reports how and from where the running elan was installed, as JSON if
+/// `json` is set
+pub fn which_installation(json: bool) -> Result<()> {
+    let installation = Installation::detect()?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&installation).chain_err(|| "failed to print JSON")?
+        );
+        return Ok(());
+    }
+    let executable = match &installation.executable {
+        Some(exe) => exe.display().to_string(),
+        None => "(unknown)".to_owned(),
+    };
+    println!("executable:     {}", executable);
+    println!(
+        "elan home:      {} ({})",
+        installation.elan_home.display(),
+        if installation.in_elan_home {
+            "contains this executable"
+        } else {
+            "does not contain this executable"
+        }
+    );
+    println!("install method: {}", installation.install_method);
+    println!("version:        {}", installation.version);
+    println!(
+        "commit:         {}",
+        installation.commit.as_deref().unwrap_or("(unknown)")
+    );
+    println!(
+        "update root:    {}{}",
+        installation.update_root,
+        match installation.update_root_source {
+            "environment" => " (from ELAN_UPDATE_ROOT)",
+            "settings" => " (from the update_root setting)",
+            _ => "",
+        }
+    );
+    println!(
+        "self-update:    {}",
+        if installation.self_update {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    );
     Ok(())
 }
 
-pub fn uninstall(no_prompt: bool) -> Result<()> {
+/// Uninstalls elan, or with `dry_run` only reports what would be deleted or
+/// edited, as JSON if `json` is set
+pub fn uninstall(no_prompt: bool, dry_run: bool, json: bool) -> Result<()> {
     if elan::install::NEVER_SELF_UPDATE {
         err!("self-uninstall is disabled for this build of elan");
-        err!("you should probably use your system package manager to uninstall elan");
+        match elan::install::package_manager_commands() {
+            Some(commands) => err!("run `{}` to uninstall elan", commands.uninstall),
+            None => err!("{}", package_manager_hint("uninstall")),
+        }
         process::exit(1);
     }
 
     if cfg!(feature = "msi-installed") {
+        if dry_run {
+            return Err(
+                "`--dry-run` is not supported for installations from an MSI package".into(),
+            );
+        }
         // Get the product code of the MSI installer from the registry
         // and spawn `msiexec /x`, then exit immediately
         let product_code = get_msi_product_code()?;
@@ -581,11 +1307,34 @@ pub fn uninstall(no_prompt: bool) -> Result<()> {
         process::exit(0);
     }
 
-    let elan_home = &(utils::elan_home()?);
+    let elan_home = &(install_home()?);
+    let system = utils::elan_system_home().is_some();
 
     if !elan_home.join(format!("bin/elan{}", EXE_SUFFIX)).exists() {
         return Err(ErrorKind::NotSelfInstalled(elan_home.clone()).into());
     }
+    if dry_run {
+        let plan = uninstall_plan(elan_home, system)?;
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&plan).chain_err(|| "failed to print JSON")?
+            );
+        } else {
+            plan.print();
+        }
+        return Ok(());
+    }
+    if system && !utils::is_writable_dir(elan_home) {
+        return Err(ErrorKind::SystemInstallNeedsRoot.into());
+    }
+    if system {
+        // The marker file identifying the installation is deleted below
+        env::set_var(env_var::ELAN_SYSTEM_HOME, elan_home);
+    }
+
+    // Read before the settings file is deleted along with ELAN_HOME
+    let strategy = self_delete_strategy()?;
 
     if !no_prompt {
         println!();
@@ -602,8 +1351,21 @@ pub fn uninstall(no_prompt: bool) -> Result<()> {
     info!("removing elan home");
 
     // Remove ELAN_HOME/bin from PATH
-    let remove_path_methods = &(get_remove_path_methods()?);
-    do_remove_from_path(remove_path_methods)?;
+    if system {
+        let profile = Path::new(SYSTEM_PROFILE_SNIPPET);
+        if system_profile_is_ours() {
+            utils::remove_file("profile", profile)?;
+        } else if profile.exists() {
+            warn!(
+                "keeping '{}', which was not written by elan-init",
+                profile.display()
+            );
+        }
+    } else {
+        let path_changes = &(get_remove_path_changes()?);
+        do_remove_from_path(path_changes)?;
+        do_remove_registrations(&get_registrations()?)?;
+    }
 
     // Delete everything in ELAN_HOME *except* the elan bin
 
@@ -641,13 +1403,131 @@ pub fn uninstall(no_prompt: bool) -> Result<()> {
     // Delete elan. This is tricky because this is *probably*
     // the running executable and on Windows can't be unlinked until
     // the process exits.
-    delete_elan_and_elan_home()?;
+    delete_elan_and_elan_home(strategy)?;
 
     info!("elan is uninstalled");
 
     process::exit(0);
 }
 
+/// Everything `elan self uninstall` would delete or edit, as reported by
+/// `--dry-run`
+#[derive(Serialize)]
+struct UninstallPlan {
+    elan_home: PathBuf,
+    /// Files and directories to delete, in order; directories are deleted
+    /// with all their contents
+    removed_paths: Vec<PathBuf>,
+    /// Lines to remove from shell configuration files
+    edited_files: Vec<FileEdit>,
+    /// Windows registry keys to delete with all their values
+    removed_registry_keys: Vec<String>,
+    /// Entries to remove from Windows registry values
+    edited_registry_values: Vec<RegistryEdit>,
+}
+
+#[derive(Serialize)]
+struct FileEdit {
+    path: PathBuf,
+    removed_line: String,
+}
+
+#[derive(Serialize)]
+struct RegistryEdit {
+    key: String,
+    value: String,
+    removed_entry: String,
+}
+
+impl UninstallPlan {
+    fn print(&self) {
+        println!("Uninstalling elan would");
+        for path in &self.removed_paths {
+            println!("- delete '{}'", path.display());
+        }
+        for edit in &self.edited_files {
+            println!(
+                "- remove the line `{}` from '{}'",
+                edit.removed_line,
+                edit.path.display()
+            );
+        }
+        for key in &self.removed_registry_keys {
+            println!("- delete the registry key '{}'", key);
+        }
+        for edit in &self.edited_registry_values {
+            println!(
+                "- remove '{}' from the registry value '{}\\{}'",
+                edit.removed_entry, edit.key, edit.value
+            );
+        }
+    }
+}
+
+fn uninstall_plan(elan_home: &Path, system: bool) -> Result<UninstallPlan> {
+    let mut plan = UninstallPlan {
+        elan_home: elan_home.to_owned(),
+        removed_paths: vec![],
+        edited_files: vec![],
+        removed_registry_keys: vec![],
+        edited_registry_values: vec![],
+    };
+    if system {
+        if system_profile_is_ours() {
+            plan.removed_paths
+                .push(PathBuf::from(SYSTEM_PROFILE_SNIPPET));
+        }
+    } else {
+        for (method, inserted) in get_remove_path_changes()? {
+            match method {
+                PathUpdateMethod::RcFile(rcpath) => {
+                    let addition = format!("\n{}\n", inserted);
+                    if rcpath.exists() && utils::read_file("rcfile", &rcpath)?.contains(&addition) {
+                        plan.edited_files.push(FileEdit {
+                            path: rcpath,
+                            removed_line: inserted,
+                        });
+                    }
+                }
+                PathUpdateMethod::Windows => {
+                    if windows_path_contains(&inserted)? {
+                        let (key, value) = WINDOWS_PATH_KEY.rsplit_once('\\').unwrap();
+                        plan.edited_registry_values.push(RegistryEdit {
+                            key: key.to_owned(),
+                            value: value.to_owned(),
+                            removed_entry: inserted,
+                        });
+                    }
+                }
+            }
+        }
+        for key in get_registrations()?.into_keys() {
+            plan.removed_registry_keys
+                .push(format!(r"HKEY_CURRENT_USER\{}", key));
+        }
+    }
+
+    // Like `uninstall`, first everything except the bin directory, then the
+    // rest of ELAN_HOME
+    let read_dir = |dir: &Path| -> Result<Vec<PathBuf>> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|entries| {
+                entries
+                    .map(|e| e.map(|e| e.path()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .chain_err(|| "failure reading directory")?;
+        entries.sort();
+        Ok(entries)
+    };
+    let bin_path = elan_home.join("bin");
+    plan.removed_paths
+        .extend(read_dir(elan_home)?.into_iter().filter(|p| *p != bin_path));
+    plan.removed_paths.extend(read_dir(&bin_path)?);
+    plan.removed_paths.push(elan_home.to_owned());
+    Ok(plan)
+}
+
 #[cfg(not(feature = "msi-installed"))]
 fn get_msi_product_code() -> Result<String> {
     unreachable!()
@@ -670,9 +1550,39 @@ fn get_msi_product_code() -> Result<String> {
     }
 }
 
+/// How elan removes or replaces its own running executable on Windows,
+/// configured by the `self_delete_strategy` setting
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SelfDeleteStrategy {
+    /// Use `DeleteOnClose`, falling back to `Script` if that fails, e.g.
+    /// because antivirus software blocked the copied executable
+    Auto,
+    /// Run a copy of elan that deletes itself via FILE_FLAG_DELETE_ON_CLOSE
+    DeleteOnClose,
+    /// Let a batch script wait for elan to exit and then finish the job
+    Script,
+}
+
+fn self_delete_strategy() -> Result<SelfDeleteStrategy> {
+    let cfg = &(common::set_globals(false)?);
+    let strategy = cfg
+        .settings_file
+        .with(|s| Ok(s.self_delete_strategy.clone()))?;
+    match strategy.as_deref() {
+        None | Some("auto") => Ok(SelfDeleteStrategy::Auto),
+        Some("delete-on-close") => Ok(SelfDeleteStrategy::DeleteOnClose),
+        Some("script") => Ok(SelfDeleteStrategy::Script),
+        Some(s) => Err(format!(
+            "invalid `self_delete_strategy` setting '{}'; expected 'auto', 'delete-on-close', or 'script'",
+            s
+        )
+        .into()),
+    }
+}
+
 #[cfg(unix)]
-fn delete_elan_and_elan_home() -> Result<()> {
-    let elan_home = &(utils::elan_home()?);
+fn delete_elan_and_elan_home(_: SelfDeleteStrategy) -> Result<()> {
+    let elan_home = &(install_home()?);
     utils::remove_dir("elan_home", elan_home, &|_| ())?;
 
     Ok(())
@@ -708,13 +1618,78 @@ fn delete_elan_and_elan_home() -> Result<()> {
 //
 // .. augmented with this SO answer
 // http://stackoverflow.com/questions/10319526/understanding-a-self-deleting-program-in-c
+//
+// Some endpoint protection software considers this dance suspicious and
+// blocks or quarantines the gc exe, in which case we fall back to a plain
+// batch script that waits for us to exit (see `run_helper_script`).
+#[cfg(windows)]
+fn delete_elan_and_elan_home(strategy: SelfDeleteStrategy) -> Result<()> {
+    let elan_home = install_home()?;
+    let remove_home = format!("rmdir /S /Q \"{}\"", elan_home.display());
+    match strategy {
+        SelfDeleteStrategy::DeleteOnClose => delete_on_close(),
+        SelfDeleteStrategy::Script => run_helper_script("uninstall", &remove_home),
+        SelfDeleteStrategy::Auto => delete_on_close().or_else(|e| {
+            warn!("could not start the uninstaller, possibly because of antivirus software");
+            common::report_error(&e);
+            info!("falling back to uninstalling with a helper script");
+            run_helper_script("uninstall", &remove_home)
+        }),
+    }
+}
+
+/// Writes a batch script to the directory containing ELAN_HOME that waits for
+/// this process to exit, then runs `command` and deletes itself, and starts
+/// it in the background.
+#[cfg(windows)]
+fn run_helper_script(name: &str, command: &str) -> Result<()> {
+    use std::os::windows::process::CommandExt;
+    use std::process::Stdio;
+    use winapi::um::winbase::{CREATE_NO_WINDOW, DETACHED_PROCESS};
+
+    let elan_home = install_home()?;
+    let work_path = elan_home
+        .parent()
+        .expect("ELAN_HOME doesn't have a parent?");
+    let numbah: u32 = rand::random();
+    let script = work_path.join(&format!("elan-{}-{:x}.cmd", name, numbah));
+
+    let pid = process::id();
+    let contents = format!(
+        "@echo off\r\n\
+         :wait\r\n\
+         tasklist /FI \"PID eq {pid}\" 2>NUL | find \"{pid}\" >NUL\r\n\
+         if not errorlevel 1 (\r\n\
+         \x20   timeout /T 1 /NOBREAK >NUL\r\n\
+         \x20   goto wait\r\n\
+         )\r\n\
+         {command}\r\n\
+         (goto) 2>NUL & del \"%~f0\"\r\n",
+        pid = pid,
+        command = command
+    );
+    utils::write_file("helper script", &script, &contents)?;
+
+    Command::new("cmd")
+        .arg("/C")
+        .arg(&script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .creation_flags(CREATE_NO_WINDOW | DETACHED_PROCESS)
+        .spawn()
+        .chain_err(|| ErrorKind::WindowsUninstallMadness)?;
+
+    Ok(())
+}
+
 #[cfg(windows)]
-fn delete_elan_and_elan_home() -> Result<()> {
+fn delete_on_close() -> Result<()> {
     use std::thread;
     use std::time::Duration;
 
     // ELAN_HOME, hopefully empty except for bin/elan.exe
-    let ref elan_home = utils::elan_home()?;
+    let ref elan_home = install_home()?;
     // The elan.exe bin
     let ref elan_path = elan_home.join(&format!("bin/elan{}", EXE_SUFFIX));
 
@@ -771,7 +1746,7 @@ fn delete_elan_and_elan_home() -> Result<()> {
             let _ = CloseHandle(h);
         });
 
-        Command::new(gc_exe)
+        let mut gc = Command::new(&gc_exe)
             .spawn()
             .chain_err(|| ErrorKind::WindowsUninstallMadness)?;
 
@@ -783,6 +1758,15 @@ fn delete_elan_and_elan_home() -> Result<()> {
 
         // (.. and months later acrichto owes me a beer).
         thread::sleep(Duration::from_millis(100));
+
+        // The gc exe waits for us to exit, so if it is already gone, it was
+        // most likely killed by antivirus software
+        if let Ok(Some(status)) = gc.try_wait() {
+            return Err(
+                Error::from(format!("uninstaller exited prematurely with {}", status))
+                    .chain_err(|| ErrorKind::WindowsUninstallMadness),
+            );
+        }
     }
 
     Ok(())
@@ -797,7 +1781,7 @@ pub fn complete_windows_uninstall() -> Result<()> {
     wait_for_parent()?;
 
     // Now that the parent has exited there are hopefully no more files open in ELAN_HOME
-    let ref elan_home = utils::elan_home()?;
+    let ref elan_home = install_home()?;
     utils::remove_dir("elan_home", elan_home, &|_| ())?;
 
     // Now, run a *system* binary to inherit the DELETE_ON_CLOSE
@@ -900,6 +1884,94 @@ enum PathUpdateMethod {
     Windows,
 }
 
+/// A PATH update method together with the exact text elan inserted
+type PathChange = (PathUpdateMethod, String);
+
+const WINDOWS_PATH_KEY: &str = r"HKEY_CURRENT_USER\Environment\PATH";
+
+impl PathUpdateMethod {
+    fn settings_key(&self) -> String {
+        match *self {
+            PathUpdateMethod::RcFile(ref path) => path.display().to_string(),
+            PathUpdateMethod::Windows => WINDOWS_PATH_KEY.to_string(),
+        }
+    }
+
+    fn from_settings_key(key: &str) -> Self {
+        if key == WINDOWS_PATH_KEY {
+            PathUpdateMethod::Windows
+        } else {
+            PathUpdateMethod::RcFile(PathBuf::from(key))
+        }
+    }
+}
+
+/// Remember the proxy authentication method given to `elan-init` for
+/// subsequent downloads
+fn record_proxy_auth(method: Option<&str>) -> Result<()> {
+    if let Some(method) = method {
+        let cfg = &(common::set_globals(false)?);
+        if utils::elan_system_home().is_some() {
+            if let Some(ref system) = cfg.system_settings_file {
+                system.with_mut(|s| {
+                    s.proxy_auth = Some(method.to_owned());
+                    Ok(())
+                })?;
+                return Ok(());
+            }
+        }
+        cfg.settings_file.with_mut(|s| {
+            s.proxy_auth = Some(method.to_owned());
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Remember what we changed so that uninstallation can revert precisely
+/// these changes, even if e.g. the user's shell changed in the meantime.
+fn record_path_changes(changes: Vec<PathChange>) -> Result<()> {
+    let cfg = &(common::set_globals(false)?);
+    cfg.settings_file.with_mut(|s| {
+        let recorded = s.path_changes.get_or_insert_with(BTreeMap::new);
+        for (method, inserted) in changes {
+            recorded.insert(method.settings_key(), inserted);
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Remember a registry key below `HKEY_CURRENT_USER` that elan created and
+/// owns entirely, so that uninstallation deletes it. Whatever registers
+/// something with Windows at install time, be it the uninstall entry, file
+/// associations, or App Paths entries, records its key here.
+#[cfg(windows)]
+fn record_registration(key: &str, description: &str) -> Result<()> {
+    let cfg = &(common::set_globals(false)?);
+    cfg.settings_file.with_mut(|s| {
+        s.registrations
+            .insert(key.to_owned(), description.to_owned());
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// The registry keys uninstallation deletes, keyed by their path below
+/// `HKEY_CURRENT_USER`, with what they register
+fn get_registrations() -> Result<BTreeMap<String, String>> {
+    let cfg = &(common::set_globals(false)?);
+    let mut registrations = cfg.settings_file.with(|s| Ok(s.registrations.clone()))?;
+    // Installations by older versions of elan did not record their
+    // uninstall entry
+    if cfg!(windows) {
+        registrations
+            .entry(WINDOWS_UNINSTALL_KEY.to_owned())
+            .or_insert_with(|| UNINSTALL_ENTRY_DESCRIPTION.to_owned());
+    }
+    Ok(registrations)
+}
+
 /// Decide which rcfiles we're going to update, so we
 /// can tell the user before they confirm.
 fn get_add_path_methods() -> Vec<PathUpdateMethod> {
@@ -941,7 +2013,8 @@ fn shell_export_string() -> Result<String> {
 }
 
 #[cfg(unix)]
-fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<()> {
+fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<Vec<PathChange>> {
+    let mut changes = vec![];
     for method in methods {
         if let PathUpdateMethod::RcFile(ref rcpath) = *method {
             let file = if rcpath.exists() {
@@ -949,22 +2022,48 @@ fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<()> {
             } else {
                 String::new()
             };
-            let addition = &format!("\n{}", shell_export_string()?);
+            let export_str = shell_export_string()?;
+            let addition = &format!("\n{}", export_str);
+            // A line that was there before is the user's and stays on uninstall
             if !file.contains(addition) {
                 utils::append_file("rcfile", rcpath, addition)?;
+                changes.push((PathUpdateMethod::RcFile(rcpath.clone()), export_str));
             }
         } else {
             unreachable!()
         }
     }
 
-    Ok(())
+    Ok(changes)
 }
 
 #[cfg(windows)]
-fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<()> {
+fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<Vec<PathChange>> {
     assert!(methods.len() == 1 && methods[0] == PathUpdateMethod::Windows);
 
+    let bin_path = utils::elan_home()?
+        .join("bin")
+        .to_string_lossy()
+        .to_string();
+    match add_to_windows_path(&bin_path)? {
+        WindowsPathUpdate::Added => Ok(vec![(PathUpdateMethod::Windows, bin_path)]),
+        WindowsPathUpdate::AlreadyPresent | WindowsPathUpdate::NotUnicode => Ok(vec![]),
+    }
+}
+
+/// The outcome of `add_to_windows_path`
+#[cfg(windows)]
+enum WindowsPathUpdate {
+    Added,
+    /// The entry was there before and is left to the user on uninstall
+    AlreadyPresent,
+    /// PATH is not Unicode and was left alone
+    NotUnicode,
+}
+
+/// Prepends `bin_path` to the user's PATH unless it is already there
+#[cfg(windows)]
+fn add_to_windows_path(bin_path: &str) -> Result<WindowsPathUpdate> {
     use std::ptr;
     use winapi::shared::minwindef::*;
     use winapi::um::winuser::{
@@ -976,18 +2075,15 @@ fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<()> {
     let old_path = if let Some(s) = get_windows_path_var()? {
         s
     } else {
-        // Non-unicode path
-        return Ok(());
+        return Ok(WindowsPathUpdate::NotUnicode);
     };
 
-    let mut new_path = utils::elan_home()?
-        .join("bin")
-        .to_string_lossy()
-        .to_string();
-    if old_path.contains(&new_path) {
-        return Ok(());
+    if path_var_contains(&old_path, bin_path) {
+        return Ok(WindowsPathUpdate::AlreadyPresent);
     }
 
+    let mut new_path = bin_path.to_owned();
+
     if !old_path.is_empty() {
         new_path.push_str(";");
         new_path.push_str(&old_path);
@@ -1018,9 +2114,214 @@ fn do_add_to_path(methods: &[PathUpdateMethod]) -> Result<()> {
         );
     }
 
+    Ok(WindowsPathUpdate::Added)
+}
+
+/// Registry key of the entry in Windows' list of installed programs
+const WINDOWS_UNINSTALL_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Uninstall\elan";
+const UNINSTALL_ENTRY_DESCRIPTION: &str = "elan in the list of installed programs";
+
+/// Registers elan in Windows' list of installed programs, unless the MSI
+/// installer already did so. Package managers like winget also read this
+/// entry to detect existing installations.
+#[cfg(windows)]
+fn do_add_uninstall_entry() -> Result<()> {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    if cfg!(feature = "msi-installed") {
+        return Ok(());
+    }
+
+    let elan_home = utils::elan_home()?;
+    let elan_path = elan_home.join(format!("bin\\elan{}", EXE_SUFFIX));
+    let uninstall = format!("\"{}\" self uninstall", elan_path.display());
+
+    let root = RegKey::predef(HKEY_CURRENT_USER);
+    let (key, _) = root
+        .create_subkey(WINDOWS_UNINSTALL_KEY)
+        .chain_err(|| ErrorKind::PermissionDenied)?;
+    let set = |name: &str, value: &str| {
+        key.set_value(name, &value.to_owned())
+            .chain_err(|| ErrorKind::PermissionDenied)
+    };
+    set("DisplayName", "elan")?;
+    set("DisplayVersion", env!("CARGO_PKG_VERSION"))?;
+    set("Publisher", "Lean FRO")?;
+    set("URLInfoAbout", "https://github.com/leanprover/elan")?;
+    set("DisplayIcon", &elan_path.to_string_lossy())?;
+    set("InstallLocation", &elan_home.to_string_lossy())?;
+    set("UninstallString", &uninstall)?;
+    set("QuietUninstallString", &format!("{} -y", uninstall))?;
+    for flag in &["NoModify", "NoRepair"] {
+        key.set_value(flag, &1u32)
+            .chain_err(|| ErrorKind::PermissionDenied)?;
+    }
+    record_registration(WINDOWS_UNINSTALL_KEY, UNINSTALL_ENTRY_DESCRIPTION)
+}
+
+#[cfg(unix)]
+fn do_add_uninstall_entry() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(windows)]
+fn do_remove_registrations(registrations: &BTreeMap<String, String>) -> Result<()> {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    let root = RegKey::predef(HKEY_CURRENT_USER);
+    for key in registrations.keys() {
+        match root.delete_subkey_all(key) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).chain_err(|| ErrorKind::PermissionDenied),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn do_remove_registrations(_registrations: &BTreeMap<String, String>) -> Result<()> {
+    Ok(())
+}
+
+/// Retries adding ELAN_HOME/bin to PATH in a copy of elan-init started with
+/// administrator rights, asking the user for consent via UAC
+#[cfg(windows)]
+fn do_add_to_path_elevated() -> Result<Vec<PathChange>> {
+    use std::ffi::OsStr;
+    use std::iter;
+    use std::mem;
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::GetExitCodeProcess;
+    use winapi::um::shellapi::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+    use winapi::um::synchapi::WaitForSingleObject;
+    use winapi::um::winbase::{INFINITE, WAIT_OBJECT_0};
+    use winapi::um::winuser::SW_HIDE;
+
+    fn wide(s: &OsStr) -> Vec<u16> {
+        s.encode_wide().chain(iter::once(0)).collect()
+    }
+
+    let bin_path = utils::elan_home()?
+        .join("bin")
+        .to_string_lossy()
+        .to_string();
+    // The elevated process does not inherit our environment, so pass the
+    // directory explicitly, and who it is meant for
+    let verb = wide(OsStr::new("runas"));
+    let file = wide(utils::current_exe()?.as_os_str());
+    let params = wide(OsStr::new(&format!(
+        "--modify-path-elevated \"{}\" {}",
+        bin_path,
+        current_user_sid()?
+    )));
+
+    unsafe {
+        let mut info: SHELLEXECUTEINFOW = mem::zeroed();
+        info.cbSize = mem::size_of::<SHELLEXECUTEINFOW>() as DWORD;
+        info.fMask = SEE_MASK_NOCLOSEPROCESS;
+        info.lpVerb = verb.as_ptr();
+        info.lpFile = file.as_ptr();
+        info.lpParameters = params.as_ptr();
+        info.nShow = SW_HIDE;
+
+        // Fails with ERROR_CANCELLED if the user declines
+        if ShellExecuteExW(&mut info) == 0 {
+            let err = io::Error::last_os_error();
+            return Err(err).chain_err(|| "could not start elevated process");
+        }
+
+        let _g = scopeguard::guard(info.hProcess, |h| {
+            let _ = CloseHandle(h);
+        });
+
+        let mut code: DWORD = 1;
+        if WaitForSingleObject(info.hProcess, INFINITE) != WAIT_OBJECT_0
+            || GetExitCodeProcess(info.hProcess, &mut code) == 0
+        {
+            let err = io::Error::last_os_error();
+            return Err(err).chain_err(|| "failed waiting for elevated process");
+        }
+        if code != 0 {
+            return Err(ErrorKind::PermissionDenied.into());
+        }
+    }
+
+    Ok(vec![(PathUpdateMethod::Windows, bin_path)])
+}
+
+#[cfg(unix)]
+fn do_add_to_path_elevated() -> Result<Vec<PathChange>> {
+    Err(ErrorKind::PermissionDenied.into())
+}
+
+/// Run by elan-init with administrator rights to add `bin_path` to PATH on
+/// behalf of a non-elevated installation by the user with the SID `user_sid`
+#[cfg(windows)]
+pub fn modify_path_elevated(bin_path: &str, user_sid: &str) -> Result<()> {
+    // When a different administrator account is entered at the prompt, this
+    // runs as that account and would modify its PATH instead
+    if current_user_sid()? != user_sid {
+        return Err(ErrorKind::PathModificationDenied(PathBuf::from(bin_path)).into());
+    }
+    add_to_windows_path(bin_path)?;
     Ok(())
 }
 
+#[cfg(unix)]
+pub fn modify_path_elevated(_: &str, _: &str) -> Result<()> {
+    Err("modifying PATH with administrator rights is only supported on Windows".into())
+}
+
+/// The SID of the user running this process, e.g. `S-1-5-21-...-1001`
+#[cfg(windows)]
+fn current_user_sid() -> Result<String> {
+    use std::ptr;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcessToken};
+    use winapi::um::sddl::ConvertSidToStringSidW;
+    use winapi::um::securitybaseapi::GetTokenInformation;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::winnt::{TokenUser, HANDLE, LPWSTR, TOKEN_QUERY, TOKEN_USER};
+
+    let failed = || "could not determine the current user";
+    unsafe {
+        let mut token: HANDLE = ptr::null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return Err(io::Error::last_os_error()).chain_err(failed);
+        }
+        let _g = scopeguard::guard(token, |h| {
+            let _ = CloseHandle(h);
+        });
+
+        // Ask for the size first; u64s keep the buffer aligned for TOKEN_USER
+        let mut len: DWORD = 0;
+        GetTokenInformation(token, TokenUser, ptr::null_mut(), 0, &mut len);
+        let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+        if GetTokenInformation(token, TokenUser, buf.as_mut_ptr() as *mut _, len, &mut len) == 0 {
+            return Err(io::Error::last_os_error()).chain_err(failed);
+        }
+        let user = &*(buf.as_ptr() as *const TOKEN_USER);
+
+        let mut sid: LPWSTR = ptr::null_mut();
+        if ConvertSidToStringSidW(user.User.Sid, &mut sid) == 0 {
+            return Err(io::Error::last_os_error()).chain_err(failed);
+        }
+        let _g = scopeguard::guard(sid, |s| {
+            LocalFree(s as *mut _);
+        });
+        let len = (0..).take_while(|&i| *sid.add(i) != 0).count();
+        Ok(String::from_utf16_lossy(std::slice::from_raw_parts(
+            sid, len,
+        )))
+    }
+}
+
 // Get the windows PATH variable out of the registry as a String. If
 // this returns None then the PATH varible is not unicode and we
 // should not mess with it.
@@ -1050,11 +2351,26 @@ fn get_windows_path_var() -> Result<Option<String>> {
     }
 }
 
-/// Decide which rcfiles we're going to update, so we
-/// can tell the user before they confirm.
-fn get_remove_path_methods() -> Result<Vec<PathUpdateMethod>> {
+/// Decide which PATH changes we're going to revert, preferring the ones
+/// recorded at install time.
+fn get_remove_path_changes() -> Result<Vec<PathChange>> {
+    let cfg = &(common::set_globals(false)?);
+    let recorded = cfg.settings_file.with(|s| Ok(s.path_changes.clone()))?;
+    if let Some(recorded) = recorded {
+        return Ok(recorded
+            .into_iter()
+            .map(|(k, v)| (PathUpdateMethod::from_settings_key(&k), v))
+            .collect());
+    }
+
+    // Installations by older versions of elan did not record their changes,
+    // so guess them from the current environment.
     if cfg!(windows) {
-        return Ok(vec![PathUpdateMethod::Windows]);
+        let bin_path = utils::elan_home()?
+            .join("bin")
+            .to_string_lossy()
+            .to_string();
+        return Ok(vec![(PathUpdateMethod::Windows, bin_path)]);
     }
 
     let profile = utils::home_dir().map(|p| p.join(".profile"));
@@ -1070,12 +2386,21 @@ fn get_remove_path_methods() -> Result<Vec<PathUpdateMethod>> {
         file.contains(addition)
     });
 
-    Ok(matching_rcfiles.map(PathUpdateMethod::RcFile).collect())
+    Ok(matching_rcfiles
+        .map(|f| (PathUpdateMethod::RcFile(f), export_str.clone()))
+        .collect())
 }
 
 #[cfg(windows)]
-fn do_remove_from_path(methods: &[PathUpdateMethod]) -> Result<()> {
-    assert!(methods.len() == 1 && methods[0] == PathUpdateMethod::Windows);
+fn do_remove_from_path(changes: &[PathChange]) -> Result<()> {
+    let path_str = if let Some((_, inserted)) = changes
+        .iter()
+        .find(|(method, _)| *method == PathUpdateMethod::Windows)
+    {
+        inserted
+    } else {
+        return Ok(());
+    };
 
     use std::ptr;
     use winapi::shared::minwindef::*;
@@ -1092,10 +2417,6 @@ fn do_remove_from_path(methods: &[PathUpdateMethod]) -> Result<()> {
         return Ok(());
     };
 
-    let ref path_str = utils::elan_home()?
-        .join("bin")
-        .to_string_lossy()
-        .to_string();
     let idx = if let Some(i) = old_path.find(path_str) {
         i
     } else {
@@ -1147,11 +2468,15 @@ fn do_remove_from_path(methods: &[PathUpdateMethod]) -> Result<()> {
 }
 
 #[cfg(unix)]
-fn do_remove_from_path(methods: &[PathUpdateMethod]) -> Result<()> {
-    for method in methods {
+fn do_remove_from_path(changes: &[PathChange]) -> Result<()> {
+    for (method, inserted) in changes {
         if let PathUpdateMethod::RcFile(ref rcpath) = *method {
+            if !rcpath.exists() {
+                // The user removed the file in the meantime, nothing to revert
+                continue;
+            }
             let file = utils::read_file("rcfile", rcpath)?;
-            let addition = format!("\n{}\n", shell_export_string()?);
+            let addition = format!("\n{}\n", inserted);
 
             let file_bytes = file.into_bytes();
             let addition_bytes = addition.into_bytes();
@@ -1190,10 +2515,13 @@ fn do_remove_from_path(methods: &[PathUpdateMethod]) -> Result<()> {
 /// (and on windows this process will not be running to do it),
 /// elan-init is stored in `ELAN_HOME`/bin, and then deleted next
 /// time elan runs.
-pub fn update() -> Result<()> {
+pub fn update(yes: &YesArg) -> Result<()> {
     if elan::install::NEVER_SELF_UPDATE {
         err!("self-update is disabled for this build of elan");
-        err!("you should probably use your system package manager to update elan");
+        match elan::install::package_manager_commands() {
+            Some(commands) => err!("run `{}` to update elan", commands.update),
+            None => err!("{}", package_manager_hint("update")),
+        }
         process::exit(1);
     }
     let setup_path = prepare_update()?;
@@ -1206,11 +2534,29 @@ pub fn update() -> Result<()> {
             }
         };
 
+        // Only replacing elan with an older version, e.g. a development
+        // build with the latest release, needs confirmation
+        let current = env!("CARGO_PKG_VERSION");
+        let downgrade = match (
+            semver::Version::parse(current),
+            semver::Version::parse(&version),
+        ) {
+            (Ok(current), Ok(new)) => new < current,
+            _ => false,
+        };
+        let question = format!(
+            "Replace elan {} with the older version {}?",
+            current, version
+        );
+        if downgrade && !common::confirm_destructive(yes, &question)? {
+            utils::remove_file("setup", p)?;
+            return Ok(());
+        }
         info!("elan updated successfully to {}", version);
         run_update(p)?;
     } else {
         // Try again in case we emitted "tool `{}` is already installed" last time.
-        install_proxies()?
+        install_proxies()?;
     }
 
     Ok(())
@@ -1237,7 +2583,7 @@ fn parse_new_elan_version(version: String) -> String {
 }
 
 pub fn prepare_update() -> Result<Option<PathBuf>> {
-    let elan_home = &(utils::elan_home()?);
+    let elan_home = &(install_home()?);
     let elan_path = &elan_home.join(format!("bin/elan{}", EXE_SUFFIX));
     let setup_path = &elan_home.join(format!("bin/elan-init{}", EXE_SUFFIX));
 
@@ -1249,7 +2595,7 @@ pub fn prepare_update() -> Result<Option<PathBuf>> {
         utils::remove_file("setup", setup_path)?;
     }
 
-    let update_root = env::var("ELAN_UPDATE_ROOT").unwrap_or(String::from(UPDATE_ROOT));
+    let update_root = endpoints::UPDATE_ROOT.url();
 
     let tempdir = tempdir().chain_err(|| "error creating temp directory")?;
 
@@ -1275,6 +2621,17 @@ pub fn prepare_update() -> Result<Option<PathBuf>> {
     info!("downloading self-update");
     utils::download_file(&download_url, &archive_path, &|_| ())?;
 
+    // Updates from roots with a configured key must be signed with it
+    let cfg = &(common::set_globals(false)?);
+    if let Some(key) = cfg.trust_root_key(&update_root)? {
+        let sig_url = utils::parse_url(&format!("{}.sig", url))?;
+        let sig_path = tempdir.path().join(format!("{}.sig", archive_name));
+        utils::download_file(&sig_url, &sig_path, &|_| ())?;
+        let signature = utils::read_file("signature", &sig_path)?;
+        utils::verify_signature(&archive_path, &signature, &key)?;
+        info!("verified self-update signature for '{}'", update_root);
+    }
+
     let file = fs::File::open(archive_path)?;
     if cfg!(target_os = "windows") {
         let mut archive =
@@ -1319,10 +2676,28 @@ pub fn run_update(setup_path: &Path) -> Result<()> {
 
 #[cfg(windows)]
 pub fn run_update(setup_path: &Path) -> Result<()> {
-    Command::new(setup_path)
-        .arg("--self-replace")
-        .spawn()
-        .chain_err(|| "unable to run updater")?;
+    // `start` lets the script exit right away so that the updater does not
+    // wait for it in `wait_for_parent`
+    let run_setup = format!("start \"\" /B \"{}\" --self-replace", setup_path.display());
+    match self_delete_strategy()? {
+        SelfDeleteStrategy::Script => run_helper_script("update", &run_setup)?,
+        SelfDeleteStrategy::DeleteOnClose => {
+            Command::new(setup_path)
+                .arg("--self-replace")
+                .spawn()
+                .chain_err(|| "unable to run updater")?;
+        }
+        SelfDeleteStrategy::Auto => {
+            if let Err(e) = Command::new(setup_path).arg("--self-replace").spawn() {
+                warn!(
+                    "could not start the updater, possibly because of antivirus software: {}",
+                    e
+                );
+                info!("falling back to updating with a helper script");
+                run_helper_script("update", &run_setup)?;
+            }
+        }
+    }
 
     process::exit(0);
 }
@@ -1343,13 +2718,14 @@ pub fn self_replace() -> Result<()> {
 pub fn self_replace() -> Result<()> {
     wait_for_parent()?;
     install_bins()?;
+    do_add_uninstall_entry()?;
     clean_up_old_state()?;
 
     Ok(())
 }
 
 pub fn cleanup_self_updater() -> Result<()> {
-    let elan_home = utils::elan_home()?;
+    let elan_home = install_home()?;
     let setup = &elan_home.join(format!("bin/elan-init{}", EXE_SUFFIX));
 
     if setup.exists() {
@@ -1,10 +1,55 @@
 use crate::common;
 use crate::errors::*;
+use crate::log;
 use crate::self_update::{self, InstallOpts};
-use clap::{App, AppSettings, Arg};
+use clap::builder::PossibleValuesParser;
+use clap::Parser;
+use download::PROXY_AUTH_METHODS;
+use elan::env_var;
+use serde_json::json;
 use std::env;
+use std::env::consts::EXE_SUFFIX;
+use std::path::{Path, PathBuf};
+
+// XXX: If you change anything here, please make the same changes in elan-init.sh
+/// The installer for elan
+#[derive(Parser, Debug)]
+#[command(name = "elan-init", version = common::version())]
+struct InitArgs {
+    /// Enable verbose output
+    #[arg(short, long)]
+    verbose: bool,
+    /// Disable confirmation prompt.
+    #[arg(short = 'y')]
+    no_prompt: bool,
+    /// Disable prompts and progress output and print the result as JSON
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Choose a default toolchain
+    #[arg(long, env = env_var::ELAN_DEFAULT_TOOLCHAIN)]
+    default_toolchain: Option<String>,
+    /// Component to install into the default toolchain right away; may be repeated
+    #[arg(short, long, alias = "components", value_delimiter = ',')]
+    component: Vec<String>,
+    /// Don't configure the PATH environment variable
+    #[arg(long)]
+    no_modify_path: bool,
+    /// Authenticate with the HTTP proxy using the given method, e.g. ntlm
+    #[arg(long, value_parser = PossibleValuesParser::new(PROXY_AUTH_METHODS))]
+    proxy_auth: Option<String>,
+    /// Fix an existing installation, keeping its settings and toolchains
+    #[arg(long, conflicts_with = "system")]
+    repair: bool,
+    /// Install elan and toolchains for all users; requires root
+    #[arg(long)]
+    system: bool,
+    /// Installation directory for `--system` [default: /opt/elan]
+    #[arg(long, requires = "system")]
+    prefix: Option<PathBuf>,
+}
 
 pub fn main() -> Result<()> {
+    crate::ci::mask_secrets();
     let args: Vec<_> = env::args().collect();
     let arg1 = args.get(1).map(|a| &**a);
 
@@ -12,46 +57,80 @@ pub fn main() -> Result<()> {
     if arg1 == Some("--self-replace") {
         return self_update::self_replace();
     }
-    // XXX: If you change anything here, please make the same changes in elan-init.sh
-    let cli = App::new("elan-init")
-        .version(common::version())
-        .about("The installer for elan")
-        .setting(AppSettings::DeriveDisplayOrder)
-        .arg(
-            Arg::with_name("verbose")
-                .short("v")
-                .long("verbose")
-                .help("Enable verbose output"),
-        )
-        .arg(
-            Arg::with_name("no-prompt")
-                .short("y")
-                .help("Disable confirmation prompt."),
-        )
-        .arg(
-            Arg::with_name("default-toolchain")
-                .long("default-toolchain")
-                .takes_value(true)
-                .help("Choose a default toolchain"),
-        )
-        .arg(
-            Arg::with_name("no-modify-path")
-                .long("no-modify-path")
-                .help("Don't configure the PATH environment variable"),
-        );
+    // Secret command used to modify PATH with administrator rights
+    if arg1 == Some("--modify-path-elevated") {
+        let bin_path = args.get(2).ok_or("missing path")?;
+        let user_sid = args.get(3).ok_or("missing user")?;
+        return self_update::modify_path_elevated(bin_path, user_sid);
+    }
+    let args = InitArgs::parse();
+    let quiet = args.quiet;
+    let no_prompt = args.no_prompt || quiet;
+    let verbose = args.verbose;
+    let default_toolchain = args.default_toolchain.as_deref();
+    let components = args.component;
 
-    let matches = cli.get_matches();
-    let no_prompt = matches.is_present("no-prompt");
-    let verbose = matches.is_present("verbose");
-    let default_toolchain = matches.value_of("default-toolchain").unwrap_or("stable");
-    let no_modify_path = matches.is_present("no-modify-path");
+    // Fail before anything is installed
+    if let Some(toolchain) = default_toolchain {
+        self_update::check_default_toolchain(toolchain)?;
+    }
+    if !components.is_empty() && default_toolchain == Some("none") {
+        return Err(ErrorKind::ComponentsWithoutDefaultToolchain.into());
+    }
+    if quiet {
+        log::set_quiet(true);
+    }
+    let no_modify_path = args.no_modify_path;
+    let proxy_auth = args.proxy_auth.as_deref();
+    if let Some(method) = proxy_auth {
+        // Used for all downloads from here on
+        env::set_var(env_var::ELAN_PROXY_AUTH, method);
+    }
 
-    let opts = InstallOpts {
-        default_toolchain: default_toolchain.to_owned(),
-        no_modify_path,
+    let path_modified = if !args.system && (args.repair || self_update::is_installed()?) {
+        if !args.repair {
+            info!("elan is already installed, checking the existing installation");
+        }
+        self_update::repair(
+            no_prompt,
+            verbose,
+            no_modify_path,
+            default_toolchain,
+            proxy_auth,
+            &components,
+        )?
+    } else {
+        let opts = InstallOpts {
+            default_toolchain: default_toolchain.unwrap_or("stable").to_owned(),
+            no_modify_path,
+            proxy_auth: proxy_auth.map(|m| m.to_owned()),
+            components: components.clone(),
+        };
+        if args.system {
+            let prefix = args.prefix.as_deref().unwrap_or(Path::new("/opt/elan"));
+            self_update::install_system(no_prompt, verbose, prefix, opts)?
+        } else {
+            self_update::install(no_prompt, verbose, opts)?
+        }
     };
 
-    self_update::install(no_prompt, verbose, opts)?;
+    if quiet {
+        print_result(path_modified, &components)?;
+    }
+    Ok(())
+}
 
+/// Prints the outcome of `elan-init --quiet` for provisioning tools
+fn print_result(path_modified: bool, components: &[String]) -> Result<()> {
+    let home = self_update::install_home()?;
+    let cfg = common::set_globals(false)?;
+    let result = json!({
+        "elan_home": home,
+        "elan_path": home.join("bin").join(format!("elan{}", EXE_SUFFIX)),
+        "default_toolchain": cfg.get_default()?,
+        "path_modified": path_modified,
+        "components": components,
+    });
+    println!("{}", serde_json::to_string_pretty(&result).unwrap());
     Ok(())
 }
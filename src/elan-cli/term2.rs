@@ -28,20 +28,30 @@ impl Instantiable for io::Stderr {
 
 pub trait Isatty {
     fn isatty() -> bool;
+    fn width() -> Option<usize>;
 }
 
 impl Isatty for io::Stdout {
     fn isatty() -> bool {
         tty::stdout_isatty()
     }
+    fn width() -> Option<usize> {
+        tty::stdout_width()
+    }
 }
 
 impl Isatty for io::Stderr {
     fn isatty() -> bool {
         tty::stderr_isatty()
     }
+    fn width() -> Option<usize> {
+        tty::stderr_width()
+    }
 }
 
+/// Prose is not wrapped at more than this many columns even on wide terminals
+const MAX_MARGIN: u32 = 79;
+
 pub struct Terminal<T>(Option<Box<dyn term::Terminal<Output = T> + Send>>)
 where
     T: Instantiable + Isatty + io::Write;
@@ -85,7 +95,7 @@ impl<'a, T: io::Write + 'a> LineWrapper<'a, T> {
     fn write_word(&mut self, word: &str) {
         // Ensure correct indentation
         self.write_indent();
-        let word_len = word.len() as u32;
+        let word_len = word.chars().count() as u32;
 
         // If this word goes past the margin
         if self.pos + word_len > self.margin {
@@ -224,6 +234,112 @@ impl<'a, T: Instantiable + Isatty + io::Write + 'a> LineFormatter<'a, T> {
             _ => {}
         }
     }
+    // Writes a table with columns aligned, truncating cells so that it fits
+    // into `width` columns
+    fn do_table(&mut self, header: Option<&[String]>, rows: &[Vec<String>], width: usize) {
+        const GAP: usize = 2;
+        let indent = self.wrapper.indent as usize;
+        let ncols = header
+            .iter()
+            .map(|h| h.len())
+            .chain(rows.iter().map(|r| r.len()))
+            .max()
+            .unwrap_or(0);
+        if ncols == 0 {
+            return;
+        }
+        let mut widths = vec![0; ncols];
+        for row in header.into_iter().chain(rows.iter().map(|r| &r[..])) {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+        // Shrink the widest columns until the table fits
+        let available = width.saturating_sub(indent + GAP * (ncols - 1));
+        while widths.iter().sum::<usize>() > available {
+            let widest = (0..ncols).max_by_key(|&i| widths[i]).unwrap();
+            if widths[widest] <= 3 {
+                break;
+            }
+            widths[widest] -= 1;
+        }
+
+        if let Some(header) = header {
+            self.push_attr(Attr::Bold);
+            self.write_table_row(header, &widths, GAP);
+            self.pop_attr();
+            let rule: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+            self.write_table_row(&rule, &widths, GAP);
+        }
+        for row in rows {
+            self.write_table_row(row, &widths, GAP);
+        }
+    }
+    fn write_table_row(&mut self, row: &[String], widths: &[usize], gap: usize) {
+        use std::io::Write;
+        let mut line = " ".repeat(self.wrapper.indent as usize);
+        for (i, &width) in widths.iter().enumerate() {
+            let cell = row.get(i).map(|c| &c[..]).unwrap_or("");
+            let cell: String = if cell.chars().count() > width {
+                cell.chars()
+                    .take(width - 1)
+                    .chain(std::iter::once('…'))
+                    .collect()
+            } else {
+                cell.to_owned()
+            };
+            line.push_str(&cell);
+            // No trailing whitespace after the last column
+            if i + 1 < widths.len() {
+                let padding = width - cell.chars().count() + gap;
+                line.push_str(&" ".repeat(padding));
+            }
+        }
+        let _ = writeln!(self.wrapper.w, "{}", line.trim_end());
+        self.wrapper.pos = 0;
+    }
+}
+
+// The `markdown` crate does not support tables, so runs of lines starting
+// with `|` are split off and rendered separately. Returns the runs in order,
+// marking tables with `true`.
+fn split_tables(content: &str) -> Vec<(bool, String)> {
+    let mut runs: Vec<(bool, String)> = Vec::new();
+    for line in content.lines() {
+        let is_table = line.trim_start().starts_with('|');
+        match runs.last_mut() {
+            Some((t, run)) if *t == is_table => {
+                run.push_str(line);
+                run.push('\n');
+            }
+            _ => runs.push((is_table, format!("{}\n", line))),
+        }
+    }
+    runs
+}
+
+// Parses a pipe table into its header, if it has a delimiter row, and rows
+fn parse_table(table: &str) -> (Option<Vec<String>>, Vec<Vec<String>>) {
+    let mut rows: Vec<Vec<String>> = table
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('|').unwrap_or(line);
+            let line = line.strip_suffix('|').unwrap_or(line);
+            line.split('|').map(|c| c.trim().to_owned()).collect()
+        })
+        .collect();
+    let is_delimiter = |row: &Vec<String>| {
+        row.iter()
+            .all(|c| !c.is_empty() && c.chars().all(|ch| ch == '-' || ch == ':'))
+    };
+    if rows.len() >= 2 && is_delimiter(&rows[1]) {
+        let header = rows.remove(0);
+        rows.remove(0);
+        (Some(header), rows)
+    } else {
+        (None, rows)
+    }
 }
 
 impl<T: Instantiable + Isatty + io::Write> io::Write for Terminal<T> {
@@ -291,11 +407,33 @@ impl<T: Instantiable + Isatty + io::Write> Terminal<T> {
         }
     }
 
+    /// The width of the terminal in columns, or 80 if unknown
+    pub fn width(&self) -> usize {
+        T::width().unwrap_or(80)
+    }
+
     pub fn md<S: AsRef<str>>(&mut self, content: S) {
-        let mut f = LineFormatter::new(self, 0, 79);
-        let blocks = tokenize(content.as_ref());
-        for b in blocks {
-            f.do_block(b);
+        let width = self.width();
+        let margin = (width.saturating_sub(1) as u32).clamp(20, MAX_MARGIN);
+        let mut f = LineFormatter::new(self, 0, margin);
+        for (is_table, run) in split_tables(content.as_ref()) {
+            if is_table {
+                let (header, rows) = parse_table(&run);
+                f.wrapper.write_line();
+                f.do_table(header.as_deref(), &rows, width - 1);
+            } else {
+                for b in tokenize(&run) {
+                    f.do_block(b);
+                }
+            }
         }
     }
+
+    /// Writes a table with aligned columns that fits into the terminal
+    pub fn table(&mut self, header: &[&str], rows: &[Vec<String>]) {
+        let width = self.width();
+        let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+        let mut f = LineFormatter::new(self, 0, MAX_MARGIN);
+        f.do_table(Some(&header), rows, width - 1);
+    }
 }
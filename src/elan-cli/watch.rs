@@ -0,0 +1,37 @@
+//! Change detection for files and directories by polling their modification
+//! times. Paths that do not exist yet are watched as well, so creating them is
+//! detected as a change.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Default)]
+pub struct Watcher {
+    stamps: BTreeMap<PathBuf, Option<SystemTime>>,
+}
+
+fn stamp(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Watcher {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Starts watching `path` if not watched already, taking its current
+    /// state as the baseline.
+    pub fn watch(&mut self, path: &Path) {
+        if !self.stamps.contains_key(path) {
+            self.stamps.insert(path.to_owned(), stamp(path));
+        }
+    }
+
+    /// Returns whether any watched path was modified, created, or removed
+    /// since it was first watched.
+    pub fn changed(&self) -> bool {
+        self.stamps.iter().any(|(path, s)| stamp(path) != *s)
+    }
+}
@@ -0,0 +1,230 @@
+//! The install manifest of a toolchain prefix.
+//!
+//! `elan-install.toml` in the toolchain directory records the origin, release,
+//! target, and checksum of the installed archive, when it was installed, and
+//! the size of the installation, lists the installed optional components, and
+//! lists the paths removed by `elan toolchain strip`, which are thus known not
+//! to be missing by accident. For each
+//! component, the files it added are recorded in `elan-manifest-<component>`
+//! so that it can later be removed without touching the rest of the
+//! toolchain.
+
+use crate::config::Config;
+use crate::errors::*;
+use crate::manifest::Component;
+use crate::notifications::*;
+use crate::prefix::InstallPrefix;
+use elan_utils::utils;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const INSTALL_MANIFEST: &str = "elan-install.toml";
+
+#[derive(Clone, Debug)]
+pub struct Components {
+    prefix: InstallPrefix,
+}
+
+impl Components {
+    pub fn open(prefix: InstallPrefix) -> Self {
+        Components { prefix }
+    }
+
+    fn read_config(&self) -> Result<Config> {
+        let path = self.prefix.manifest_file(INSTALL_MANIFEST);
+        if !utils::is_file(&path) {
+            return Ok(Config::new());
+        }
+        Config::parse(&utils::read_file("install manifest", &path)?)
+    }
+
+    fn write_config(&self, config: Config) -> Result<()> {
+        let path = self.prefix.manifest_file(INSTALL_MANIFEST);
+        Ok(utils::write_file(
+            "install manifest",
+            &path,
+            &config.stringify(),
+        )?)
+    }
+
+    fn manifest_path(&self, component: &Component) -> PathBuf {
+        self.prefix
+            .manifest_file(&format!("elan-manifest-{}", component.pkg))
+    }
+
+    pub fn list(&self) -> Result<Vec<Component>> {
+        Ok(self.read_config()?.components)
+    }
+
+    /// The target of the installed toolchain archive, if recorded
+    pub fn target(&self) -> Result<Option<String>> {
+        Ok(self.read_config()?.target)
+    }
+
+    pub fn set_target(&self, target: &str) -> Result<()> {
+        let mut config = self.read_config()?;
+        config.target = Some(target.to_owned());
+        self.write_config(config)
+    }
+
+    /// The origin and release the toolchain was installed from, if recorded
+    pub fn provenance(&self) -> Result<Option<(String, String)>> {
+        let config = self.read_config()?;
+        Ok(config.origin.zip(config.release))
+    }
+
+    pub fn set_provenance(&self, origin: &str, release: &str) -> Result<()> {
+        let mut config = self.read_config()?;
+        config.origin = Some(origin.to_owned());
+        config.release = Some(release.to_owned());
+        self.write_config(config)
+    }
+
+    /// When the toolchain was installed, as a Unix timestamp in seconds, if
+    /// recorded
+    pub fn installed_at(&self) -> Result<Option<u64>> {
+        Ok(self.read_config()?.installed_at)
+    }
+
+    /// Records the current time as the installation time, together with the
+    /// checksum of the installed archive
+    pub fn set_installed(&self, checksum: &str) -> Result<()> {
+        let mut config = self.read_config()?;
+        config.installed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        config.checksum = Some(checksum.to_owned());
+        self.write_config(config)
+    }
+
+    /// The SHA-256 hash of the installed archive, if recorded
+    pub fn checksum(&self) -> Result<Option<String>> {
+        Ok(self.read_config()?.checksum)
+    }
+
+    /// The size of the prefix in bytes as of the last change elan made to it,
+    /// if recorded
+    pub fn size(&self) -> Result<Option<u64>> {
+        Ok(self.read_config()?.size)
+    }
+
+    /// Records the current size of the prefix, to be called after adding or
+    /// removing files
+    pub fn update_size(&self) -> Result<()> {
+        let mut config = self.read_config()?;
+        config.size = Some(utils::dir_size(self.prefix.path())?);
+        self.write_config(config)
+    }
+
+    /// The paths removed from the prefix after installation
+    pub fn stripped(&self) -> Result<Vec<String>> {
+        Ok(self.read_config()?.stripped)
+    }
+
+    pub fn add_stripped(&self, paths: &[String]) -> Result<()> {
+        let mut config = self.read_config()?;
+        for path in paths {
+            if !config.stripped.contains(path) {
+                config.stripped.push(path.clone());
+            }
+        }
+        self.write_config(config)
+    }
+
+    /// Moves the files unpacked to `src` into the prefix and records them
+    /// as belonging to `component`
+    pub fn add(&self, component: &Component, src: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        for entry in walkdir::WalkDir::new(src) {
+            let entry = entry.chain_err(|| ErrorKind::ExtractingPackage)?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let rel = entry
+                .path()
+                .strip_prefix(src)
+                .expect("walkdir entry outside of root")
+                .to_owned();
+            let dest = self.prefix.abs_path(&rel);
+            if dest.exists() {
+                return Err(ErrorKind::ComponentConflict {
+                    name: component.name(),
+                    path: rel,
+                }
+                .into());
+            }
+            files.push(rel);
+        }
+
+        for rel in &files {
+            let dest = self.prefix.abs_path(rel);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            utils::rename_file("component file", &src.join(rel), &dest)?;
+        }
+
+        let manifest = files
+            .iter()
+            .map(|rel| format!("{}\n", rel.to_string_lossy()))
+            .collect::<String>();
+        utils::write_file(
+            "component manifest",
+            &self.manifest_path(component),
+            &manifest,
+        )?;
+
+        let mut config = self.read_config()?;
+        config.components.push(component.clone());
+        self.write_config(config)
+    }
+
+    /// Removes all files recorded for `component`, as well as any directories
+    /// left empty by that
+    pub fn remove(
+        &self,
+        component: &Component,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<()> {
+        let mut config = self.read_config()?;
+        if !config.components.contains(component) {
+            return Err(format!("component {} is not installed", component.description()).into());
+        }
+
+        let manifest_path = self.manifest_path(component);
+        if utils::is_file(&manifest_path) {
+            let manifest = utils::read_file("component manifest", &manifest_path)?;
+            for rel in manifest.lines().filter(|l| !l.is_empty()) {
+                let path = self.prefix.abs_path(rel);
+                if !path.exists() && fs::symlink_metadata(&path).is_err() {
+                    let rel_path = Path::new(rel);
+                    if !config.stripped.iter().any(|s| rel_path.starts_with(s)) {
+                        notify_handler(Notification::MissingInstalledComponent(rel));
+                    }
+                    continue;
+                }
+                utils::remove_file("component file", &path)?;
+                self.remove_empty_parents(&path);
+            }
+            utils::remove_file("component manifest", &manifest_path)?;
+        } else {
+            return Err(ErrorKind::CorruptComponent(component.name()).into());
+        }
+
+        config.components.retain(|c| c != component);
+        self.write_config(config)
+    }
+
+    fn remove_empty_parents(&self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.prefix.path() || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
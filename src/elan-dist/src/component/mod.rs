@@ -1,7 +1,10 @@
 /// An interpreter for the lean-installer [1] installation format.
 ///
 /// https://github.com/rust-lang/rust-installer
+pub use self::components::*;
 pub use self::package::*;
 
+// The optional components installed into a toolchain
+mod components;
 // The representation of a package, its components, and installation
 mod package;
@@ -3,49 +3,163 @@
 //! prefix, represented by a `Components` instance.
 
 use crate::errors::*;
+use elan_utils::interrupt;
 
+use std::cell::Cell;
 use std::fs::{self, File};
 use std::io::{self, Read, Seek};
-use std::path::{Path, PathBuf};
+use std::path::{Component as PathComponent, Path, PathBuf};
 
 use zip::ZipArchive;
 
+/// Caps on the resources used by extracting the archives of a single
+/// toolchain installation, guarding against decompression bombs
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ExtractionLimits {
+    /// Total size of the extracted files in bytes
+    pub max_size: u64,
+    /// Number of extracted files, directories, and links
+    pub max_files: u64,
+}
+
+impl ExtractionLimits {
+    // Generous, as official releases unpack to less than 3 GiB in about
+    // 10,000 files
+    pub const DEFAULT: ExtractionLimits = ExtractionLimits {
+        max_size: 16 << 30,
+        max_files: 1_000_000,
+    };
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// `size` in bytes, in MiB unless it is not a whole number of them
+fn describe_size(size: u64) -> String {
+    if size > 0 && size.is_multiple_of(1 << 20) {
+        format!("{} MiB", size >> 20)
+    } else {
+        format!("{} bytes", size)
+    }
+}
+
+/// The resources left for extracting the archives of a toolchain
+/// installation
+#[derive(Debug)]
+pub struct ExtractionBudget {
+    limits: ExtractionLimits,
+    size: Cell<u64>,
+    files: Cell<u64>,
+}
+
+impl ExtractionBudget {
+    pub fn new(limits: ExtractionLimits) -> Self {
+        ExtractionBudget {
+            limits,
+            size: Cell::new(0),
+            files: Cell::new(0),
+        }
+    }
+
+    /// The size and number of the entries accounted for so far, to go back to
+    /// with `reset` should an archive have to be extracted again
+    pub(crate) fn used(&self) -> (u64, u64) {
+        (self.size.get(), self.files.get())
+    }
+
+    pub(crate) fn reset(&self, (size, files): (u64, u64)) {
+        self.size.set(size);
+        self.files.set(files);
+    }
+
+    /// Accounts for an archive entry of `size` bytes, failing if a limit is
+    /// exceeded or elan has been interrupted
+    fn add_entry(&self, size: u64) -> Result<()> {
+        interrupt::check()?;
+        self.files.set(self.files.get() + 1);
+        if self.files.get() > self.limits.max_files {
+            return Err(ErrorKind::ExtractionLimitExceeded(
+                format!("{} files", self.limits.max_files),
+                "max_extracted_files",
+            )
+            .into());
+        }
+        self.size.set(self.size.get().saturating_add(size));
+        if self.size.get() > self.limits.max_size {
+            return Err(ErrorKind::ExtractionLimitExceeded(
+                describe_size(self.limits.max_size),
+                "max_extracted_size",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for ExtractionBudget {
+    fn default() -> Self {
+        Self::new(ExtractionLimits::DEFAULT)
+    }
+}
+
 #[derive(Debug)]
 pub struct TarPackage();
 
 impl TarPackage {
-    pub fn unpack<R: Read>(stream: R, path: &Path) -> Result<()> {
+    pub fn unpack<R: Read>(stream: R, path: &Path, budget: &ExtractionBudget) -> Result<()> {
         let mut archive = tar::Archive::new(stream);
         // The lean-installer packages unpack to a directory called
         // $pkgname-$version-$target. Skip that directory when
         // unpacking.
-        unpack_without_first_dir(&mut archive, path)
+        unpack_without_first_dir(&mut archive, path, budget)
     }
 }
 
-fn unpack_without_first_dir<R: Read>(archive: &mut tar::Archive<R>, path: &Path) -> Result<()> {
+fn unpack_without_first_dir<R: Read>(
+    archive: &mut tar::Archive<R>,
+    path: &Path,
+    budget: &ExtractionBudget,
+) -> Result<()> {
+    let root = fs::create_dir_all(path)
+        .and_then(|_| fs::canonicalize(path))
+        .chain_err(|| ErrorKind::ExtractingPackage)?;
     let entries = archive
         .entries()
         .chain_err(|| ErrorKind::ExtractingPackage)?;
     for entry in entries {
         let mut entry = entry.chain_err(|| ErrorKind::ExtractingPackage)?;
+        // The header size is exactly what is written for regular files
+        budget.add_entry(entry.header().size().unwrap_or(0))?;
         let relpath = {
             let path = entry.path();
             let path = path.chain_err(|| ErrorKind::ExtractingPackage)?;
             path.into_owned()
         };
-        let mut components = relpath.components();
-        // Throw away the first path component
-        components.next();
-        let full_path = path.join(&components.as_path());
-
-        // Create the full path to the entry if it does not exist already
-        match full_path.parent() {
-            Some(parent) if !parent.exists() => {
-                ::std::fs::create_dir_all(&parent).chain_err(|| ErrorKind::ExtractingPackage)?
-            }
-            _ => (),
+        let full_path = match entry_path(path, &relpath)? {
+            Some(full_path) => full_path,
+            None => continue,
         };
+        create_parent_dir(&root, &full_path, &relpath)?;
+
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry
+                .link_name()
+                .chain_err(|| ErrorKind::ExtractingPackage)?
+                .ok_or(ErrorKind::ExtractingPackage)?
+                .into_owned();
+            if entry_type.is_hard_link() {
+                // Hard link targets are other entries of the archive
+                let target = entry_path(path, &target)?.ok_or_else(|| unsafe_entry(&relpath))?;
+                fs::hard_link(&target, &full_path).chain_err(|| ErrorKind::ExtractingPackage)?;
+                continue;
+            }
+            let dir = full_path.parent().unwrap_or(path);
+            check_link_target(path, dir, &target).map_err(|_| unsafe_entry(&relpath))?;
+        }
 
         entry
             .unpack(&full_path)
@@ -55,11 +169,69 @@ fn unpack_without_first_dir<R: Read>(archive: &mut tar::Archive<R>, path: &Path)
     Ok(())
 }
 
+fn unsafe_entry(relpath: &Path) -> Error {
+    ErrorKind::UnsafeArchiveEntry(relpath.display().to_string()).into()
+}
+
+/// The path the archive entry `relpath` is extracted to inside `path`. The
+/// lean-installer packages unpack to a directory called
+/// $pkgname-$version-$target, so the first path component is skipped,
+/// making the top-level directory itself map to `None`. Fails for absolute
+/// paths and paths containing `..`, which could be used to write outside of
+/// `path`.
+fn entry_path(path: &Path, relpath: &Path) -> Result<Option<PathBuf>> {
+    let mut components = relpath.components().filter(|c| *c != PathComponent::CurDir);
+    match components.next() {
+        Some(PathComponent::Normal(_)) => {}
+        None => return Ok(None),
+        Some(_) => return Err(unsafe_entry(relpath)),
+    }
+    let mut full_path = path.to_owned();
+    for component in components {
+        match component {
+            PathComponent::Normal(c) => full_path.push(c),
+            _ => return Err(unsafe_entry(relpath)),
+        }
+    }
+    Ok(Some(full_path).filter(|p| p != path))
+}
+
+/// Fails if the symbolic link target `target` of a link in `dir` points
+/// outside of `path`
+fn check_link_target(path: &Path, dir: &Path, target: &Path) -> std::result::Result<(), ()> {
+    let mut depth = dir.strip_prefix(path).map_err(|_| ())?.components().count();
+    for component in target.components() {
+        match component {
+            PathComponent::Normal(_) => depth += 1,
+            PathComponent::CurDir => {}
+            PathComponent::ParentDir => depth = depth.checked_sub(1).ok_or(())?,
+            PathComponent::RootDir | PathComponent::Prefix(_) => return Err(()),
+        }
+    }
+    Ok(())
+}
+
+/// Creates the parent directory of `full_path` if it does not exist already,
+/// failing if it is outside of the extraction directory `root` because one of
+/// its ancestors is a symbolic link extracted earlier
+fn create_parent_dir(root: &Path, full_path: &Path, relpath: &Path) -> Result<()> {
+    if let Some(parent) = full_path.parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent).chain_err(|| ErrorKind::ExtractingPackage)?;
+        }
+        let parent = fs::canonicalize(parent).chain_err(|| ErrorKind::ExtractingPackage)?;
+        if !parent.starts_with(root) {
+            return Err(unsafe_entry(relpath));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ZipPackage();
 
 impl ZipPackage {
-    pub fn unpack<R: Read + Seek>(stream: R, path: &Path) -> Result<()> {
+    pub fn unpack<R: Read + Seek>(stream: R, path: &Path, budget: &ExtractionBudget) -> Result<()> {
         let mut archive = ZipArchive::new(stream).chain_err(|| ErrorKind::ExtractingPackage)?;
         /*
         let mut src = archive.by_name("elan-init.exe").chain_err(|| "failed to extract update")?;
@@ -69,42 +241,47 @@ impl ZipPackage {
         // The lean-installer packages unpack to a directory called
         // $pkgname-$version-$target. Skip that directory when
         // unpacking.
-        Self::unpack_without_first_dir(&mut archive, &path)
+        Self::unpack_without_first_dir(&mut archive, path, budget)
     }
-    pub fn unpack_file(path: &Path, into: &Path) -> Result<()> {
+    pub fn unpack_file(path: &Path, into: &Path, budget: &ExtractionBudget) -> Result<()> {
         let file = File::open(path).chain_err(|| ErrorKind::ExtractingPackage)?;
-        Self::unpack(file, into)
+        Self::unpack(file, into, budget)
     }
 
     fn unpack_without_first_dir<R: Read + Seek>(
         archive: &mut ZipArchive<R>,
         path: &Path,
+        budget: &ExtractionBudget,
     ) -> Result<()> {
+        let root = fs::create_dir_all(path)
+            .and_then(|_| fs::canonicalize(path))
+            .chain_err(|| ErrorKind::ExtractingPackage)?;
         for i in 0..archive.len() {
             let mut entry = archive
                 .by_index(i)
                 .chain_err(|| ErrorKind::ExtractingPackage)?;
+            let size = entry.size();
+            budget.add_entry(size)?;
             if entry.name().ends_with('/') {
                 continue; // skip directories
             }
-            let relpath = PathBuf::from(entry.name());
-            let mut components = relpath.components();
-            // Throw away the first path component
-            components.next();
-            let full_path = path.join(&components.as_path());
-
-            // Create the full path to the entry if it does not exist already
-            match full_path.parent() {
-                Some(parent) if !parent.exists() => {
-                    fs::create_dir_all(&parent).chain_err(|| ErrorKind::ExtractingPackage)?
-                }
-                _ => (),
+            // Rejects absolute paths and `..` components
+            let relpath = entry
+                .enclosed_name()
+                .map(PathBuf::from)
+                .ok_or_else(|| ErrorKind::UnsafeArchiveEntry(entry.name().to_owned()))?;
+            let full_path = match entry_path(path, &relpath)? {
+                Some(full_path) => full_path,
+                None => continue,
             };
+            create_parent_dir(&root, &full_path, &relpath)?;
 
             {
                 let mut dst =
                     File::create(&full_path).chain_err(|| ErrorKind::ExtractingPackage)?;
-                io::copy(&mut entry, &mut dst).chain_err(|| ErrorKind::ExtractingPackage)?;
+                // Never write more than the size accounted for above
+                io::copy(&mut (&mut entry).take(size), &mut dst)
+                    .chain_err(|| ErrorKind::ExtractingPackage)?;
                 #[cfg(unix)]
                 {
                     use std::os::unix::fs::PermissionsExt;
@@ -112,7 +289,8 @@ impl ZipPackage {
                     if let Some(mode) = entry.unix_mode() {
                         let mut ro_mode = fs::Permissions::from_mode(mode);
                         ro_mode.set_readonly(true);
-                        fs::set_permissions(&full_path, ro_mode).unwrap();
+                        fs::set_permissions(&full_path, ro_mode)
+                            .chain_err(|| ErrorKind::ExtractingPackage)?;
                     }
                 }
             } // make sure to close `dst` before setting mtime
@@ -121,7 +299,8 @@ impl ZipPackage {
                 (mtime / 1000000000) as i64,
                 (mtime % 1000000000) as u32,
             );
-            filetime::set_file_times(&full_path, mtime, mtime).unwrap();
+            filetime::set_file_times(&full_path, mtime, mtime)
+                .chain_err(|| ErrorKind::ExtractingPackage)?;
         }
 
         Ok(())
@@ -132,14 +311,14 @@ impl ZipPackage {
 pub struct TarGzPackage();
 
 impl TarGzPackage {
-    pub fn unpack<R: Read>(stream: R, path: &Path) -> Result<()> {
+    pub fn unpack<R: Read>(stream: R, path: &Path, budget: &ExtractionBudget) -> Result<()> {
         let stream = flate2::read::GzDecoder::new(stream);
 
-        TarPackage::unpack(stream, path)
+        TarPackage::unpack(stream, path, budget)
     }
-    pub fn unpack_file(path: &Path, into: &Path) -> Result<()> {
+    pub fn unpack_file(path: &Path, into: &Path, budget: &ExtractionBudget) -> Result<()> {
         let file = File::open(path).chain_err(|| ErrorKind::ExtractingPackage)?;
-        Self::unpack(file, into)
+        Self::unpack(file, into, budget)
     }
 }
 
@@ -147,13 +326,184 @@ impl TarGzPackage {
 pub struct TarZstdPackage();
 
 impl TarZstdPackage {
-    pub fn unpack<R: Read>(stream: R, path: &Path) -> Result<()> {
+    pub fn unpack<R: Read>(stream: R, path: &Path, budget: &ExtractionBudget) -> Result<()> {
         let stream = zstd::stream::read::Decoder::new(stream)?;
 
-        TarPackage::unpack(stream, path)
+        TarPackage::unpack(stream, path, budget)
     }
-    pub fn unpack_file(path: &Path, into: &Path) -> Result<()> {
+    pub fn unpack_file(path: &Path, into: &Path, budget: &ExtractionBudget) -> Result<()> {
         let file = File::open(path).chain_err(|| ErrorKind::ExtractingPackage)?;
-        Self::unpack(file, into)
+        Self::unpack(file, into, budget)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    /// Builds a tarball, bypassing the path checks of `tar::Builder`
+    fn tarball(entries: &[(&str, tar::EntryType, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, entry_type, link_or_data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            let data = if entry_type.is_symlink() || entry_type.is_hard_link() {
+                header.set_link_name(link_or_data).unwrap();
+                &[]
+            } else {
+                link_or_data.as_bytes()
+            };
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn zip(names: &[&str]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for name in names {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            writer.write_all(b"data").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Unpacks into a subdirectory of a fresh temporary directory, checking
+    /// that nothing else was written to the latter
+    fn unpack(unpack: impl FnOnce(&Path) -> Result<()>) -> (tempfile::TempDir, Result<()>) {
+        let root = tempfile::tempdir().unwrap();
+        let into = root.path().join("into");
+        let result = unpack(&into);
+        for entry in fs::read_dir(root.path()).unwrap() {
+            assert_eq!(entry.unwrap().path(), into);
+        }
+        (root, result)
+    }
+
+    fn unsafe_entry_name(result: Result<()>) -> String {
+        match result {
+            Err(Error(ErrorKind::UnsafeArchiveEntry(name), _)) => name,
+            r => panic!("expected unsafe entry error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_tar_rejects_escaping_paths() {
+        let file = tar::EntryType::Regular;
+        for name in &["top/../../evil", "top/a/../../../evil", "../evil", "/evil"] {
+            let data = tarball(&[(name, file, "data")]);
+            let (_root, result) =
+                unpack(|into| TarPackage::unpack(&data[..], into, &ExtractionBudget::default()));
+            assert_eq!(unsafe_entry_name(result), *name);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_tar_rejects_escaping_links() {
+        let file = tar::EntryType::Regular;
+        let symlink = tar::EntryType::Symlink;
+        let hard_link = tar::EntryType::Link;
+        for entries in &[
+            vec![("top/link", symlink, "../evil")],
+            vec![("top/dir/link", symlink, "../../../evil")],
+            vec![("top/link", symlink, "/etc/passwd")],
+            vec![("top/link", hard_link, "../../etc/passwd")],
+            // writing through symlinks that each stay inside on their own
+            vec![
+                ("top/dir/link", symlink, ".."),
+                ("top/link", symlink, "dir/link/.."),
+                ("top/link/evil", file, "data"),
+            ],
+        ] {
+            let data = tarball(entries);
+            let (_root, result) =
+                unpack(|into| TarPackage::unpack(&data[..], into, &ExtractionBudget::default()));
+            unsafe_entry_name(result);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_tar_allows_inner_links() {
+        let data = tarball(&[
+            ("top/", tar::EntryType::Directory, ""),
+            ("top/lib/libfoo.so.1", tar::EntryType::Regular, "data"),
+            ("top/lib/libfoo.so", tar::EntryType::Symlink, "libfoo.so.1"),
+            ("top/bin/lib", tar::EntryType::Symlink, "../lib"),
+            ("top/bin/foo", tar::EntryType::Link, "top/lib/libfoo.so.1"),
+        ]);
+        let (root, result) =
+            unpack(|into| TarPackage::unpack(&data[..], into, &ExtractionBudget::default()));
+        result.unwrap();
+        let into = root.path().join("into");
+        assert_eq!(fs::read(into.join("lib/libfoo.so")).unwrap(), b"data");
+        assert_eq!(fs::read(into.join("bin/lib/libfoo.so.1")).unwrap(), b"data");
+        assert_eq!(fs::read(into.join("bin/foo")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_extraction_limits() {
+        let file = tar::EntryType::Regular;
+        let data = tarball(&[("top/a", file, "1234"), ("top/b", file, "5678")]);
+        let limits = |max_size, max_files| {
+            ExtractionBudget::new(ExtractionLimits {
+                max_size,
+                max_files,
+            })
+        };
+        for (budget, setting) in &[
+            (limits(7, 10), Some("max_extracted_size")),
+            (limits(8, 1), Some("max_extracted_files")),
+            (limits(8, 2), None),
+        ] {
+            let (_root, result) = unpack(|into| TarPackage::unpack(&data[..], into, budget));
+            match (result, setting) {
+                (Err(Error(ErrorKind::ExtractionLimitExceeded(limit, s), _)), Some(setting)) => {
+                    assert_eq!(s, *setting);
+                    assert!(
+                        ["7 bytes", "1 files"].contains(&limit.as_str()),
+                        "{}",
+                        limit
+                    );
+                }
+                (Ok(()), None) => {}
+                (r, _) => panic!("unexpected result {:?} for {:?}", r, setting),
+            }
+        }
+
+        assert_eq!(describe_size(16 << 30), "16384 MiB");
+        assert_eq!(describe_size((1 << 20) + 1), "1048577 bytes");
+
+        // the budget is shared by all archives of an installation
+        let budget = limits(12, 10);
+        let (_root, result) = unpack(|into| TarPackage::unpack(&data[..], into, &budget));
+        result.unwrap();
+        let (_root, result) = unpack(|into| TarPackage::unpack(&data[..], into, &budget));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_zip_rejects_escaping_paths() {
+        for name in &["top/../../evil", "../evil", "/evil"] {
+            let data = zip(&[name]);
+            let (_root, result) = unpack(|into| {
+                ZipPackage::unpack(Cursor::new(&data), into, &ExtractionBudget::default())
+            });
+            assert_eq!(unsafe_entry_name(result), *name);
+        }
+        let data = zip(&["top/bin/lean", "top/./lib/x"]);
+        let (root, result) = unpack(|into| {
+            ZipPackage::unpack(Cursor::new(&data), into, &ExtractionBudget::default())
+        });
+        result.unwrap();
+        assert!(root.path().join("into/bin/lean").is_file());
+        assert!(root.path().join("into/lib/x").is_file());
     }
 }
@@ -11,6 +11,20 @@ pub const DEFAULT_CONFIG_VERSION: &'static str = "1";
 pub struct Config {
     pub config_version: String,
     pub components: Vec<Component>,
+    /// The target of the installed toolchain archive
+    pub target: Option<String>,
+    /// The origin and release the toolchain was installed from
+    pub origin: Option<String>,
+    pub release: Option<String>,
+    /// Paths relative to the prefix that were removed after installation by
+    /// `elan toolchain strip`, `/`-separated
+    pub stripped: Vec<String>,
+    /// When the toolchain was installed, as a Unix timestamp in seconds
+    pub installed_at: Option<u64>,
+    /// SHA-256 hash of the installed toolchain archive, lowercase hex
+    pub checksum: Option<String>,
+    /// Size of the prefix in bytes, updated whenever elan adds or removes files
+    pub size: Option<u64>,
 }
 
 impl Config {
@@ -27,6 +41,14 @@ impl Config {
         Ok(Config {
             config_version: version,
             components: components,
+            target: get_opt_string(&mut table, "target", path)?,
+            origin: get_opt_string(&mut table, "origin", path)?,
+            release: get_opt_string(&mut table, "release", path)?,
+            stripped: get_opt_string_array(&mut table, "stripped", path)?.unwrap_or_default(),
+            installed_at: get_opt_integer(&mut table, "installed_at", path)?
+                .and_then(|n| u64::try_from(n).ok()),
+            checksum: get_opt_string(&mut table, "checksum", path)?,
+            size: get_opt_integer(&mut table, "size", path)?.and_then(|n| u64::try_from(n).ok()),
         })
     }
     pub fn to_toml(self) -> toml::value::Table {
@@ -39,6 +61,31 @@ impl Config {
         if !components.is_empty() {
             result.insert("components".to_owned(), toml::Value::Array(components));
         }
+        if let Some(target) = self.target {
+            result.insert("target".to_owned(), toml::Value::String(target));
+        }
+        if let Some(origin) = self.origin {
+            result.insert("origin".to_owned(), toml::Value::String(origin));
+        }
+        if let Some(release) = self.release {
+            result.insert("release".to_owned(), toml::Value::String(release));
+        }
+        if !self.stripped.is_empty() {
+            let stripped = self.stripped.into_iter().map(toml::Value::String).collect();
+            result.insert("stripped".to_owned(), toml::Value::Array(stripped));
+        }
+        if let Some(installed_at) = self.installed_at {
+            result.insert(
+                "installed_at".to_owned(),
+                toml::Value::Integer(installed_at as i64),
+            );
+        }
+        if let Some(checksum) = self.checksum {
+            result.insert("checksum".to_owned(), toml::Value::String(checksum));
+        }
+        if let Some(size) = self.size {
+            result.insert("size".to_owned(), toml::Value::Integer(size as i64));
+        }
         result
     }
 
@@ -76,6 +123,13 @@ impl Config {
         Config {
             config_version: DEFAULT_CONFIG_VERSION.to_owned(),
             components: Vec::new(),
+            target: None,
+            origin: None,
+            release: None,
+            stripped: Vec::new(),
+            installed_at: None,
+            checksum: None,
+            size: None,
         }
     }
 }
@@ -1,15 +1,22 @@
+use crate::component::{Components, ExtractionLimits};
 use crate::download::DownloadCfg;
 use crate::errors::*;
-use crate::manifestation::Manifestation;
+use crate::journal::Journal;
+use crate::manifest::Component;
+pub use crate::manifestation::InstallPlan;
+use crate::manifestation::{InstallRequest, Manifestation};
+use crate::notifications::*;
 use crate::prefix::InstallPrefix;
+use crate::target::Targets;
 use elan_utils::{
-    self,
+    self, endpoints, lock,
     utils::{self},
 };
 use regex::Regex;
 use serde_derive::Serialize;
 
 use std::fmt;
+use std::path::Path;
 
 // Fully-resolved toolchain descriptors. These always have full target
 // triples attached to them and are used for canonical identification,
@@ -30,36 +37,151 @@ pub enum ToolchainDesc {
     },
 }
 
+/// Origin of toolchains whose name does not specify one
+pub const DEFAULT_ORIGIN: &str = "leanprover/lean4";
+
+/// Whether `release` looks like an abbreviated or full Git commit hash rather
+/// than a tag
+pub fn is_commit_hash(release: &str) -> bool {
+    (7..=40).contains(&release.len())
+        && release
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        && release.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// Device names that cannot be used as file names on Windows, even with an
+/// extension
+const RESERVED_WINDOWS_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 impl ToolchainDesc {
+    /// The canonical form of `release` of `origin` as spelled by a user, so
+    /// that e.g. `4.9.0`, `v4.9.0`, and `leanprover/lean4:v4.9.0` all
+    /// describe the same toolchain. Channels, `lean-toolchain`, and commit
+    /// hashes are recorded in `from_channel` to be resolved later. GitHub
+    /// repository names are case-insensitive, so the origin is lowercased.
+    pub fn from_release_spec(origin: Option<&str>, release: &str) -> Self {
+        let mut origin = origin.unwrap_or(DEFAULT_ORIGIN).to_ascii_lowercase();
+        let mut release = release.to_owned();
+        if release.starts_with("nightly") && !origin.ends_with("-nightly") {
+            origin = format!("{}-nightly", origin);
+        }
+        let mut from_channel = None;
+        if ["lean-toolchain", "stable", "beta", "nightly"].contains(&&*release)
+            || is_commit_hash(&release)
+        {
+            from_channel = Some(release.clone());
+        } else if release.starts_with(char::is_numeric) {
+            release = format!("v{}", release)
+        }
+        ToolchainDesc::Remote {
+            origin,
+            release,
+            from_channel,
+        }
+    }
+
     pub fn from_resolved_str(name: &str) -> Result<Self> {
-        let pattern = r"^(?:([a-zA-Z0-9-]+[/][a-zA-Z0-9-]+)[:])?([a-zA-Z0-9-.]+)$";
+        let pattern = r"^(?:([a-zA-Z0-9-_]+[/][a-zA-Z0-9-_]+)[:])?([a-zA-Z0-9-.]+)$";
 
         let re = Regex::new(&pattern).unwrap();
-        if let Some(c) = re.captures(name) {
-            match c.get(1) {
-                Some(origin) => {
-                    let origin = origin.as_str().to_owned();
-                    let release = c.get(2).unwrap().as_str().to_owned();
-                    Ok(ToolchainDesc::Remote {
-                        origin,
-                        release,
-                        from_channel: None,
-                    })
-                }
-                None => {
-                    let name = c.get(2).unwrap().as_str().to_owned();
-                    Ok(ToolchainDesc::Local { name })
+        let c = re
+            .captures(name)
+            .ok_or_else(|| ErrorKind::InvalidToolchainName(name.to_string()))?;
+        let desc = match c.get(1) {
+            Some(origin) => {
+                let origin = origin.as_str().to_owned();
+                let release = c.get(2).unwrap().as_str().to_owned();
+                ToolchainDesc::Remote {
+                    origin,
+                    release,
+                    from_channel: None,
                 }
             }
-        } else {
-            Err(ErrorKind::InvalidToolchainName(name.to_string()).into())
-        }
+            None => {
+                let name = c.get(2).unwrap().as_str().to_owned();
+                ToolchainDesc::Local { name }
+            }
+        };
+        desc.validate()?;
+        Ok(desc)
     }
 
+    /// Parses the name of a toolchain's installation directory, failing
+    /// unless it is the canonical directory name of the resulting toolchain
     pub fn from_toolchain_dir(dir_name: &str) -> Result<Self> {
-        // de-sanitize toolchain file names (best effort...)
         let name = dir_name.replace("---", ":").replace("--", "/");
-        Self::from_resolved_str(&name)
+        let desc = Self::from_resolved_str(&name)?;
+        if desc.to_toolchain_dir() != dir_name {
+            return Err(ErrorKind::UnsafeToolchainName(
+                dir_name.to_owned(),
+                "not a canonical toolchain directory name",
+            )
+            .into());
+        }
+        Ok(desc)
+    }
+
+    /// The name of the toolchain's installation directory. ':' and '/' are
+    /// not valid in directory names on all platforms, so they are replaced
+    /// with "---" and "--".
+    pub fn to_toolchain_dir(&self) -> String {
+        self.to_string().replace('/', "--").replace(':', "---")
+    }
+
+    /// Checks that the toolchain can be safely stored in its installation
+    /// directory, i.e. that the directory name cannot escape the toolchains
+    /// directory, is valid on all platforms, and maps back to this toolchain
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason| Err(ErrorKind::UnsafeToolchainName(self.to_string(), reason).into());
+        let name = match self {
+            ToolchainDesc::Local { name } => name,
+            ToolchainDesc::Remote { release, .. } => release,
+        };
+        if let ToolchainDesc::Remote { origin, .. } = self {
+            let segments: Vec<_> = origin.split('/').collect();
+            if segments.len() != 2 {
+                return invalid("the origin must be of the form '<owner>/<repo>'");
+            }
+            for segment in &segments {
+                if segment.is_empty()
+                    || !segment
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    return invalid("the origin may only contain letters, digits, '-', and '_'");
+                }
+                // would be confused with the separators in the directory name
+                if segment.contains("--") || segment.starts_with('-') || segment.ends_with('-') {
+                    return invalid("the origin may not contain '--' or start or end with '-'");
+                }
+            }
+        }
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        {
+            return invalid("the name may only contain letters, digits, '-', and '.'");
+        }
+        if name.starts_with('.') || name.ends_with('.') {
+            // includes "." and "..", and trailing dots are dropped on Windows
+            return invalid("the name may not start or end with '.'");
+        }
+        if name.contains("--") || name.starts_with('-') || name.ends_with('-') {
+            return invalid("the name may not contain '--' or start or end with '-'");
+        }
+        let stem = name.split('.').next().unwrap_or(name);
+        if RESERVED_WINDOWS_NAMES
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(stem))
+        {
+            return invalid("the name is reserved on Windows");
+        }
+        Ok(())
     }
 }
 
@@ -78,8 +200,13 @@ pub fn install_from_dist<'a>(
     download: DownloadCfg<'a>,
     toolchain: &ToolchainDesc,
     prefix: &InstallPrefix,
+    components: &[Component],
+    targets: &Targets,
+    reinstall: bool,
+    limits: ExtractionLimits,
 ) -> Result<()> {
     let toolchain_str = toolchain.to_string();
+    let existed = utils::path_exists(prefix.path());
     let manifestation = Manifestation::open(prefix.clone())?;
 
     let ToolchainDesc::Remote {
@@ -88,26 +215,31 @@ pub fn install_from_dist<'a>(
     else {
         return Ok(());
     };
-    let url = format!(
-        "https://github.com/{}/releases/expanded_assets/{}",
-        origin, release
-    );
-    let res =
-        match manifestation.install(&origin, &url, &download.temp_cfg, download.notify_handler) {
-            Ok(()) => Ok(()),
-            e
-            @ Err(Error(ErrorKind::Utils(elan_utils::ErrorKind::DownloadNotExists { .. }), _)) => e
-                .chain_err(|| {
-                    format!(
-                        "could not download nonexistent lean version `{}`",
-                        toolchain_str
-                    )
-                }),
-            Err(e) => Err(e),
-        };
+    let url = release_url(origin, release);
+    let request = InstallRequest {
+        origin,
+        release,
+        url: &url,
+        targets,
+        reinstall,
+        limits,
+    };
+    let res = match manifestation.install(
+        &request,
+        components,
+        download.temp_cfg,
+        download.notify_handler,
+    ) {
+        Ok(()) => Ok(()),
+        e @ Err(Error(ErrorKind::Utils(elan_utils::ErrorKind::DownloadNotExists { .. }), _)) => {
+            e.chain_err(|| ErrorKind::NonexistentRelease(toolchain_str))
+        }
+        Err(e) => Err(e),
+    };
 
-    // Don't leave behind an empty / broken installation directory
-    if res.is_err() {
+    // Don't leave behind an empty / broken installation directory, but keep
+    // an installation that was to be replaced
+    if res.is_err() && !existed {
         // FIXME Ignoring cascading errors
         let _ = utils::remove_dir("toolchain", prefix.path(), &|n| {
             (download.notify_handler)(n.into())
@@ -117,6 +249,367 @@ pub fn install_from_dist<'a>(
     res
 }
 
+/// Determines the archives `install_from_dist` would download, without
+/// downloading them or touching `prefix`
+pub fn plan_install_from_dist<'a>(
+    download: DownloadCfg<'a>,
+    toolchain: &ToolchainDesc,
+    prefix: &InstallPrefix,
+    components: &[Component],
+    targets: &Targets,
+) -> Result<InstallPlan> {
+    let (origin, release) = remote_release(toolchain)?;
+    let manifestation = Manifestation::open(prefix.clone())?;
+    match manifestation.plan(
+        origin,
+        &release_url(origin, release),
+        components,
+        targets,
+        download.temp_cfg,
+        download.notify_handler,
+    ) {
+        e @ Err(Error(ErrorKind::Utils(elan_utils::ErrorKind::DownloadNotExists { .. }), _)) => {
+            e.chain_err(|| ErrorKind::NonexistentRelease(toolchain.to_string()))
+        }
+        res => res,
+    }
+}
+
+fn release_url(origin: &str, release: &str) -> String {
+    format!(
+        "{}/{}/releases/expanded_assets/{}",
+        endpoints::GITHUB.url(),
+        origin,
+        release
+    )
+}
+
+fn remote_release(toolchain: &ToolchainDesc) -> Result<(&str, &str)> {
+    match toolchain {
+        ToolchainDesc::Remote {
+            origin, release, ..
+        } => Ok((origin, release)),
+        ToolchainDesc::Local { name } => {
+            Err(format!("linked toolchain '{}' does not support components", name).into())
+        }
+    }
+}
+
+/// Lists the optional components published for a toolchain release
+pub fn available_components<'a>(
+    download: DownloadCfg<'a>,
+    toolchain: &ToolchainDesc,
+    prefix: &InstallPrefix,
+    targets: &Targets,
+) -> Result<Vec<Component>> {
+    let (origin, release) = remote_release(toolchain)?;
+    let manifestation = Manifestation::open(prefix.clone())?;
+    manifestation.available_components(
+        origin,
+        &release_url(origin, release),
+        targets,
+        download.temp_cfg,
+        download.notify_handler,
+    )
+}
+
+pub fn installed_components(prefix: &InstallPrefix) -> Result<Vec<Component>> {
+    Components::open(prefix.clone()).list()
+}
+
+/// Completes or rolls back all interrupted toolchain installations in
+/// `toolchains_dir` and removes the lock files of installations whose
+/// process was killed
+pub fn recover_interrupted_installs(
+    toolchains_dir: &Path,
+    notify_handler: &dyn Fn(Notification<'_>),
+) -> Result<()> {
+    if !utils::is_directory(toolchains_dir) {
+        return Ok(());
+    }
+    for entry in utils::read_dir("toolchains", toolchains_dir)? {
+        let path = entry?.path();
+        if let Some(prefix) = Journal::prefix_of(&path) {
+            Manifestation::open(InstallPrefix::from(prefix))?.recover(notify_handler)?;
+        }
+    }
+    for entry in utils::read_dir("toolchains", toolchains_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "lock") {
+            lock::remove_stale(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether an installation into `prefix` was interrupted and could not be
+/// completed or rolled back by `recover_interrupted_installs`
+pub fn has_interrupted_install(prefix: &Path) -> bool {
+    Journal::path_for(prefix).exists()
+}
+
+/// Fails if the toolchain installed at `prefix` is recorded to come from a
+/// different origin or release than `toolchain`, e.g. because both map to the
+/// same directory on a case-insensitive file system. Toolchains installed by
+/// versions of elan that did not record this are not checked.
+pub fn check_provenance(prefix: &InstallPrefix, toolchain: &ToolchainDesc) -> Result<()> {
+    let ToolchainDesc::Remote {
+        origin, release, ..
+    } = toolchain
+    else {
+        return Ok(());
+    };
+    let (recorded_origin, recorded_release) = match Components::open(prefix.clone()).provenance()? {
+        Some(provenance) => provenance,
+        None => return Ok(()),
+    };
+    // GitHub repository names are case-insensitive
+    if !recorded_origin.eq_ignore_ascii_case(origin) || recorded_release != *release {
+        return Err(ErrorKind::ToolchainProvenanceMismatch(
+            toolchain.to_string(),
+            format!("{}:{}", recorded_origin, recorded_release),
+            prefix.path().to_owned(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Installs the toolchain directory `staged`, e.g. one restored from a
+/// cache, into `prefix` unless `toolchain` is installed already, returning
+/// whether it was installed. Fails if `staged` was installed from a different
+/// release than `toolchain`.
+pub fn install_unpacked(
+    toolchain: &ToolchainDesc,
+    prefix: &InstallPrefix,
+    staged: &Path,
+    notify_handler: &dyn Fn(Notification<'_>),
+) -> Result<bool> {
+    check_provenance(&InstallPrefix::from(staged.to_owned()), toolchain)?;
+    Manifestation::open(prefix.clone())?.install_staged(staged, notify_handler)
+}
+
+/// The paths recorded as stripped from an installed toolchain
+pub fn stripped_paths(prefix: &InstallPrefix) -> Result<Vec<String>> {
+    Components::open(prefix.clone()).stripped()
+}
+
+/// Records that `paths`, relative to `prefix` and `/`-separated, have been
+/// removed from an installed toolchain on purpose
+pub fn record_stripped(prefix: &InstallPrefix, paths: &[String]) -> Result<()> {
+    let components = Components::open(prefix.clone());
+    components.add_stripped(paths)?;
+    components.update_size()
+}
+
+/// The target recorded for an installed toolchain, if any
+pub fn installed_target(prefix: &InstallPrefix) -> Result<Option<String>> {
+    Components::open(prefix.clone()).target()
+}
+
+/// When a toolchain was installed, as a Unix timestamp in seconds, if recorded
+pub fn installed_at(prefix: &InstallPrefix) -> Result<Option<u64>> {
+    Components::open(prefix.clone()).installed_at()
+}
+
+/// The SHA-256 hash of the archive a toolchain was installed from, if
+/// recorded
+pub fn installed_checksum(prefix: &InstallPrefix) -> Result<Option<String>> {
+    Components::open(prefix.clone()).checksum()
+}
+
+/// The size of an installed toolchain in bytes as recorded by the last
+/// change elan made to it, if any
+pub fn installed_size(prefix: &InstallPrefix) -> Result<Option<u64>> {
+    Components::open(prefix.clone()).size()
+}
+
+pub fn install_component<'a>(
+    download: DownloadCfg<'a>,
+    toolchain: &ToolchainDesc,
+    prefix: &InstallPrefix,
+    component: &Component,
+    targets: &Targets,
+    limits: ExtractionLimits,
+) -> Result<()> {
+    let (origin, release) = remote_release(toolchain)?;
+    let manifestation = Manifestation::open(prefix.clone())?;
+    let request = InstallRequest {
+        origin,
+        release,
+        url: &release_url(origin, release),
+        targets,
+        reinstall: false,
+        limits,
+    };
+    manifestation.install_component(
+        &request,
+        component,
+        download.temp_cfg,
+        download.notify_handler,
+    )
+}
+
+pub fn remove_component(
+    prefix: &InstallPrefix,
+    component: &Component,
+    notify_handler: &dyn Fn(Notification<'_>),
+) -> Result<()> {
+    let manifestation = Manifestation::open(prefix.clone())?;
+    manifestation.remove_component(component, notify_handler)
+}
+
 pub fn host_triple() -> &'static str {
     include_str!(concat!(env!("OUT_DIR"), "/target.txt"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small deterministic xorshift generator, so that failures are
+    /// reproducible without depending on a randomness crate
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn string(&mut self, alphabet: &[&str], max_len: u64) -> String {
+            let len = self.next() % (max_len + 1);
+            (0..len)
+                .map(|_| alphabet[(self.next() % alphabet.len() as u64) as usize])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_toolchain_dir_round_trip() {
+        let alphabet = &[
+            "a", "Z", "0", "9", "-", "--", "---", ".", "..", "_", "/", ":", "\\", " ", "CON",
+            "nul", "é", "\0",
+        ];
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..5_000 {
+            let name = rng.string(alphabet, 8);
+            if let Ok(desc) = ToolchainDesc::from_resolved_str(&name) {
+                assert_eq!(desc.to_string(), name);
+                let dir = desc.to_toolchain_dir();
+                assert!(!dir.is_empty() && !dir.starts_with('.'), "{:?}", name);
+                assert!(
+                    !dir.contains(['/', '\\', ':', '\0']) && dir != "..",
+                    "{:?}",
+                    name
+                );
+                assert_eq!(ToolchainDesc::from_toolchain_dir(&dir).unwrap(), desc);
+            }
+            if let Ok(desc) = ToolchainDesc::from_toolchain_dir(&name) {
+                assert_eq!(desc.to_toolchain_dir(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_release_spec() {
+        for (origin, release, expected) in [
+            (None, "4.9.0", "leanprover/lean4:v4.9.0"),
+            (None, "v4.9.0", "leanprover/lean4:v4.9.0"),
+            (
+                Some("LeanProver/Lean4"),
+                "v4.9.0",
+                "leanprover/lean4:v4.9.0",
+            ),
+            (
+                Some("LeanProver/lean4"),
+                "nightly-2024-01-01",
+                "leanprover/lean4-nightly:nightly-2024-01-01",
+            ),
+        ] {
+            assert_eq!(
+                ToolchainDesc::from_release_spec(origin, release).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_commit_hash() {
+        for release in ["abcdef0", "0123456789abcdef0123456789abcdef01234567"] {
+            assert!(is_commit_hash(release), "{}", release);
+        }
+        for release in [
+            "abcdef",
+            "0123456789abcdef0123456789abcdef012345678",
+            // could be a date or a version
+            "20240101",
+            "ABCDEF0",
+            "v4.9.0",
+            "nightly",
+        ] {
+            assert!(!is_commit_hash(release), "{}", release);
+        }
+    }
+
+    #[test]
+    fn test_unsafe_toolchain_names() {
+        for name in &[
+            "..",
+            ".",
+            "../x",
+            "a/b:..",
+            "a/b:.hidden",
+            "trailing.",
+            "CON",
+            "con.txt",
+            "lpt1",
+            "a--b",
+            "a-/b:c",
+            "a/-b:c",
+            "a/b:c--d",
+        ] {
+            assert!(
+                ToolchainDesc::from_resolved_str(name).is_err(),
+                "{:?}",
+                name
+            );
+        }
+        for name in &[
+            "leanprover/lean4:v4.9.0-rc1",
+            "leanprover/lean4-nightly:nightly-2024-01-01",
+            "my_org/my_repo:v1.0",
+            "system",
+            "console",
+        ] {
+            assert!(ToolchainDesc::from_resolved_str(name).is_ok(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_check_provenance() {
+        let dir = tempfile::tempdir().unwrap();
+        let prefix = InstallPrefix::from(dir.path().to_owned());
+        let toolchain = ToolchainDesc::from_resolved_str("leanprover/lean4:v4.9.0").unwrap();
+        // Installed by a version of elan that did not record it
+        check_provenance(&prefix, &toolchain).unwrap();
+
+        Components::open(prefix.clone())
+            .set_provenance("LeanProver/lean4", "v4.9.0")
+            .unwrap();
+        check_provenance(&prefix, &toolchain).unwrap();
+
+        Components::open(prefix.clone())
+            .set_provenance("leanprover/lean4", "v4.8.0")
+            .unwrap();
+        let err = check_provenance(&prefix, &toolchain).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("contains 'leanprover/lean4:v4.8.0' instead"),
+            "{}",
+            err
+        );
+    }
+}
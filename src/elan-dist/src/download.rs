@@ -1,13 +1,22 @@
 use crate::errors::*;
 use crate::notifications::*;
 use crate::temp;
-use elan_utils::utils;
+use elan_utils::{endpoints, utils};
 
 use std::ops;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 const _UPDATE_HASH_LEN: usize = 20;
 
+static SIZE_PROBE: AtomicBool = AtomicBool::new(true);
+
+/// Sets whether downloads are preceded by a HEAD request for their size,
+/// which is done by default
+pub fn set_size_probe(enabled: bool) {
+    SIZE_PROBE.store(enabled, Ordering::Relaxed);
+}
+
 #[derive(Copy, Clone)]
 pub struct DownloadCfg<'a> {
     pub temp_cfg: &'a temp::Cfg,
@@ -27,10 +36,43 @@ impl ops::Deref for File {
 }
 
 impl<'a> DownloadCfg<'a> {
+    /// Downloads `url`, trying the further URLs of the GitHub endpoint in
+    /// turn if it is below the most preferred one
     pub fn download_and_check(&self, url_str: &str) -> Result<temp::File<'a>> {
+        self.download_and_check_any(&endpoints::GITHUB.mirrors_of(url_str))
+    }
+
+    /// Downloads the first of `urls` whose server can be reached and does
+    /// not fail, moving on to the next one otherwise
+    pub fn download_and_check_any(&self, urls: &[String]) -> Result<temp::File<'a>> {
+        for (i, url) in urls.iter().enumerate() {
+            match self.download_one(url) {
+                Err(e) if i + 1 < urls.len() && utils::is_mirror_failure(&e) => {
+                    (self.notify_handler)(
+                        elan_utils::Notification::MirrorFailed(url, &urls[i + 1]).into(),
+                    );
+                }
+                res => return res,
+            }
+        }
+        Err("no URL to download from".into())
+    }
+
+    fn download_one(&self, url_str: &str) -> Result<temp::File<'a>> {
         let url = utils::parse_url(url_str)?;
         let file = self.temp_cfg.new_file()?;
 
+        // Mirrors streaming a download may not send its size, which the
+        // progress display then lacks a total for. Best-effort only, as some
+        // servers do not support HEAD requests.
+        if SIZE_PROBE.load(Ordering::Relaxed) && matches!(url.scheme(), "http" | "https") {
+            if let Ok(Some(len)) = utils::fetch_content_length(&url) {
+                (self.notify_handler)(
+                    elan_utils::Notification::DownloadContentLengthReceived(len).into(),
+                );
+            }
+        }
+
         utils::download_file(&url, &file, &|n| (self.notify_handler)(n.into()))?;
 
         Ok(file)
@@ -23,6 +23,14 @@ error_chain! {
             description("invalid toolchain name")
             display("invalid toolchain name: '{}'", t)
         }
+        UnsafeToolchainName(t: String, reason: &'static str) {
+            description("invalid toolchain name")
+            display("invalid toolchain name '{}': {}", t, reason)
+        }
+        NonexistentRelease(t: String) {
+            description("nonexistent lean version")
+            display("could not download nonexistent lean version `{}`", t)
+        }
         ChecksumFailed {
             url: String,
             expected: String,
@@ -60,6 +68,24 @@ error_chain! {
                     name,
                     path)
         }
+        NoMatchingAsset(targets: Vec<String>, assets: Vec<String>) {
+            description("binary package was not provided for this platform")
+            display("binary package was not provided for this platform (accepted targets: {}); release assets: {}",
+                    targets.join(", "),
+                    if assets.is_empty() { "none".to_owned() } else { assets.join(", ") })
+        }
+        NoMuslAsset(assets: Vec<String>) {
+            description("no release asset for musl-based systems")
+            display("this system uses the musl C library (e.g. Alpine Linux), but this release only \
+                     provides builds for glibc, which would fail to run (release assets: {}). \
+                     Consider building Lean from source and registering it with \
+                     `elan toolchain link`, or use a glibc-based system or container.",
+                    if assets.is_empty() { "none".to_owned() } else { assets.join(", ") })
+        }
+        CorruptJournal(path: PathBuf) {
+            description("corrupt install journal")
+            display("install journal '{}' is corrupt", path.display())
+        }
         CorruptComponent(name: String) {
             description("corrupt component manifest")
             display("component manifest for '{}' is corrupt", name)
@@ -67,6 +93,18 @@ error_chain! {
         ExtractingPackage {
             description("failed to extract package")
         }
+        ExtractionLimitExceeded(limit: String, setting: &'static str) {
+            description("extraction limit exceeded")
+            display("extracting the toolchain exceeds the limit of {}; if the toolchain is expected to be this large, raise the `{}` setting", limit, setting)
+        }
+        ToolchainProvenanceMismatch(toolchain: String, recorded: String, path: PathBuf) {
+            description("toolchain directory contains a different toolchain")
+            display("the directory '{}' of toolchain '{}' contains '{}' instead; uninstall it and try again", path.display(), toolchain, recorded)
+        }
+        UnsafeArchiveEntry(name: String) {
+            description("archive entry outside of the extraction directory")
+            display("refusing to extract archive entry '{}' outside of the extraction directory", name)
+        }
         BadInstallerVersion(v: String) {
             description("unsupported installer version")
             display("unsupported installer version: {}", v)
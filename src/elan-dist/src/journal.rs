@@ -0,0 +1,101 @@
+//! Journal of in-progress toolchain installations.
+//!
+//! Toolchains are unpacked into a unique staging directory and then renamed
+//! into place. Before staging starts, a journal entry `<toolchain>.journal` is
+//! written next to the toolchain directory, and it is updated once the staging
+//! directory is complete. If elan is interrupted, the entry tells a later
+//! recovery pass whether to finish the installation by renaming the staging
+//! directory or to roll it back by deleting it.
+
+use crate::errors::*;
+use elan_utils::toml_utils::*;
+use elan_utils::utils;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+const JOURNAL_EXTENSION: &str = ".journal";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    /// Files are still being unpacked into the staging directory
+    Staging,
+    /// The staging directory is complete and about to be renamed into place
+    Staged,
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    pub staging: PathBuf,
+    pub state: State,
+}
+
+impl Journal {
+    /// The journal entry for the toolchain directory `prefix`
+    pub fn path_for(prefix: &Path) -> PathBuf {
+        let mut name = OsString::from(prefix.as_os_str());
+        name.push(JOURNAL_EXTENSION);
+        PathBuf::from(name)
+    }
+
+    /// The toolchain directory of a journal entry, if `path` is one
+    pub fn prefix_of(path: &Path) -> Option<PathBuf> {
+        let path = path.to_str()?;
+        path.strip_suffix(JOURNAL_EXTENSION).map(PathBuf::from)
+    }
+
+    pub fn begin(prefix: &Path, staging: &Path) -> Result<Self> {
+        let journal = Journal {
+            path: Self::path_for(prefix),
+            staging: staging.to_owned(),
+            state: State::Staging,
+        };
+        journal.write()?;
+        Ok(journal)
+    }
+
+    pub fn read(prefix: &Path) -> Result<Self> {
+        let path = Self::path_for(prefix);
+        let data = utils::read_file("install journal", &path)?;
+        let mut table: toml::value::Table =
+            toml::from_str(&data).map_err(|_| ErrorKind::CorruptJournal(path.clone()))?;
+        let staging = get_string(&mut table, "staging", "")?;
+        let state = match &*get_string(&mut table, "state", "")? {
+            "staging" => State::Staging,
+            "staged" => State::Staged,
+            _ => return Err(ErrorKind::CorruptJournal(path).into()),
+        };
+        Ok(Journal {
+            path,
+            staging: PathBuf::from(staging),
+            state,
+        })
+    }
+
+    fn write(&self) -> Result<()> {
+        let mut table = toml::value::Table::new();
+        table.insert(
+            "staging".to_owned(),
+            toml::Value::String(self.staging.to_string_lossy().into_owned()),
+        );
+        let state = match self.state {
+            State::Staging => "staging",
+            State::Staged => "staged",
+        };
+        table.insert("state".to_owned(), toml::Value::String(state.to_owned()));
+        Ok(utils::write_file(
+            "install journal",
+            &self.path,
+            &toml::Value::Table(table).to_string(),
+        )?)
+    }
+
+    pub fn staged(&mut self) -> Result<()> {
+        self.state = State::Staged;
+        self.write()
+    }
+
+    pub fn finish(self) -> Result<()> {
+        Ok(utils::remove_file("install journal", &self.path)?)
+    }
+}
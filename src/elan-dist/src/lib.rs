@@ -7,11 +7,16 @@ pub use notifications::Notification;
 pub mod temp;
 
 mod component;
+/// The archive unpackers, exposed for fuzzing
+pub use component::{ExtractionBudget, ExtractionLimits, TarGzPackage, TarZstdPackage, ZipPackage};
 pub mod config;
 pub mod dist;
 pub mod download;
 pub mod errors;
+mod journal;
 pub mod manifest;
 mod manifestation;
 pub mod notifications;
+pub mod peer_cache;
 pub mod prefix;
+pub mod target;
@@ -2,14 +2,219 @@
 
 use std::{thread::sleep, time::Duration};
 
-use crate::component::{TarGzPackage, TarZstdPackage, ZipPackage};
+use crate::component::{
+    Components, ExtractionBudget, ExtractionLimits, TarGzPackage, TarZstdPackage, ZipPackage,
+};
+use crate::dist::{check_provenance, ToolchainDesc};
 use crate::download::DownloadCfg;
 use crate::errors::*;
+use crate::journal::{Journal, State};
+use crate::manifest::Component;
 use crate::notifications::*;
+use crate::peer_cache;
 use crate::prefix::InstallPrefix;
+use crate::target::{asset_component, file_name, has_rosetta, select_asset, Targets};
 use crate::temp;
-use elan_utils::{raw::read_file, utils};
-use fslock::LockFile;
+use elan_utils::lock::{self, FileLock};
+use elan_utils::{endpoints, interrupt, raw, utils};
+use std::path::Path;
+
+/// A downloaded release archive
+struct Archive<'a> {
+    file: temp::File<'a>,
+    /// Whether it came from the peer cache rather than its sources
+    cached: bool,
+}
+
+/// Fetches the release archive at `url` from the peer cache if enabled, and
+/// downloads it from its sources otherwise
+fn download_archive<'a>(
+    dlcfg: &DownloadCfg<'a>,
+    origin: &str,
+    release: &str,
+    url: &str,
+) -> Result<Archive<'a>> {
+    let name = file_name(url);
+    if let Some(file) = peer_cache::fetch(dlcfg, origin, release, name)? {
+        return Ok(Archive { file, cached: true });
+    }
+    let file = download_from_sources(dlcfg, origin, release, url)?;
+    peer_cache::store(origin, release, name, &file, dlcfg.notify_handler);
+    Ok(Archive {
+        file,
+        cached: false,
+    })
+}
+
+/// Unpacks the release archive downloaded from `url` into the empty directory
+/// `into` and returns its SHA-256 hash. As archives from peers cannot be
+/// verified, one from the peer cache that fails to unpack is evicted from the
+/// cache and downloaded again from its sources.
+fn unpack_archive(
+    dlcfg: &DownloadCfg<'_>,
+    origin: &str,
+    release: &str,
+    url: &str,
+    archive: Archive<'_>,
+    into: &Path,
+    budget: &ExtractionBudget,
+) -> Result<String> {
+    let used = budget.used();
+    let err = match unpack(url, &archive.file, into, budget) {
+        Err(e) if archive.cached && !interrupt::interrupted() => e,
+        Err(e) => return Err(e),
+        Ok(()) => return Ok(peer_cache::hash_file(&archive.file)?),
+    };
+    (dlcfg.notify_handler)(Notification::BadCachedArchive(url, &err.to_string()));
+    let name = file_name(url);
+    peer_cache::evict(origin, release, name);
+    let file = download_from_sources(dlcfg, origin, release, url)?;
+    utils::remove_dir("temp directory", into, &|n| {
+        (dlcfg.notify_handler)(n.into())
+    })?;
+    utils::ensure_dir_exists("temp directory", into, &|n| {
+        (dlcfg.notify_handler)(n.into())
+    })?;
+    budget.reset(used);
+    unpack(url, &file, into, budget)?;
+    peer_cache::store(origin, release, name, &file, dlcfg.notify_handler);
+    Ok(peer_cache::hash_file(&file)?)
+}
+
+/// Downloads the release archive at `url` from the configured GitHub mirrors
+/// or, should all of them fail, from the alternative URLs listed for it in
+/// the release index of `origin`. With the `ipfs` feature, `ipfs://` URLs
+/// listed there are tried first.
+fn download_from_sources<'a>(
+    dlcfg: &DownloadCfg<'a>,
+    origin: &str,
+    release: &str,
+    url: &str,
+) -> Result<temp::File<'a>> {
+    let urls = endpoints::GITHUB.mirrors_of(url);
+    #[cfg(feature = "ipfs")]
+    if let Some(file) = download_from_ipfs(dlcfg, origin, release, url, &urls[0])? {
+        return Ok(file);
+    }
+    let err = match dlcfg.download_and_check_any(&urls) {
+        Err(e) if utils::is_mirror_failure(&e) => e,
+        res => return res,
+    };
+    let indexed = match utils::indexed_asset_urls(origin, release) {
+        Ok(indexed) => indexed,
+        Err(_) if interrupt::interrupted() => return Err(err),
+        Err(e) => {
+            let msg = e.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            (dlcfg.notify_handler)(Notification::AssetMirrorsUnavailable(&msg.join(": ")));
+            return Err(err);
+        }
+    };
+    let alternatives: Vec<String> = indexed
+        .get(file_name(url))
+        .into_iter()
+        .flatten()
+        .filter(|alt| !urls.contains(alt) && is_http_or_file(alt))
+        .cloned()
+        .collect();
+    match alternatives.first() {
+        Some(next) => {
+            (dlcfg.notify_handler)(
+                elan_utils::Notification::MirrorFailed(urls.last().unwrap(), next).into(),
+            );
+            dlcfg.download_and_check_any(&alternatives)
+        }
+        None => Err(err),
+    }
+}
+
+/// Whether `url` can be downloaded directly, unlike e.g. `ipfs://` URLs
+fn is_http_or_file(url: &str) -> bool {
+    ["http://", "https://", "file://"]
+        .iter()
+        .any(|scheme| url.starts_with(scheme))
+}
+
+/// Downloads the release archive at `url` through the IPFS gateway if the
+/// release index of `origin` lists `ipfs://` URLs for it. Returns `None` if
+/// it lists none or the gateway fails, so that the archive is downloaded
+/// from `fallback` and the other HTTP sources instead.
+#[cfg(feature = "ipfs")]
+fn download_from_ipfs<'a>(
+    dlcfg: &DownloadCfg<'a>,
+    origin: &str,
+    release: &str,
+    url: &str,
+    fallback: &str,
+) -> Result<Option<temp::File<'a>>> {
+    let indexed = match utils::indexed_asset_urls(origin, release) {
+        Ok(indexed) => indexed,
+        Err(e) if interrupt::interrupted() => return Err(e.into()),
+        Err(e) => {
+            let msg = e.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            (dlcfg.notify_handler)(Notification::AssetMirrorsUnavailable(&msg.join(": ")));
+            return Ok(None);
+        }
+    };
+    let gateway_urls: Vec<String> = indexed
+        .get(file_name(url))
+        .into_iter()
+        .flatten()
+        .filter_map(|alt| endpoints::ipfs_gateway_url(alt))
+        .collect();
+    if gateway_urls.is_empty() {
+        return Ok(None);
+    }
+    match dlcfg.download_and_check_any(&gateway_urls) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if interrupt::interrupted() => Err(e),
+        Err(_) => {
+            (dlcfg.notify_handler)(
+                elan_utils::Notification::MirrorFailed(gateway_urls.last().unwrap(), fallback)
+                    .into(),
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn unpack(url: &str, file: &Path, into: &Path, budget: &ExtractionBudget) -> Result<()> {
+    if url.ends_with(".tar.gz") {
+        TarGzPackage::unpack_file(file, into, budget)
+    } else if url.ends_with(".tar.zst") {
+        TarZstdPackage::unpack_file(file, into, budget)
+    } else if url.ends_with(".zip") {
+        ZipPackage::unpack_file(file, into, budget)
+    } else {
+        Err(format!("unsupported archive format: {}", url).into())
+    }
+}
+
+/// The archives an installation downloads
+#[derive(Debug)]
+pub struct InstallPlan {
+    /// URL of the toolchain archive
+    pub url: String,
+    /// The accepted target the archive was selected for
+    pub target: String,
+    /// Whether the archive needs emulation to run on this host
+    pub emulated: bool,
+    /// URLs of the archives of the requested optional components
+    pub components: Vec<(Component, String)>,
+}
+
+/// The release `Manifestation::install` and `install_component` install and
+/// how
+pub struct InstallRequest<'a> {
+    pub origin: &'a str,
+    pub release: &'a str,
+    /// URL of the release page listing its archives
+    pub url: &'a str,
+    pub targets: &'a Targets,
+    /// Whether `install` replaces an existing installation
+    pub reinstall: bool,
+    /// Caps on what the archives may extract, applying to all of them together
+    pub limits: ExtractionLimits,
+}
 
 #[derive(Debug)]
 pub struct Manifestation {
@@ -21,10 +226,14 @@ impl Manifestation {
         Ok(Manifestation { prefix })
     }
 
+    /// Installs the release into the prefix together with `components`,
+    /// extracting its archives within the request's limits. An existing
+    /// installation is left alone unless the request is to reinstall, in
+    /// which case it is replaced only once the new one is complete.
     pub fn install(
         &self,
-        origin: &String,
-        url: &String,
+        request: &InstallRequest<'_>,
+        components: &[Component],
         temp_cfg: &temp::Cfg,
         notify_handler: &dyn Fn(Notification<'_>),
     ) -> Result<()> {
@@ -33,107 +242,386 @@ impl Manifestation {
             (notify_handler)(n.into())
         })?;
 
-        let lockfile_path = prefix.with_extension("lock");
-        let mut lockfile = LockFile::open(&lockfile_path)?;
-        if !lockfile.try_lock_with_pid()? {
-            notify_handler(Notification::WaitingForFileLock(
-                &lockfile_path,
-                read_file(&lockfile_path)?.trim(),
-            ));
-            while !lockfile.try_lock_with_pid()? {
-                sleep(Duration::from_secs(1));
+        self.with_lock(notify_handler, || {
+            self.do_install(request, components, temp_cfg, notify_handler)
+        })
+    }
+
+    /// Runs `f` while holding the installation lock of this toolchain
+    fn with_lock<T>(
+        &self,
+        notify_handler: &dyn Fn(Notification<'_>),
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let lockfile_path = self.prefix.path().with_extension("lock");
+        if let Some(parent) = lockfile_path.parent() {
+            if let Some(kind) = raw::network_fs_type(parent) {
+                notify_handler(Notification::UsingNetworkFileSystem(parent, kind));
             }
         }
-        let res = self.do_install(origin, url, temp_cfg, notify_handler);
-        let _ = std::fs::remove_file(&lockfile_path);
-        res
+        let _lock = match FileLock::try_acquire(&lockfile_path)? {
+            Some(lock) => lock,
+            None => {
+                notify_handler(Notification::WaitingForFileLock(
+                    &lockfile_path,
+                    lock::holder(&lockfile_path),
+                ));
+                if lock::is_held_remotely(&lockfile_path) {
+                    notify_handler(Notification::FileLockHeldRemotely(&lockfile_path));
+                }
+                loop {
+                    if let Some(lock) = FileLock::try_acquire(&lockfile_path)? {
+                        break lock;
+                    }
+                    sleep(Duration::from_secs(1));
+                }
+            }
+        };
+        f()
+    }
+
+    /// Lists the URLs of all assets of a release from its GitHub assets page
+    fn fetch_asset_urls(
+        &self,
+        origin: &str,
+        url: &str,
+        dlcfg: &DownloadCfg<'_>,
+    ) -> Result<Vec<String>> {
+        // find downloads on HTML page (AAAAH)
+        use regex::Regex;
+        use std::fs;
+        use std::io::Read;
+        let re = Regex::new(format!(r#"/{}/releases/download/[^"]+"#, origin).as_str()).unwrap();
+        let download_page_file = dlcfg.download_and_check(url)?;
+        let mut html = String::new();
+        fs::File::open(&download_page_file as &::std::path::Path)?.read_to_string(&mut html)?;
+        Ok(re
+            .find_iter(&html)
+            .map(|m| format!("{}{}", endpoints::GITHUB.url(), m.as_str()))
+            .collect())
+    }
+
+    /// Lists the optional components available for this release
+    pub fn available_components(
+        &self,
+        origin: &str,
+        url: &str,
+        targets: &Targets,
+        temp_cfg: &temp::Cfg,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<Vec<Component>> {
+        let dlcfg = DownloadCfg {
+            temp_cfg,
+            notify_handler,
+        };
+        let mut components: Vec<_> = self
+            .fetch_asset_urls(origin, url, &dlcfg)?
+            .iter()
+            .filter_map(|u| asset_component(file_name(u), targets))
+            .collect();
+        components.sort();
+        components.dedup();
+        Ok(components)
+    }
+
+    pub fn install_component(
+        &self,
+        request: &InstallRequest<'_>,
+        component: &Component,
+        temp_cfg: &temp::Cfg,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<()> {
+        let InstallRequest {
+            origin,
+            release,
+            url,
+            targets,
+            limits,
+            ..
+        } = *request;
+        self.with_lock(notify_handler, || {
+            let components = Components::open(self.prefix.clone());
+            if components.list()?.contains(component) {
+                notify_handler(Notification::ComponentAlreadyInstalled(component));
+                return Ok(());
+            }
+
+            let dlcfg = DownloadCfg {
+                temp_cfg,
+                notify_handler,
+            };
+            let urls = self.fetch_asset_urls(origin, url, &dlcfg)?;
+            let url = urls
+                .iter()
+                .find(|u| asset_component(file_name(u), targets).as_ref() == Some(component))
+                .ok_or_else(|| {
+                    ErrorKind::RequestedComponentsUnavailable(vec![component.clone()])
+                })?;
+            notify_handler(Notification::DownloadingComponent(url));
+            let archive = download_archive(&dlcfg, origin, release, url)?;
+
+            notify_handler(Notification::InstallingComponent(&component.name()));
+            let unpack_dir = temp_cfg.new_directory()?;
+            unpack_archive(
+                &dlcfg,
+                origin,
+                release,
+                url,
+                archive,
+                &unpack_dir,
+                &ExtractionBudget::new(limits),
+            )?;
+            components.add(component, &unpack_dir)?;
+            components.update_size()
+        })
+    }
+
+    pub fn remove_component(
+        &self,
+        component: &Component,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<()> {
+        self.with_lock(notify_handler, || {
+            notify_handler(Notification::RemovingComponent(&component.name()));
+            let components = Components::open(self.prefix.clone());
+            components.remove(component, notify_handler)?;
+            components.update_size()
+        })
+    }
+
+    /// Determines the archives installing the release and the given optional
+    /// components would download, without downloading them
+    pub fn plan(
+        &self,
+        origin: &str,
+        url: &str,
+        components: &[Component],
+        targets: &Targets,
+        temp_cfg: &temp::Cfg,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<InstallPlan> {
+        let dlcfg = DownloadCfg {
+            temp_cfg,
+            notify_handler,
+        };
+        self.select_assets(origin, url, components, targets, &dlcfg)
+    }
+
+    fn select_assets(
+        &self,
+        origin: &str,
+        url: &str,
+        components: &[Component],
+        targets: &Targets,
+        dlcfg: &DownloadCfg<'_>,
+    ) -> Result<InstallPlan> {
+        let asset_urls = self.fetch_asset_urls(origin, url, dlcfg)?;
+        let asset = select_asset(&asset_urls, targets)?;
+
+        // Make sure all requested components exist before downloading anything
+        let mut component_urls = Vec::new();
+        let mut unavailable = Vec::new();
+        for component in components {
+            match asset_urls
+                .iter()
+                .find(|u| asset_component(file_name(u), targets).as_ref() == Some(component))
+            {
+                Some(url) => component_urls.push((component.clone(), url.clone())),
+                None => unavailable.push(component.clone()),
+            }
+        }
+        if !unavailable.is_empty() {
+            return Err(ErrorKind::RequestedComponentsUnavailable(unavailable).into());
+        }
+
+        Ok(InstallPlan {
+            url: asset.url.to_owned(),
+            target: asset.target,
+            emulated: asset.emulated,
+            components: component_urls,
+        })
     }
 
     fn do_install(
         &self,
-        origin: &String,
-        url: &String,
+        request: &InstallRequest<'_>,
+        components: &[Component],
         temp_cfg: &temp::Cfg,
         notify_handler: &dyn Fn(Notification<'_>),
     ) -> Result<()> {
+        let InstallRequest {
+            origin,
+            release,
+            url,
+            targets,
+            reinstall,
+            ..
+        } = *request;
         let prefix = self.prefix.path();
         let dlcfg = DownloadCfg {
             temp_cfg: temp_cfg,
             notify_handler: notify_handler,
         };
 
-        if utils::is_directory(prefix) {
-            return Ok(());
+        let replaced = utils::is_directory(prefix) && reinstall;
+        if utils::is_directory(prefix) && !reinstall {
+            // Never silently reuse a different toolchain
+            let toolchain = ToolchainDesc::Remote {
+                origin: origin.to_owned(),
+                release: release.to_owned(),
+                from_channel: None,
+            };
+            return check_provenance(&self.prefix, &toolchain);
         }
 
-        // find correct download on HTML page (AAAAH)
-        use regex::Regex;
-        use std::fs;
-        use std::io::Read;
-        let informal_target = if cfg!(target_os = "windows") {
-            "windows"
-        } else if cfg!(target_os = "linux") {
-            "linux"
-        } else if cfg!(target_os = "macos") {
-            "darwin"
-        } else {
-            unreachable!()
-        };
-        let informal_target = informal_target.to_owned();
-        let informal_target = if cfg!(target_arch = "x86_64") {
-            informal_target
-        } else if cfg!(target_arch = "aarch64") {
-            informal_target + "_aarch64"
-        } else {
-            unreachable!();
-        };
-        let url_substring = informal_target.clone() + ".";
-        let re = Regex::new(format!(r#"/{}/releases/download/[^"]+"#, origin).as_str()).unwrap();
-        let download_page_file = dlcfg.download_and_check(&url)?;
-        let mut html = String::new();
-        fs::File::open(&download_page_file as &::std::path::Path)?.read_to_string(&mut html)?;
-        let url = re
-            .find_iter(&html)
-            .map(|m| m.as_str().to_string())
-            .find(|m| m.contains(&url_substring));
-        if url.is_none() {
-            return Err(
-                format!("binary package was not provided for '{}'", informal_target).into(),
-            );
+        let plan = self.select_assets(origin, url, components, targets, &dlcfg)?;
+        let url = &plan.url;
+        if plan.emulated {
+            notify_handler(Notification::InstallingEmulatedBuild(
+                file_name(url),
+                has_rosetta(),
+            ));
         }
-        let url = format!("https://github.com{}", url.unwrap());
-        notify_handler(Notification::DownloadingComponent(&url));
 
-        let installer_file = dlcfg.download_and_check(&url)?;
+        notify_handler(Notification::DownloadingComponent(url));
+        let installer = download_archive(&dlcfg, origin, release, url)?;
+        let mut component_files = Vec::new();
+        for (component, url) in &plan.components {
+            notify_handler(Notification::DownloadingComponent(url));
+            component_files.push((
+                component,
+                url,
+                download_archive(&dlcfg, origin, release, url)?,
+            ));
+        }
 
         notify_handler(Notification::InstallingComponent(&prefix.to_string_lossy()));
 
-        // unpack into temporary place, then move atomically to guard against aborts during unpacking
-        let unpack_dir = prefix.with_extension("tmp");
+        // unpack into a unique staging directory, then move atomically to guard against aborts
+        // during unpacking. The journal lets `recover` clean up after an abort.
+        let unpack_dir = temp_cfg.new_directory()?;
+        let mut journal = Journal::begin(prefix, &unpack_dir)?;
+        let res = self
+            .stage(
+                &dlcfg,
+                request,
+                &plan,
+                installer,
+                &unpack_dir,
+                component_files,
+            )
+            // Don't complete an installation the user asked to abort
+            .and_then(|()| Ok(interrupt::check()?));
+        if res.is_err() {
+            // The staging directory itself is deleted when dropped
+            let _ = journal.finish();
+            return res;
+        }
+        journal.staged()?;
 
-        if utils::is_directory(&unpack_dir) {
-            utils::remove_dir("temp toolchain directory", &unpack_dir, &|n| {
-                (notify_handler)(n.into())
-            })?;
+        // The old installation is deleted together with the temporary
+        // directory it is moved into. Should elan be interrupted before the
+        // new one is in place, recovery completes the installation.
+        let old_dir = temp_cfg.new_directory()?;
+        if replaced {
+            utils::rename_dir("toolchain", prefix, &old_dir.join("toolchain"))?;
         }
+        utils::rename_dir("temp toolchain directory", &unpack_dir, prefix)?;
+        journal.finish()
+    }
 
-        utils::ensure_dir_exists("temp toolchain directory", &unpack_dir, &|n| {
-            (notify_handler)(n.into())
-        })?;
+    /// Moves the complete toolchain directory `staged` into the prefix unless
+    /// the toolchain is installed already, returning whether it was moved.
+    /// `staged` must be on the same file system as the prefix.
+    pub fn install_staged(
+        &self,
+        staged: &Path,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Result<bool> {
+        let prefix = self.prefix.path();
+        self.with_lock(notify_handler, || {
+            if utils::is_directory(prefix) {
+                return Ok(false);
+            }
+            let mut journal = Journal::begin(prefix, staged)?;
+            journal.staged()?;
+            utils::rename_dir("temp toolchain directory", staged, prefix)?;
+            journal.finish()?;
+            Ok(true)
+        })
+    }
 
-        // Extract new files
-        if url.ends_with(".tar.gz") {
-            TarGzPackage::unpack_file(&installer_file, &unpack_dir)?
-        } else if url.ends_with(".tar.zst") {
-            TarZstdPackage::unpack_file(&installer_file, &unpack_dir)?
-        } else if url.ends_with(".zip") {
-            ZipPackage::unpack_file(&installer_file, &unpack_dir)?
-        } else {
-            return Err(format!("unsupported archive format: {}", url).into());
+    /// Unpacks the toolchain archive and the archives of the selected components into `unpack_dir`
+    fn stage(
+        &self,
+        dlcfg: &DownloadCfg<'_>,
+        request: &InstallRequest<'_>,
+        plan: &InstallPlan,
+        installer: Archive<'_>,
+        unpack_dir: &Path,
+        component_files: Vec<(&Component, &String, Archive<'_>)>,
+    ) -> Result<()> {
+        let InstallRequest {
+            origin, release, ..
+        } = *request;
+        // Extract new files; the limits apply to all archives together
+        let budget = ExtractionBudget::new(request.limits);
+        let checksum = unpack_archive(
+            dlcfg, origin, release, &plan.url, installer, unpack_dir, &budget,
+        )?;
+
+        // Add the selected components on top, failing on any overlap with the
+        // files extracted so far
+        let installed = Components::open(InstallPrefix::from(unpack_dir.to_owned()));
+        installed.set_target(&plan.target)?;
+        installed.set_provenance(origin, release)?;
+        for (component, url, archive) in component_files {
+            (dlcfg.notify_handler)(Notification::InstallingComponent(&component.name()));
+            let component_dir = dlcfg.temp_cfg.new_directory()?;
+            unpack_archive(
+                dlcfg,
+                origin,
+                release,
+                url,
+                archive,
+                &component_dir,
+                &budget,
+            )?;
+            installed.add(component, &component_dir)?;
         }
+        installed.set_installed(&checksum)?;
+        installed.update_size()
+    }
 
-        utils::rename_dir("temp toolchain directory", &unpack_dir, prefix)?;
+    /// Completes or rolls back an installation of this toolchain that was
+    /// interrupted, unless another process is currently installing it
+    pub fn recover(&self, notify_handler: &dyn Fn(Notification<'_>)) -> Result<()> {
+        let prefix = self.prefix.path();
+        if !utils::is_file(Journal::path_for(prefix)) {
+            return Ok(());
+        }
+        let _lock = match FileLock::try_acquire(&prefix.with_extension("lock"))? {
+            Some(lock) => lock,
+            None => return Ok(()),
+        };
+        self.do_recover(notify_handler)
+    }
 
-        Ok(())
+    fn do_recover(&self, notify_handler: &dyn Fn(Notification<'_>)) -> Result<()> {
+        let prefix = self.prefix.path();
+        let journal = Journal::read(prefix)?;
+        let staged = utils::is_directory(&journal.staging);
+        if journal.state == State::Staged && staged && !utils::is_directory(prefix) {
+            utils::rename_dir("temp toolchain directory", &journal.staging, prefix)?;
+            notify_handler(Notification::RecoveredInstall(prefix, true));
+        } else if staged {
+            utils::remove_dir("temp toolchain directory", &journal.staging, &|n| {
+                (notify_handler)(n.into())
+            })?;
+            if journal.state == State::Staging {
+                notify_handler(Notification::RecoveredInstall(prefix, false));
+            }
+        }
+        journal.finish()
     }
 }
@@ -31,7 +31,32 @@ pub enum Notification<'a> {
     DownloadingLegacyManifest,
     ManifestChecksumFailedHack,
     NewVersionAvailable(String),
-    WaitingForFileLock(&'a Path, &'a str),
+    /// Lock file and the PID recorded in it
+    WaitingForFileLock(&'a Path, Option<u32>),
+    /// The lock is held by a process not running on this machine
+    FileLockHeldRemotely(&'a Path),
+    /// Directory on a network file system, and the kind of file system
+    UsingNetworkFileSystem(&'a Path, &'a str),
+    /// An emulated build is being installed; whether Rosetta 2 is available
+    InstallingEmulatedBuild(&'a str, bool),
+    /// An interrupted installation was completed (`true`) or rolled back
+    RecoveredInstall(&'a Path, bool),
+    /// The release index could not be consulted for alternative URLs of a
+    /// failed download
+    AssetMirrorsUnavailable(&'a str),
+    /// A release archive was fetched from the download cache or a peer
+    UsingCachedArchive(&'a str),
+    /// An archive from the download cache or a peer could not be unpacked
+    /// and is downloaded again from its sources
+    BadCachedArchive(&'a str, &'a str),
+    /// Number of peer caches found on the local network
+    PeersFound(usize),
+    PeerDiscoveryFailed(&'a str),
+    /// A downloaded archive could not be added to the download cache
+    ArchiveNotCached(&'a Path, &'a str),
+    /// The download cache is being served to peers on the given port
+    ServingPeerCache(&'a Path, u16),
+    PeerAnnouncementFailed(&'a str),
 }
 
 impl<'a> From<elan_utils::Notification<'a>> for Notification<'a> {
@@ -55,7 +80,10 @@ impl<'a> Notification<'a> {
             ChecksumValid(_)
             | NoUpdateHash(_)
             | FileAlreadyDownloaded
-            | DownloadingLegacyManifest => NotificationLevel::Verbose,
+            | DownloadingLegacyManifest
+            | AssetMirrorsUnavailable(_)
+            | PeersFound(_)
+            | PeerDiscoveryFailed(_) => NotificationLevel::Verbose,
             Extracting(_, _)
             | SignatureValid(_)
             | DownloadingComponent(_)
@@ -67,10 +95,19 @@ impl<'a> Notification<'a> {
             | DownloadingManifest(_)
             | NewVersionAvailable(_)
             | WaitingForFileLock(_, _)
+            | UsingNetworkFileSystem(_, _)
+            | RecoveredInstall(_, _)
+            | UsingCachedArchive(_)
+            | ServingPeerCache(_, _)
             | DownloadedManifest(_, _) => NotificationLevel::Info,
             CantReadUpdateHash(_)
             | ExtensionNotInstalled(_)
+            | InstallingEmulatedBuild(_, _)
             | MissingInstalledComponent(_)
+            | FileLockHeldRemotely(_)
+            | ArchiveNotCached(_, _)
+            | BadCachedArchive(_, _)
+            | PeerAnnouncementFailed(_)
             | CachedFileChecksumFailed => NotificationLevel::Warn,
             NonFatalError(_) => NotificationLevel::Error,
         }
@@ -127,7 +164,28 @@ impl<'a> Display for Notification<'a> {
                     "Version {version} of elan is available! Use `elan self update` to update."
                 )
             }
-            WaitingForFileLock(path, pid) => {
+            InstallingEmulatedBuild(asset, true) => write!(
+                f,
+                "this release has no native build for Apple silicon; installing '{}' to run under Rosetta 2",
+                asset
+            ),
+            InstallingEmulatedBuild(asset, false) => write!(
+                f,
+                "this release has no native build for Apple silicon; installing '{}', which requires Rosetta 2 \
+                 (install it with `softwareupdate --install-rosetta`)",
+                asset
+            ),
+            RecoveredInstall(path, true) => write!(
+                f,
+                "completed interrupted installation of '{}'",
+                path.display()
+            ),
+            RecoveredInstall(path, false) => write!(
+                f,
+                "rolled back interrupted installation of '{}'",
+                path.display()
+            ),
+            WaitingForFileLock(path, Some(pid)) => {
                 write!(
                     f,
                     "waiting for previous installation request to finish ({}, held by PID {})",
@@ -135,6 +193,59 @@ impl<'a> Display for Notification<'a> {
                     pid
                 )
             }
+            WaitingForFileLock(path, None) => {
+                write!(
+                    f,
+                    "waiting for previous installation request to finish ({})",
+                    path.display()
+                )
+            }
+            UsingNetworkFileSystem(path, kind) => write!(
+                f,
+                "'{}' is on a network file system ({}); using lock directories instead of \
+                 file locks and copying toolchains into place where renaming fails",
+                path.display(),
+                kind
+            ),
+            AssetMirrorsUnavailable(e) => write!(
+                f,
+                "could not look up alternative download URLs in the release index: {}",
+                e
+            ),
+            UsingCachedArchive(url) => write!(f, "using cached archive '{}'", url),
+            BadCachedArchive(url, e) => write!(
+                f,
+                "could not unpack cached archive '{}', downloading it again: {}",
+                url, e
+            ),
+            PeersFound(n) => write!(f, "found {} peer caches on the local network", n),
+            PeerDiscoveryFailed(e) => {
+                write!(f, "could not look for peer caches on the local network: {}", e)
+            }
+            ArchiveNotCached(path, e) => write!(
+                f,
+                "could not add '{}' to the download cache: {}",
+                path.display(),
+                e
+            ),
+            ServingPeerCache(path, port) => write!(
+                f,
+                "serving '{}' to peers on port {}; press Ctrl-C to stop",
+                path.display(),
+                port
+            ),
+            PeerAnnouncementFailed(e) => write!(
+                f,
+                "could not announce the cache on the local network, so peers will not find it: {}",
+                e
+            ),
+            FileLockHeldRemotely(path) => write!(
+                f,
+                "'{}' is locked by a process that is not running on this machine; \
+                 if no other machine sharing this directory is installing the toolchain, \
+                 the file server may have failed to release the lock",
+                path.display()
+            ),
         }
     }
 }
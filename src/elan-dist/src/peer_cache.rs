@@ -0,0 +1,487 @@
+//! Sharing downloaded release archives with other elan instances on the
+//! local network
+//!
+//! With the `peer_cache` setting, release archives are kept in the download
+//! cache after being downloaded, and an archive is looked up in that cache
+//! and then at the peers on the local network before it is downloaded from
+//! the internet. Peers are found with DNS-SD over multicast DNS and serve
+//! their download caches over plain HTTP while `elan cache serve` runs.
+//! Archives are stored at `<origin>/<release>/<file name>` below the cache,
+//! and peers serve them at the same path.
+//!
+//! Like IPFS gateways, peers are trusted to serve the archives they claim to;
+//! the cache is meant for networks whose machines trust each other, such as a
+//! workshop or a CI farm. Only archives downloaded from their sources are
+//! added to the cache, never ones received from peers, so that a peer cannot
+//! pass on an archive that it did not download itself. Archives are checked
+//! against the SHA-256 hash recorded next to them when they were added to the
+//! cache, which catches damaged local copies; as release archives come without
+//! published hashes, an archive that fails to unpack is evicted and downloaded
+//! again from its sources instead.
+
+use crate::download::DownloadCfg;
+use crate::errors::*;
+use crate::notifications::*;
+use crate::temp;
+use elan_utils::{interrupt, mdns, utils};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// DNS-SD service type of `elan cache serve`
+pub const SERVICE: &str = "_elan-cache._tcp.local";
+
+/// How long to wait for peers to respond to a discovery query
+const DISCOVERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Most requests answered at once by `elan cache serve`, which turns away
+/// further ones so that peers cannot exhaust its threads and file handles
+const MAX_CONNECTIONS: usize = 16;
+
+/// Longest request line and headers read by `elan cache serve`; peers only
+/// send short `GET` requests
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+/// The download cache, if the peer cache is enabled
+static DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+/// Whether downloaded archives are added to the download cache
+static STORE: AtomicBool = AtomicBool::new(false);
+/// Peers found by this process, which looks for them only once
+static PEERS: Mutex<Option<Vec<SocketAddr>>> = Mutex::new(None);
+
+/// Enables the peer cache with the download cache at `dir`, which archives
+/// are added to unless `store` is false, e.g. in read-only mode
+pub fn set_dir(dir: Option<PathBuf>, store: bool) {
+    *DIR.lock().unwrap() = dir;
+    STORE.store(store, Ordering::Relaxed);
+}
+
+/// Whether the download cache holds an archive of `release` of `origin`,
+/// which installing the release then uses instead of downloading it
+pub fn is_cached(origin: &str, release: &str) -> bool {
+    DIR.lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|dir| has_archive(dir, origin, release))
+}
+
+/// Whether the download cache at `dir` holds a complete archive of `release`
+/// of `origin`
+fn has_archive(dir: &Path, origin: &str, release: &str) -> bool {
+    fs::read_dir(dir.join(origin).join(release)).is_ok_and(|mut entries| {
+        entries.any(|e| {
+            e.is_ok_and(|e| e.path().is_file() && !e.file_name().to_string_lossy().starts_with('.'))
+        })
+    })
+}
+
+/// Path of an archive below the download cache, as served to peers
+fn cache_path(origin: &str, release: &str, file_name: &str) -> String {
+    format!("{}/{}/{}", origin, release, file_name)
+}
+
+/// The file next to the cached archive `archive` that holds its SHA-256 hash,
+/// hidden from peers like partial archives
+fn checksum_path(archive: &Path) -> PathBuf {
+    let file_name = archive.file_name().unwrap_or_default().to_string_lossy();
+    archive.with_file_name(format!(".{}.sha256", file_name))
+}
+
+pub(crate) fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Whether the cached archive `archive` matches the hash recorded for it.
+/// Archives cached by earlier versions of elan have no hash to check.
+fn is_intact(archive: &Path) -> bool {
+    match fs::read_to_string(checksum_path(archive)) {
+        Ok(recorded) => hash_file(archive).is_ok_and(|hash| hash == recorded.trim()),
+        Err(e) => e.kind() == io::ErrorKind::NotFound,
+    }
+}
+
+fn peers(notify_handler: &dyn Fn(Notification<'_>)) -> Vec<SocketAddr> {
+    let mut peers = PEERS.lock().unwrap();
+    peers
+        .get_or_insert_with(|| match mdns::discover(SERVICE, DISCOVERY_TIMEOUT) {
+            Ok(found) => {
+                notify_handler(Notification::PeersFound(found.len()));
+                found
+            }
+            Err(e) => {
+                notify_handler(Notification::PeerDiscoveryFailed(&e.to_string()));
+                vec![]
+            }
+        })
+        .clone()
+}
+
+/// Fetches the archive `file_name` of `release` of `origin` from the
+/// download cache or a peer, or returns `None` if neither has it or the
+/// peer cache is disabled. Archives from peers are not added to the cache.
+pub(crate) fn fetch<'a>(
+    dlcfg: &DownloadCfg<'a>,
+    origin: &str,
+    release: &str,
+    file_name: &str,
+) -> Result<Option<temp::File<'a>>> {
+    let dir = match DIR.lock().unwrap().clone() {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    let path = cache_path(origin, release, file_name);
+    let local = dir.join(&path);
+    if local.is_file() && !is_intact(&local) {
+        (dlcfg.notify_handler)(Notification::CachedFileChecksumFailed);
+        evict(origin, release, file_name);
+    }
+    let mut urls = vec![];
+    if local.is_file() {
+        urls.extend(url::Url::from_file_path(&local).ok().map(String::from));
+    } else {
+        urls.extend(
+            peers(dlcfg.notify_handler)
+                .iter()
+                .map(|peer| format!("http://{}/{}", peer, path)),
+        );
+    }
+    for url in urls {
+        match dlcfg.download_and_check_any(std::slice::from_ref(&url)) {
+            Ok(file) => {
+                (dlcfg.notify_handler)(Notification::UsingCachedArchive(&url));
+                return Ok(Some(file));
+            }
+            Err(e) if interrupt::interrupted() => return Err(e),
+            // Peers may not have the archive or may have gone away
+            Err(_) => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Adds the downloaded archive `file_name` of `release` of `origin` to the
+/// download cache if the peer cache is enabled. Failing to do so only
+/// warrants a warning.
+pub(crate) fn store(
+    origin: &str,
+    release: &str,
+    file_name: &str,
+    downloaded: &Path,
+    notify_handler: &dyn Fn(Notification<'_>),
+) {
+    let dir = match DIR.lock().unwrap().clone() {
+        Some(dir) if STORE.load(Ordering::Relaxed) => dir,
+        _ => return,
+    };
+    let path = dir.join(cache_path(origin, release, file_name));
+    // Copied next to its destination first, so that peers never see a
+    // partial archive
+    let partial = path.with_file_name(format!(".{}.partial", file_name));
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::copy(downloaded, &partial))
+        .and_then(|_| hash_file(&partial))
+        .and_then(|hash| fs::write(checksum_path(&path), hash))
+        .and_then(|()| fs::rename(&partial, &path));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        notify_handler(Notification::ArchiveNotCached(&path, &e.to_string()));
+    }
+}
+
+/// Removes the archive `file_name` of `release` of `origin` from the download
+/// cache, e.g. because it turned out to be damaged, unless the cache is not
+/// to be written to
+pub(crate) fn evict(origin: &str, release: &str, file_name: &str) {
+    let dir = match DIR.lock().unwrap().clone() {
+        Some(dir) if STORE.load(Ordering::Relaxed) => dir,
+        _ => return,
+    };
+    let path = dir.join(cache_path(origin, release, file_name));
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(checksum_path(&path));
+}
+
+/// The file below `dir` that the HTTP request target `target` refers to,
+/// rejecting targets that would escape `dir` or refer to partial archives
+fn cached_file(dir: &Path, target: &str) -> Option<PathBuf> {
+    let path = Path::new(target.strip_prefix('/')?);
+    let normal = path.components().all(|c| match c {
+        Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+        _ => false,
+    });
+    if !normal || target.contains('\\') || path.as_os_str().is_empty() {
+        return None;
+    }
+    Some(dir.join(path)).filter(|file| file.is_file())
+}
+
+/// Answers a single HTTP request for a file below `dir`
+fn respond(dir: &Path, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    let mut stream = stream;
+    if reader.get_ref().limit() == 0 {
+        return write!(
+            stream,
+            "HTTP/1.1 431 Request Header Fields Too Large\r\nContent-Length: 0\r\n\
+             Connection: close\r\n\r\n"
+        );
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    if method != "GET" && method != "HEAD" {
+        return write!(
+            stream,
+            "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+    let file = match cached_file(dir, target).map(fs::File::open) {
+        Some(Ok(file)) => file,
+        _ => {
+            return write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+        }
+    };
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: application/octet-stream\r\n\
+         Connection: close\r\n\r\n",
+        file.metadata()?.len()
+    )?;
+    if method == "GET" {
+        io::copy(&mut BufReader::new(file), &mut stream)?;
+    }
+    Ok(())
+}
+
+/// Serves the download cache at `dir` to peers on `port`, or on any free
+/// port if it is 0, and announces it on the local network. Runs until an
+/// error occurs.
+pub fn serve(dir: &Path, port: u16, notify_handler: &dyn Fn(Notification<'_>)) -> Result<()> {
+    utils::ensure_dir_exists("download cache", dir, &|n| notify_handler(n.into()))?;
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .chain_err(|| format!("could not listen on port {}", port))?;
+    let port = listener.local_addr()?.port();
+
+    let instance = format!("elan-{}", std::process::id());
+    match mdns::Responder::bind(SERVICE, &instance, port) {
+        Ok(responder) => {
+            thread::spawn(move || responder.run());
+        }
+        Err(e) => notify_handler(Notification::PeerAnnouncementFailed(&e.to_string())),
+    }
+    notify_handler(Notification::ServingPeerCache(dir, port));
+
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            // Peers then download the archive from elsewhere
+            let _ = write!(
+                stream,
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            );
+            continue;
+        }
+        let dir = dir.to_owned();
+        let active = active.clone();
+        thread::spawn(move || {
+            // Peers that hang up early are of no concern
+            let _ = respond(&dir, stream);
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("leanprover/lean4/v4.9.0");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join("lean.tar.zst"), "").unwrap();
+        fs::write(release.join(".lean.tar.zst.partial"), "").unwrap();
+
+        assert_eq!(
+            cached_file(dir.path(), "/leanprover/lean4/v4.9.0/lean.tar.zst"),
+            Some(release.join("lean.tar.zst"))
+        );
+        for target in [
+            "leanprover/lean4/v4.9.0/lean.tar.zst",
+            "/leanprover/lean4/v4.9.0",
+            "/leanprover/lean4/v4.9.0/.lean.tar.zst.partial",
+            "/leanprover/lean4/v4.9.0/../v4.9.0/lean.tar.zst",
+            "//etc/passwd",
+            "/",
+        ] {
+            assert_eq!(cached_file(dir.path(), target), None, "{}", target);
+        }
+    }
+
+    #[test]
+    fn test_has_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("leanprover/lean4/v4.9.0");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join(".lean.tar.zst.partial"), "").unwrap();
+        assert!(!has_archive(dir.path(), "leanprover/lean4", "v4.9.0"));
+        fs::write(release.join("lean.tar.zst"), "").unwrap();
+        assert!(has_archive(dir.path(), "leanprover/lean4", "v4.9.0"));
+        assert!(!has_archive(dir.path(), "leanprover/lean4", "v4.10.0"));
+    }
+
+    #[test]
+    fn test_store_and_fetch() {
+        let cache = tempfile::tempdir().unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let temp_cfg = temp::Cfg::new(temp.path().join("tmp"), Box::new(|_| {}));
+        let dlcfg = DownloadCfg {
+            temp_cfg: &temp_cfg,
+            notify_handler: &|_| {},
+        };
+        let downloaded = temp.path().join("lean.tar.zst");
+        fs::write(&downloaded, "archive").unwrap();
+        set_dir(Some(cache.path().to_owned()), true);
+        // Never look for peers on the actual network
+        *PEERS.lock().unwrap() = Some(vec![]);
+
+        store(
+            "leanprover/lean4",
+            "v4.9.0",
+            "lean.tar.zst",
+            &downloaded,
+            &|_| {},
+        );
+        let archive = cache.path().join("leanprover/lean4/v4.9.0/lean.tar.zst");
+        assert!(is_cached("leanprover/lean4", "v4.9.0"));
+        assert!(checksum_path(&archive).is_file());
+        let file = fetch(&dlcfg, "leanprover/lean4", "v4.9.0", "lean.tar.zst")
+            .unwrap()
+            .unwrap();
+        assert_eq!(fs::read_to_string(&*file).unwrap(), "archive");
+
+        // Damaged archives are evicted rather than used
+        fs::write(&archive, "archiv").unwrap();
+        assert!(fetch(&dlcfg, "leanprover/lean4", "v4.9.0", "lean.tar.zst")
+            .unwrap()
+            .is_none());
+        assert!(!archive.exists());
+        assert!(!checksum_path(&archive).exists());
+        assert!(!is_cached("leanprover/lean4", "v4.9.0"));
+
+        // Archives from peers are used but not passed on
+        let peer = tempfile::tempdir().unwrap();
+        let release = peer.path().join("leanprover/lean4/v4.10.0");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join("lean.tar.zst"), "peer archive").unwrap();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        *PEERS.lock().unwrap() = Some(vec![listener.local_addr().unwrap()]);
+        let peer_dir = peer.path().to_owned();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = respond(&peer_dir, stream.unwrap());
+            }
+        });
+        let file = fetch(&dlcfg, "leanprover/lean4", "v4.10.0", "lean.tar.zst")
+            .unwrap()
+            .unwrap();
+        assert_eq!(fs::read_to_string(&*file).unwrap(), "peer archive");
+        assert!(!is_cached("leanprover/lean4", "v4.10.0"));
+
+        set_dir(None, false);
+        assert!(fetch(&dlcfg, "leanprover/lean4", "v4.9.0", "lean.tar.zst")
+            .unwrap()
+            .is_none());
+    }
+
+    /// The response of `respond` serving `dir` to `request`
+    fn request(dir: &Path, request: &str) -> String {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        respond(dir, stream).unwrap();
+        let mut response = String::new();
+        io::Read::read_to_string(&mut client, &mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_respond() {
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("leanprover/lean4/v4.9.0");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join("lean.tar.zst"), "archive").unwrap();
+
+        let target = "/leanprover/lean4/v4.9.0/lean.tar.zst";
+        let response = request(dir.path(), &format!("GET {} HTTP/1.1\r\n\r\n", target));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Length: 7\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\narchive"), "{}", response);
+
+        let response = request(dir.path(), &format!("HEAD {} HTTP/1.1\r\n\r\n", target));
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\n"), "{}", response);
+
+        let response = request(
+            dir.path(),
+            "GET /leanprover/lean4/v4.9.0/.lean.tar.zst.sha256 HTTP/1.1\r\n\r\n",
+        );
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            response
+        );
+
+        let response = request(dir.path(), &format!("PUT {} HTTP/1.1\r\n\r\n", target));
+        assert!(
+            response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            "{}",
+            response
+        );
+
+        // Reading stops at the limit, before the end of the headers
+        let mut oversized = format!("GET {} HTTP/1.1\r\nX-Padding: ", target);
+        oversized.extend(std::iter::repeat('x').take(MAX_REQUEST_SIZE as usize - oversized.len()));
+        let response = request(dir.path(), &oversized);
+        assert!(
+            response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"),
+            "{}",
+            response
+        );
+    }
+}
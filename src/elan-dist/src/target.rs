@@ -0,0 +1,422 @@
+//! Matching of release assets against the host platform.
+//!
+//! Toolchain archives are named `<name>-<target>.<ext>`, where `<target>` is
+//! either a full target triple or one of the informal platform names used by
+//! Lean 4 releases such as `linux` or `darwin_aarch64`. Each host accepts a
+//! list of targets in order of preference; the first one for which an asset
+//! exists wins.
+
+use crate::dist::host_triple;
+use crate::errors::*;
+use crate::manifest::Component;
+#[cfg(any(target_os = "linux", test))]
+use std::io::{Read, Seek, SeekFrom};
+
+/// Archive formats we know how to unpack
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tar.zst", ".zip"];
+
+/// Targets of all supported platforms, used to tell platform-specific assets
+/// apart from platform-independent ones
+const KNOWN_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "aarch64-unknown-linux-gnu",
+    "x86_64-unknown-linux-musl",
+    "aarch64-unknown-linux-musl",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "aarch64-pc-windows-msvc",
+    "linux",
+    "linux_x86_64",
+    "linux_aarch64",
+    "linux_musl",
+    "linux_static",
+    "linux_aarch64_musl",
+    "linux_aarch64_static",
+    "darwin",
+    "darwin_x86_64",
+    "darwin_aarch64",
+    "windows",
+    "windows_x86_64",
+    "windows_aarch64",
+];
+
+/// Whether the host's C library is musl, as on Alpine Linux. Toolchains linked
+/// against glibc cannot run on such systems.
+///
+/// The dynamic loader requested by a running binary tells which C library the
+/// host actually uses, unlike the mere presence of a musl loader in `/lib`,
+/// which glibc systems with the musl package installed have as well. A
+/// statically linked elan has no loader, so `/bin/sh` is asked instead.
+#[cfg(target_os = "linux")]
+pub fn is_musl() -> bool {
+    ["/proc/self/exe", "/bin/sh"]
+        .iter()
+        .find_map(|path| {
+            let mut file = std::fs::File::open(path).ok()?;
+            elf_interpreter(&mut file)
+        })
+        .map_or(cfg!(target_env = "musl"), |interpreter| {
+            is_musl_interpreter(&interpreter)
+        })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_musl() -> bool {
+    false
+}
+
+/// Whether `interpreter` is the musl dynamic loader, e.g.
+/// `/lib/ld-musl-x86_64.so.1`
+#[cfg(any(target_os = "linux", test))]
+fn is_musl_interpreter(interpreter: &str) -> bool {
+    interpreter
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name.starts_with("ld-musl-"))
+}
+
+/// The program interpreter, i.e. the dynamic loader, requested by the
+/// `PT_INTERP` program header of an ELF binary, or `None` if the binary is
+/// statically linked or not ELF at all
+#[cfg(any(target_os = "linux", test))]
+fn elf_interpreter(file: &mut (impl Read + Seek)) -> Option<String> {
+    const PT_INTERP: u32 = 3;
+
+    let mut header = [0; 64];
+    file.read_exact(&mut header[..52]).ok()?;
+    if header[..4] != *b"\x7fELF" {
+        return None;
+    }
+    let is_64 = match header[4] {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let le = match header[5] {
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let word = |bytes: &[u8], offset: usize, size: usize| -> u64 {
+        let bytes = &bytes[offset..offset + size];
+        let fold = |n: u64, b: &u8| n << 8 | u64::from(*b);
+        if le {
+            bytes.iter().rev().fold(0, fold)
+        } else {
+            bytes.iter().fold(0, fold)
+        }
+    };
+    if is_64 {
+        file.read_exact(&mut header[52..]).ok()?;
+    }
+    let (phoff, phentsize, phnum) = if is_64 {
+        (
+            word(&header, 0x20, 8),
+            word(&header, 0x36, 2),
+            word(&header, 0x38, 2),
+        )
+    } else {
+        (
+            word(&header, 0x1c, 4),
+            word(&header, 0x2a, 2),
+            word(&header, 0x2c, 2),
+        )
+    };
+    if phentsize < if is_64 { 56 } else { 32 } {
+        return None;
+    }
+
+    let mut entry = vec![0; phentsize as usize];
+    for i in 0..phnum {
+        file.seek(SeekFrom::Start(phoff + i * phentsize)).ok()?;
+        file.read_exact(&mut entry).ok()?;
+        if word(&entry, 0, 4) != u64::from(PT_INTERP) {
+            continue;
+        }
+        let (offset, size) = if is_64 {
+            (word(&entry, 8, 8), word(&entry, 32, 8))
+        } else {
+            (word(&entry, 4, 4), word(&entry, 16, 4))
+        };
+        // Paths of loaders are short, anything else is not a sane binary
+        if size > 4096 {
+            return None;
+        }
+        let mut interpreter = vec![0; size as usize];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut interpreter).ok()?;
+        let end = interpreter
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(size as usize);
+        return String::from_utf8(interpreter[..end].to_vec()).ok();
+    }
+    None
+}
+
+/// The release asset targets accepted for a toolchain
+#[derive(Clone, Debug, PartialEq)]
+pub struct Targets {
+    /// Targets that run natively, most preferred first
+    pub native: Vec<String>,
+    /// Targets that can run under emulation, used only if the release has no
+    /// native build
+    pub emulated: Vec<String>,
+}
+
+impl Targets {
+    /// The default targets for this host
+    pub fn host() -> Self {
+        Targets {
+            native: host_targets(),
+            emulated: emulated_targets(),
+        }
+    }
+
+    /// Explicitly configured targets, never falling back to emulation
+    pub fn custom(native: Vec<String>) -> Self {
+        Targets {
+            native,
+            emulated: Vec::new(),
+        }
+    }
+
+    pub fn without_emulation(self) -> Self {
+        Targets {
+            emulated: Vec::new(),
+            ..self
+        }
+    }
+}
+
+/// Informal platform names used by Lean releases for a target triple, most
+/// preferred first
+fn informal_names(target: &str) -> &'static [&'static str] {
+    match target {
+        "x86_64-unknown-linux-gnu" => &["linux_x86_64", "linux"],
+        "aarch64-unknown-linux-gnu" => &["linux_aarch64"],
+        "x86_64-unknown-linux-musl" => &["linux_musl", "linux_static"],
+        "aarch64-unknown-linux-musl" => &["linux_aarch64_musl", "linux_aarch64_static"],
+        "x86_64-apple-darwin" => &["darwin_x86_64", "darwin"],
+        "aarch64-apple-darwin" => &["darwin_aarch64"],
+        "x86_64-pc-windows-msvc" | "x86_64-pc-windows-gnu" => &["windows_x86_64", "windows"],
+        "aarch64-pc-windows-msvc" => &["windows_aarch64"],
+        _ => &[],
+    }
+}
+
+/// The asset targets accepted for a target triple or informal platform name,
+/// most preferred first
+pub fn targets_for(target: &str) -> Vec<String> {
+    std::iter::once(target)
+        .chain(informal_names(target).iter().cloned())
+        .map(|t| t.to_owned())
+        .collect()
+}
+
+/// The target triple of the host, taking the C library in use into account
+fn host_target() -> &'static str {
+    if !is_musl() {
+        host_triple()
+    } else if cfg!(target_arch = "aarch64") {
+        "aarch64-unknown-linux-musl"
+    } else {
+        "x86_64-unknown-linux-musl"
+    }
+}
+
+/// The targets accepted by default on this host, most preferred first
+pub fn host_targets() -> Vec<String> {
+    targets_for(host_target())
+}
+
+/// Targets that the host can run under emulation, i.e. x86_64 macOS builds
+/// under Rosetta 2 on Apple silicon
+fn emulated_targets() -> Vec<String> {
+    if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+        targets_for("x86_64-apple-darwin")
+    } else {
+        Vec::new()
+    }
+}
+
+/// Whether Rosetta 2 is installed, on Apple silicon
+pub fn has_rosetta() -> bool {
+    std::path::Path::new("/Library/Apple/usr/libexec/oah/libRosettaRuntime").exists()
+}
+
+pub fn file_name(url: &str) -> &str {
+    url.rsplit('/').next().unwrap_or(url)
+}
+
+/// Strips the archive extension from an asset name, if it has a supported one
+pub fn archive_stem(file_name: &str) -> Option<&str> {
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .find(|ext| file_name.ends_with(*ext))
+        .map(|ext| &file_name[..file_name.len() - ext.len()])
+}
+
+fn matches_target(stem: &str, target: &str) -> bool {
+    stem.len() > target.len()
+        && stem.ends_with(target)
+        && stem[..stem.len() - target.len()].ends_with('-')
+}
+
+/// A toolchain archive selected for installation
+pub struct Asset<'a> {
+    pub url: &'a str,
+    /// The accepted target the archive was selected for
+    pub target: String,
+    /// Whether the archive needs emulation to run on this host
+    pub emulated: bool,
+}
+
+fn find_asset<'a, 'b>(urls: &'a [String], targets: &'b [String]) -> Option<(&'a String, &'b str)> {
+    targets.iter().find_map(|target| {
+        urls.iter()
+            .find(|url| {
+                archive_stem(file_name(url)).is_some_and(|stem| matches_target(stem, target))
+            })
+            .map(|url| (url, target.as_str()))
+    })
+}
+
+/// Picks the toolchain archive for the most preferred native target, falling
+/// back to emulated ones
+pub fn select_asset<'a>(urls: &'a [String], targets: &Targets) -> Result<Asset<'a>> {
+    if let Some((url, target)) = find_asset(urls, &targets.native) {
+        return Ok(Asset {
+            url,
+            target: target.to_owned(),
+            emulated: false,
+        });
+    }
+    if let Some((url, target)) = find_asset(urls, &targets.emulated) {
+        return Ok(Asset {
+            url,
+            target: target.to_owned(),
+            emulated: true,
+        });
+    }
+    let assets = urls.iter().map(|url| file_name(url).to_owned()).collect();
+    if is_musl() {
+        return Err(ErrorKind::NoMuslAsset(assets).into());
+    }
+    Err(ErrorKind::NoMatchingAsset(targets.native.clone(), assets).into())
+}
+
+/// Determines the optional component provided by a release asset, if any.
+///
+/// Optional components are named `<name>-<target>-<component>.<ext>`, or
+/// `<name>-<component>.<ext>` if they are platform-independent. Components for
+/// platforms other than the native `targets` are ignored.
+pub fn asset_component(file_name: &str, targets: &Targets) -> Option<Component> {
+    let targets = &targets.native;
+    let is_known = |stem: &str| {
+        KNOWN_TARGETS.iter().any(|t| matches_target(stem, t))
+            || targets.iter().any(|t| matches_target(stem, t))
+    };
+    let stem = archive_stem(file_name)?;
+    if is_known(stem) {
+        return None;
+    }
+    let (rest, last) = stem.rsplit_once('-')?;
+    if is_known(rest) && !targets.iter().any(|t| matches_target(rest, t)) {
+        return None;
+    }
+    Some(Component {
+        pkg: last.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A minimal little-endian ELF64 binary with a `PT_LOAD` program header
+    /// and, given an interpreter, a `PT_INTERP` one
+    fn elf64(interpreter: Option<&str>) -> Vec<u8> {
+        let mut headers = vec![(1u32, 0u64, 0u64)];
+        let data_offset = 64 + 56 * 2;
+        if let Some(interpreter) = interpreter {
+            headers.push((3, data_offset, interpreter.len() as u64 + 1));
+        }
+        let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+        elf.resize(64, 0);
+        elf[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        elf[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        elf[0x38..0x3a].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+        for (kind, offset, size) in headers {
+            let mut entry = vec![0; 56];
+            entry[0..4].copy_from_slice(&kind.to_le_bytes());
+            entry[8..16].copy_from_slice(&offset.to_le_bytes());
+            entry[32..40].copy_from_slice(&size.to_le_bytes());
+            elf.extend(entry);
+        }
+        elf.resize(data_offset as usize, 0);
+        if let Some(interpreter) = interpreter {
+            elf.extend(interpreter.as_bytes());
+            elf.push(0);
+        }
+        elf
+    }
+
+    /// Like `elf64`, but big-endian ELF32 with only a `PT_INTERP` header
+    fn elf32_be(interpreter: &str) -> Vec<u8> {
+        let mut elf = b"\x7fELF\x01\x02\x01".to_vec();
+        elf.resize(52, 0);
+        elf[0x1c..0x20].copy_from_slice(&52u32.to_be_bytes());
+        elf[0x2a..0x2c].copy_from_slice(&32u16.to_be_bytes());
+        elf[0x2c..0x2e].copy_from_slice(&1u16.to_be_bytes());
+        let mut entry = vec![0; 32];
+        entry[0..4].copy_from_slice(&3u32.to_be_bytes());
+        entry[4..8].copy_from_slice(&84u32.to_be_bytes());
+        entry[16..20].copy_from_slice(&(interpreter.len() as u32 + 1).to_be_bytes());
+        elf.extend(entry);
+        elf.extend(interpreter.as_bytes());
+        elf.push(0);
+        elf
+    }
+
+    fn interpreter(elf: Vec<u8>) -> Option<String> {
+        elf_interpreter(&mut Cursor::new(elf))
+    }
+
+    #[test]
+    fn test_glibc_host() {
+        let glibc = interpreter(elf64(Some("/lib64/ld-linux-x86-64.so.2"))).unwrap();
+        assert_eq!(glibc, "/lib64/ld-linux-x86-64.so.2");
+        assert!(!is_musl_interpreter(&glibc));
+        assert!(!is_musl_interpreter("/lib/ld-linux-aarch64.so.1"));
+    }
+
+    #[test]
+    fn test_musl_host() {
+        let musl = interpreter(elf64(Some("/lib/ld-musl-x86_64.so.1"))).unwrap();
+        assert!(is_musl_interpreter(&musl));
+        let musl = interpreter(elf32_be("/lib/ld-musl-armhf.so.1")).unwrap();
+        assert!(is_musl_interpreter(&musl));
+    }
+
+    #[test]
+    fn test_no_interpreter() {
+        assert_eq!(interpreter(elf64(None)), None);
+        assert_eq!(interpreter(b"#!/bin/sh\n".to_vec()), None);
+        let mut truncated = elf64(Some("/lib/ld-musl-x86_64.so.1"));
+        truncated.truncate(100);
+        assert_eq!(interpreter(truncated), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_running_binary() {
+        // The test binary is linked against the same C library as elan
+        let mut exe = std::fs::File::open("/proc/self/exe").unwrap();
+        if let Some(interpreter) = elf_interpreter(&mut exe) {
+            assert_eq!(is_musl_interpreter(&interpreter), cfg!(target_env = "musl"));
+        }
+    }
+}
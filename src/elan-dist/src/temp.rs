@@ -28,7 +28,7 @@ pub enum Notification<'a> {
 
 pub struct Cfg {
     root_directory: PathBuf,
-    notify_handler: Box<dyn Fn(Notification<'_>)>,
+    notify_handler: Box<dyn Fn(Notification<'_>) + Send + Sync>,
 }
 
 #[derive(Debug)]
@@ -122,7 +122,10 @@ impl Display for Error {
 }
 
 impl Cfg {
-    pub fn new(root_directory: PathBuf, notify_handler: Box<dyn Fn(Notification<'_>)>) -> Self {
+    pub fn new(
+        root_directory: PathBuf,
+        notify_handler: Box<dyn Fn(Notification<'_>) + Send + Sync>,
+    ) -> Self {
         Cfg {
             root_directory: root_directory,
             notify_handler: notify_handler,
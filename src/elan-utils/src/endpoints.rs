@@ -0,0 +1,228 @@
+//! External services elan contacts
+//!
+//! Every URL elan fetches is derived from one of the endpoints below, so that
+//! an internal mirror can stand in for all of them. Each endpoint is set by
+//! an environment variable or, with lower precedence, by the setting of the
+//! same name in `settings.toml`; release indexes are configured per origin
+//! instead, see `utils::set_release_indexes`. The GitHub endpoint may be set
+//! to several comma-separated URLs, which release archives and pages are
+//! downloaded from in turn should a server fail.
+//!
+//! `ipfs://` URLs listed in release indexes are not fetched from IPFS
+//! directly but through the HTTP gateway of an IPFS node, usually one on the
+//! local machine or network, which is what lets many machines nearby share
+//! a download.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Mutex;
+
+/// Default index of the latest releases of the official Lean repositories
+pub const DEFAULT_RELEASE_INDEX_URL: &str = "https://release.lean-lang.org/";
+
+#[derive(Debug)]
+pub struct Endpoint {
+    /// Name of the setting in `settings.toml`
+    pub setting: &'static str,
+    /// Name of the environment variable, which takes precedence over the
+    /// setting
+    pub env_var: &'static str,
+    default: fn() -> String,
+    pub description: &'static str,
+}
+
+/// Where the URL of an endpoint comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Env,
+    Setting,
+    Default,
+}
+
+/// GitHub web site, which release archives, release pages, and source code
+/// of toolchains are downloaded from
+pub const GITHUB: Endpoint = Endpoint {
+    setting: "github_url",
+    env_var: "ELAN_GITHUB_URL",
+    default: || "https://github.com".to_owned(),
+    description: "the GitHub web site that releases and sources are downloaded from",
+};
+
+/// GitHub REST API, used to look up releases and tags
+pub const GITHUB_API: Endpoint = Endpoint {
+    setting: "github_api_url",
+    env_var: "ELAN_GITHUB_API_URL",
+    default: || "https://api.github.com".to_owned(),
+    description: "the GitHub API that releases and tags are looked up in",
+};
+
+/// Raw file contents of GitHub repositories, used for `<origin>:lean-toolchain`
+pub const GITHUB_RAW: Endpoint = Endpoint {
+    setting: "github_raw_url",
+    env_var: "ELAN_GITHUB_RAW_URL",
+    default: || "https://raw.githubusercontent.com".to_owned(),
+    description: "the host serving raw files of GitHub repositories",
+};
+
+/// Base URL that `elan self update` downloads elan releases from, below the
+/// GitHub endpoint by default
+pub const UPDATE_ROOT: Endpoint = Endpoint {
+    setting: "update_root",
+    env_var: "ELAN_UPDATE_ROOT",
+    default: || format!("{}/leanprover/elan/releases/download", GITHUB.url()),
+    description: "the base URL that `elan self update` downloads releases of elan from",
+};
+
+/// HTTP gateway of an IPFS node, which release archives with `ipfs://` URLs
+/// are downloaded through if elan is built with the `ipfs` feature
+pub const IPFS_GATEWAY: Endpoint = Endpoint {
+    setting: "ipfs_gateway",
+    env_var: "ELAN_IPFS_GATEWAY",
+    default: || "http://127.0.0.1:8080".to_owned(),
+    description:
+        "the IPFS gateway that release archives with `ipfs://` URLs are downloaded through",
+};
+
+pub const ENDPOINTS: &[&Endpoint] = &[
+    &GITHUB,
+    &GITHUB_API,
+    &GITHUB_RAW,
+    &UPDATE_ROOT,
+    &IPFS_GATEWAY,
+];
+
+/// URLs set in `settings.toml`, keyed by setting name
+static CONFIGURED: Mutex<Option<BTreeMap<String, String>>> = Mutex::new(None);
+
+/// Applies the endpoint URLs set in `settings.toml`, keyed by setting name
+pub fn set_configured(urls: BTreeMap<String, String>) {
+    *CONFIGURED.lock().unwrap() = Some(urls);
+}
+
+impl Endpoint {
+    /// The URLs in effect, most preferred first, and where they come from,
+    /// without trailing slashes
+    pub fn resolve_all(&self) -> (Vec<String>, Source) {
+        let from_env = env::var(self.env_var).ok().filter(|url| !url.is_empty());
+        let from_setting = || {
+            CONFIGURED
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|urls| urls.get(self.setting).cloned())
+        };
+        let (url, source) = if let Some(url) = from_env {
+            (url, Source::Env)
+        } else if let Some(url) = from_setting() {
+            (url, Source::Setting)
+        } else {
+            ((self.default)(), Source::Default)
+        };
+        let urls = url
+            .split(',')
+            .map(|url| url.trim().trim_end_matches('/').to_owned())
+            .filter(|url| !url.is_empty())
+            .collect();
+        (urls, source)
+    }
+
+    /// The most preferred URL in effect and where it comes from, without a
+    /// trailing slash
+    pub fn resolve(&self) -> (String, Source) {
+        let (urls, source) = self.resolve_all();
+        (urls.into_iter().next().unwrap_or_default(), source)
+    }
+
+    /// The most preferred URL in effect, without a trailing slash
+    pub fn url(&self) -> String {
+        self.resolve().0
+    }
+
+    /// The URLs `url` can be downloaded from: below each URL of this endpoint
+    /// in turn if `url` is below the most preferred one, or else just `url`
+    pub fn mirrors_of(&self, url: &str) -> Vec<String> {
+        let (urls, _) = self.resolve_all();
+        let path = urls
+            .first()
+            .and_then(|base| url.strip_prefix(base.as_str()))
+            .filter(|path| path.is_empty() || path.starts_with('/'));
+        match path {
+            Some(path) => urls
+                .iter()
+                .map(|base| format!("{}{}", base, path))
+                .collect(),
+            None => vec![url.to_owned()],
+        }
+    }
+}
+
+/// The URL that `ipfs://<cid>/<path>` is downloaded from through the IPFS
+/// gateway, or `None` if `url` is not an IPFS URL
+pub fn ipfs_gateway_url(url: &str) -> Option<String> {
+    let path = url.strip_prefix("ipfs://")?.trim_start_matches('/');
+    if path.is_empty() {
+        return None;
+    }
+    Some(format!("{}/ipfs/{}", IPFS_GATEWAY.url(), path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints() {
+        assert_eq!(
+            GITHUB_RAW.resolve(),
+            (
+                "https://raw.githubusercontent.com".to_owned(),
+                Source::Default
+            )
+        );
+
+        let mut urls = BTreeMap::new();
+        urls.insert(
+            "github_url".to_owned(),
+            "https://mirror.example.org/".to_owned(),
+        );
+        set_configured(urls.clone());
+        assert_eq!(
+            GITHUB.resolve(),
+            ("https://mirror.example.org".to_owned(), Source::Setting)
+        );
+        // Derived from the GitHub endpoint
+        assert_eq!(
+            UPDATE_ROOT.resolve(),
+            (
+                "https://mirror.example.org/leanprover/elan/releases/download".to_owned(),
+                Source::Default
+            )
+        );
+
+        urls.insert(
+            "github_url".to_owned(),
+            "https://mirror.example.org/, https://github.com".to_owned(),
+        );
+        set_configured(urls);
+        assert_eq!(GITHUB.url(), "https://mirror.example.org");
+        assert_eq!(
+            GITHUB.mirrors_of("https://mirror.example.org/o/r/releases/download/v1/a.zip"),
+            vec![
+                "https://mirror.example.org/o/r/releases/download/v1/a.zip",
+                "https://github.com/o/r/releases/download/v1/a.zip",
+            ]
+        );
+        assert_eq!(
+            GITHUB.mirrors_of("https://mirror.example.org.evil/a.zip"),
+            vec!["https://mirror.example.org.evil/a.zip"]
+        );
+        set_configured(BTreeMap::new());
+
+        assert_eq!(
+            ipfs_gateway_url("ipfs://bafyexample/lean-4.9.0-linux.tar.zst").as_deref(),
+            Some("http://127.0.0.1:8080/ipfs/bafyexample/lean-4.9.0-linux.tar.zst")
+        );
+        assert_eq!(ipfs_gateway_url("ipfs://"), None);
+        assert_eq!(ipfs_gateway_url("https://example.org/ipfs/a"), None);
+    }
+}
@@ -46,6 +46,22 @@ error_chain! {
             description("could not create directory")
             display("could not create {} directory: '{}'", name, path.display())
         }
+        InvalidPublicKey(key: String) {
+            description("invalid public key")
+            display("invalid public key '{}'; expected a base64-encoded Ed25519 key", key)
+        }
+        SignatureVerificationFailed(path: PathBuf) {
+            description("signature verification failed")
+            display("signature verification failed for '{}'", path.display())
+        }
+        InvalidToolchainFile(source: String, reason: String) {
+            description("invalid toolchain file")
+            display("invalid toolchain file '{}': {}", source, reason)
+        }
+        ReleaseIndexSchema(url: String, reason: String) {
+            description("release index does not match the expected schema")
+            display("release index '{}' does not match the schema expected by this version of elan: {}", url, reason)
+        }
         ExpectedType(t: &'static str, n: String) {
             description("expected type")
             display("expected type: '{}' for '{}'", t, n)
@@ -88,12 +104,20 @@ error_chain! {
             description("could not download file")
             display("could not download file from '{}' to '{}'", url, path.display())
         }
+        FetchingUrl(url: String) {
+            description("could not fetch url")
+            display("could not fetch '{}'", url)
+        }
         InvalidUrl {
             url: String,
         } {
             description("invalid url")
             display("invalid url: {}", url)
         }
+        Interrupted(signal: &'static str) {
+            description("interrupted")
+            display("interrupted by {}", signal)
+        }
         RunningCommand {
             name: OsString,
         } {
@@ -0,0 +1,189 @@
+//! Cooperative handling of SIGINT and SIGTERM, or Ctrl-C and Ctrl-Break on
+//! Windows
+//!
+//! While a `Guard` returned by `catch` is alive, the first signal is only
+//! recorded so that long-running operations such as toolchain installation
+//! can notice it via `check` or `interrupted`, abort in-flight downloads, and
+//! remove their partial state and release their locks by unwinding normally.
+//! A second signal terminates the process immediately. Once the error has
+//! been reported, `reraise` terminates the process by the recorded signal so
+//! that the parent observes the same exit status as if elan had not caught
+//! it, i.e. 130 for SIGINT in a shell. Windows has no such status, so there
+//! the process exits with `EXIT_CODE_INTERRUPTED` instead.
+
+use crate::errors::*;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// The signal received while a `Guard` was alive, or 0
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Exit code of a process interrupted by Ctrl-C, as reported by shells for
+/// SIGINT
+pub const EXIT_CODE_INTERRUPTED: i32 = 130;
+
+/// Whether a signal has been received while a `Guard` was alive
+pub fn interrupted() -> bool {
+    SIGNAL.load(Ordering::SeqCst) != 0
+}
+
+/// Fails if a signal has been received while a `Guard` was alive
+pub fn check() -> Result<()> {
+    match SIGNAL.load(Ordering::SeqCst) {
+        0 => Ok(()),
+        signal => Err(ErrorKind::Interrupted(imp::signal_name(signal)).into()),
+    }
+}
+
+pub use self::imp::{catch, reraise, Guard};
+
+#[cfg(unix)]
+mod imp {
+    use super::SIGNAL;
+    use libc::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGNALS: [c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    pub struct Guard {
+        previous: Vec<(c_int, libc::sighandler_t)>,
+    }
+
+    extern "C" fn handler(signal: c_int) {
+        // Only async-signal-safe operations are allowed here
+        if SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    /// Records SIGINT and SIGTERM until the returned guard is dropped
+    pub fn catch() -> Guard {
+        let mut previous = Vec::new();
+        for &signal in &SIGNALS {
+            let old = unsafe {
+                libc::signal(
+                    signal,
+                    handler as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                )
+            };
+            // Respect signals ignored by our parent, e.g. `nohup`
+            if old == libc::SIG_IGN {
+                unsafe { libc::signal(signal, libc::SIG_IGN) };
+            } else if old != libc::SIG_ERR {
+                previous.push((signal, old));
+            }
+        }
+        Guard { previous }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            for &(signal, old) in &self.previous {
+                unsafe { libc::signal(signal, old) };
+            }
+        }
+    }
+
+    /// Terminates the process by the recorded signal, if any
+    pub fn reraise() {
+        let signal = SIGNAL.load(Ordering::SeqCst);
+        if signal != 0 {
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+
+    pub fn signal_name(signal: c_int) -> &'static str {
+        match signal {
+            libc::SIGINT => "SIGINT",
+            libc::SIGTERM => "SIGTERM",
+            _ => "a signal",
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::{EXIT_CODE_INTERRUPTED, SIGNAL};
+    use std::sync::atomic::Ordering;
+    use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    // `CTRL_C_EVENT` is 0, so events are recorded by the numbers of the
+    // corresponding C signals instead
+    const SIGINT: i32 = 2;
+    const SIGBREAK: i32 = 21;
+
+    pub struct Guard {
+        installed: bool,
+    }
+
+    /// Runs on a thread created by the system for each event
+    unsafe extern "system" fn handler(ctrl_type: DWORD) -> BOOL {
+        let signal = match ctrl_type {
+            CTRL_C_EVENT => SIGINT,
+            CTRL_BREAK_EVENT => SIGBREAK,
+            // Closing the console or logging off cannot be deferred
+            _ => return FALSE,
+        };
+        if SIGNAL.swap(signal, Ordering::SeqCst) != 0 {
+            // Let the default handler terminate the process
+            return FALSE;
+        }
+        TRUE
+    }
+
+    /// Records Ctrl-C and Ctrl-Break until the returned guard is dropped
+    pub fn catch() -> Guard {
+        let installed = unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) } != 0;
+        Guard { installed }
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            if self.installed {
+                unsafe { SetConsoleCtrlHandler(Some(handler), FALSE) };
+            }
+        }
+    }
+
+    /// Exits with `EXIT_CODE_INTERRUPTED` if an event was recorded
+    pub fn reraise() {
+        if SIGNAL.load(Ordering::SeqCst) != 0 {
+            std::process::exit(EXIT_CODE_INTERRUPTED);
+        }
+    }
+
+    pub fn signal_name(signal: i32) -> &'static str {
+        match signal {
+            SIGINT => "Ctrl-C",
+            SIGBREAK => "Ctrl-Break",
+            _ => "a signal",
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch_signal() {
+        assert!(check().is_ok());
+        {
+            let _guard = catch();
+            unsafe { libc::raise(libc::SIGTERM) };
+            assert!(interrupted());
+            assert_eq!(check().unwrap_err().to_string(), "interrupted by SIGTERM");
+        }
+        // The default disposition is restored
+        let old = unsafe { libc::signal(libc::SIGTERM, libc::SIG_DFL) };
+        assert_eq!(old, libc::SIG_DFL);
+        SIGNAL.store(0, Ordering::SeqCst);
+    }
+}
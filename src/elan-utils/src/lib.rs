@@ -1,7 +1,11 @@
 #![recursion_limit = "1024"] // for error_chain!
 #![deny(rust_2018_idioms)]
 
+pub mod endpoints;
 pub mod errors;
+pub mod interrupt;
+pub mod lock;
+pub mod mdns;
 pub mod notifications;
 pub mod raw;
 pub mod toml_utils;
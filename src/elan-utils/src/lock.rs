@@ -0,0 +1,279 @@
+//! Exclusive locks on files, used to serialize toolchain installations
+//!
+//! Mutual exclusion relies solely on OS advisory locks (`flock` on Unix,
+//! `LockFileEx` on Windows), which the OS releases however the holder exits,
+//! so a lock file left behind by a killed process or from before a reboot is
+//! never mistaken for a held lock. The PID the holder writes into the file is
+//! informational: it is shown while waiting and used to detect locks whose
+//! recorded holder is not running on this machine, e.g. because the lock is
+//! held by another machine sharing the directory or was never released by a
+//! network file server.
+//!
+//! Network file systems often do not implement OS locks reliably, so there a
+//! lock is a directory instead, created atomically by `mkdir` and holding an
+//! `owner` file with the PID and host name of the holder. As such locks are
+//! not released by the OS, one is broken once its owner is found to have
+//! exited, which is only possible on the owner's host. Both kinds of lock use
+//! the same path, so each kind also excludes holders of the other.
+
+use crate::errors::*;
+use crate::raw;
+use fslock::LockFile;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long a lock directory may lack its `owner` file before it is
+/// considered abandoned by a process killed right after creating it
+const MISSING_OWNER_GRACE: Duration = Duration::from_secs(60);
+
+/// A held lock; the lock file is removed on drop, including while unwinding
+/// after an error or interruption
+pub struct FileLock {
+    path: PathBuf,
+    kind: Kind,
+}
+
+enum Kind {
+    // Unlocked on drop, which must happen only after the file is removed
+    File(#[allow(dead_code)] LockFile),
+    Dir,
+}
+
+impl FileLock {
+    /// Takes the lock at `path`, or returns `None` if another process holds
+    /// it. Lock directories are used if `path` is on a network file system.
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        if raw::network_fs_type(parent).is_some() {
+            Self::try_acquire_dir(path)
+        } else {
+            Self::try_acquire_file(path)
+        }
+    }
+
+    fn try_acquire_file(path: &Path) -> Result<Option<Self>> {
+        loop {
+            if path.is_dir() {
+                // Held by a process using a lock directory
+                return Ok(None);
+            }
+            let mut file = LockFile::open(path)?;
+            if !file.try_lock_with_pid()? {
+                return Ok(None);
+            }
+            // The previous holder may have removed the file between our
+            // opening and locking it, in which case our lock excludes nobody.
+            // Windows does not allow opening files pending deletion, and
+            // does not let other handles read a locked file either.
+            if cfg!(windows) || holder(path) == Some(std::process::id()) {
+                return Ok(Some(FileLock {
+                    path: path.to_owned(),
+                    kind: Kind::File(file),
+                }));
+            }
+        }
+    }
+
+    fn try_acquire_dir(path: &Path) -> Result<Option<Self>> {
+        loop {
+            match fs::create_dir(path) {
+                Ok(()) => {
+                    let lock = FileLock {
+                        path: path.to_owned(),
+                        kind: Kind::Dir,
+                    };
+                    let owner = format!("{}@{}\n", std::process::id(), hostname());
+                    fs::write(path.join("owner"), owner)?;
+                    return Ok(Some(lock));
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e.into()),
+            }
+            if path.is_file() {
+                // A lock file, which can be taken over once no one holds it
+                match Self::try_acquire_file(path)? {
+                    Some(lock) => drop(lock),
+                    None => return Ok(None),
+                }
+            } else if is_abandoned_dir(path) {
+                let _ = raw::remove_dir(path);
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = match self.kind {
+            Kind::File(_) => fs::remove_file(&self.path),
+            Kind::Dir => raw::remove_dir(&self.path),
+        };
+    }
+}
+
+/// The PID and host name recorded in the lock directory `path`
+fn dir_owner(path: &Path) -> Option<(u32, String)> {
+    let owner = fs::read_to_string(path.join("owner")).ok()?;
+    let (pid, host) = owner.trim().split_once('@')?;
+    Some((pid.parse().ok()?, host.to_owned()))
+}
+
+/// Whether the lock directory `path` was left behind by a process that no
+/// longer exists
+fn is_abandoned_dir(path: &Path) -> bool {
+    match dir_owner(path) {
+        Some((pid, host)) => host == hostname() && !process_exists(pid),
+        None => fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > MISSING_OWNER_GRACE),
+    }
+}
+
+/// The PID recorded in the lock at `path`, if it can be read
+pub fn holder(path: &Path) -> Option<u32> {
+    if path.is_dir() {
+        return dir_owner(path).map(|(pid, _)| pid);
+    }
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Whether the lock at `path` is held by a process that is not running on
+/// this machine
+pub fn is_held_remotely(path: &Path) -> bool {
+    if path.is_dir() {
+        return dir_owner(path).is_some_and(|(_, host)| host != hostname());
+    }
+    holder(path).is_some_and(|pid| !process_exists(pid))
+}
+
+/// Removes the lock at `path` if no process holds it, e.g. because its
+/// holder was killed
+pub fn remove_stale(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        if is_abandoned_dir(path) {
+            raw::remove_dir(path)?;
+        }
+        return Ok(());
+    }
+    // The lock file is removed again on drop
+    FileLock::try_acquire_file(path)?;
+    Ok(())
+}
+
+/// Name of this machine as recorded in lock directories
+pub(crate) fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0 {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+        String::new()
+    }
+    #[cfg(windows)]
+    {
+        std::env::var("COMPUTERNAME").unwrap_or_default()
+    }
+}
+
+/// Whether a process with the given ID is running on this machine
+#[cfg(unix)]
+pub fn process_exists(pid: u32) -> bool {
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+    // Signal 0 only checks whether the process could be signaled
+    let res = unsafe { libc::kill(pid, 0) };
+    res == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Whether a process with the given ID is running on this machine
+#[cfg(windows)]
+pub fn process_exists(pid: u32) -> bool {
+    use winapi::shared::minwindef::DWORD;
+    use winapi::shared::winerror::ERROR_ACCESS_DENIED;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::minwinbase::STILL_ACTIVE;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            // Processes of other users exist but cannot be opened
+            return GetLastError() == ERROR_ACCESS_DENIED;
+        }
+        let mut code: DWORD = 0;
+        let running = GetExitCodeProcess(handle, &mut code) != 0 && code == STILL_ACTIVE;
+        CloseHandle(handle);
+        running
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("toolchain.lock");
+        let lock = FileLock::try_acquire(&path).unwrap().unwrap();
+        assert!(FileLock::try_acquire(&path).unwrap().is_none());
+        assert!(!is_held_remotely(&path));
+        drop(lock);
+        assert!(!path.exists());
+
+        // Recorded holders do not matter once the lock has been released
+        std::fs::write(&path, "4194304\n").unwrap();
+        remove_stale(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_dir_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("toolchain.lock");
+        let lock = FileLock::try_acquire_dir(&path).unwrap().unwrap();
+        assert!(FileLock::try_acquire_dir(&path).unwrap().is_none());
+        // Lock files and directories exclude each other
+        assert!(FileLock::try_acquire_file(&path).unwrap().is_none());
+        assert_eq!(holder(&path), Some(std::process::id()));
+        assert!(!is_held_remotely(&path));
+        drop(lock);
+        assert!(!path.exists());
+
+        let file_lock = FileLock::try_acquire_file(&path).unwrap().unwrap();
+        assert!(FileLock::try_acquire_dir(&path).unwrap().is_none());
+        drop(file_lock);
+
+        // Abandoned by a process that has exited on this machine
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("owner"), format!("0@{}", hostname())).unwrap();
+        remove_stale(&path).unwrap();
+        assert!(!path.exists());
+
+        // Held by another machine
+        fs::create_dir(&path).unwrap();
+        fs::write(path.join("owner"), "1@elsewhere.invalid").unwrap();
+        assert!(is_held_remotely(&path));
+        assert!(FileLock::try_acquire_dir(&path).unwrap().is_none());
+        remove_stale(&path).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_process_exists() {
+        assert!(process_exists(std::process::id()));
+        assert!(!process_exists(0));
+    }
+}
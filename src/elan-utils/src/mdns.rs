@@ -0,0 +1,309 @@
+//! Minimal DNS-SD over multicast DNS, used to find other elan instances on
+//! the local network
+//!
+//! Only what elan needs is implemented: a browser sends a PTR query for a
+//! service type and collects the SRV records of the answers, and a responder
+//! answers such queries for a single instance. Responses carry no address
+//! records; the browser connects to the address a response came from
+//! instead, which spares the responder from choosing among its interfaces.
+//! Queries are sent from an ephemeral port, so responders reply by unicast
+//! as for legacy queriers (RFC 6762, section 6.7).
+
+use crate::errors::*;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::BTreeSet;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// Time to live of the records in a response, in seconds
+const TTL: u32 = 120;
+
+/// Appends `name` in DNS wire format, without compression
+fn encode_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+/// Reads the possibly compressed name at `pos` of `packet`, returning it and
+/// the position after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = vec![];
+    let mut end = None;
+    // Bounds the number of compression pointers followed, so that loops in
+    // malformed packets terminate
+    for _ in 0..packet.len() {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let target = (len & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            len => {
+                let label = packet.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+        }
+    }
+    None
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *packet.get(pos)?,
+        *packet.get(pos + 1)?,
+    ]))
+}
+
+fn header(buf: &mut Vec<u8>, id: u16, flags: u16, counts: [u16; 4]) {
+    buf.extend_from_slice(&id.to_be_bytes());
+    buf.extend_from_slice(&flags.to_be_bytes());
+    for count in counts {
+        buf.extend_from_slice(&count.to_be_bytes());
+    }
+}
+
+/// A query for the instances of `service`, e.g. `_http._tcp.local`
+fn query_packet(service: &str) -> Vec<u8> {
+    let mut buf = vec![];
+    header(&mut buf, 0, 0, [1, 0, 0, 0]);
+    encode_name(&mut buf, service);
+    buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buf
+}
+
+/// A response announcing that `instance` of `service` listens on `port` of
+/// `host`, repeating the question for unicast replies to legacy queriers
+fn response_packet(
+    id: u16,
+    service: &str,
+    instance: &str,
+    host: &str,
+    port: u16,
+    unicast: bool,
+) -> Vec<u8> {
+    let instance_name = format!("{}.{}", instance, service);
+    let mut buf = vec![];
+    header(&mut buf, id, 0x8400, [unicast as u16, 2, 0, 0]);
+    if unicast {
+        encode_name(&mut buf, service);
+        buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+
+    let record = |buf: &mut Vec<u8>, name: &str, ty: u16, rdata: &[u8]| {
+        encode_name(buf, name);
+        buf.extend_from_slice(&ty.to_be_bytes());
+        buf.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buf.extend_from_slice(&TTL.to_be_bytes());
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(rdata);
+    };
+    let mut ptr = vec![];
+    encode_name(&mut ptr, &instance_name);
+    record(&mut buf, service, TYPE_PTR, &ptr);
+    // Priority and weight, which are meaningless for a single instance
+    let mut srv = vec![0, 0, 0, 0];
+    srv.extend_from_slice(&port.to_be_bytes());
+    encode_name(&mut srv, &format!("{}.local", host));
+    record(&mut buf, &instance_name, TYPE_SRV, &srv);
+    buf
+}
+
+/// The ID of `packet` if it is a query containing a PTR question for
+/// `service`
+fn query_for(packet: &[u8], service: &str) -> Option<u16> {
+    let id = read_u16(packet, 0)?;
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 != 0 {
+        return None;
+    }
+    let mut pos = 12;
+    for _ in 0..read_u16(packet, 4)? {
+        let (name, next) = read_name(packet, pos)?;
+        let ty = read_u16(packet, next)?;
+        if ty == TYPE_PTR && name.eq_ignore_ascii_case(service.trim_end_matches('.')) {
+            return Some(id);
+        }
+        pos = next + 4;
+    }
+    None
+}
+
+/// The ports that the SRV records in the response `packet` announce for
+/// instances of `service`
+fn advertised_ports(packet: &[u8], service: &str) -> Vec<u16> {
+    fn parse(packet: &[u8], service: &str) -> Option<Vec<u16>> {
+        if read_u16(packet, 2)? & 0x8000 == 0 {
+            return Some(vec![]);
+        }
+        let suffix = format!(".{}", service.trim_end_matches('.')).to_ascii_lowercase();
+        let mut pos = 12;
+        for _ in 0..read_u16(packet, 4)? {
+            pos = read_name(packet, pos)?.1 + 4;
+        }
+        let records: u32 = (6..12)
+            .step_by(2)
+            .map(|offset| read_u16(packet, offset).map(u32::from))
+            .sum::<Option<u32>>()?;
+        let mut ports = vec![];
+        for _ in 0..records {
+            let (name, next) = read_name(packet, pos)?;
+            let ty = read_u16(packet, next)?;
+            let rdata = next + 10;
+            let rdata_len = read_u16(packet, next + 8)? as usize;
+            // Truncated packets are ignored altogether
+            packet.get(rdata..rdata + rdata_len)?;
+            if ty == TYPE_SRV && name.to_ascii_lowercase().ends_with(&suffix) {
+                ports.push(read_u16(packet, rdata + 4)?);
+            }
+            pos = rdata + rdata_len;
+        }
+        Some(ports)
+    }
+    parse(packet, service).unwrap_or_default()
+}
+
+/// Addresses of the instances of `service` that respond on the local network
+/// within `timeout`
+pub fn discover(service: &str, timeout: Duration) -> Result<Vec<SocketAddr>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.send_to(&query_packet(service), (MDNS_GROUP, MDNS_PORT))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut found = BTreeSet::new();
+    let mut buf = [0; 9000];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                for port in advertised_ports(&buf[..len], service) {
+                    found.insert(SocketAddr::new(from.ip(), port));
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(found.into_iter().collect())
+}
+
+/// Answers queries for one instance of a service on the local network
+pub struct Responder {
+    socket: UdpSocket,
+    service: String,
+    instance: String,
+    port: u16,
+}
+
+impl Responder {
+    /// Joins the multicast DNS group to announce that `instance` of `service`
+    /// listens on `port` of this machine. The mDNS port is shared with other
+    /// responders such as Avahi or Bonjour.
+    pub fn bind(service: &str, instance: &str, port: u16) -> Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT).into())?;
+        socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_ttl_v4(255)?;
+        Ok(Responder {
+            socket: socket.into(),
+            service: service.to_owned(),
+            instance: instance.to_owned(),
+            port,
+        })
+    }
+
+    /// Answers queries until an error occurs
+    pub fn run(&self) -> Result<()> {
+        let host = crate::lock::hostname();
+        let host = host.split('.').next().unwrap_or("elan");
+        let mut buf = [0; 9000];
+        loop {
+            let (len, from) = self.socket.recv_from(&mut buf)?;
+            let id = match query_for(&buf[..len], &self.service) {
+                Some(id) => id,
+                None => continue,
+            };
+            let unicast = from.port() != MDNS_PORT;
+            let response = response_packet(
+                if unicast { id } else { 0 },
+                &self.service,
+                &self.instance,
+                host,
+                self.port,
+                unicast,
+            );
+            let to = if unicast {
+                from
+            } else {
+                (MDNS_GROUP, MDNS_PORT).into()
+            };
+            // Peers that have gone away are of no concern
+            let _ = self.socket.send_to(&response, to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: &str = "_elan-cache._tcp.local";
+
+    #[test]
+    fn test_packets() {
+        let query = query_packet(SERVICE);
+        assert_eq!(query_for(&query, SERVICE), Some(0));
+        assert_eq!(query_for(&query, "_http._tcp.local"), None);
+        // Responses are not queries
+        let response = response_packet(7, SERVICE, "host", "host", 4242, true);
+        assert_eq!(query_for(&response, SERVICE), None);
+
+        assert_eq!(advertised_ports(&response, SERVICE), vec![4242]);
+        let response = response_packet(0, SERVICE, "host", "host", 4243, false);
+        assert_eq!(advertised_ports(&response, SERVICE), vec![4243]);
+        assert!(advertised_ports(&response, "_http._tcp.local").is_empty());
+        assert!(advertised_ports(&query, SERVICE).is_empty());
+        assert!(advertised_ports(&response[..response.len() - 3], SERVICE).is_empty());
+    }
+
+    #[test]
+    fn test_compressed_names() {
+        // `b.a` followed by a pointer to it prefixed with `c`
+        let packet = [1, b'b', 1, b'a', 0, 1, b'c', 0xc0, 0];
+        assert_eq!(read_name(&packet, 0), Some(("b.a".to_owned(), 5)));
+        assert_eq!(read_name(&packet, 5), Some(("c.b.a".to_owned(), 9)));
+        // A pointer to itself
+        assert_eq!(read_name(&[0xc0, 0], 0), None);
+    }
+}
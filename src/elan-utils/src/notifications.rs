@@ -20,6 +20,19 @@ pub enum Notification<'a> {
     DownloadFinished,
     NoCanonicalPath(&'a Path),
     ResumingPartialDownload,
+    RetryingTruncatedDownload(&'a Url),
+    RetryingFailedDownload(&'a Url),
+    WaitingForRateLimit(u64),
+    /// Repository, channel, release tag, and the source it was found in
+    FoundLatestRelease(&'a str, &'a str, &'a str, &'a str),
+    /// Source that could not be queried, and why
+    QueryingLatestReleaseFailed(&'a str, &'a str),
+    /// Sparse release index entry that could not be used, and why
+    SparseReleaseIndexUnavailable(&'a Url, &'a str),
+    /// A release index was skipped because its schema is not supported
+    ReleaseIndexSchemaMismatch(&'a str),
+    /// Downloading from the first URL failed; the second one is tried next
+    MirrorFailed(&'a str, &'a str),
     UsingCurl,
     UsingReqwest,
     UsingHyperDeprecated,
@@ -37,9 +50,18 @@ impl<'a> Notification<'a> {
             | DownloadDataReceived(_)
             | DownloadFinished
             | ResumingPartialDownload
+            | FoundLatestRelease(..)
+            | QueryingLatestReleaseFailed(..)
+            | SparseReleaseIndexUnavailable(..)
             | UsingCurl
             | UsingReqwest => NotificationLevel::Verbose,
-            UsingHyperDeprecated | NoCanonicalPath(_) => NotificationLevel::Warn,
+            UsingHyperDeprecated
+            | NoCanonicalPath(_)
+            | RetryingTruncatedDownload(_)
+            | RetryingFailedDownload(_)
+            | WaitingForRateLimit(_)
+            | MirrorFailed(..)
+            | ReleaseIndexSchemaMismatch(_) => NotificationLevel::Warn,
         }
     }
 }
@@ -62,6 +84,36 @@ impl<'a> Display for Notification<'a> {
             DownloadFinished => write!(f, "download finished"),
             NoCanonicalPath(path) => write!(f, "could not canonicalize path: '{}'", path.display()),
             ResumingPartialDownload => write!(f, "resuming partial download"),
+            RetryingTruncatedDownload(url) => {
+                write!(f, "download from '{}' was cut short, retrying", url)
+            }
+            RetryingFailedDownload(url) => {
+                write!(f, "download from '{}' failed, retrying", url)
+            }
+            WaitingForRateLimit(secs) => {
+                write!(
+                    f,
+                    "GitHub rate limit exceeded, retrying in {} seconds",
+                    secs
+                )
+            }
+            FoundLatestRelease(repo, channel, tag, source) => write!(
+                f,
+                "latest {} release of '{}' is '{}' according to {}",
+                channel, repo, tag, source
+            ),
+            QueryingLatestReleaseFailed(source, e) => {
+                write!(f, "failed to query {}: {}", source, e)
+            }
+            SparseReleaseIndexUnavailable(url, e) => write!(
+                f,
+                "no usable release index entry at '{}' ({}), fetching the full index",
+                url, e
+            ),
+            ReleaseIndexSchemaMismatch(e) => write!(f, "{}; ignoring the release index", e),
+            MirrorFailed(url, next) => {
+                write!(f, "failed to download '{}'; trying '{}' instead", url, next)
+            }
             UsingCurl => write!(f, "downloading with curl"),
             UsingReqwest => write!(f, "downloading with reqwest"),
             UsingHyperDeprecated => f.write_str(
@@ -36,6 +36,45 @@ pub fn path_exists<P: AsRef<Path>>(path: P) -> bool {
     fs::metadata(path).is_ok()
 }
 
+/// Whether the current user can create files in the existing directory `path`
+#[cfg(unix)]
+pub fn is_writable_dir<P: AsRef<Path>>(path: P) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match CString::new(path.as_ref().as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::W_OK | libc::X_OK,
+            libc::AT_EACCESS,
+        ) == 0
+    }
+}
+
+/// Whether the current user can create files in the existing directory `path`
+#[cfg(windows)]
+pub fn is_writable_dir<P: AsRef<Path>>(path: P) -> bool {
+    let probe = path
+        .as_ref()
+        .join(format!(".elan-write-test-{}", random_string(8)));
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
 pub fn random_string(length: usize) -> String {
     let chars = b"abcdefghijklmnopqrstuvwxyz0123456789_";
     (0..length)
@@ -336,6 +375,209 @@ pub fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Moves the directory `src` to `dest`, which must not exist. Where a plain
+/// rename is impossible because the two are on different devices, `src` is
+/// copied next to `dest` first so that `dest` still appears atomically. On
+/// network file systems, renames failing because a file is briefly in use
+/// by the server or a virus scanner are retried; other failures, such as a
+/// missing `src`, are not.
+pub fn rename_dir(src: &Path, dest: &Path) -> io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match fs::rename(src, dest) {
+            Ok(()) => return Ok(()),
+            Err(e) if is_cross_device(&e) => break,
+            Err(e) if attempt < 5 && is_transient(&e) && network_fs_type(src).is_some() => {
+                std::thread::sleep(std::time::Duration::from_millis(200 * attempt));
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let parent = dest.parent().unwrap_or_else(|| Path::new("."));
+    let copy = parent.join(format!(".{}.partial", random_string(12)));
+    copy_dir(src, &copy)
+        .and_then(|()| fs::rename(&copy, dest))
+        .inspect_err(|_| {
+            let _ = remove_dir(&copy);
+        })?;
+    remove_dir(src)
+}
+
+/// Whether `e` may be caused by another process briefly holding a file
+fn is_transient(e: &io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        use winapi::shared::winerror::{ERROR_LOCK_VIOLATION, ERROR_SHARING_VIOLATION};
+        if matches!(
+            e.raw_os_error(),
+            Some(code) if code == ERROR_SHARING_VIOLATION as i32 || code == ERROR_LOCK_VIOLATION as i32
+        ) {
+            return true;
+        }
+    }
+    matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::ResourceBusy
+            | io::ErrorKind::ExecutableFileBusy
+            | io::ErrorKind::StaleNetworkFileHandle
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::TimedOut
+    )
+}
+
+fn is_cross_device(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    const CROSS_DEVICE: i32 = libc::EXDEV;
+    #[cfg(windows)]
+    const CROSS_DEVICE: i32 = winapi::shared::winerror::ERROR_NOT_SAME_DEVICE as i32;
+    e.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+/// The kind of network file system `path` is on, if any. Such file systems
+/// may not honor OS file locks and may fail renames that local file systems
+/// perform atomically.
+#[cfg(target_os = "linux")]
+pub fn network_fs_type(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // The width and signedness of `f_type` vary between architectures
+    match stat.f_type as u32 {
+        0x6969 => Some("nfs"),
+        0x517b => Some("smb"),
+        0xff53_4d42 => Some("cifs"),
+        0xfe53_4d42 => Some("smb2"),
+        0x5346_414f => Some("afs"),
+        0x0bd0_0bd0 => Some("lustre"),
+        0x4750_4653 => Some("gpfs"),
+        // Most FUSE file systems are local, e.g. those of Flatpak or
+        // AppImages, so only those known to be backed by a server count
+        0x6573_5546 => fs::read_to_string("/proc/mounts")
+            .ok()
+            .and_then(|mounts| network_fuse_type(&mounts, &fs::canonicalize(path).ok()?)),
+        _ => None,
+    }
+}
+
+/// The kind of network file system of the FUSE mount in `mounts`, in the
+/// format of `/proc/mounts`, that `path` is on, if any
+#[cfg(any(target_os = "linux", test))]
+fn network_fuse_type(mounts: &str, path: &Path) -> Option<&'static str> {
+    let (_, fs_type) = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let mount_point = unescape_mount_field(fields.nth(1)?);
+            let fs_type = fields.next()?;
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.as_os_str().len(), fs_type))
+        })
+        // The innermost mount point
+        .max_by_key(|(len, _)| *len)?;
+    let subtype = fs_type.strip_prefix("fuse.").unwrap_or(fs_type);
+    [
+        "sshfs",
+        "rclone",
+        "s3fs",
+        "gcsfuse",
+        "glusterfs",
+        "ceph-fuse",
+        "juicefs",
+    ]
+    .into_iter()
+    .find(|known| *known == subtype)
+}
+
+/// Undoes the octal escaping of spaces and other special characters in the
+/// fields of `/proc/mounts`
+#[cfg(any(target_os = "linux", test))]
+fn unescape_mount_field(field: &str) -> std::path::PathBuf {
+    let mut bytes = vec![];
+    let mut rest = field.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let code = tail
+            .get(..3)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) if b == b'\\' => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    std::path::PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(target_os = "macos")]
+pub fn network_fs_type(path: &Path) -> Option<&'static str> {
+    use std::ffi::CStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    match name.to_bytes() {
+        b"nfs" => Some("nfs"),
+        b"smbfs" => Some("smb"),
+        b"afpfs" => Some("afp"),
+        b"webdav" => Some("webdav"),
+        _ => None,
+    }
+}
+
+#[cfg(windows)]
+pub fn network_fs_type(path: &Path) -> Option<&'static str> {
+    use winapi::um::fileapi::{GetDriveTypeW, GetVolumePathNameW};
+    use winapi::um::winbase::DRIVE_REMOTE;
+
+    let path = windows::to_u16s(path).ok()?;
+    let mut root = [0u16; 1024];
+    unsafe {
+        if GetVolumePathNameW(path.as_ptr(), root.as_mut_ptr(), root.len() as u32) == 0 {
+            return None;
+        }
+        if GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE {
+            Some("smb")
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn network_fs_type(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+pub fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in path.read_dir()? {
+        let entry = entry?;
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
 pub fn prefix_arg<S: AsRef<OsStr>>(name: &str, s: S) -> OsString {
     let mut arg = OsString::from(name);
     arg.push(s);
@@ -478,3 +720,35 @@ pub mod windows {
         inner(s.as_ref())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_fuse_type() {
+        let mounts = "\
+/dev/sda1 / ext4 rw,relatime 0 0
+user@host:/srv /mnt/remote fuse.sshfs rw,nosuid,nodev 0 0
+/dev/fuse /mnt/remote/local fuse.squashfuse ro 0 0
+remote: /home/me/my\\040drive fuse.rclone rw 0 0
+";
+        let fuse_type = |path: &str| network_fuse_type(mounts, Path::new(path));
+        assert_eq!(fuse_type("/mnt/remote/project"), Some("sshfs"));
+        assert_eq!(fuse_type("/mnt/remote/local/project"), None);
+        assert_eq!(fuse_type("/mnt/remote2"), None);
+        assert_eq!(fuse_type("/home/me/my drive/project"), Some("rclone"));
+        assert_eq!(fuse_type("/home/me"), None);
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+        assert!(!is_transient(&io::Error::from(io::ErrorKind::NotFound)));
+        assert!(!is_transient(&io::Error::from(
+            io::ErrorKind::AlreadyExists
+        )));
+    }
+}
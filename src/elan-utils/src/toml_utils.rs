@@ -86,3 +86,41 @@ pub fn get_array(
         Ok(toml::value::Array::new())
     }
 }
+
+pub fn get_opt_string_array(
+    table: &mut toml::value::Table,
+    key: &str,
+    path: &str,
+) -> Result<Option<Vec<String>>> {
+    if let Some(v) = table.remove(key) {
+        if let toml::Value::Array(a) = v {
+            a.into_iter()
+                .map(|v| match v {
+                    toml::Value::String(s) => Ok(s),
+                    _ => Err(ErrorKind::ExpectedType("string", path.to_owned() + key).into()),
+                })
+                .collect::<Result<_>>()
+                .map(Some)
+        } else {
+            Err(ErrorKind::ExpectedType("array", path.to_owned() + key).into())
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn get_opt_integer(
+    table: &mut toml::value::Table,
+    key: &str,
+    path: &str,
+) -> Result<Option<i64>> {
+    if let Ok(v) = get_value(table, key, path) {
+        if let toml::Value::Integer(i) = v {
+            Ok(Some(i))
+        } else {
+            Err(ErrorKind::ExpectedType("integer", path.to_owned() + key).into())
+        }
+    } else {
+        Ok(None)
+    }
+}
@@ -44,3 +44,145 @@ pub fn stdout_isatty() -> bool {
         GetConsoleMode(handle, &mut out) != 0
     }
 }
+
+#[cfg(unix)]
+pub fn stdin_isatty() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) != 0 }
+}
+
+#[cfg(windows)]
+pub fn stdin_isatty() -> bool {
+    type DWORD = u32;
+    type BOOL = i32;
+    type HANDLE = *mut u8;
+    const STD_INPUT_HANDLE: DWORD = -10i32 as DWORD;
+    extern "system" {
+        fn GetStdHandle(which: DWORD) -> HANDLE;
+        fn GetConsoleMode(hConsoleHandle: HANDLE, lpMode: *mut DWORD) -> BOOL;
+    }
+    unsafe {
+        let handle = GetStdHandle(STD_INPUT_HANDLE);
+        let mut out = 0;
+        GetConsoleMode(handle, &mut out) != 0
+    }
+}
+
+/// The width of the terminal attached to stdout in columns, if any
+#[cfg(unix)]
+pub fn stdout_width() -> Option<usize> {
+    terminal_width(libc::STDOUT_FILENO)
+}
+
+/// The width of the terminal attached to stderr in columns, if any
+#[cfg(unix)]
+pub fn stderr_width() -> Option<usize> {
+    terminal_width(libc::STDERR_FILENO)
+}
+
+#[cfg(unix)]
+fn terminal_width(fd: libc::c_int) -> Option<usize> {
+    unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 {
+            Some(size.ws_col as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn stdout_width() -> Option<usize> {
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    terminal_width(STD_OUTPUT_HANDLE)
+}
+
+#[cfg(windows)]
+pub fn stderr_width() -> Option<usize> {
+    const STD_ERROR_HANDLE: u32 = -12i32 as u32;
+    terminal_width(STD_ERROR_HANDLE)
+}
+
+#[cfg(windows)]
+fn terminal_width(which: u32) -> Option<usize> {
+    type DWORD = u32;
+    type BOOL = i32;
+    type HANDLE = *mut u8;
+    #[repr(C)]
+    struct ConsoleScreenBufferInfo {
+        size: [i16; 2],
+        cursor_position: [i16; 2],
+        attributes: u16,
+        // left, top, right, bottom
+        window: [i16; 4],
+        maximum_window_size: [i16; 2],
+    }
+    extern "system" {
+        fn GetStdHandle(which: DWORD) -> HANDLE;
+        fn GetConsoleScreenBufferInfo(
+            hConsoleOutput: HANDLE,
+            lpConsoleScreenBufferInfo: *mut ConsoleScreenBufferInfo,
+        ) -> BOOL;
+    }
+    unsafe {
+        let handle = GetStdHandle(which);
+        let mut info: ConsoleScreenBufferInfo = std::mem::zeroed();
+        if GetConsoleScreenBufferInfo(handle, &mut info) != 0 {
+            Some((info.window[2] - info.window[0] + 1).max(1) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Switches the console to UTF-8 output and enables the processing of ANSI
+/// escape sequences so that non-ASCII text and colors render correctly in
+/// legacy consoles. Returns the previous output code page, which should be
+/// restored using `restore_console` as it outlives the process.
+#[cfg(windows)]
+pub fn setup_console() -> Option<u32> {
+    type DWORD = u32;
+    type BOOL = i32;
+    type UINT = u32;
+    type HANDLE = *mut u8;
+    const STD_OUTPUT_HANDLE: DWORD = -11i32 as DWORD;
+    const STD_ERROR_HANDLE: DWORD = -12i32 as DWORD;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: DWORD = 0x0004;
+    const CP_UTF8: UINT = 65001;
+    extern "system" {
+        fn GetStdHandle(which: DWORD) -> HANDLE;
+        fn GetConsoleMode(hConsoleHandle: HANDLE, lpMode: *mut DWORD) -> BOOL;
+        fn SetConsoleMode(hConsoleHandle: HANDLE, dwMode: DWORD) -> BOOL;
+        fn GetConsoleOutputCP() -> UINT;
+        fn SetConsoleOutputCP(wCodePageID: UINT) -> BOOL;
+    }
+    unsafe {
+        for which in [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE] {
+            let handle = GetStdHandle(which);
+            let mut mode = 0;
+            // Fails on versions of Windows before 10, which then keep using
+            // the console API for colors
+            if GetConsoleMode(handle, &mut mode) != 0 {
+                SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING);
+            }
+        }
+        let previous = GetConsoleOutputCP();
+        if previous == 0 || previous == CP_UTF8 || SetConsoleOutputCP(CP_UTF8) == 0 {
+            return None;
+        }
+        Some(previous)
+    }
+}
+
+/// Restores the console output code page changed by `setup_console`
+#[cfg(windows)]
+pub fn restore_console(code_page: Option<u32>) {
+    extern "system" {
+        fn SetConsoleOutputCP(wCodePageID: u32) -> i32;
+    }
+    if let Some(code_page) = code_page {
+        unsafe {
+            SetConsoleOutputCP(code_page);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! JSON interface to toolchain resolution, binary lookup, listing,
+//! installation, uninstallation, and garbage collection, shared by
+//! `elan daemon`, `elan api`, and the C ABI in `libelan`
+//!
+//! Requests name a method and pass its parameters as a JSON object; results
+//! are returned as JSON values. Installation progress is reported through the
+//! notification handler of the `Cfg`, which `ProgressEncoder` turns into JSON.
+//!
+//! The parameters and results of all methods are a stable interface
+//! versioned by `API_VERSION`: within a version, fields are only ever added,
+//! never removed or changed in meaning.
+
+use crate::errors::*;
+use crate::{
+    gc, lookup_toolchain_desc, resolve_toolchain_desc, utils, Cfg, Notification, Toolchain,
+};
+use elan_utils::notify::NotificationLevel;
+use itertools::Itertools;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Version of the parameter and result schemas of all methods
+pub const API_VERSION: u64 = 1;
+
+/// Methods accepted by `dispatch`
+pub const METHODS: &[&str] = &["resolve", "which", "list", "install", "uninstall", "gc"];
+
+// JSON-RPC 2.0 error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const SERVER_ERROR: i64 = -32000;
+
+pub enum RequestError {
+    Method,
+    Params(String),
+    Elan(Error),
+}
+
+impl RequestError {
+    /// JSON-RPC error code and message
+    pub fn describe(&self) -> (i64, String) {
+        match self {
+            RequestError::Method => (METHOD_NOT_FOUND, "method not found".to_owned()),
+            RequestError::Params(msg) => (INVALID_PARAMS, msg.clone()),
+            RequestError::Elan(e) => (SERVER_ERROR, e.iter().map(|e| e.to_string()).join(": ")),
+        }
+    }
+}
+
+impl From<Error> for RequestError {
+    fn from(e: Error) -> Self {
+        RequestError::Elan(e)
+    }
+}
+
+impl From<elan_utils::Error> for RequestError {
+    fn from(e: elan_utils::Error) -> Self {
+        RequestError::Elan(e.into())
+    }
+}
+
+fn str_param<'a>(params: &'a Value, name: &str) -> std::result::Result<&'a str, RequestError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RequestError::Params(format!("missing string parameter '{}'", name)))
+}
+
+fn bool_param(params: &Value, name: &str) -> std::result::Result<bool, RequestError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(false),
+        Some(Value::Bool(b)) => Ok(*b),
+        Some(_) => Err(RequestError::Params(format!(
+            "parameter '{}' must be a boolean",
+            name
+        ))),
+    }
+}
+
+/// The `dir` parameter, defaulting to the current directory
+pub fn dir_param(params: &Value) -> std::result::Result<PathBuf, RequestError> {
+    match params.get("dir").and_then(Value::as_str) {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Ok(utils::current_dir()?),
+    }
+}
+
+pub fn dispatch(
+    cfg: &Cfg,
+    method: &str,
+    params: &Value,
+) -> std::result::Result<Value, RequestError> {
+    match method {
+        "resolve" => {
+            if let Some(name) = params.get("toolchain").and_then(Value::as_str) {
+                let desc = lookup_toolchain_desc(cfg, name)?;
+                return Ok(json!({ "toolchain": desc.to_string(), "reason": Value::Null }));
+            }
+            let dir = dir_param(params)?;
+            if let Some((unresolved, reason)) = cfg.find_override(&dir)? {
+                let desc = resolve_toolchain_desc(cfg, &unresolved)?;
+                Ok(json!({ "toolchain": desc.to_string(), "reason": reason.to_string() }))
+            } else if let Some(desc) = cfg.resolve_default_if_enabled()? {
+                Ok(json!({ "toolchain": desc.to_string(), "reason": "default toolchain" }))
+            } else {
+                Ok(json!({ "toolchain": Value::Null, "reason": Value::Null }))
+            }
+        }
+        "which" => {
+            let command = str_param(params, "command")?;
+            let dir = dir_param(params)?;
+            let path = cfg.which_binary(&dir, command)?;
+            Ok(json!({ "path": path }))
+        }
+        "list" => {
+            let toolchains = cfg
+                .list_toolchains()?
+                .into_iter()
+                .map(|t| {
+                    json!({
+                        "name": t.to_string(),
+                        "path": Toolchain::from(cfg, &t).path(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(json!({ "toolchains": toolchains }))
+        }
+        "install" => {
+            let name = str_param(params, "toolchain")?;
+            let desc = lookup_toolchain_desc(cfg, name)?;
+            let toolchain = cfg.get_toolchain(&desc, false)?;
+            let was_absent = !toolchain.exists();
+            if was_absent {
+                toolchain.install_from_dist()?;
+            }
+            Ok(json!({ "toolchain": desc.to_string(), "installed": was_absent }))
+        }
+        "uninstall" => {
+            let name = str_param(params, "toolchain")?;
+            let force = bool_param(params, "force")?;
+            let desc = lookup_toolchain_desc(cfg, name)?;
+            let toolchain = cfg.get_toolchain(&desc, false)?;
+            let uninstalled = toolchain.exists();
+            if uninstalled {
+                if !force {
+                    toolchain.check_removable()?;
+                }
+                toolchain.remove()?;
+            }
+            Ok(json!({ "toolchain": desc.to_string(), "uninstalled": uninstalled }))
+        }
+        "gc" => {
+            let delete = bool_param(params, "delete")?;
+            let force = bool_param(params, "force")?;
+            let (unused, used) = gc::analyze_toolchains(cfg)?;
+            let (frozen, unused): (Vec<_>, Vec<_>) =
+                unused.into_iter().partition(|t| !force && t.is_frozen());
+            let removed_paths = unused.iter().flat_map(|t| t.removed_paths()).collect_vec();
+            if delete {
+                for t in &unused {
+                    t.remove_unused()?;
+                }
+            }
+            let names = |ts: &[Toolchain<'_>]| ts.iter().map(|t| t.desc.to_string()).collect_vec();
+            Ok(json!({
+                "unused_toolchains": names(&unused),
+                "frozen_toolchains": names(&frozen),
+                "used_toolchains": used
+                    .iter()
+                    .map(|(user, tc)| json!({ "user": user, "toolchain": tc.to_string() }))
+                    .collect_vec(),
+                "removed_paths": removed_paths,
+                "deleted": delete,
+            }))
+        }
+        _ => Err(RequestError::Method),
+    }
+}
+
+/// Turns notifications into JSON progress reports, tracking the state of the
+/// current download
+#[derive(Default)]
+pub struct ProgressEncoder {
+    /// (bytes downloaded, Content-Length) of the current download
+    download: Mutex<(u64, Option<u64>)>,
+}
+
+impl ProgressEncoder {
+    /// Returns the report for `n`, if it should be reported at all
+    pub fn encode(&self, n: &Notification<'_>) -> Option<Value> {
+        use elan_dist::Notification as In;
+        use elan_utils::Notification as Un;
+
+        match n {
+            Notification::Install(In::Utils(Un::DownloadContentLengthReceived(len))) => {
+                *self.download.lock().unwrap() = (0, Some(*len));
+                None
+            }
+            Notification::Install(In::Utils(Un::DownloadDataReceived(data))) => {
+                let mut download = self.download.lock().unwrap();
+                let (before, total) = *download;
+                let after = before + data.len() as u64;
+                *download = (after, total);
+                // Report at most once per MiB
+                if before >> 20 == after >> 20 {
+                    return None;
+                }
+                Some(json!({ "kind": "download", "downloaded": after, "total": total }))
+            }
+            Notification::Install(In::Utils(Un::DownloadFinished)) => {
+                let (downloaded, total) = std::mem::take(&mut *self.download.lock().unwrap());
+                Some(
+                    json!({ "kind": "download", "downloaded": downloaded, "total": total, "finished": true }),
+                )
+            }
+            _ => match n.level() {
+                NotificationLevel::Verbose => None,
+                level => Some(json!({
+                    "kind": "message",
+                    "level": format!("{:?}", level).to_lowercase(),
+                    "message": n.to_string(),
+                })),
+            },
+        }
+    }
+}
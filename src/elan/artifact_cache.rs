@@ -0,0 +1,318 @@
+//! Supplementary caches that projects declare, such as prebuilt Mathlib
+//! build artifacts
+//!
+//! A project lists the caches it wants in an `elan-artifacts.toml` file next
+//! to its `lean-toolchain` file:
+//!
+//! ```toml
+//! [[cache]]
+//! name = "mathlib"
+//! index = "https://example.org/mathlib/v4.9.0.json"
+//! toolchains = ["leanprover/lean4:v4.9.0"]
+//! env = "MATHLIB_CACHE_DIR"
+//! ```
+//!
+//! The index is a JSON object `{"files": [{"name", "sha256", "url"}]}`,
+//! where `url` is relative to the index and defaults to the name of the file.
+//! `elan cache fetch` downloads the files of each cache that applies to the
+//! toolchain of the project into `$ELAN_HOME/artifact-cache/<name>`. Files
+//! are stored once per content below `artifact-cache/objects` and hard-linked
+//! into each cache listing them, so projects share both the downloads and the
+//! disk space of their artifacts; tools must thus not modify them in place.
+//!
+//! Tools run through a toolchain find the artifact cache in
+//! `ELAN_ARTIFACT_CACHE`, and each cache of the project that applies to the
+//! toolchain in the variable named by its `env` key unless that is set
+//! already.
+
+use crate::errors::*;
+use crate::{env_var, utils, Cfg, Toolchain};
+use elan_dist::dist::ToolchainDesc;
+use elan_utils::toml_utils::{get_array, get_opt_string, get_opt_string_array, get_string};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use url::Url;
+
+/// Name of the file declaring the artifact caches of a project
+pub const DECLARATION_FILE: &str = "elan-artifacts.toml";
+/// Directory below the artifact cache storing files by content hash
+const OBJECTS_DIR: &str = "objects";
+
+/// A cache declared by a project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArtifactCache {
+    /// Name of the cache, which is also its directory in the artifact cache
+    pub name: String,
+    /// Index listing the files of the cache
+    pub index: Url,
+    /// Toolchains the cache applies to, or `None` for any toolchain
+    pub toolchains: Option<Vec<String>>,
+    /// Environment variable telling tools where the cache is
+    pub env: Option<String>,
+}
+
+impl ArtifactCache {
+    pub fn applies_to(&self, desc: &ToolchainDesc) -> bool {
+        self.toolchains
+            .as_ref()
+            .is_none_or(|toolchains| toolchains.contains(&desc.to_string()))
+    }
+}
+
+/// What fetching a cache amounted to
+#[derive(Debug, Default)]
+pub struct Fetched {
+    pub files: usize,
+    pub downloaded: usize,
+    pub downloaded_bytes: u64,
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name != OBJECTS_DIR
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+/// Whether elan may set `var` for tools: it must neither be one of elan's own
+/// variables nor a search path
+fn is_valid_env_var(var: &str) -> bool {
+    !var.is_empty()
+        && !var.starts_with(|c: char| c.is_ascii_digit())
+        && var
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        && !var.starts_with("ELAN_")
+        && !var.ends_with("PATH")
+}
+
+/// Whether `name` is a relative path that stays within its cache
+fn is_valid_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains('\\')
+        && Path::new(name).components().all(|c| match c {
+            Component::Normal(part) => !part.to_string_lossy().starts_with('.'),
+            _ => false,
+        })
+}
+
+/// Parses the declarations in `file`, whose contents are `data`. Indexes
+/// given as relative paths are relative to the directory of `file`.
+pub fn parse_declarations(file: &Path, data: &str) -> Result<Vec<ArtifactCache>> {
+    let invalid = |reason: String| -> Error {
+        ErrorKind::InvalidArtifactCaches(file.to_owned(), reason).into()
+    };
+    let mut table: toml::value::Table = toml::from_str(data).map_err(|e| invalid(e.to_string()))?;
+    let dir = utils::to_absolute(file)?
+        .parent()
+        .and_then(|dir| Url::from_directory_path(dir).ok())
+        .ok_or_else(|| invalid("it is not in a directory".to_owned()))?;
+
+    let mut caches: Vec<ArtifactCache> = vec![];
+    for (i, cache) in get_array(&mut table, "cache", "")?.into_iter().enumerate() {
+        let path = format!("cache[{}].", i);
+        let mut cache = match cache {
+            toml::Value::Table(cache) => cache,
+            _ => {
+                return Err(invalid(format!(
+                    "'{}' is not a table",
+                    &path[..path.len() - 1]
+                )))
+            }
+        };
+        let name = get_string(&mut cache, "name", &path)?;
+        if !is_valid_name(&name) {
+            return Err(invalid(format!(
+                "'{}' is not a valid cache name; use letters, digits, '.', '_', and '-'",
+                name
+            )));
+        }
+        if caches.iter().any(|c| c.name == name) {
+            return Err(invalid(format!("cache '{}' is declared twice", name)));
+        }
+        let index = get_string(&mut cache, "index", &path)?;
+        let index = match Url::parse(&index) {
+            Err(url::ParseError::RelativeUrlWithoutBase) => dir.join(&index),
+            url => url,
+        }
+        .map_err(|_| invalid(format!("'{}' is not a valid index URL", index)))?;
+        let env = get_opt_string(&mut cache, "env", &path)?;
+        if let Some(var) = env.as_ref().filter(|var| !is_valid_env_var(var)) {
+            return Err(invalid(format!(
+                "elan cannot set '{}' for tools; use an upper-case name that does not start \
+                 with `ELAN_` and is not a search path",
+                var
+            )));
+        }
+        caches.push(ArtifactCache {
+            name,
+            index,
+            toolchains: get_opt_string_array(&mut cache, "toolchains", &path)?,
+            env,
+        });
+    }
+    Ok(caches)
+}
+
+/// The caches declared by the project whose `lean-toolchain` file is
+/// `toolchain_file`, if it has a declaration file
+pub fn declared_caches(toolchain_file: &Path) -> Result<Vec<ArtifactCache>> {
+    let file = toolchain_file.with_file_name(DECLARATION_FILE);
+    if !utils::is_file(&file) {
+        return Ok(vec![]);
+    }
+    parse_declarations(
+        &file,
+        &utils::read_file("artifact cache declaration", &file)?,
+    )
+}
+
+/// A file listed in the index of a cache
+struct IndexEntry {
+    name: String,
+    sha256: String,
+    url: Url,
+}
+
+fn parse_index(index: &Url, data: &str) -> Result<Vec<IndexEntry>> {
+    let invalid = |reason: String| -> Error {
+        ErrorKind::InvalidArtifactIndex(index.to_string(), reason).into()
+    };
+    let json: serde_json::Value = serde_json::from_str(data).map_err(|e| invalid(e.to_string()))?;
+    let files = json["files"]
+        .as_array()
+        .ok_or_else(|| invalid("it does not list files".to_owned()))?;
+    files
+        .iter()
+        .map(|file| {
+            let name = file["name"]
+                .as_str()
+                .filter(|name| is_valid_file_name(name))
+                .ok_or_else(|| invalid("a file lacks a valid name".to_owned()))?;
+            let sha256 = file["sha256"]
+                .as_str()
+                .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+                .ok_or_else(|| invalid(format!("'{}' lacks a valid SHA-256 hash", name)))?;
+            let url = index
+                .join(file["url"].as_str().unwrap_or(name))
+                .map_err(|_| invalid(format!("'{}' has an invalid URL", name)))?;
+            Ok(IndexEntry {
+                name: name.to_owned(),
+                sha256: sha256.to_ascii_lowercase(),
+                url,
+            })
+        })
+        .collect()
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Moves or copies `src` to `dest`, replacing it only once complete
+fn place(src: &Path, dest: &Path, hard_link: bool) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file_name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let partial = dest.with_file_name(format!(".{}.partial", file_name));
+    let _ = fs::remove_file(&partial);
+    let linked = if hard_link {
+        fs::hard_link(src, &partial)
+    } else {
+        fs::rename(src, &partial)
+    };
+    if linked.is_err() {
+        // E.g. across file systems
+        fs::copy(src, &partial)?;
+    }
+    fs::rename(&partial, dest)
+}
+
+/// Fetches the files of `cache` into the artifact cache, downloading those
+/// not stored yet for any cache
+pub fn fetch(cfg: &Cfg, toolchain: &Toolchain<'_>, cache: &ArtifactCache) -> Result<Fetched> {
+    if cfg.read_only {
+        return Err(ErrorKind::ReadOnly.into());
+    }
+    let dlcfg = toolchain.download_cfg();
+    let index = dlcfg.download_and_check_any(&[cache.index.to_string()])?;
+    let files = parse_index(
+        &cache.index,
+        &utils::read_file("artifact cache index", &index)?,
+    )?;
+
+    let objects = cfg.artifact_cache_dir().join(OBJECTS_DIR);
+    let dir = cfg.artifact_cache_dir().join(&cache.name);
+    utils::ensure_dir_exists("artifact cache", &objects, &|n| {
+        (cfg.notify_handler)(n.into())
+    })?;
+    let mut fetched = Fetched::default();
+    for file in files {
+        let object = objects.join(&file.sha256);
+        let mut fetch_file = || -> Result<()> {
+            if !utils::is_file(&object) {
+                let downloaded = dlcfg.download_and_check_any(&[file.url.to_string()])?;
+                let calculated =
+                    hash_file(&downloaded).chain_err(|| "could not read the download")?;
+                if calculated != file.sha256 {
+                    return Err(
+                        elan_dist::Error::from(elan_dist::ErrorKind::ChecksumFailed {
+                            url: file.url.to_string(),
+                            expected: file.sha256.clone(),
+                            calculated,
+                        })
+                        .into(),
+                    );
+                }
+                place(&downloaded, &object, false)
+                    .chain_err(|| format!("could not store '{}'", object.display()))?;
+                fetched.downloaded += 1;
+                fetched.downloaded_bytes += fs::metadata(&object).map_or(0, |m| m.len());
+            }
+            let dest = dir.join(&file.name);
+            if !same_file::is_same_file(&object, &dest).unwrap_or(false) {
+                place(&object, &dest, true)
+                    .chain_err(|| format!("could not write '{}'", dest.display()))?;
+            }
+            Ok(())
+        };
+        fetch_file()
+            .chain_err(|| ErrorKind::FetchingArtifact(file.name.clone(), cache.name.clone()))?;
+        fetched.files += 1;
+    }
+    Ok(fetched)
+}
+
+/// The environment variables telling tools run with `desc` in the current
+/// directory where the artifact caches are
+pub fn env_vars(cfg: &Cfg, desc: &ToolchainDesc) -> Vec<(String, PathBuf)> {
+    let root = cfg.artifact_cache_dir();
+    let mut vars = vec![(env_var::ELAN_ARTIFACT_CACHE.to_owned(), root.clone())];
+    // Declarations that cannot be read are reported by `elan cache fetch`
+    // rather than keeping tools from running
+    let caches = utils::current_dir()
+        .ok()
+        .and_then(|dir| crate::cache::toolchain_files(&[dir]).ok())
+        .and_then(|files| declared_caches(&files[0]).ok())
+        .unwrap_or_default();
+    for cache in caches.iter().filter(|cache| cache.applies_to(desc)) {
+        if let Some(var) = &cache.env {
+            if env::var_os(var).is_none() {
+                vars.push((var.clone(), root.join(&cache.name)));
+            }
+        }
+    }
+    vars
+}
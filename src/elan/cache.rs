@@ -0,0 +1,159 @@
+//! Archives of the toolchains a workspace needs, for caches in CI
+//!
+//! `elan cache export` packs the installed toolchains named by the
+//! `lean-toolchain` files of a workspace into a single `.tar.zst` archive of
+//! their toolchain directories, which contain no absolute paths and can thus
+//! be restored into any elan home by `elan cache import`. The cache key is
+//! derived from the contents of those `lean-toolchain` files and the target
+//! toolchains are installed for, so it changes exactly when the archive would.
+
+use crate::errors::*;
+use crate::{read_toolchain_desc_from_file, utils, Cfg, Toolchain};
+use elan_dist::dist::{self, ToolchainDesc};
+use elan_dist::prefix::InstallPrefix;
+use elan_dist::{ExtractionBudget, TarZstdPackage};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Name of the file listing the toolchains of an archive
+const MANIFEST_FILE: &str = "elan-cache.json";
+/// Version of the archive layout
+const FORMAT_VERSION: u64 = 1;
+
+/// The nearest `lean-toolchain` file at or above each of `dirs`, without
+/// duplicates
+pub fn toolchain_files(dirs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for dir in dirs {
+        let dir = utils::to_absolute(dir)?;
+        let file = dir
+            .ancestors()
+            .map(|d| d.join("lean-toolchain"))
+            .find(|f| utils::is_file(f))
+            .ok_or_else(|| ErrorKind::NoToolchainFile(dir.clone()))?;
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+/// The cache key for the workspace whose toolchains are named by `files`,
+/// e.g. `elan-x86_64-unknown-linux-gnu-<hash>`. It depends only on the
+/// contents of the files, not on where they are, and on the target.
+pub fn cache_key(cfg: &Cfg, files: &[PathBuf]) -> Result<String> {
+    let mut contents = files
+        .iter()
+        .map(|f| Ok(utils::read_file("toolchain", f)?.trim().to_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    contents.sort();
+    contents.dedup();
+    let hash = Sha256::digest(contents.join("\n").as_bytes());
+    let hash: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    let target = cfg
+        .settings_file
+        .with(|s| Ok(s.default_target.clone()))?
+        .unwrap_or_else(|| dist::host_triple().to_owned());
+    Ok(format!("elan-{}-{}", target, hash))
+}
+
+/// Writes the toolchains named by `files` to the archive at `path`,
+/// returning them. They must have been installed from releases.
+pub fn export(cfg: &Cfg, files: &[PathBuf], path: &Path) -> Result<Vec<ToolchainDesc>> {
+    let mut toolchains: Vec<ToolchainDesc> = vec![];
+    for file in files {
+        let desc = read_toolchain_desc_from_file(cfg, file)?;
+        let toolchain = Toolchain::from(cfg, &desc);
+        if !toolchain.exists() {
+            return Err(ErrorKind::ExportingToolchainNotInstalled(desc).into());
+        }
+        if toolchain.is_custom() || !matches!(desc, ToolchainDesc::Remote { .. }) {
+            return Err(ErrorKind::ExportingCustomToolchain(desc).into());
+        }
+        if !toolchains.contains(&desc) {
+            toolchains.push(desc);
+        }
+    }
+
+    let manifest = json!({
+        "version": FORMAT_VERSION,
+        "key": cache_key(cfg, files)?,
+        "toolchains": toolchains.iter().map(|t| t.to_toolchain_dir()).collect::<Vec<_>>(),
+    });
+    let write = || -> std::io::Result<()> {
+        let file = File::create(path)?;
+        let mut tar = tar::Builder::new(zstd::Encoder::new(file, 0)?);
+        // Symbolic links within toolchains are kept as such
+        tar.follow_symlinks(false);
+        let manifest = manifest.to_string();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(
+            &mut header,
+            format!("elan-cache/{}", MANIFEST_FILE),
+            manifest.as_bytes(),
+        )?;
+        for toolchain in &toolchains {
+            tar.append_dir_all(
+                format!("elan-cache/toolchains/{}", toolchain.to_toolchain_dir()),
+                Toolchain::from(cfg, toolchain).path(),
+            )?;
+        }
+        tar.into_inner()?.finish()?.sync_all()
+    };
+    if let Err(e) = write() {
+        let _ = fs::remove_file(path);
+        return Err(e).chain_err(|| ErrorKind::WritingCacheArchive(path.to_owned()));
+    }
+    Ok(toolchains)
+}
+
+/// Installs the toolchains in the archive at `path` that are not installed
+/// yet, returning each toolchain of the archive and whether it was installed
+pub fn import(cfg: &Cfg, path: &Path) -> Result<Vec<(ToolchainDesc, bool)>> {
+    let invalid = |reason: &str| ErrorKind::InvalidCacheArchive(path.to_owned(), reason.to_owned());
+    let staging = cfg.temp_cfg.new_directory()?;
+    TarZstdPackage::unpack_file(
+        path,
+        &staging,
+        &ExtractionBudget::new(cfg.extraction_limits),
+    )
+    .chain_err(|| invalid("it could not be unpacked"))?;
+    let manifest: serde_json::Value =
+        utils::read_file("cache manifest", &staging.join(MANIFEST_FILE))
+            .ok()
+            .and_then(|m| serde_json::from_str(&m).ok())
+            .ok_or_else(|| invalid("it lacks a readable manifest"))?;
+    match manifest["version"].as_u64() {
+        Some(version) if version <= FORMAT_VERSION => {}
+        _ => return Err(invalid("it was written by an unsupported version of elan").into()),
+    }
+    let dirs = manifest["toolchains"]
+        .as_array()
+        .and_then(|dirs| dirs.iter().map(|d| d.as_str()).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| invalid("its manifest does not list toolchains"))?;
+
+    utils::ensure_dir_exists("toolchains", &cfg.toolchains_dir, &|n| {
+        (cfg.notify_handler)(n.into())
+    })?;
+    let mut result = vec![];
+    for dir in dirs {
+        let desc = ToolchainDesc::from_toolchain_dir(dir)?;
+        let staged = staging.join("toolchains").join(dir);
+        if !utils::is_directory(&staged) {
+            return Err(invalid(&format!("it lacks the toolchain '{}'", desc)).into());
+        }
+        let toolchain = Toolchain::from(cfg, &desc);
+        let installed = dist::install_unpacked(
+            &desc,
+            &InstallPrefix::from(toolchain.path().to_owned()),
+            &staged,
+            &|n| (cfg.notify_handler)(n.into()),
+        )?;
+        result.push((desc, installed));
+    }
+    Ok(result)
+}
@@ -1,34 +1,117 @@
 use std::ffi::OsStr;
 use std::io;
+#[cfg(unix)]
+use std::io::Read;
+#[cfg(unix)]
+use std::path::Path;
 use std::process::{self, Command};
 
 use crate::errors::*;
 use elan_utils;
 
-pub fn run_command_for_dir<S: AsRef<OsStr>>(
+/// Exit code of a command that ran to completion. On Windows, this includes
+/// NTSTATUS values such as `0xC0000005` of crashed processes.
+pub struct ExitCode(pub i32);
+
+/// Replaces the current process with `cmd` on Unix. On Windows, where this
+/// is not possible, runs `cmd` to completion and returns its exit code,
+/// which the caller should exit with after cleaning up.
+pub fn run_command_for_dir<A: AsRef<OsStr>, S: AsRef<OsStr>>(
     mut cmd: Command,
-    arg0: &str,
+    arg0: A,
     args: &[S],
-) -> Result<()> {
+) -> Result<ExitCode> {
     cmd.args(args);
 
     // FIXME rust-lang/rust#32254. It's not clear to me
     // when and why this is needed.
     cmd.stdin(process::Stdio::inherit());
 
+    // Don't run the command if elan was interrupted while installing it
+    elan_utils::interrupt::check()?;
+
     return exec(&mut cmd).chain_err(|| elan_utils::ErrorKind::RunningCommand {
-        name: OsStr::new(arg0).to_owned(),
+        name: arg0.as_ref().to_owned(),
     });
 
     #[cfg(unix)]
-    fn exec(cmd: &mut Command) -> io::Result<()> {
+    fn exec(cmd: &mut Command) -> io::Result<ExitCode> {
         use std::os::unix::prelude::*;
-        Err(cmd.exec())
+        let err = cmd.exec();
+        let program = Path::new(cmd.get_program());
+        match err.raw_os_error() {
+            // Like `execvp`, run files without a `#!` line as shell scripts
+            Some(libc::ENOEXEC) => Err(shell_command(cmd).exec()),
+            // The file exists, so its interpreter must be missing
+            Some(libc::ENOENT) if program.is_file() => match interpreter(program) {
+                Some(interpreter) => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "interpreter '{}' named in the first line of '{}' not found",
+                        interpreter,
+                        program.display()
+                    ),
+                )),
+                None => Err(err),
+            },
+            _ => Err(err),
+        }
+    }
+
+    /// `cmd` run by `/bin/sh`
+    #[cfg(unix)]
+    fn shell_command(cmd: &Command) -> Command {
+        let mut sh = Command::new("/bin/sh");
+        sh.arg(cmd.get_program()).args(cmd.get_args());
+        for (key, value) in cmd.get_envs() {
+            match value {
+                Some(value) => sh.env(key, value),
+                None => sh.env_remove(key),
+            };
+        }
+        if let Some(dir) = cmd.get_current_dir() {
+            sh.current_dir(dir);
+        }
+        sh.stdin(process::Stdio::inherit());
+        sh
+    }
+
+    /// The interpreter named in the `#!` line of `program`, if any
+    #[cfg(unix)]
+    fn interpreter(program: &Path) -> Option<String> {
+        let mut head = [0; 256];
+        let len = std::fs::File::open(program).ok()?.read(&mut head).ok()?;
+        let line = head[..len]
+            .strip_prefix(b"#!")?
+            .split(|b| *b == b'\n')
+            .next()?;
+        let line = String::from_utf8_lossy(line);
+        line.split_whitespace().next().map(str::to_owned)
     }
 
     #[cfg(windows)]
-    fn exec(cmd: &mut Command) -> io::Result<()> {
+    fn exec(cmd: &mut Command) -> io::Result<ExitCode> {
+        use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+        use winapi::um::consoleapi::SetConsoleCtrlHandler;
+
+        // Ctrl-C and Ctrl-Break are delivered to every process attached to
+        // the console, so the child receives them as well and decides how to
+        // react. We must not die first, or the child would be orphaned and
+        // its exit code lost.
+        unsafe extern "system" fn ctrl_handler(_: DWORD) -> BOOL {
+            TRUE
+        }
+        unsafe {
+            if SetConsoleCtrlHandler(Some(ctrl_handler), TRUE) == FALSE {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        // The child inherits our standard handles and, if `job::setup`
+        // succeeded, our job object, so it is torn down together with us.
         let status = cmd.status()?;
-        process::exit(status.code().unwrap());
+        // Always present on Windows; `process::exit` passes the bits of
+        // NTSTATUS values through to `ExitProcess` unchanged
+        Ok(ExitCode(status.code().unwrap()))
     }
 }
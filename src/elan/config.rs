@@ -1,17 +1,22 @@
+use std::collections::BTreeMap;
 use std::env;
+use std::ffi::OsStr;
 use std::fmt::{self, Display};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::env_var;
 use crate::errors::*;
+use crate::history::{self, Entry, Operation};
 use crate::notifications::*;
-use crate::settings::{Settings, SettingsFile};
+use crate::settings::{self, Settings, SettingsFile};
 use crate::toolchain::Toolchain;
 use elan_dist::dist::ToolchainDesc;
-use elan_dist::temp;
-use elan_utils::utils;
+use elan_dist::{temp, ExtractionLimits};
+use elan_utils::{endpoints, utils};
 use itertools::Itertools;
 use serde_derive::Serialize;
 
@@ -58,26 +63,234 @@ impl Display for OverrideReason {
     }
 }
 
+impl OverrideReason {
+    pub fn source(&self) -> OverrideSource {
+        match *self {
+            OverrideReason::Environment => OverrideSource::Environment,
+            OverrideReason::OverrideDB(_) => OverrideSource::OverrideDB,
+            OverrideReason::ToolchainFile(_) => OverrideSource::ToolchainFile,
+            OverrideReason::LeanpkgFile(_) => OverrideSource::LeanpkgFile,
+            OverrideReason::InToolchainDirectory(_) => OverrideSource::InToolchainDirectory,
+        }
+    }
+}
+
+/// A place the active toolchain can be determined from
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OverrideSource {
+    Environment,
+    OverrideDB,
+    ToolchainFile,
+    LeanpkgFile,
+    InToolchainDirectory,
+    /// The default toolchain, consulted only if no other source applies
+    Default,
+}
+
+/// Time after which installations triggered by running a binary of a missing
+/// toolchain are aborted unless `implicit_install_timeout` is set
+pub const DEFAULT_IMPLICIT_INSTALL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Paths removed by `elan toolchain strip` and `--minimize` unless
+/// `strip_patterns` is set: documentation, tests, and the sources of the
+/// core library, which only editors use to navigate to definitions
+pub const DEFAULT_STRIP_PATTERNS: &[&str] =
+    &["doc", "docs", "share/doc", "share/man", "src", "**/tests"];
+
+/// Names of the override sources in the `override_precedence` setting
+pub const OVERRIDE_SOURCE_NAMES: &[&str] = &[
+    "env",
+    "override",
+    "toolchain-file",
+    "leanpkg",
+    "toolchain-dir",
+    "default",
+];
+
+impl OverrideSource {
+    pub const ALL: &'static [OverrideSource] = &[
+        OverrideSource::Environment,
+        OverrideSource::OverrideDB,
+        OverrideSource::ToolchainFile,
+        OverrideSource::LeanpkgFile,
+        OverrideSource::InToolchainDirectory,
+        OverrideSource::Default,
+    ];
+
+    pub fn name(self) -> &'static str {
+        let i = Self::ALL.iter().position(|&s| s == self).unwrap();
+        OVERRIDE_SOURCE_NAMES[i]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let i = OVERRIDE_SOURCE_NAMES.iter().position(|&n| n == name)?;
+        Some(Self::ALL[i])
+    }
+
+    /// Whether the source is looked for in the directory tree
+    pub fn is_directory_source(self) -> bool {
+        !matches!(self, OverrideSource::Environment | OverrideSource::Default)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            OverrideSource::Environment => "the ELAN_TOOLCHAIN environment variable",
+            OverrideSource::OverrideDB => "directory overrides set by `elan override`",
+            OverrideSource::ToolchainFile => "`lean-toolchain` files",
+            OverrideSource::LeanpkgFile => "`leanpkg.toml` files",
+            OverrideSource::InToolchainDirectory => "being inside a toolchain directory",
+            OverrideSource::Default => "the default toolchain",
+        }
+    }
+}
+
+/// The order in which sources of the active toolchain are consulted, highest
+/// precedence first. Sources not listed are disabled.
+///
+/// Directory sources are checked together for each directory while walking
+/// up from the current directory, so a `lean-toolchain` file in a
+/// subdirectory always wins over a directory override of a parent directory;
+/// their order only matters within a single directory. The default toolchain
+/// is always consulted last, if at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverridePrecedence(pub Vec<OverrideSource>);
+
+impl Default for OverridePrecedence {
+    fn default() -> Self {
+        OverridePrecedence(OverrideSource::ALL.to_vec())
+    }
+}
+
+impl OverridePrecedence {
+    /// Parses the names of sources, returning the unknown names separately
+    /// so that a setting written by a newer version of elan does not break
+    /// this one; `elan config set` rejects them
+    pub fn parse(names: &[String]) -> (Self, Vec<&str>) {
+        let (known, unknown): (Vec<_>, Vec<_>) = names
+            .iter()
+            .map(|name| (name.as_str(), OverrideSource::from_name(name)))
+            .partition(|(_, source)| source.is_some());
+        (
+            OverridePrecedence(known.into_iter().filter_map(|(_, s)| s).unique().collect()),
+            unknown.into_iter().map(|(name, _)| name).collect(),
+        )
+    }
+
+    pub fn contains(&self, source: OverrideSource) -> bool {
+        self.0.contains(&source)
+    }
+
+    /// The sources actually consulted, in the order they take effect
+    pub fn effective_order(&self) -> Vec<OverrideSource> {
+        let mut result = vec![];
+        for &source in &self.0 {
+            if source == OverrideSource::Default {
+                continue;
+            }
+            if source.is_directory_source() {
+                if result
+                    .iter()
+                    .any(|s: &OverrideSource| s.is_directory_source())
+                {
+                    continue;
+                }
+                result.extend(self.0.iter().filter(|s| s.is_directory_source()));
+            } else {
+                result.push(source);
+            }
+        }
+        if self.contains(OverrideSource::Default) {
+            result.push(OverrideSource::Default);
+        }
+        result
+    }
+}
+
+/// Fails early if ELAN_HOME cannot be written to, e.g. because another user
+/// owns it on a shared mount, instead of deep inside some later IO operation
+fn check_writable_home(elan_dir: &Path) -> Result<()> {
+    if utils::is_writable_dir(elan_dir) {
+        return Ok(());
+    }
+    Err(ErrorKind::ElanHomeNotWritable(elan_dir.to_owned(), owner_note(elan_dir)).into())
+}
+
+#[cfg(unix)]
+fn owner_note(path: &Path) -> String {
+    use std::os::unix::fs::MetadataExt;
+
+    match std::fs::metadata(path) {
+        Ok(m) if m.uid() != unsafe { libc::geteuid() } => {
+            format!(" (owned by user ID {})", m.uid())
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(windows)]
+fn owner_note(_: &Path) -> String {
+    String::new()
+}
+
 pub struct Cfg {
     pub elan_dir: PathBuf,
     pub settings_file: SettingsFile,
+    /// Directory containing toolchains and temporary files; the root of the
+    /// system-wide installation if elan is installed system-wide, otherwise
+    /// the same as `elan_dir`
+    pub install_dir: PathBuf,
+    /// Settings of the system-wide installation, providing a default
+    /// toolchain for users that have not chosen one
+    pub system_settings_file: Option<SettingsFile>,
     pub toolchains_dir: PathBuf,
     pub temp_cfg: temp::Cfg,
     //pub gpg_key: Cow<'static, str>,
     pub env_override: Option<String>,
-    pub notify_handler: Arc<dyn Fn(Notification<'_>)>,
+    /// Sources of the active toolchain to consult, from the
+    /// `override_precedence` setting
+    pub override_precedence: OverridePrecedence,
+    /// Whether state-modifying operations are refused
+    pub read_only: bool,
+    /// Caps on extracting the archives of a toolchain installation, from the
+    /// `max_extracted_size` and `max_extracted_files` settings
+    pub extraction_limits: ExtractionLimits,
+    pub notify_handler: Arc<NotifyHandler>,
 }
 
+// Worker threads may share a `Cfg`
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Cfg>();
+};
+
 impl Cfg {
-    pub fn from_env(notify_handler: Arc<dyn Fn(Notification<'_>)>) -> Result<Self> {
+    pub fn from_env(notify_handler: Arc<NotifyHandler>) -> Result<Self> {
+        Self::from_env_ext(notify_handler, false)
+    }
+
+    /// Like `from_env`, but with `read_only`, ELAN_HOME is never modified and
+    /// need not be writable, so existing toolchains can be used but not
+    /// changed
+    pub fn from_env_ext(notify_handler: Arc<NotifyHandler>, read_only: bool) -> Result<Self> {
         // Set up the elan home directory
         let elan_dir = utils::elan_home()?;
+        let read_only = read_only || utils::elan_read_only();
 
-        utils::ensure_dir_exists("home", &elan_dir, &|n| notify_handler(n.into()))?;
+        let settings_file = if read_only {
+            SettingsFile::new_read_only(elan_dir.join("settings.toml"))
+        } else {
+            utils::ensure_dir_exists("home", &elan_dir, &|n| notify_handler(n.into()))?;
+            check_writable_home(&elan_dir)?;
+            SettingsFile::new(elan_dir.join("settings.toml"))
+        };
 
-        let settings_file = SettingsFile::new(elan_dir.join("settings.toml"));
+        let system_dir = utils::elan_system_home();
+        let system_settings_file = system_dir
+            .as_ref()
+            .map(|dir| SettingsFile::new(dir.join("settings.toml")));
+        let install_dir = system_dir.unwrap_or_else(|| elan_dir.clone());
 
-        let toolchains_dir = elan_dir.join("toolchains");
+        let toolchains_dir = install_dir.join("toolchains");
 
         // GPG key
         /*let gpg_key = ""; if let Some(path) = env::var_os("ELAN_GPG_KEY")
@@ -88,33 +301,514 @@ impl Cfg {
         };*/
 
         // Environment override
-        let env_override = env::var("ELAN_TOOLCHAIN")
+        let env_override = env::var(env_var::ELAN_TOOLCHAIN)
             .ok()
             .and_then(utils::if_not_empty);
 
         let notify_clone = notify_handler.clone();
         let temp_cfg = temp::Cfg::new(
-            elan_dir.join("tmp"),
+            install_dir.join("tmp"),
             Box::new(move |n| (notify_clone)(n.into())),
         );
 
-        Ok(Cfg {
+        // Locked-down system-wide installations take priority over users
+        let mut override_precedence = None;
+        if let Some(ref system) = system_settings_file {
+            if utils::is_file(system.path()) {
+                override_precedence = system.with(|s| Ok(s.override_precedence.clone()))?;
+            }
+        }
+        if override_precedence.is_none() {
+            override_precedence = settings_file.with(|s| Ok(s.override_precedence.clone()))?;
+        }
+        let override_precedence = match override_precedence {
+            Some(names) => {
+                let (precedence, unknown) = OverridePrecedence::parse(&names);
+                for name in unknown {
+                    notify_handler(Notification::UnknownOverrideSource(name));
+                }
+                precedence
+            }
+            None => OverridePrecedence::default(),
+        };
+
+        let mut cfg = Cfg {
             elan_dir,
             settings_file,
+            install_dir,
+            system_settings_file,
             toolchains_dir,
             temp_cfg,
             //gpg_key: gpg_key,
             notify_handler,
             env_override,
-        })
+            override_precedence,
+            read_only,
+            extraction_limits: ExtractionLimits::DEFAULT,
+        };
+        #[cfg(feature = "curl-backend")]
+        download::curl::set_user_agent(concat!("elan/", env!("CARGO_PKG_VERSION")));
+        cfg.configure_proxy_auth()?;
+        cfg.extraction_limits = cfg.read_extraction_limits()?;
+        cfg.configure_release_indexes()?;
+        cfg.configure_endpoints()?;
+        cfg.configure_size_probe()?;
+        cfg.configure_peer_cache()?;
+        utils::set_metadata_cache_dir(Some(cfg.metadata_cache_dir()).filter(|_| !cfg.read_only));
+        Ok(cfg)
+    }
+
+    /// Applies the proxy authentication method from `ELAN_PROXY_AUTH` or the
+    /// `proxy_auth` setting to all downloads
+    #[cfg(feature = "curl-backend")]
+    fn configure_proxy_auth(&self) -> Result<()> {
+        let mut method = env::var(env_var::ELAN_PROXY_AUTH)
+            .ok()
+            .and_then(utils::if_not_empty);
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        for file in settings_files {
+            // Don't create the settings file just for this
+            if method.is_none() && utils::is_file(file.path()) {
+                method = file.with(|s| Ok(s.proxy_auth.clone()))?;
+            }
+        }
+        if let Some(method) = method {
+            download::curl::set_proxy_auth(&method).map_err(elan_utils::Error::from)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "curl-backend"))]
+    fn configure_proxy_auth(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The limits set by the `max_extracted_size` and `max_extracted_files`
+    /// settings. Where both the user and a system-wide installation set a
+    /// limit, the lower one applies so that shared machines can enforce it.
+    fn read_extraction_limits(&self) -> Result<ExtractionLimits> {
+        let mut limits = ExtractionLimits::DEFAULT;
+        let mut configured = (None, None);
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        for file in settings_files {
+            if !utils::is_file(file.path()) {
+                continue;
+            }
+            let (size, files) = file.with(|s| Ok((s.max_extracted_size, s.max_extracted_files)))?;
+            configured.0 = configured.0.into_iter().chain(size).min();
+            configured.1 = configured.1.into_iter().chain(files).min();
+        }
+        limits.max_size = configured.0.unwrap_or(limits.max_size);
+        limits.max_files = configured.1.unwrap_or(limits.max_files);
+        Ok(limits)
+    }
+
+    /// Applies the `release_index` settings of a system-wide installation, the
+    /// user, and `ELAN_RELEASE_INDEX`, each taking precedence over the
+    /// previous ones, to all release lookups
+    fn configure_release_indexes(&self) -> Result<()> {
+        let mut indexes = utils::default_release_indexes();
+        let settings_files = self
+            .system_settings_file
+            .as_ref()
+            .into_iter()
+            .chain(Some(&self.settings_file));
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                indexes.extend(file.with(|s| Ok(s.release_indexes.clone()))?);
+            }
+        }
+        if let Some(value) = env::var(env_var::ELAN_RELEASE_INDEX)
+            .ok()
+            .and_then(utils::if_not_empty)
+        {
+            let (setting, _) = settings::lookup_setting_key("release_index.*")?;
+            for pair in value.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (origin, url) = pair
+                    .split_once('=')
+                    .filter(|(origin, _)| !origin.trim().is_empty())
+                    .ok_or_else(|| {
+                        format!(
+                            "invalid {} entry '{}'; expected '<origin>=<url>'",
+                            env_var::ELAN_RELEASE_INDEX,
+                            pair
+                        )
+                    })?;
+                let origin = origin.trim();
+                let url = setting
+                    .validate(&format!("release_index.{}", origin), url.trim())
+                    .chain_err(|| format!("invalid {}", env_var::ELAN_RELEASE_INDEX))?;
+                indexes.insert(origin.to_owned(), url);
+            }
+        }
+        utils::set_release_indexes(indexes);
+        Ok(())
+    }
+
+    /// Applies the endpoint URLs set by a system-wide installation and the
+    /// user, who takes precedence; environment variables are consulted by
+    /// `endpoints` itself
+    fn configure_endpoints(&self) -> Result<()> {
+        let mut urls = BTreeMap::new();
+        let settings_files = self
+            .system_settings_file
+            .as_ref()
+            .into_iter()
+            .chain(Some(&self.settings_file));
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                urls.extend(file.with(|s| Ok(s.endpoints.clone()))?);
+            }
+        }
+        endpoints::set_configured(urls);
+        Ok(())
+    }
+
+    /// Applies the `size_probe` setting of the user or, failing that, of a
+    /// system-wide installation to all downloads
+    fn configure_size_probe(&self) -> Result<()> {
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                if let Some(enabled) = file.with(|s| Ok(s.size_probe))? {
+                    elan_dist::download::set_size_probe(enabled);
+                    return Ok(());
+                }
+            }
+        }
+        elan_dist::download::set_size_probe(true);
+        Ok(())
+    }
+
+    /// Enables the peer cache if the `peer_cache` setting of the user or,
+    /// failing that, of a system-wide installation is true
+    fn configure_peer_cache(&self) -> Result<()> {
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        let mut enabled = false;
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                if let Some(e) = file.with(|s| Ok(s.peer_cache))? {
+                    enabled = e;
+                    break;
+                }
+            }
+        }
+        elan_dist::peer_cache::set_dir(
+            Some(self.download_cache_dir()).filter(|_| enabled),
+            !self.read_only,
+        );
+        Ok(())
+    }
+
+    /// The `strip_patterns` setting of the user or, failing that, of a
+    /// system-wide installation, or else the default patterns
+    pub fn strip_patterns(&self) -> Result<Vec<String>> {
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                if let Some(patterns) = file.with(|s| Ok(s.strip_patterns.clone()))? {
+                    return Ok(patterns);
+                }
+            }
+        }
+        Ok(DEFAULT_STRIP_PATTERNS
+            .iter()
+            .map(|p| p.to_string())
+            .collect())
+    }
+
+    /// The `implicit_install_timeout` setting of the user or, failing that,
+    /// of a system-wide installation
+    pub fn implicit_install_timeout(&self) -> Result<Duration> {
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                if let Some(secs) = file.with(|s| Ok(s.implicit_install_timeout))? {
+                    return Ok(Duration::from_secs(secs));
+                }
+            }
+        }
+        Ok(DEFAULT_IMPLICIT_INSTALL_TIMEOUT)
+    }
+
+    /// The public key configured for the self-update root `root`, if any
+    pub fn trust_root_key(&self, root: &str) -> Result<Option<String>> {
+        let root = root.trim_end_matches('/');
+        let settings_files = Some(&self.settings_file)
+            .into_iter()
+            .chain(self.system_settings_file.as_ref());
+        for file in settings_files {
+            if utils::is_file(file.path()) {
+                if let Some(key) = file.with(|s| Ok(s.trust_roots.get(root).cloned()))? {
+                    return Ok(Some(key));
+                }
+            }
+        }
+        Ok(None)
     }
 
     pub fn set_default(&self, toolchain: &str) -> Result<()> {
+        // Store the canonical name so that e.g. `4.9.0` and `v4.9.0` are
+        // recognizably the same toolchain
+        let toolchain = &lookup_unresolved_toolchain_desc(self, toolchain)?
+            .0
+            .to_string();
+        let previous = self
+            .settings_file
+            .with_mut(|s| Ok(s.default_toolchain.replace(toolchain.to_owned())))?;
+        (self.notify_handler)(Notification::SetDefaultToolchain(toolchain));
+        if previous.as_ref() != Some(toolchain) {
+            history::record(
+                self,
+                Entry {
+                    toolchain: Some(toolchain.to_owned()),
+                    previous,
+                    ..Entry::new(Operation::Default)
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Removes the override for `path`, returning whether there was one
+    pub fn remove_override(&self, path: &Path) -> Result<bool> {
+        let previous = self.settings_file.with_mut(|s| {
+            let previous = s.dir_override(path, self.notify_handler.as_ref());
+            s.remove_override(path, self.notify_handler.as_ref());
+            Ok(previous)
+        })?;
+        if let Some(previous) = &previous {
+            history::record(
+                self,
+                Entry {
+                    previous: Some(previous.to_string()),
+                    path: Some(path.display().to_string()),
+                    ..Entry::new(Operation::OverrideUnset)
+                },
+            );
+        }
+        Ok(previous.is_some())
+    }
+
+    /// Renames the linked toolchain `old` to `new` and points the default
+    /// toolchain and all overrides naming `old` to `new`, returning the
+    /// paths of the updated overrides. The link is moved back if the
+    /// settings cannot be updated.
+    pub fn rename_linked_toolchain(
+        &self,
+        old: &ToolchainDesc,
+        new: &ToolchainDesc,
+    ) -> Result<Vec<String>> {
+        if let ToolchainDesc::Remote { .. } = old {
+            return Err(ErrorKind::NotALinkedToolchain(old.clone()).into());
+        }
+        let old_tc = self.get_toolchain(old, false)?;
+        let new_tc = self.get_toolchain(new, true)?;
+        if !old_tc.exists() {
+            return Err(format!("toolchain '{}' is not installed", old).into());
+        }
+        if !old_tc.is_custom() {
+            return Err(ErrorKind::NotALinkedToolchain(old.clone()).into());
+        }
+        if new_tc.exists() {
+            return Err(format!("'{}' is already installed", new).into());
+        }
+        old_tc.rename(&new_tc)?;
+        let result = self.settings_file.with_mut(|s| {
+            if s.default_toolchain.as_deref() == Some(&*old.to_string()) {
+                s.default_toolchain = Some(new.to_string());
+            }
+            let mut updated = vec![];
+            for (key, toolchain) in s.overrides.iter_mut() {
+                if toolchain == old {
+                    *toolchain = new.clone();
+                    updated.push(key.clone());
+                }
+            }
+            Ok(updated)
+        });
+        if result.is_err() {
+            let _ = new_tc.rename(&old_tc);
+        }
+        result
+    }
+
+    /// Rewrites the user's settings file so that the default toolchain and
+    /// overrides use canonical toolchain names and no directory has more than
+    /// one override, and merges toolchains installed under differently cased
+    /// origins, returning a description of each change
+    pub fn normalize_settings(&self) -> Result<Vec<String>> {
+        let mut changes = vec![];
         self.settings_file.with_mut(|s| {
-            s.default_toolchain = Some(toolchain.to_owned());
+            if let Some(default) = s.default_toolchain.clone() {
+                if let Ok(UnresolvedToolchainDesc(desc)) =
+                    lookup_unresolved_toolchain_desc(self, &default)
+                {
+                    if desc.to_string() != default {
+                        changes.push(format!("default toolchain '{}' -> '{}'", default, desc));
+                        s.default_toolchain = Some(desc.to_string());
+                    }
+                }
+            }
+            for (key, new_key) in s.merge_duplicate_overrides(self.notify_handler.as_ref()) {
+                changes.push(match new_key {
+                    Some(new_key) => format!("override for '{}' -> '{}'", key, new_key),
+                    None => format!("override for '{}' removed as a duplicate", key),
+                });
+            }
+            for (key, toolchain) in s.overrides.iter_mut() {
+                let old = toolchain.to_string();
+                if let ToolchainDesc::Remote { origin, .. } = toolchain {
+                    if *origin != origin.to_ascii_lowercase() {
+                        *origin = origin.to_ascii_lowercase();
+                        changes.push(format!(
+                            "override for '{}': '{}' -> '{}'",
+                            key, old, toolchain
+                        ));
+                    }
+                }
+                // Hand-written overrides may name releases without an origin,
+                // which are read as linked toolchains
+                if let ToolchainDesc::Local { name } = toolchain.clone() {
+                    if Toolchain::from(self, toolchain).exists() {
+                        continue;
+                    }
+                    if let Ok(UnresolvedToolchainDesc(
+                        desc @ ToolchainDesc::Remote {
+                            from_channel: None, ..
+                        },
+                    )) = lookup_unresolved_toolchain_desc(self, &name)
+                    {
+                        changes.push(format!("override for '{}': '{}' -> '{}'", key, name, desc));
+                        *toolchain = desc;
+                    }
+                }
+            }
             Ok(())
         })?;
-        (self.notify_handler)(Notification::SetDefaultToolchain(toolchain));
+        changes.extend(self.merge_origin_case_duplicates()?);
+        Ok(changes)
+    }
+
+    /// Moves toolchains installed under an origin that is not lowercase, such
+    /// as `LeanProver/lean4`, to the directory of the lowercase origin, or
+    /// removes them if that toolchain is installed as well
+    fn merge_origin_case_duplicates(&self) -> Result<Vec<String>> {
+        let installed = self.list_toolchains()?;
+        let mut changes = vec![];
+        for desc in &installed {
+            let canonical = match desc {
+                ToolchainDesc::Remote {
+                    origin, release, ..
+                } if *origin != origin.to_ascii_lowercase() => ToolchainDesc::Remote {
+                    origin: origin.to_ascii_lowercase(),
+                    release: release.clone(),
+                    from_channel: None,
+                },
+                _ => continue,
+            };
+            let old = self.get_toolchain(desc, false)?;
+            let new = self.get_toolchain(&canonical, false)?;
+            // On case-insensitive file systems, both names refer to the same
+            // directory, which then only needs to be renamed
+            if installed.contains(&canonical) {
+                if old.is_frozen() {
+                    new.set_frozen(true)?;
+                }
+                old.remove()?;
+                changes.push(format!(
+                    "toolchain '{}' removed as a duplicate of '{}'",
+                    desc, canonical
+                ));
+            } else {
+                old.rename(&new)?;
+                changes.push(format!("toolchain '{}' -> '{}'", desc, canonical));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Completes or rolls back toolchain installations that were interrupted,
+    /// e.g. by Ctrl-C
+    pub fn recover_interrupted_installs(&self) -> Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        Ok(elan_dist::dist::recover_interrupted_installs(
+            &self.toolchains_dir,
+            &|n| (self.notify_handler)(n.into()),
+        )?)
+    }
+
+    /// Directory in which metadata such as release indexes is cached along
+    /// with the validators to revalidate it with
+    pub fn metadata_cache_dir(&self) -> PathBuf {
+        self.elan_dir.join("cache").join("metadata")
+    }
+
+    /// Directory in which release archives are kept for peers if the
+    /// `peer_cache` setting is enabled
+    pub fn download_cache_dir(&self) -> PathBuf {
+        self.elan_dir.join("cache").join("downloads")
+    }
+
+    /// Directory into which `elan cache fetch` fetches the artifact caches
+    /// declared by projects
+    pub fn artifact_cache_dir(&self) -> PathBuf {
+        self.elan_dir.join("artifact-cache")
+    }
+
+    /// Removes cached metadata and release archives, which are fetched again
+    /// when needed
+    pub fn clean_caches(&self) -> Result<()> {
+        if self.read_only {
+            return Err(ErrorKind::ReadOnly.into());
+        }
+        for dir in [self.metadata_cache_dir(), self.download_cache_dir()] {
+            if utils::is_directory(&dir) {
+                utils::remove_dir("cache", &dir, &|n| (self.notify_handler)(n.into()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes temporary files and directories that are at least `min_age` old
+    pub fn clean_temp_dir(&self, min_age: Duration) -> Result<()> {
+        if self.read_only {
+            return Err(ErrorKind::ReadOnly.into());
+        }
+        let temp_dir = self.install_dir.join("tmp");
+        if !utils::is_directory(&temp_dir) {
+            return Ok(());
+        }
+        for entry in utils::read_dir("temp", &temp_dir)?.filter_map(io::Result::ok) {
+            let age = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.elapsed().ok());
+            if age.is_none_or(|age| age < min_age) {
+                continue;
+            }
+            let path = entry.path();
+            if utils::is_directory(&path) {
+                utils::remove_dir("temp", &path, &|n| (self.notify_handler)(n.into()))?;
+            } else {
+                utils::remove_file("temp", &path)?;
+            }
+        }
         Ok(())
     }
 
@@ -123,7 +817,8 @@ impl Cfg {
         name: &ToolchainDesc,
         create_parent: bool,
     ) -> Result<Toolchain<'_>> {
-        if create_parent {
+        name.validate()?;
+        if create_parent && !self.read_only {
             utils::ensure_dir_exists("toolchains", &self.toolchains_dir, &|n| {
                 (self.notify_handler)(n.into())
             })?;
@@ -141,7 +836,28 @@ impl Cfg {
     }
 
     pub fn get_default(&self) -> Result<Option<String>> {
-        self.settings_file.with(|s| Ok(s.default_toolchain.clone()))
+        let default = self
+            .settings_file
+            .with(|s| Ok(s.default_toolchain.clone()))?;
+        match (default, &self.system_settings_file) {
+            (None, Some(system)) if utils::is_file(system.path()) => {
+                system.with(|s| Ok(s.default_toolchain.clone()))
+            }
+            (default, _) => Ok(default),
+        }
+    }
+
+    /// Fails if toolchains cannot be installed or removed by the current user,
+    /// i.e. in read-only mode or if they belong to a system-wide installation
+    /// owned by root
+    pub fn check_toolchains_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(ErrorKind::ReadOnly.into());
+        }
+        if self.system_settings_file.is_some() && !utils::is_writable_dir(&self.install_dir) {
+            return Err(ErrorKind::SystemInstallReadOnly(self.install_dir.clone()).into());
+        }
+        Ok(())
     }
 
     pub fn resolve_default(&self) -> Result<Option<ToolchainDesc>> {
@@ -153,26 +869,37 @@ impl Cfg {
         }
     }
 
+    /// Finds the toolchain overriding the default toolchain in `path` by
+    /// consulting the sources in `override_precedence`
     pub fn find_override(
         &self,
         path: &Path,
     ) -> Result<Option<(UnresolvedToolchainDesc, OverrideReason)>> {
-        // First check ELAN_TOOLCHAIN
-        if let Some(ref name) = self.env_override {
-            return Ok(Some((
-                lookup_unresolved_toolchain_desc(self, name)?,
-                OverrideReason::Environment,
-            )));
-        }
-
-        // Then walk up the directory tree from 'path' looking for either the
-        // directory in override database, a `lean-toolchain` file, or a
-        // `leanpkg.toml` file.
-        if let Some(res) = self
-            .settings_file
-            .with(|s| self.find_override_from_dir_walk(path, s))?
-        {
-            return Ok(Some(res));
+        let mut walked = false;
+        for &source in &self.override_precedence.0 {
+            match source {
+                OverrideSource::Environment => {
+                    if let Some(ref name) = self.env_override {
+                        return Ok(Some((
+                            lookup_unresolved_toolchain_desc(self, name)?,
+                            OverrideReason::Environment,
+                        )));
+                    }
+                }
+                OverrideSource::Default => {}
+                _ if walked => {}
+                _ => {
+                    // Walk up the directory tree from 'path' looking for any
+                    // of the enabled directory sources
+                    walked = true;
+                    if let Some(res) = self
+                        .settings_file
+                        .with(|s| self.find_override_from_dir_walk(path, s))?
+                    {
+                        return Ok(Some(res));
+                    }
+                }
+            }
         }
         Ok(None)
     }
@@ -187,46 +914,66 @@ impl Cfg {
         let mut dir = Some(&*dir);
 
         while let Some(d) = dir {
-            // First check the override database
-            if let Some(name) = settings.dir_override(d, notify) {
-                let reason = OverrideReason::OverrideDB(d.to_owned());
-                return Ok(Some((UnresolvedToolchainDesc(name), reason)));
-            }
-
-            // Then look for 'lean-toolchain'
-            let toolchain_file = d.join("lean-toolchain");
-            if let Ok(desc) = read_unresolved_toolchain_desc_from_file(self, &toolchain_file) {
-                let reason = OverrideReason::ToolchainFile(toolchain_file);
-                gc::add_root(self, d)?;
-                return Ok(Some((desc, reason)));
-            }
-
-            // Then look for 'leanpkg.toml'
-            let leanpkg_file = d.join("leanpkg.toml");
-            if let Ok(content) = utils::read_file("leanpkg.toml", &leanpkg_file) {
-                let value = content
-                    .parse::<toml::Value>()
-                    .map_err(|error| ErrorKind::InvalidLeanpkgFile(leanpkg_file.clone(), error))?;
-                match value
-                    .get("package")
-                    .and_then(|package| package.get("lean_version"))
-                {
-                    None => {}
-                    Some(toml::Value::String(s)) => {
-                        let desc = lookup_unresolved_toolchain_desc(self, s)?;
-                        return Ok(Some((desc, OverrideReason::LeanpkgFile(leanpkg_file))));
-                    }
-                    Some(a) => {
-                        return Err(ErrorKind::InvalidLeanVersion(leanpkg_file, a.type_str()).into())
-                    }
+            for &source in &self.override_precedence.0 {
+                if let Some(res) = self.find_override_in_dir(source, d, settings)? {
+                    return Ok(Some(res));
                 }
             }
-
             dir = d.parent();
+        }
 
-            if dir == Some(&self.toolchains_dir) {
-                if let Some(last) = d.file_name() {
-                    if let Some(last) = last.to_str() {
+        Ok(None)
+    }
+
+    fn find_override_in_dir(
+        &self,
+        source: OverrideSource,
+        d: &Path,
+        settings: &Settings,
+    ) -> Result<Option<(UnresolvedToolchainDesc, OverrideReason)>> {
+        let notify = self.notify_handler.as_ref();
+        match source {
+            OverrideSource::OverrideDB => {
+                if let Some(name) = settings.dir_override(d, notify) {
+                    let reason = OverrideReason::OverrideDB(d.to_owned());
+                    return Ok(Some((UnresolvedToolchainDesc(name), reason)));
+                }
+            }
+            OverrideSource::ToolchainFile => {
+                let toolchain_file = d.join("lean-toolchain");
+                if utils::is_file(&toolchain_file) {
+                    let desc = read_unresolved_toolchain_desc_from_file(self, &toolchain_file)?;
+                    let reason = OverrideReason::ToolchainFile(toolchain_file);
+                    gc::add_root(self, d)?;
+                    return Ok(Some((desc, reason)));
+                }
+            }
+            OverrideSource::LeanpkgFile => {
+                let leanpkg_file = d.join("leanpkg.toml");
+                if let Ok(content) = utils::read_file("leanpkg.toml", &leanpkg_file) {
+                    let value = content.parse::<toml::Value>().map_err(|error| {
+                        ErrorKind::InvalidLeanpkgFile(leanpkg_file.clone(), error)
+                    })?;
+                    match value
+                        .get("package")
+                        .and_then(|package| package.get("lean_version"))
+                    {
+                        None => {}
+                        Some(toml::Value::String(s)) => {
+                            let desc = lookup_unresolved_toolchain_desc(self, s)?;
+                            return Ok(Some((desc, OverrideReason::LeanpkgFile(leanpkg_file))));
+                        }
+                        Some(a) => {
+                            return Err(
+                                ErrorKind::InvalidLeanVersion(leanpkg_file, a.type_str()).into()
+                            )
+                        }
+                    }
+                }
+            }
+            OverrideSource::InToolchainDirectory => {
+                if d.parent() == Some(&self.toolchains_dir) {
+                    if let Some(last) = d.file_name().and_then(|last| last.to_str()) {
                         return Ok(Some((
                             UnresolvedToolchainDesc(ToolchainDesc::from_toolchain_dir(last)?),
                             OverrideReason::InToolchainDirectory(d.into()),
@@ -234,11 +981,20 @@ impl Cfg {
                     }
                 }
             }
+            OverrideSource::Environment | OverrideSource::Default => {}
         }
-
         Ok(None)
     }
 
+    /// The default toolchain, unless disabled by `override_precedence`
+    pub fn resolve_default_if_enabled(&self) -> Result<Option<ToolchainDesc>> {
+        if self.override_precedence.contains(OverrideSource::Default) {
+            self.resolve_default()
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn find_override_toolchain_or_default(
         &self,
         path: &Path,
@@ -250,7 +1006,7 @@ impl Cfg {
                     if toolchain.exists() {
                         Ok(Some((toolchain, Some(reason))))
                     } else {
-                        toolchain.install_from_dist()?;
+                        toolchain.install_implicitly()?;
                         Ok(Some((toolchain, Some(reason))))
                     }
                 }
@@ -294,7 +1050,7 @@ impl Cfg {
                         .chain_err(|| ErrorKind::OverrideToolchainNotInstalled(toolchain))
                 }
             }
-        } else if let Some(tc) = self.resolve_default()? {
+        } else if let Some(tc) = self.resolve_default_if_enabled()? {
             Ok(Some((self.get_toolchain(&tc, false)?, None)))
         } else {
             Ok(None)
@@ -306,15 +1062,34 @@ impl Cfg {
             .with(|s| Ok(s.overrides.clone().into_iter().collect_vec()))
     }
 
+    /// Overrides naming a linked toolchain that no longer exists, which,
+    /// unlike releases, cannot be installed on demand
+    pub fn dangling_overrides(&self) -> Result<Vec<(String, ToolchainDesc)>> {
+        Ok(self
+            .get_overrides()?
+            .into_iter()
+            .filter(|(_, t)| {
+                matches!(t, ToolchainDesc::Local { .. }) && !Toolchain::from(self, t).exists()
+            })
+            .collect())
+    }
+
     pub fn list_toolchains(&self) -> Result<Vec<ToolchainDesc>> {
         if utils::is_directory(&self.toolchains_dir) {
             let mut toolchains: Vec<_> = utils::read_dir("toolchains", &self.toolchains_dir)?
                 .filter_map(io::Result::ok)
                 .filter(|e| e.file_type().map(|f| !f.is_file()).unwrap_or(false))
-                .filter_map(|e| e.file_name().into_string().ok())
-                .map(|n| ToolchainDesc::from_toolchain_dir(&n).map_err(|e| e.into()))
-                .collect::<Result<Vec<ToolchainDesc>>>()?
-                .into_iter()
+                .filter_map(|e| {
+                    let desc = e
+                        .file_name()
+                        .into_string()
+                        .ok()
+                        .and_then(|n| ToolchainDesc::from_toolchain_dir(&n).ok());
+                    if desc.is_none() {
+                        (self.notify_handler)(Notification::IgnoringToolchainDir(&e.path()));
+                    }
+                    desc
+                })
                 .map(|tc| tc.to_string())
                 .collect();
 
@@ -344,15 +1119,15 @@ impl Cfg {
         toolchain.create_command(binary)
     }
 
-    pub fn create_command_for_toolchain(
+    pub fn create_command_for_toolchain<T: AsRef<OsStr>>(
         &self,
         toolchain: &ToolchainDesc,
         install_if_missing: bool,
-        binary: &str,
+        binary: T,
     ) -> Result<Command> {
         let toolchain = &(self.get_toolchain(toolchain, false)?);
         if install_if_missing && !toolchain.exists() {
-            toolchain.install_from_dist()?;
+            toolchain.install_implicitly()?;
         }
 
         toolchain.create_command(binary)
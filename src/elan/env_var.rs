@@ -1,9 +1,262 @@
+use elan_utils::endpoints;
 use std::env;
 use std::path::PathBuf;
 use std::process::Command;
 
 pub const LEAN_RECURSION_COUNT_MAX: u32 = 5;
 
+pub const ELAN_HOME: &str = "ELAN_HOME";
+pub const ELAN_SYSTEM_HOME: &str = "ELAN_SYSTEM_HOME";
+pub const ELAN_TOOLCHAIN: &str = "ELAN_TOOLCHAIN";
+pub const ELAN_READ_ONLY: &str = "ELAN_READ_ONLY";
+pub const ELAN_PROXY_NAME: &str = "ELAN_PROXY_NAME";
+pub const ELAN_ARTIFACT_CACHE: &str = "ELAN_ARTIFACT_CACHE";
+pub const ELAN_PROXY_AUTH: &str = "ELAN_PROXY_AUTH";
+pub const ELAN_GITHUB_TOKEN: &str = "ELAN_GITHUB_TOKEN";
+pub const ELAN_RELEASE_INDEX: &str = "ELAN_RELEASE_INDEX";
+pub const ELAN_UPDATE_ROOT: &str = endpoints::UPDATE_ROOT.env_var;
+pub const ELAN_GITHUB_URL: &str = endpoints::GITHUB.env_var;
+pub const ELAN_GITHUB_API_URL: &str = endpoints::GITHUB_API.env_var;
+pub const ELAN_GITHUB_RAW_URL: &str = endpoints::GITHUB_RAW.env_var;
+pub const ELAN_IPFS_GATEWAY: &str = endpoints::IPFS_GATEWAY.env_var;
+pub const ELAN_DEFAULT_TOOLCHAIN: &str = "ELAN_DEFAULT_TOOLCHAIN";
+pub const ELAN_CI: &str = "ELAN_CI";
+pub const ELAN_QUIET: &str = "ELAN_QUIET";
+pub const ELAN_INIT_SKIP_PATH_CHECK: &str = "ELAN_INIT_SKIP_PATH_CHECK";
+pub const ELAN_INIT_SKIP_SUDO_CHECK: &str = "ELAN_INIT_SKIP_SUDO_CHECK";
+pub const LEAN_RECURSION_COUNT: &str = "LEAN_RECURSION_COUNT";
+pub const ELAN_INSTALL_METHOD: &str = "ELAN_INSTALL_METHOD";
+pub const ELAN_PACKAGE_NAME: &str = "ELAN_PACKAGE_NAME";
+
+/// The kind of value an environment variable takes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EnvVarType {
+    Path,
+    /// Enabled by any non-empty value other than `0`
+    Flag,
+    /// Enabled only by the value `yes`
+    Yes,
+    Integer,
+    Url,
+    Toolchain,
+    Text,
+}
+
+impl EnvVarType {
+    pub fn describe(self) -> &'static str {
+        match self {
+            EnvVarType::Path => "path",
+            EnvVarType::Flag => "flag",
+            EnvVarType::Yes => "'yes'",
+            EnvVarType::Integer => "integer",
+            EnvVarType::Url => "URL",
+            EnvVarType::Toolchain => "toolchain",
+            EnvVarType::Text => "text",
+        }
+    }
+}
+
+/// An environment variable that elan reads or sets
+#[derive(Debug)]
+pub struct EnvVar {
+    pub name: &'static str,
+    pub ty: EnvVarType,
+    pub description: &'static str,
+    /// Whether the variable is read when building elan rather than at runtime
+    pub build_time: bool,
+    /// Whether the value must not be printed
+    pub secret: bool,
+}
+
+/// All environment variables known to elan, including those read by
+/// `elan-utils` and `download`
+pub const ENV_VARS: &[EnvVar] = &[
+    EnvVar {
+        name: ELAN_HOME,
+        ty: EnvVarType::Path,
+        description: "Directory of the elan installation, its settings, and toolchains. \
+            Defaults to `~/.elan`. Set by elan for the tools it runs.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_SYSTEM_HOME,
+        ty: EnvVarType::Path,
+        description: "Directory of a system-wide elan installation whose toolchains are used \
+            instead of those in `ELAN_HOME`, which keeps the user's settings and overrides.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_TOOLCHAIN,
+        ty: EnvVarType::Toolchain,
+        description: "Toolchain to use regardless of directory overrides and `lean-toolchain` \
+            files. Set by elan for the tools it runs.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_READ_ONLY,
+        ty: EnvVarType::Flag,
+        description: "Use the installed toolchains without modifying any toolchains or \
+            settings, like `--read-only`.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_PROXY_NAME,
+        ty: EnvVarType::Text,
+        description: "Name of the tool to run as a proxy, regardless of the name elan was \
+            invoked as.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_ARTIFACT_CACHE,
+        ty: EnvVarType::Path,
+        description: "Directory of the artifact caches fetched by `elan cache fetch`. Set by \
+            elan for the tools it runs.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_PROXY_AUTH,
+        ty: EnvVarType::Text,
+        description: "Authentication method for HTTP proxies: basic, digest, ntlm, negotiate, \
+            or any. Takes precedence over the `proxy_auth` setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_GITHUB_TOKEN,
+        ty: EnvVarType::Text,
+        description: "GitHub access token sent with requests to GitHub to raise its rate limit; \
+            also makes elan look up the latest release of non-default origins through the \
+            GitHub API.",
+        build_time: false,
+        secret: true,
+    },
+    EnvVar {
+        name: ELAN_RELEASE_INDEX,
+        ty: EnvVarType::Text,
+        description: "Comma-separated `<origin>=<url>` pairs setting the release index that the \
+            latest releases of an origin are looked up in, or `<origin>=none` to look them up on \
+            GitHub only. Takes precedence over the `release_index` setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_UPDATE_ROOT,
+        ty: EnvVarType::Url,
+        description: "Base URL that `elan self update` downloads releases of elan from, \
+            `<ELAN_GITHUB_URL>/leanprover/elan/releases/download` by default. Takes precedence \
+            over the `update_root` setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_GITHUB_URL,
+        ty: EnvVarType::Url,
+        description: "GitHub web site, or a mirror of it, that release archives and pages and \
+            toolchain sources are downloaded from. Several comma-separated URLs are tried in \
+            turn for release archives and pages. Takes precedence over the `github_url` \
+            setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_GITHUB_API_URL,
+        ty: EnvVarType::Url,
+        description: "GitHub REST API, or a mirror of it, that releases and tags are looked up \
+            in. Takes precedence over the `github_api_url` setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_GITHUB_RAW_URL,
+        ty: EnvVarType::Url,
+        description: "Host serving raw files of GitHub repositories, used to read \
+            `lean-toolchain` for `<origin>:lean-toolchain`. Takes precedence over the \
+            `github_raw_url` setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_IPFS_GATEWAY,
+        ty: EnvVarType::Url,
+        description: "HTTP gateway of an IPFS node, `http://127.0.0.1:8080` by default, that \
+            release archives with `ipfs://` URLs in the release index are downloaded through. \
+            Only used if elan was built with the `ipfs` feature. Takes precedence over the \
+            `ipfs_gateway` setting.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_CI,
+        ty: EnvVarType::Text,
+        description: "CI system to format output for: `github` for GitHub Actions, which groups \
+            installation logs, annotates errors, and masks credentials, or `none`. Defaults to \
+            `github` when `GITHUB_ACTIONS` is `true`.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_QUIET,
+        ty: EnvVarType::Flag,
+        description: "Suppress info messages and download progress, like `--quiet`, or show \
+            them if `0`. Defaults to suppressing them in containers without a terminal, such as \
+            in `docker build`.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_DEFAULT_TOOLCHAIN,
+        ty: EnvVarType::Toolchain,
+        description: "Default toolchain for `elan-init` to set up, like `--default-toolchain`, \
+            for unattended installations.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_INIT_SKIP_PATH_CHECK,
+        ty: EnvVarType::Yes,
+        description: "Skip checking for an existing Lean installation in PATH when installing \
+            elan. Meant for tests.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_INIT_SKIP_SUDO_CHECK,
+        ty: EnvVarType::Yes,
+        description: "Skip checking whether elan-init runs under sudo. Meant for tests.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: LEAN_RECURSION_COUNT,
+        ty: EnvVarType::Integer,
+        description: "Nesting depth of proxy invocations, set by elan to detect infinite \
+            recursion.",
+        build_time: false,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_INSTALL_METHOD,
+        ty: EnvVarType::Text,
+        description: "Package manager elan is built for, e.g. `homebrew`, which disables \
+            self-update in favor of the package manager.",
+        build_time: true,
+        secret: false,
+    },
+    EnvVar {
+        name: ELAN_PACKAGE_NAME,
+        ty: EnvVarType::Text,
+        description: "Name of the package elan is distributed as, used in update hints.",
+        build_time: true,
+        secret: false,
+    },
+];
+
 #[allow(unused)]
 pub fn append_path(name: &str, value: Vec<PathBuf>, cmd: &mut Command) {
     let old_value = env::var_os(name);
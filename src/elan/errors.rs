@@ -20,9 +20,49 @@ error_chain! {
             description("invalid toolchain name")
             display("invalid toolchain name: '{}'", t)
         }
+        NoReleaseForCommit(origin: String, commit: String) {
+            description("no release found for commit")
+            display("no release of '{}' was found for commit '{}'; only commits with a release can be used as toolchains", origin, commit)
+        }
+        OriginWithoutRelease(name: String, suggestions: Vec<String>) {
+            description("toolchain name without a release")
+            display("'{}' names a repository but no release; use e.g. {}", name, suggestions.iter().map(|s| format!("`{}`", s)).collect::<Vec<_>>().join(" or "))
+        }
+        ReleaseNotFound(desc: ToolchainDesc, suggestions: Vec<String>) {
+            description("release not found")
+            display("toolchain '{}' does not exist; did you mean {}?", desc, suggestions.iter().map(|s| format!("`{}`", s)).collect::<Vec<_>>().join(" or "))
+        }
+        ToolchainFileCycle(origins: Vec<String>) {
+            description("cycle in `lean-toolchain` references")
+            display("the `lean-toolchain` files of these repositories refer to each other in a cycle: {}", origins.iter().map(|o| format!("{}:lean-toolchain", o)).collect::<Vec<_>>().join(" -> "))
+        }
+        ToolchainFileTooDeep(origins: Vec<String>) {
+            description("too many nested `lean-toolchain` references")
+            display("stopped following `lean-toolchain` files after {} repositories: {}", origins.len() - 1, origins.iter().map(|o| format!("{}:lean-toolchain", o)).collect::<Vec<_>>().join(" -> "))
+        }
+        MissingBuildPrerequisites(tools: Vec<String>) {
+            description("missing prerequisites for building Lean")
+            display("building Lean from source requires {} to be installed; see https://lean-lang.org/lean4/doc/make/index.html", tools.join(", "))
+        }
+        AbbreviatedCommitHash(rev: String) {
+            description("abbreviated commit hash")
+            display("cannot fetch the abbreviated commit hash '{}' to build it; pass the full 40-character hash instead", rev)
+        }
+        UnsupportedGitSpec(spec: String) {
+            description("unsupported git toolchain specification")
+            display("unsupported toolchain specification '{}'; expected `git+https://github.com/<owner>/<repo>#<revision>`", spec)
+        }
+        UnknownSettingKey(key: String) {
+            description("unknown setting")
+            display("unknown setting '{}'; known settings are {}", key, crate::settings::SETTING_KEYS.iter().map(|k| k.display_name()).collect::<Vec<_>>().join(", "))
+        }
+        InvalidSettingValue(key: String, value: String, expected: String) {
+            description("invalid setting value")
+            display("invalid value '{}' for setting '{}'; expected {}", value, key, expected)
+        }
         UnknownMetadataVersion(v: String) {
             description("unknown metadata version")
-            display("unknown metadata version: '{}'", v)
+            display("unknown metadata version: '{}'; the settings may have been written by a newer version of elan, try `elan self update`", v)
         }
         NoDefaultToolchain {
             description("no default toolchain configured. run `elan default stable` to install & configure the latest Lean 4 stable release.")
@@ -35,6 +75,86 @@ error_chain! {
             description("toolchain does not contain binary")
             display("toolchain '{}' does not have the binary `{}`", t, bin)
         }
+        SystemInstallReadOnly(p: PathBuf) {
+            description("toolchains of the system-wide installation cannot be modified")
+            display("toolchains of the system-wide installation at '{}' can only be installed or uninstalled by its owner; try again with `sudo`", p.display())
+        }
+        ElanHomeNotWritable(p: PathBuf, owner: String) {
+            description("ELAN_HOME is not writable")
+            display("ELAN_HOME '{}' is not writable{}; run elan as its owner, set ELAN_HOME to a directory you own, or pass `--read-only` to use the installed toolchains without modifying them", p.display(), owner)
+        }
+        ReadOnly {
+            description("elan is in read-only mode")
+            display("cannot modify toolchains or settings in read-only mode")
+        }
+        ToolchainAlreadyInstalled(t: ToolchainDesc) {
+            description("toolchain is already installed")
+            display("'{}' is already installed; pass `--reinstall` to replace it", t)
+        }
+        ReinstallingLinkedToolchain(t: ToolchainDesc) {
+            description("cannot reinstall a linked toolchain")
+            display("'{}' is a linked toolchain, which cannot be reinstalled; pass `--force` to replace it with the release of that name", t)
+        }
+        InvalidStripPattern(p: String) {
+            description("invalid strip pattern")
+            display("invalid pattern '{}'; patterns must be relative to the toolchain directory", p)
+        }
+        StrippingLinkedToolchain(t: ToolchainDesc) {
+            description("cannot strip a linked toolchain")
+            display("'{}' is a linked toolchain, which elan does not modify", t)
+        }
+        NoToolchainFile(dir: PathBuf) {
+            description("no `lean-toolchain` file found")
+            display("no `lean-toolchain` file in '{}' or any of its parents", dir.display())
+        }
+        ExportingToolchainNotInstalled(t: ToolchainDesc) {
+            description("toolchain to export is not installed")
+            display("toolchain '{}' is not installed; install it before exporting it", t)
+        }
+        ExportingCustomToolchain(t: ToolchainDesc) {
+            description("custom toolchains cannot be exported")
+            display("'{}' is a custom toolchain and cannot be exported; only toolchains installed from releases can", t)
+        }
+        WritingCacheArchive(p: PathBuf) {
+            description("could not write cache archive")
+            display("could not write cache archive '{}'", p.display())
+        }
+        InvalidCacheArchive(p: PathBuf, reason: String) {
+            description("invalid cache archive")
+            display("'{}' is not a valid elan cache archive: {}", p.display(), reason)
+        }
+        InvalidArtifactCaches(p: PathBuf, reason: String) {
+            description("invalid artifact cache declaration")
+            display("invalid artifact cache declaration in '{}': {}", p.display(), reason)
+        }
+        InvalidArtifactIndex(url: String, reason: String) {
+            description("invalid artifact cache index")
+            display("'{}' is not a valid artifact cache index: {}", url, reason)
+        }
+        FetchingArtifact(name: String, cache: String) {
+            description("could not fetch artifact")
+            display("could not fetch '{}' of artifact cache '{}'", name, cache)
+        }
+        ToolchainNotInstalledReadOnly(t: ToolchainDesc) {
+            description("toolchain is not installed")
+            display("toolchain '{}' is not installed and cannot be installed in read-only mode", t)
+        }
+        ToolchainInUse(t: ToolchainDesc, reason: String) {
+            description("toolchain is in use")
+            display("toolchain '{}' is {}; pass `--force` to uninstall it anyway", t, reason)
+        }
+        ToolchainFrozen(t: ToolchainDesc) {
+            description("toolchain is frozen")
+            display("toolchain '{}' is frozen; pass `--force` to remove it anyway or unfreeze it with `elan toolchain unfreeze {}`", t, t)
+        }
+        NotALinkedToolchain(t: ToolchainDesc) {
+            description("toolchain is not linked")
+            display("'{}' is not a linked toolchain; only toolchains created with `elan toolchain link` can be renamed", t)
+        }
+        ImplicitInstallTimedOut(t: ToolchainDesc, secs: u64) {
+            description("toolchain installation timed out")
+            display("installation of toolchain '{}' did not finish within {} seconds; install it manually with `elan toolchain install {}`, or raise the limit with `elan config set implicit_install_timeout <seconds>`", t, secs, t)
+        }
         NeedMetadataUpgrade {
             description("elan's metadata is out of date. run `elan self upgrade-data`")
         }
@@ -48,6 +168,10 @@ error_chain! {
         ParsingSettings(e: toml::de::Error) {
             description("error parsing settings")
         }
+        ParsingKnownProjects(e: toml::de::Error) {
+            description("error parsing known projects")
+            display("error parsing known projects: {}", e)
+        }
         RemovingRequiredComponent(t: ToolchainDesc, c: Component) {
             description("required component cannot be removed")
             display("component {} is required for toolchain '{}' and cannot be removed",
@@ -1,40 +1,196 @@
 use std::{
     collections::HashSet,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use elan_dist::dist::ToolchainDesc;
 use itertools::Itertools;
 
+use crate::env_var;
+use crate::errors::*;
+use crate::toml_utils::*;
+use crate::utils;
 use crate::{
     lookup_unresolved_toolchain_desc, read_toolchain_desc_from_file, resolve_toolchain_desc_ext,
     Cfg, Toolchain,
 };
 
-fn get_root_file(cfg: &Cfg) -> PathBuf {
-    cfg.elan_dir.join("known-projects")
+const ROOT_FILE: &str = "known-projects.toml";
+/// Newline-separated list of roots used by earlier versions. It is kept up to
+/// date so that an earlier version sharing the same ELAN_HOME does not
+/// consider toolchains of projects it cannot see unused.
+const LEGACY_ROOT_FILE: &str = "known-projects";
+/// Granularity of `last_used` updates, so that not every toolchain resolution
+/// has to rewrite the root file
+const LAST_USED_RESOLUTION: u64 = 24 * 60 * 60;
+
+/// A known project directory, whose `lean-toolchain` keeps a toolchain alive
+#[derive(Clone, Debug)]
+pub struct Root {
+    pub path: String,
+    /// When the project was first seen, in seconds since the Unix epoch
+    pub added: u64,
+    /// When the project's toolchain was last resolved, in seconds since the
+    /// Unix epoch
+    pub last_used: u64,
+}
+
+impl Root {
+    /// Whether the project directory and its `lean-toolchain` file still exist
+    pub fn exists(&self) -> bool {
+        Path::new(&self.path).join("lean-toolchain").is_file()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn get_int(table: &mut toml::value::Table, key: &str, path: &str) -> crate::Result<u64> {
+    match get_value(table, key, path)? {
+        toml::Value::Integer(i) if i >= 0 => Ok(i as u64),
+        _ => Err(elan_utils::Error::from(elan_utils::ErrorKind::ExpectedType(
+            "integer",
+            path.to_owned() + key,
+        ))
+        .into()),
+    }
+}
+
+fn parse_roots(data: &str) -> crate::Result<Vec<Root>> {
+    let mut table: toml::value::Table =
+        toml::from_str(data).map_err(ErrorKind::ParsingKnownProjects)?;
+    let projects = get_table(&mut table, "projects", "")?;
+    projects
+        .into_iter()
+        .map(|(path, v)| {
+            let key = format!("projects.{}.", path);
+            let mut v = match v {
+                toml::Value::Table(t) => t,
+                _ => {
+                    return Err(elan_utils::Error::from(elan_utils::ErrorKind::ExpectedType(
+                        "table", key,
+                    ))
+                    .into())
+                }
+            };
+            Ok(Root {
+                added: get_int(&mut v, "added", &key)?,
+                last_used: get_int(&mut v, "last_used", &key)?,
+                path,
+            })
+        })
+        .collect()
 }
 
-fn get_roots(cfg: &Cfg) -> elan_utils::Result<Vec<String>> {
-    let path = get_root_file(cfg);
-    if path.exists() {
-        let roots = std::fs::read_to_string(&path)?;
-        Ok(roots.split("\n").map(|s| s.to_string()).collect_vec())
+fn stringify_roots(roots: &[Root]) -> String {
+    let projects = roots
+        .iter()
+        .map(|r| {
+            let mut t = toml::value::Table::new();
+            t.insert("added".to_owned(), toml::Value::Integer(r.added as i64));
+            t.insert(
+                "last_used".to_owned(),
+                toml::Value::Integer(r.last_used as i64),
+            );
+            (r.path.clone(), toml::Value::Table(t))
+        })
+        .collect();
+    let mut table = toml::value::Table::new();
+    table.insert("projects".to_owned(), toml::Value::Table(projects));
+    toml::Value::Table(table).to_string()
+}
+
+/// Reads the known project roots, including those added to the legacy root
+/// file by earlier versions
+pub fn read_roots(cfg: &Cfg) -> crate::Result<Vec<Root>> {
+    let path = cfg.elan_dir.join(ROOT_FILE);
+    let mut roots = if path.exists() {
+        parse_roots(&utils::read_file("known projects", &path)?)?
     } else {
-        Ok(vec![])
+        vec![]
+    };
+    let legacy_path = cfg.elan_dir.join(LEGACY_ROOT_FILE);
+    if !legacy_path.exists() {
+        return Ok(roots);
+    }
+    let now = now();
+    let legacy_roots = utils::read_file("known projects", &legacy_path)?;
+    let len = roots.len();
+    for root in legacy_roots.split('\n').filter(|s| !s.is_empty()).unique() {
+        if !roots.iter().any(|r| r.path == root) {
+            roots.push(Root {
+                path: root.to_string(),
+                added: now,
+                last_used: now,
+            });
+        }
+    }
+    if roots.len() != len && !cfg.read_only {
+        write_roots(cfg, &roots)?;
     }
+    Ok(roots)
+}
+
+fn write_roots(cfg: &Cfg, roots: &[Root]) -> crate::Result<()> {
+    let path = cfg.elan_dir.join(ROOT_FILE);
+    utils::write_file("known projects", &path, &stringify_roots(roots))?;
+    let legacy_roots = roots.iter().map(|r| format!("{}\n", r.path)).join("");
+    Ok(utils::write_file(
+        "known projects",
+        &cfg.elan_dir.join(LEGACY_ROOT_FILE),
+        &legacy_roots,
+    )?)
+}
+
+pub fn get_roots(cfg: &Cfg) -> crate::Result<Vec<String>> {
+    Ok(read_roots(cfg)?.into_iter().map(|r| r.path).collect())
 }
 
-pub fn add_root(cfg: &Cfg, root: &Path) -> elan_utils::Result<()> {
-    let path = get_root_file(cfg);
-    let mut roots = get_roots(cfg)?;
+pub fn add_root(cfg: &Cfg, root: &Path) -> crate::Result<()> {
+    if cfg.read_only {
+        return Ok(());
+    }
+    let mut roots = read_roots(cfg)?;
     let root = root.to_str().unwrap().to_string();
-    if !roots.contains(&root) {
-        roots.push(root);
-        let roots = roots.join("\n");
-        std::fs::write(path, roots)?;
+    let now = now();
+    match roots.iter_mut().find(|r| r.path == root) {
+        Some(r) if r.last_used + LAST_USED_RESOLUTION > now => return Ok(()),
+        Some(r) => r.last_used = now,
+        None => roots.push(Root {
+            path: root,
+            added: now,
+            last_used: now,
+        }),
+    }
+    write_roots(cfg, &roots)
+}
+
+/// Forgets the root `root`, returning whether it was known
+pub fn forget_root(cfg: &Cfg, root: &Path) -> crate::Result<bool> {
+    let mut roots = read_roots(cfg)?;
+    let root = root.to_string_lossy();
+    let len = roots.len();
+    roots.retain(|r| r.path != root);
+    if roots.len() == len {
+        return Ok(false);
+    }
+    write_roots(cfg, &roots)?;
+    Ok(true)
+}
+
+/// Forgets all roots whose directory or `lean-toolchain` file no longer
+/// exists, returning them
+pub fn prune_roots(cfg: &Cfg) -> crate::Result<Vec<Root>> {
+    let (roots, pruned): (Vec<_>, Vec<_>) = read_roots(cfg)?.into_iter().partition(Root::exists);
+    if !pruned.is_empty() {
+        write_roots(cfg, &roots)?;
     }
-    Ok(())
+    Ok(pruned)
 }
 
 pub fn analyze_toolchains(
@@ -69,7 +225,7 @@ pub fn analyze_toolchains(
             true,
             true,
         ) {
-            used_toolchains.push(("ELAN_TOOLCHAIN".to_string(), desc));
+            used_toolchains.push((env_var::ELAN_TOOLCHAIN.to_string(), desc));
         }
     }
     for (path, tc) in cfg.get_overrides()? {
@@ -0,0 +1,228 @@
+//! Log of the operations that changed which toolchains are installed or
+//! selected
+//!
+//! Each line of `$ELAN_HOME/history.jsonl` is a JSON object recording one
+//! default change, override change, installation, uninstallation, deletion
+//! by `elan toolchain gc`, or replacement of a linked toolchain, together with
+//! what it replaced, so that users can find out what deleted a toolchain and
+//! reconstruct earlier states. Failing to write the log never fails the
+//! operation itself.
+//!
+//! `elan undo` reverts the most recent of these operations it has not
+//! reverted yet, and records that it did so by the numbers of their lines.
+
+use std::collections::HashSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::*;
+use crate::utils;
+use crate::{Cfg, Notification};
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// The default toolchain was set or unset
+    Default,
+    /// An override was set for a directory
+    OverrideSet,
+    /// The override of a directory was removed
+    OverrideUnset,
+    Install,
+    Uninstall,
+    /// An unused toolchain was deleted by `elan toolchain gc`
+    Gc,
+    /// A linked toolchain was removed to install the release of the same
+    /// name in its place, by `elan toolchain install --force`
+    Replace,
+    /// Earlier operations were reverted by `elan undo`
+    Undo,
+}
+
+impl Operation {
+    /// Whether `elan undo` reverts operations of this kind
+    pub fn is_undoable(self) -> bool {
+        matches!(
+            self,
+            Operation::Default
+                | Operation::OverrideSet
+                | Operation::OverrideUnset
+                | Operation::Uninstall
+                | Operation::Gc
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the operation happened, in seconds since the Unix epoch
+    pub time: u64,
+    pub operation: Operation,
+    /// The toolchain installed, uninstalled, or selected, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// The toolchain selected before a default or override change, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    /// The directory of an override
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The command line that caused the operation, e.g. `lake build` for a
+    /// toolchain installed because it was missing
+    #[serde(default)]
+    pub command: String,
+    /// Identifies the run of elan that performed the operation, so that
+    /// operations of one run can be told apart from those of an identical
+    /// command run right after it
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub invocation: String,
+    /// The lines of the operations reverted by an `undo` operation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub undone: Vec<usize>,
+    /// The number of the line of the entry in the history file, from 0
+    #[serde(skip)]
+    pub line: usize,
+}
+
+impl Entry {
+    pub fn new(operation: Operation) -> Self {
+        let mut args = env::args();
+        let program = args
+            .next()
+            .map(|arg0| {
+                let path = Path::new(&arg0);
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or(arg0.clone())
+            })
+            .unwrap_or_default();
+        Entry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            operation,
+            toolchain: None,
+            previous: None,
+            path: None,
+            undone: vec![],
+            line: 0,
+            command: std::iter::once(program)
+                .chain(args)
+                .collect::<Vec<_>>()
+                .join(" "),
+            invocation: invocation().to_owned(),
+        }
+    }
+
+    /// The command reverting the operation, as far as elan can tell
+    pub fn undo_hint(&self) -> Option<String> {
+        let path = self.path.as_deref().unwrap_or_default();
+        match (self.operation, &self.toolchain, &self.previous) {
+            (Operation::Default, _, Some(previous)) => Some(format!("elan default {}", previous)),
+            (Operation::Default, Some(_), None) => {
+                Some("elan config unset default_toolchain".to_owned())
+            }
+            (Operation::OverrideSet | Operation::OverrideUnset, _, Some(previous)) => {
+                Some(format!("cd '{}' && elan override set {}", path, previous))
+            }
+            (Operation::OverrideSet, _, None) => {
+                Some(format!("elan override unset --path '{}'", path))
+            }
+            (Operation::Install, Some(toolchain), _) => {
+                Some(format!("elan toolchain uninstall {}", toolchain))
+            }
+            // Linked toolchains, whose names lack an origin, cannot be
+            // reinstalled from anywhere
+            (Operation::Uninstall | Operation::Gc, Some(toolchain), _)
+                if toolchain.contains(':') =>
+            {
+                Some(format!("elan toolchain install {}", toolchain))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The process ID together with the time of the first operation recorded by
+/// this process, which is unique enough even when process IDs are reused
+fn invocation() -> &'static str {
+    static INVOCATION: OnceLock<String> = OnceLock::new();
+    INVOCATION.get_or_init(|| {
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        format!("{}-{}", process::id(), start)
+    })
+}
+
+pub fn history_file(cfg: &Cfg) -> PathBuf {
+    cfg.elan_dir.join(HISTORY_FILE)
+}
+
+/// Appends `entry` to the history, warning if that fails
+pub fn record(cfg: &Cfg, entry: Entry) {
+    let result = serde_json::to_string(&entry)
+        .chain_err(|| "could not serialize history entry")
+        .and_then(|line| Ok(utils::append_file("history", &history_file(cfg), &line)?));
+    if let Err(e) = result {
+        (cfg.notify_handler)(Notification::HistoryNotRecorded(&e));
+    }
+}
+
+/// The recorded operations, oldest first. Lines that cannot be read, e.g.
+/// because they were written by a newer version of elan, are skipped.
+pub fn read(cfg: &Cfg) -> Result<Vec<Entry>> {
+    let path = history_file(cfg);
+    if !utils::is_file(&path) {
+        return Ok(vec![]);
+    }
+    Ok(utils::read_file("history", &path)?
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            serde_json::from_str(line)
+                .ok()
+                .map(|entry| Entry { line: i, ..entry })
+        })
+        .collect())
+}
+
+/// The most recent operations among `entries` that can be undone and have
+/// not been, oldest first: the last such operation together with the
+/// operations of the same kind performed by the same run of elan right before
+/// it, e.g. all toolchains deleted by one run of `elan toolchain gc`
+pub fn last_undoable(entries: &[Entry]) -> Vec<&Entry> {
+    let undone: HashSet<usize> = entries
+        .iter()
+        .flat_map(|e| e.undone.iter().copied())
+        .collect();
+    let mut candidates = entries
+        .iter()
+        .rev()
+        .filter(|e| e.operation.is_undoable() && !undone.contains(&e.line));
+    let last = match candidates.next() {
+        Some(last) => last,
+        None => return vec![],
+    };
+    let mut group = vec![last];
+    // A command changes the default toolchain at most once
+    if last.operation != Operation::Default {
+        group.extend(candidates.take_while(|e| {
+            e.operation == last.operation
+                    // Entries written by earlier versions cannot be grouped
+                    && !e.invocation.is_empty()
+                    && e.invocation == last.invocation
+        }));
+    }
+    group.reverse();
+    group
+}
@@ -4,15 +4,60 @@
 use crate::errors::Result;
 use elan_dist::dist;
 use elan_dist::download::DownloadCfg;
+use elan_dist::manifest::Component;
 use elan_dist::prefix::InstallPrefix;
-use elan_dist::Notification;
+use elan_dist::target::Targets;
+use elan_dist::{ExtractionLimits, Notification};
 use elan_utils::utils::{self, fetch_latest_release_tag};
+use std::env::consts::EXE_SUFFIX;
 use std::path::Path;
 
+/// The package manager this build of elan is distributed with, if any, as
+/// set by packagers through the `ELAN_INSTALL_METHOD` environment variable at
+/// build time, e.g. `homebrew`, `apt`, or `pacman`. `ELAN_PACKAGE_NAME` can
+/// be set as well if the package is not named as usual for that manager.
+pub const INSTALL_METHOD: Option<&str> = option_env!("ELAN_INSTALL_METHOD");
+const PACKAGE_NAME: Option<&str> = option_env!("ELAN_PACKAGE_NAME");
+
 #[cfg(feature = "no-self-update")]
 pub const NEVER_SELF_UPDATE: bool = true;
 #[cfg(not(feature = "no-self-update"))]
-pub const NEVER_SELF_UPDATE: bool = false;
+pub const NEVER_SELF_UPDATE: bool = INSTALL_METHOD.is_some();
+
+/// File recording the prefix of an external Lean installation wrapped as a
+/// toolchain, which also marks the toolchain as custom
+pub const WRAPPED_PREFIX_FILE: &str = "elan-wrapped-prefix";
+
+/// Commands for updating and uninstalling elan with the package manager it
+/// was installed with, if known
+pub struct PackageManagerCommands {
+    pub update: String,
+    pub uninstall: String,
+}
+
+pub fn package_manager_commands() -> Option<PackageManagerCommands> {
+    let method = INSTALL_METHOD?;
+    let default_name = match method {
+        "homebrew" => "elan-init",
+        "pacman" => "elan-lean",
+        _ => "elan",
+    };
+    let name = PACKAGE_NAME.unwrap_or(default_name);
+    let (update, uninstall) = match method {
+        "homebrew" => ("brew upgrade", "brew uninstall"),
+        "apt" => ("sudo apt install --only-upgrade", "sudo apt remove"),
+        "dnf" => ("sudo dnf upgrade", "sudo dnf remove"),
+        "pacman" => ("sudo pacman -S", "sudo pacman -R"),
+        "nix" => ("nix profile upgrade", "nix profile remove"),
+        "scoop" => ("scoop update", "scoop uninstall"),
+        "winget" => ("winget upgrade --id", "winget uninstall --id"),
+        _ => return None,
+    };
+    Some(PackageManagerCommands {
+        update: format!("{} {}", update, name),
+        uninstall: format!("{} {}", uninstall, name),
+    })
+}
 
 /// Downloads and returns new elan version string if not already up to date
 pub fn check_self_update() -> Result<Option<String>> {
@@ -26,7 +71,7 @@ pub fn check_self_update() -> Result<Option<String>> {
     // Get current version
     let current_version = env!("CARGO_PKG_VERSION");
 
-    let tag = fetch_latest_release_tag("leanprover/elan", false)?;
+    let tag = fetch_latest_release_tag("leanprover/elan", "stable", false, &|_| {})?;
     let available_version = &tag[1..];
 
     Ok(if available_version == current_version {
@@ -40,7 +85,19 @@ pub fn check_self_update() -> Result<Option<String>> {
 pub enum InstallMethod<'a> {
     Copy(&'a Path),
     Link(&'a Path),
-    Dist(&'a dist::ToolchainDesc, DownloadCfg<'a>),
+    /// Links just the given executables of a Lean installation sharing its
+    /// prefix with other software, e.g. `/usr`
+    Wrap(&'a Path, &'a [&'a str]),
+    /// Downloads a release; the `bool` requests replacing an existing
+    /// installation
+    Dist(
+        &'a dist::ToolchainDesc,
+        &'a [Component],
+        &'a Targets,
+        DownloadCfg<'a>,
+        bool,
+        ExtractionLimits,
+    ),
 }
 
 impl InstallMethod<'_> {
@@ -64,13 +121,32 @@ impl InstallMethod<'_> {
                 utils::symlink_dir(src, path, &|n| notify_handler(n.into()))?;
                 Ok(())
             }
-            InstallMethod::Dist(toolchain, dl_cfg) => {
+            InstallMethod::Wrap(src, tools) => {
+                let bin_path = path.join("bin");
+                utils::ensure_dir_exists("toolchain", &bin_path, &|n| notify_handler(n.into()))?;
+                for tool in tools {
+                    let name = format!("{}{}", tool, EXE_SUFFIX);
+                    let tool_path = src.join("bin").join(&name);
+                    if tool_path.is_file() {
+                        utils::symlink_file(&tool_path, &bin_path.join(name))?;
+                    }
+                }
+                utils::write_file(
+                    "prefix",
+                    &path.join(WRAPPED_PREFIX_FILE),
+                    &src.display().to_string(),
+                )?;
+                Ok(())
+            }
+            InstallMethod::Dist(toolchain, components, targets, dl_cfg, reinstall, limits) => {
                 if let Some(version) = check_self_update()? {
                     notify_handler(Notification::NewVersionAvailable(version));
                 }
 
                 let prefix = &InstallPrefix::from(path.to_owned());
-                dist::install_from_dist(dl_cfg, toolchain, prefix)?;
+                dist::install_from_dist(
+                    dl_cfg, toolchain, prefix, components, targets, reinstall, limits,
+                )?;
 
                 Ok(())
             }
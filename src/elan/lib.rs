@@ -7,12 +7,18 @@ pub use elan_utils::{notify, toml_utils, utils};
 pub use notifications::*;
 pub use toolchain::*;
 
+pub mod api;
+pub mod artifact_cache;
+pub mod cache;
 pub mod command;
 mod config;
 pub mod env_var;
 mod errors;
 pub mod gc;
+pub mod history;
 pub mod install;
 mod notifications;
 pub mod settings;
+#[cfg(feature = "build-from-source")]
+pub mod source_build;
 mod toolchain;
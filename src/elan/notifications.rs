@@ -7,6 +7,11 @@ use elan_dist::dist::ToolchainDesc;
 use elan_dist::{self, temp};
 use elan_utils::notify::NotificationLevel;
 
+/// Receives the notifications of a `Cfg`. Handlers may be called from any
+/// thread doing work for the `Cfg`, so they must synchronize any state they
+/// keep, e.g. by forwarding notifications over a channel.
+pub type NotifyHandler = dyn Fn(Notification<'_>) + Send + Sync;
+
 #[derive(Debug)]
 pub enum Notification<'a> {
     Install(elan_dist::Notification<'a>),
@@ -19,11 +24,15 @@ pub enum Notification<'a> {
     ToolchainDirectory(&'a Path, &'a ToolchainDesc),
     UpdatingToolchain(&'a ToolchainDesc),
     InstallingToolchain(&'a ToolchainDesc),
+    BuildingToolchain(&'a ToolchainDesc),
     InstalledToolchain(&'a ToolchainDesc),
     UsingExistingToolchain(&'a ToolchainDesc),
     UsingExistingRelease(&'a ToolchainDesc),
+    UsingCachedReleaseOffline(&'a ToolchainDesc),
     UninstallingToolchain(&'a ToolchainDesc),
     UninstallingObsoleteToolchain(&'a Path),
+    /// A toolchain using an obsolete format is kept because it is frozen
+    KeepingFrozenObsoleteToolchain(&'a Path),
     UninstalledToolchain(&'a ToolchainDesc),
     ToolchainNotInstalled(&'a ToolchainDesc),
     UpdateHashMatches,
@@ -32,8 +41,14 @@ pub enum Notification<'a> {
     WritingMetadataVersion(&'a str),
     ReadMetadataVersion(&'a str),
     NonFatalError(&'a Error),
+    HistoryNotRecorded(&'a Error),
     UpgradeRemovesToolchains,
     MissingFileDuringSelfUninstall(PathBuf),
+    /// A directory in the toolchains directory whose name is not that of a
+    /// valid toolchain
+    IgnoringToolchainDir(&'a Path),
+    /// A name in the `override_precedence` setting this version does not know
+    UnknownOverrideSource(&'a str),
     SetTelemetry(&'a str),
 
     TelemetryCleanupError(&'a Error),
@@ -73,7 +88,9 @@ impl Notification<'_> {
             | TelemetryCleanupError(_) => NotificationLevel::Verbose,
             SetDefaultToolchain(_)
             | SetOverrideToolchain(_, _)
+            | BuildingToolchain(_)
             | UsingExistingToolchain(_)
+            | UsingCachedReleaseOffline(_)
             | UninstallingToolchain(_)
             | UninstallingObsoleteToolchain(_)
             | UninstalledToolchain(_)
@@ -83,7 +100,11 @@ impl Notification<'_> {
             | SetTelemetry(_) => NotificationLevel::Info,
             NonFatalError(_) => NotificationLevel::Error,
             UpgradeRemovesToolchains
+            | HistoryNotRecorded(_)
             | MissingFileDuringSelfUninstall(_)
+            | IgnoringToolchainDir(_)
+            | KeepingFrozenObsoleteToolchain(_)
+            | UnknownOverrideSource(_)
             | UsingExistingRelease(_) => NotificationLevel::Warn,
         }
     }
@@ -109,6 +130,11 @@ impl Display for Notification<'_> {
             ToolchainDirectory(path, _) => write!(f, "toolchain directory: '{}'", path.display()),
             UpdatingToolchain(name) => write!(f, "updating existing install for '{}'", name),
             InstallingToolchain(name) => write!(f, "installing toolchain '{}'", name),
+            BuildingToolchain(name) => write!(
+                f,
+                "building toolchain '{}' from source; this may take a while",
+                name
+            ),
             InstalledToolchain(name) => write!(f, "toolchain '{}' installed", name),
             UsingExistingToolchain(name) => write!(f, "using existing install for '{}'", name),
             UninstallingToolchain(name) => write!(f, "uninstalling toolchain '{}'", name),
@@ -117,6 +143,12 @@ impl Display for Notification<'_> {
                 "uninstalling toolchain '{}' using obsolete format",
                 name.display()
             ),
+            KeepingFrozenObsoleteToolchain(path) => write!(
+                f,
+                "keeping frozen toolchain '{}' using obsolete format; delete the directory once it \
+                 is no longer needed",
+                path.display()
+            ),
             UninstalledToolchain(name) => write!(f, "toolchain '{}' uninstalled", name),
             ToolchainNotInstalled(name) => write!(f, "no toolchain installed for '{}'", name),
             UpdateHashMatches => {
@@ -139,6 +171,9 @@ impl Display for Notification<'_> {
             WritingMetadataVersion(ver) => write!(f, "writing metadata version: '{}'", ver),
             ReadMetadataVersion(ver) => write!(f, "read metadata version: '{}'", ver),
             NonFatalError(e) => write!(f, "{}", e),
+            HistoryNotRecorded(e) => {
+                write!(f, "could not record the operation in the history: {}", e)
+            }
             UpgradeRemovesToolchains => write!(
                 f,
                 "this upgrade will remove all existing toolchains. you will need to reinstall them"
@@ -150,8 +185,21 @@ impl Display for Notification<'_> {
                     p.display()
                 )
             }
+            IgnoringToolchainDir(path) => write!(
+                f,
+                "ignoring '{}', which is not named like a toolchain",
+                path.display()
+            ),
+            UnknownOverrideSource(name) => write!(
+                f,
+                "ignoring unknown source '{}' in the `override_precedence` setting",
+                name
+            ),
             SetTelemetry(telemetry_status) => write!(f, "telemetry set to '{}'", telemetry_status),
             TelemetryCleanupError(e) => write!(f, "unable to remove old telemetry files: '{}'", e),
+            UsingCachedReleaseOffline(tc) => {
+                write!(f, "using cached '{}'; latest unknown (offline)", tc)
+            }
             UsingExistingRelease(tc) => write!(
                 f,
                 "failed to query latest release, using existing version '{}'",
@@ -1,49 +1,119 @@
+use crate::config::OVERRIDE_SOURCE_NAMES;
 use crate::errors::*;
 use crate::notifications::*;
 use crate::toml_utils::*;
 use crate::utils;
 use elan_dist::dist::ToolchainDesc;
-use std::cell::RefCell;
+use elan_utils::endpoints;
+use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 use toml;
 
 pub const SUPPORTED_METADATA_VERSIONS: [&str; 2] = ["2", "12"];
 pub const DEFAULT_METADATA_VERSION: &str = "12";
 
-#[derive(Clone, Debug, PartialEq)]
+/// A step converting settings from one metadata version to the next.
+///
+/// Adding an optional key does not require a new metadata version, as unknown
+/// keys are preserved by older versions of elan. Bump the version only for
+/// changes older versions would misinterpret, and add a migration for it.
+struct Migration {
+    from: &'static str,
+    to: &'static str,
+    migrate: fn(&mut toml::value::Table) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: "2",
+    to: "12",
+    // Version 12 only introduced optional keys
+    migrate: |_| Ok(()),
+}];
+
+/// Brings `table` up to `DEFAULT_METADATA_VERSION`, returning the version it
+/// was migrated from, if any
+fn migrate(table: &mut toml::value::Table) -> Result<Option<String>> {
+    let original = match table.get("version") {
+        Some(toml::Value::String(v)) => v.clone(),
+        _ => return Ok(None),
+    };
+    let mut version = original.clone();
+    while version != DEFAULT_METADATA_VERSION {
+        let migration = match MIGRATIONS.iter().find(|m| m.from == version) {
+            Some(m) => m,
+            None => return Err(ErrorKind::UnknownMetadataVersion(version).into()),
+        };
+        (migration.migrate)(table)?;
+        version = migration.to.to_owned();
+        table.insert("version".to_owned(), toml::Value::String(version.clone()));
+    }
+    Ok(if version != original {
+        Some(original)
+    } else {
+        None
+    })
+}
+
+#[derive(Debug)]
 pub struct SettingsFile {
     path: PathBuf,
-    cache: RefCell<Option<Settings>>,
+    cache: RwLock<Option<Settings>>,
+    read_only: bool,
 }
 
 impl SettingsFile {
     pub fn new(path: PathBuf) -> Self {
         SettingsFile {
             path,
-            cache: RefCell::new(None),
+            cache: RwLock::new(None),
+            read_only: false,
         }
     }
+    /// A settings file that is never written, missing settings being treated
+    /// as defaults
+    pub fn new_read_only(path: PathBuf) -> Self {
+        SettingsFile {
+            read_only: true,
+            ..Self::new(path)
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
     fn write_settings(&self) -> Result<()> {
-        let s = self.cache.borrow().as_ref().unwrap().clone();
+        let s = self.cache.read().unwrap().as_ref().unwrap().clone();
         utils::write_file("settings", &self.path, &s.stringify())?;
         Ok(())
     }
     fn read_settings(&self) -> Result<()> {
+        if self.cache.read().unwrap().is_some() {
+            return Ok(());
+        }
         let mut needs_save = false;
         {
-            let mut b = self.cache.borrow_mut();
+            let mut b = self.cache.write().unwrap();
             if b.is_none() {
                 *b = Some(if utils::is_file(&self.path) {
                     let content = utils::read_file("settings", &self.path)?;
-                    Settings::parse(&content)?
+                    let mut table = toml::from_str(&content).map_err(ErrorKind::ParsingSettings)?;
+                    let migrated_from = migrate(&mut table)?;
+                    if let (Some(old), false) = (migrated_from, self.read_only) {
+                        // Keep the original in case the new version has to be
+                        // rolled back
+                        let backup = self.path.with_extension(format!("toml.v{}.bak", old));
+                        utils::copy_file(&self.path, &backup)?;
+                        needs_save = true;
+                    }
+                    Settings::from_toml(table, "")?
                 } else {
                     needs_save = true;
                     Default::default()
                 });
             }
         }
-        if needs_save {
+        if needs_save && !self.read_only {
             self.write_settings()?;
         }
         Ok(())
@@ -52,18 +122,351 @@ impl SettingsFile {
         self.read_settings()?;
 
         // Settings can no longer be None so it's OK to unwrap
-        f(self.cache.borrow().as_ref().unwrap())
+        f(self.cache.read().unwrap().as_ref().unwrap())
     }
     pub fn with_mut<T, F: FnOnce(&mut Settings) -> Result<T>>(&self, f: F) -> Result<T> {
+        if self.read_only {
+            return Err(ErrorKind::ReadOnly.into());
+        }
         self.read_settings()?;
 
         // Settings can no longer be None so it's OK to unwrap
-        let result = { f(self.cache.borrow_mut().as_mut().unwrap())? };
+        let result = { f(self.cache.write().unwrap().as_mut().unwrap())? };
         self.write_settings()?;
         Ok(result)
     }
 }
 
+/// The type of a setting's value as accepted by `elan config set`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SettingType {
+    Bool,
+    String,
+    Choice(&'static [&'static str]),
+    /// A toolchain name such as `stable`, validated by the caller
+    Toolchain,
+    /// A comma-separated list
+    List,
+    /// A comma-separated list of distinct choices
+    ChoiceList(&'static [&'static str]),
+    /// A positive integer
+    Integer,
+    /// A positive number of bytes, optionally with a binary unit such as `G`
+    Size,
+    /// An HTTP(S) or file URL, or `none` if allowed
+    Url {
+        allow_none: bool,
+    },
+    /// A comma-separated list of HTTP(S) or file URLs, most preferred first
+    UrlList,
+}
+
+/// A setting that can be managed with `elan config`
+#[derive(Debug)]
+pub struct SettingKey {
+    pub name: &'static str,
+    /// Whether the setting is a table, whose entries are addressed as
+    /// `<name>.<entry>`
+    pub is_table: bool,
+    pub ty: SettingType,
+    pub description: &'static str,
+}
+
+impl SettingKey {
+    pub fn display_name(&self) -> String {
+        if self.is_table {
+            format!("{}.<origin>", self.name)
+        } else {
+            self.name.to_owned()
+        }
+    }
+
+    /// Checks that `value` is valid for this setting, normalizing it
+    pub fn validate(&self, key: &str, value: &str) -> Result<String> {
+        let invalid = |expected: String| {
+            Error::from(ErrorKind::InvalidSettingValue(
+                key.to_owned(),
+                value.to_owned(),
+                expected,
+            ))
+        };
+        match self.ty {
+            SettingType::Bool => match value {
+                "true" | "yes" | "on" | "1" => Ok("true".to_owned()),
+                "false" | "no" | "off" | "0" => Ok("false".to_owned()),
+                _ => Err(invalid("'true' or 'false'".to_owned())),
+            },
+            SettingType::Choice(choices) if !choices.contains(&value) => Err(invalid(format!(
+                "one of {}",
+                choices
+                    .iter()
+                    .map(|c| format!("'{}'", c))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+            SettingType::List => {
+                let items: Vec<&str> = value
+                    .split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                if items.is_empty() {
+                    return Err(invalid("a comma-separated list".to_owned()));
+                }
+                Ok(items.join(","))
+            }
+            SettingType::ChoiceList(choices) => {
+                let items: Vec<&str> = value
+                    .split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .collect();
+                let valid = items
+                    .iter()
+                    .enumerate()
+                    .all(|(i, item)| choices.contains(item) && !items[..i].contains(item));
+                if items.is_empty() || !valid {
+                    return Err(invalid(format!(
+                        "a comma-separated list of distinct values from {}",
+                        choices
+                            .iter()
+                            .map(|c| format!("'{}'", c))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )));
+                }
+                Ok(items.join(","))
+            }
+            SettingType::Integer => match value.parse::<u64>() {
+                Ok(n) if n > 0 && n <= i64::MAX as u64 => Ok(n.to_string()),
+                _ => Err(invalid("a positive integer".to_owned())),
+            },
+            SettingType::Size => match parse_size(value) {
+                Some(n) if n > 0 => Ok(n.to_string()),
+                _ => Err(invalid(
+                    "a positive number of bytes such as '8192', '512M', or '16G'".to_owned(),
+                )),
+            },
+            SettingType::Url { allow_none } => match url::Url::parse(value) {
+                _ if allow_none && value == utils::NO_RELEASE_INDEX => Ok(value.to_owned()),
+                Ok(url) if ["https", "http", "file"].contains(&url.scheme()) => Ok(url.to_string()),
+                _ if allow_none => Err(invalid(format!(
+                    "an HTTP(S) or file URL or '{}'",
+                    utils::NO_RELEASE_INDEX
+                ))),
+                _ => Err(invalid("an HTTP(S) or file URL".to_owned())),
+            },
+            SettingType::UrlList => {
+                let urls = value
+                    .split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|url| match url::Url::parse(url) {
+                        Ok(url) if ["https", "http", "file"].contains(&url.scheme()) => {
+                            Some(url.to_string())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|urls| !urls.is_empty());
+                match urls {
+                    Some(urls) => Ok(urls.join(",")),
+                    None => Err(invalid(
+                        "a comma-separated list of HTTP(S) or file URLs".to_owned(),
+                    )),
+                }
+            }
+            _ if value.is_empty() => Err(invalid("a non-empty value".to_owned())),
+            _ => Ok(value.to_owned()),
+        }
+    }
+}
+
+/// Parses a number of bytes with an optional binary unit: K, M, G, or T,
+/// optionally followed by "iB" or "B"
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(digits);
+    let unit = unit.trim_start();
+    let unit = unit
+        .strip_suffix("iB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(unit);
+    let shift = match unit {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()?
+        .checked_mul(1 << shift)
+        .filter(|&n| n <= i64::MAX as u64)
+}
+
+fn get_opt_positive(table: &mut toml::value::Table, key: &str, path: &str) -> Result<Option<u64>> {
+    match get_opt_integer(table, key, path)? {
+        Some(n) if n > 0 => Ok(Some(n as u64)),
+        Some(_) => Err(elan_utils::Error::from(elan_utils::ErrorKind::ExpectedType(
+            "positive integer",
+            path.to_owned() + key,
+        ))
+        .into()),
+        None => Ok(None),
+    }
+}
+
+pub const SETTING_KEYS: &[SettingKey] = &[
+    SettingKey {
+        name: "default_toolchain",
+        is_table: false,
+        ty: SettingType::Toolchain,
+        description: "the toolchain used outside of projects",
+    },
+    SettingKey {
+        name: "default_target",
+        is_table: false,
+        ty: SettingType::String,
+        description: "the target triple to install toolchains for instead of the host's",
+    },
+    SettingKey {
+        name: "override_precedence",
+        is_table: false,
+        ty: SettingType::ChoiceList(OVERRIDE_SOURCE_NAMES),
+        description: "the sources of the active toolchain to consult, highest precedence first",
+    },
+    SettingKey {
+        name: "asset_targets",
+        is_table: true,
+        ty: SettingType::List,
+        description: "release asset targets to accept for an origin, most preferred first",
+    },
+    SettingKey {
+        name: "release_index",
+        is_table: true,
+        ty: SettingType::Url { allow_none: true },
+        description: "the release index to look up the latest releases of an origin in, or 'none'",
+    },
+    SettingKey {
+        name: endpoints::GITHUB.setting,
+        is_table: false,
+        ty: SettingType::UrlList,
+        description: endpoints::GITHUB.description,
+    },
+    SettingKey {
+        name: endpoints::GITHUB_API.setting,
+        is_table: false,
+        ty: SettingType::Url { allow_none: false },
+        description: endpoints::GITHUB_API.description,
+    },
+    SettingKey {
+        name: endpoints::GITHUB_RAW.setting,
+        is_table: false,
+        ty: SettingType::Url { allow_none: false },
+        description: endpoints::GITHUB_RAW.description,
+    },
+    SettingKey {
+        name: endpoints::UPDATE_ROOT.setting,
+        is_table: false,
+        ty: SettingType::Url { allow_none: false },
+        description: endpoints::UPDATE_ROOT.description,
+    },
+    SettingKey {
+        name: endpoints::IPFS_GATEWAY.setting,
+        is_table: false,
+        ty: SettingType::Url { allow_none: false },
+        description: endpoints::IPFS_GATEWAY.description,
+    },
+    SettingKey {
+        name: "max_extracted_size",
+        is_table: false,
+        ty: SettingType::Size,
+        description: "the maximum total size of the files extracted for a toolchain installation",
+    },
+    SettingKey {
+        name: "max_extracted_files",
+        is_table: false,
+        ty: SettingType::Integer,
+        description: "the maximum number of files extracted for a toolchain installation",
+    },
+    SettingKey {
+        name: "implicit_install_timeout",
+        is_table: false,
+        ty: SettingType::Integer,
+        description: "the number of seconds after which installations triggered by running a \
+                      binary of a missing toolchain are aborted",
+    },
+    SettingKey {
+        name: "size_probe",
+        is_table: false,
+        ty: SettingType::Bool,
+        description: "whether to ask servers for the size of a download before starting it",
+    },
+    SettingKey {
+        name: "peer_cache",
+        is_table: false,
+        ty: SettingType::Bool,
+        description: "whether to keep downloaded release archives and look for them at peers \
+                      on the local network first",
+    },
+    SettingKey {
+        name: "strip_patterns",
+        is_table: false,
+        ty: SettingType::List,
+        description: "the paths removed from toolchains by `elan toolchain strip` and \
+                      `--minimize`, as globs relative to the toolchain directory",
+    },
+    SettingKey {
+        name: "proxy_auth",
+        is_table: false,
+        ty: SettingType::Choice(download::PROXY_AUTH_METHODS),
+        description: "the authentication method for HTTP proxies",
+    },
+    SettingKey {
+        name: "proxy_link_mode",
+        is_table: false,
+        ty: SettingType::Choice(utils::LINK_MODES),
+        description: "how the tools in `ELAN_HOME/bin` are linked to the elan executable",
+    },
+    SettingKey {
+        name: "self_delete_strategy",
+        is_table: false,
+        ty: SettingType::Choice(&["auto", "delete-on-close", "script"]),
+        description: "how elan replaces or removes itself on Windows",
+    },
+    SettingKey {
+        name: "telemetry",
+        is_table: false,
+        ty: SettingType::Bool,
+        description: "whether telemetry is enabled",
+    },
+];
+
+fn is_endpoint(name: &str) -> bool {
+    endpoints::ENDPOINTS.iter().any(|e| e.setting == name)
+}
+
+/// Looks up the setting `key`, returning it together with the table entry
+/// addressed by `key`, if any
+pub fn lookup_setting_key(key: &str) -> Result<(&'static SettingKey, Option<&str>)> {
+    let (name, entry) = match key.split_once('.') {
+        Some((name, entry)) => (name, Some(entry)),
+        None => (key, None),
+    };
+    SETTING_KEYS
+        .iter()
+        .find(|k| k.name == name && k.is_table == entry.is_some())
+        .filter(|_| entry != Some(""))
+        .map(|k| (k, entry))
+        .ok_or_else(|| ErrorKind::UnknownSettingKey(key.to_owned()).into())
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TelemetryMode {
     On,
@@ -76,6 +479,56 @@ pub struct Settings {
     pub default_toolchain: Option<String>,
     pub overrides: BTreeMap<String, ToolchainDesc>,
     pub telemetry: TelemetryMode,
+    /// Changes made to the user's environment by `elan-init`, keyed by the modified rc file or
+    /// registry value, so that `elan self uninstall` can revert exactly those; `None` for
+    /// installations by versions of elan that did not record them
+    pub path_changes: Option<BTreeMap<String, String>>,
+    /// Windows registry keys below `HKEY_CURRENT_USER` that elan created, such as its entry in
+    /// the list of installed programs, with what they register; `elan self uninstall` deletes
+    /// each of them
+    pub registrations: BTreeMap<String, String>,
+    /// Release asset targets to accept for specific origins, most preferred first, replacing the
+    /// defaults for the host platform
+    pub asset_targets: BTreeMap<String, Vec<String>>,
+    /// Release index URLs for specific origins, or `none` to disable the index, overriding
+    /// `utils::default_release_indexes()`
+    pub release_indexes: BTreeMap<String, String>,
+    /// URLs of the services elan contacts, keyed by the setting names in
+    /// `endpoints::ENDPOINTS`
+    pub endpoints: BTreeMap<String, String>,
+    /// Target triple to install toolchains for instead of the host's
+    pub default_target: Option<String>,
+    /// How elan removes or replaces its own executable on Windows
+    pub self_delete_strategy: Option<String>,
+    /// Authentication method for HTTP proxies, e.g. `ntlm`
+    pub proxy_auth: Option<String>,
+    /// How the tools in `ELAN_HOME/bin` are linked to the elan executable,
+    /// one of `utils::LINK_MODES`
+    pub proxy_link_mode: Option<String>,
+    /// Keys not known to this version of elan, preserved when writing the
+    /// settings so that newer versions do not lose them
+    pub unknown: toml::value::Table,
+    /// Public keys that self-updates downloaded from a given `ELAN_UPDATE_ROOT` must be signed
+    /// with, keyed by the root URL without a trailing slash
+    pub trust_roots: BTreeMap<String, String>,
+    /// Sources of the active toolchain to consult, highest precedence first; sources not listed
+    /// are disabled
+    pub override_precedence: Option<Vec<String>>,
+    /// Maximum total size in bytes of the files extracted for a toolchain installation
+    pub max_extracted_size: Option<u64>,
+    /// Maximum number of files extracted for a toolchain installation
+    pub max_extracted_files: Option<u64>,
+    /// Number of seconds after which installations triggered by running a
+    /// binary of a missing toolchain are aborted
+    pub implicit_install_timeout: Option<u64>,
+    /// Whether downloads are preceded by a HEAD request for their size
+    pub size_probe: Option<bool>,
+    /// Whether release archives are kept in the download cache and fetched
+    /// from peers on the local network if possible
+    pub peer_cache: Option<bool>,
+    /// Globs of the paths removed from toolchains by `elan toolchain strip`
+    /// and `--minimize`, replacing `config::DEFAULT_STRIP_PATTERNS`
+    pub strip_patterns: Option<Vec<String>>,
 }
 
 impl Default for Settings {
@@ -85,6 +538,24 @@ impl Default for Settings {
             default_toolchain: None,
             overrides: BTreeMap::new(),
             telemetry: TelemetryMode::Off,
+            path_changes: None,
+            registrations: BTreeMap::new(),
+            asset_targets: BTreeMap::new(),
+            release_indexes: BTreeMap::new(),
+            endpoints: BTreeMap::new(),
+            default_target: None,
+            self_delete_strategy: None,
+            proxy_auth: None,
+            proxy_link_mode: None,
+            unknown: toml::value::Table::new(),
+            trust_roots: BTreeMap::new(),
+            override_precedence: None,
+            max_extracted_size: None,
+            max_extracted_files: None,
+            implicit_install_timeout: None,
+            size_probe: None,
+            peer_cache: None,
+            strip_patterns: None,
         }
     }
 }
@@ -96,10 +567,37 @@ impl Settings {
                 .display()
                 .to_string()
         } else {
-            path.display().to_string()
+            // Drops trailing separators and `.` components
+            path.components().collect::<PathBuf>().display().to_string()
         }
     }
 
+    /// Rewrites the keys of all overrides as `add_override` would, merging
+    /// overrides for the same directory; the first one in key order wins.
+    /// Returns the original keys that were rewritten or dropped, with their
+    /// new key if any.
+    pub fn merge_duplicate_overrides(
+        &mut self,
+        notify_handler: &dyn Fn(Notification<'_>),
+    ) -> Vec<(String, Option<String>)> {
+        let mut changes = vec![];
+        let mut merged = BTreeMap::new();
+        for (key, toolchain) in std::mem::take(&mut self.overrides) {
+            let new_key = Self::path_to_key(Path::new(&key), notify_handler);
+            match merged.entry(new_key) {
+                Entry::Occupied(_) => changes.push((key, None)),
+                Entry::Vacant(e) => {
+                    if *e.key() != key {
+                        changes.push((key, Some(e.key().clone())));
+                    }
+                    e.insert(toolchain);
+                }
+            }
+        }
+        self.overrides = merged;
+        changes
+    }
+
     pub fn remove_override(
         &mut self,
         path: &Path,
@@ -117,6 +615,10 @@ impl Settings {
     ) {
         let key = Self::path_to_key(path, notify_handler);
         notify_handler(Notification::SetOverrideToolchain(path, &toolchain));
+        // Drop entries for the same directory written under another spelling,
+        // e.g. before the directory existed
+        self.overrides
+            .retain(|k, _| Self::path_to_key(Path::new(k), &|_| {}) != key);
         self.overrides.insert(key, toolchain);
     }
 
@@ -129,8 +631,125 @@ impl Settings {
         self.overrides.get(&key).cloned()
     }
 
+    /// The value of the setting `key` as shown by `elan config get`
+    pub fn get_key(&self, key: &str) -> Result<Option<String>> {
+        let (setting, entry) = lookup_setting_key(key)?;
+        Ok(match (setting.name, entry) {
+            ("default_toolchain", _) => self.default_toolchain.clone(),
+            ("default_target", _) => self.default_target.clone(),
+            ("override_precedence", _) => self.override_precedence.as_ref().map(|s| s.join(",")),
+            ("max_extracted_size", _) => self.max_extracted_size.map(|n| n.to_string()),
+            ("max_extracted_files", _) => self.max_extracted_files.map(|n| n.to_string()),
+            ("implicit_install_timeout", _) => self.implicit_install_timeout.map(|n| n.to_string()),
+            ("size_probe", _) => self.size_probe.map(|b| b.to_string()),
+            ("peer_cache", _) => self.peer_cache.map(|b| b.to_string()),
+            ("strip_patterns", _) => self.strip_patterns.as_ref().map(|p| p.join(",")),
+            ("asset_targets", Some(origin)) => self.asset_targets.get(origin).map(|t| t.join(",")),
+            ("release_index", Some(origin)) => self.release_indexes.get(origin).cloned(),
+            (name, None) if is_endpoint(name) => self.endpoints.get(name).cloned(),
+            ("proxy_auth", _) => self.proxy_auth.clone(),
+            ("proxy_link_mode", _) => self.proxy_link_mode.clone(),
+            ("self_delete_strategy", _) => self.self_delete_strategy.clone(),
+            ("telemetry", _) => Some((self.telemetry == TelemetryMode::On).to_string()),
+            _ => unreachable!(),
+        })
+    }
+
+    /// Sets the setting `key` to the validated `value`, or resets it to its
+    /// default if `value` is `None`
+    pub fn set_key(&mut self, key: &str, value: Option<&str>) -> Result<()> {
+        let (setting, entry) = lookup_setting_key(key)?;
+        let value = value.map(|v| setting.validate(key, v)).transpose()?;
+        match (setting.name, entry) {
+            ("default_toolchain", _) => self.default_toolchain = value,
+            ("default_target", _) => self.default_target = value,
+            // validated above
+            ("max_extracted_size", _) => {
+                self.max_extracted_size = value.map(|v| v.parse().unwrap())
+            }
+            ("max_extracted_files", _) => {
+                self.max_extracted_files = value.map(|v| v.parse().unwrap())
+            }
+            ("implicit_install_timeout", _) => {
+                self.implicit_install_timeout = value.map(|v| v.parse().unwrap())
+            }
+            ("size_probe", _) => self.size_probe = value.map(|v| v == "true"),
+            ("peer_cache", _) => self.peer_cache = value.map(|v| v == "true"),
+            ("override_precedence", _) => {
+                self.override_precedence =
+                    value.map(|v| v.split(',').map(|s| s.to_owned()).collect())
+            }
+            ("strip_patterns", _) => {
+                self.strip_patterns = value.map(|v| v.split(',').map(|s| s.to_owned()).collect())
+            }
+            ("asset_targets", Some(origin)) => match value {
+                Some(v) => {
+                    let targets = v.split(',').map(|t| t.to_owned()).collect();
+                    self.asset_targets.insert(origin.to_owned(), targets);
+                }
+                None => {
+                    self.asset_targets.remove(origin);
+                }
+            },
+            ("release_index", Some(origin)) => match value {
+                Some(v) => {
+                    self.release_indexes.insert(origin.to_owned(), v);
+                }
+                None => {
+                    self.release_indexes.remove(origin);
+                }
+            },
+            (name, None) if is_endpoint(name) => match value {
+                Some(v) => {
+                    self.endpoints.insert(name.to_owned(), v);
+                }
+                None => {
+                    self.endpoints.remove(name);
+                }
+            },
+            ("proxy_auth", _) => self.proxy_auth = value,
+            ("proxy_link_mode", _) => self.proxy_link_mode = value,
+            ("self_delete_strategy", _) => self.self_delete_strategy = value,
+            ("telemetry", _) => {
+                self.telemetry = if value.as_deref() == Some("true") {
+                    TelemetryMode::On
+                } else {
+                    TelemetryMode::Off
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// All settings that `elan config` manages and are not at their
+    /// defaults, as key-value pairs
+    pub fn list_keys(&self) -> Vec<(String, String)> {
+        let mut result = vec![];
+        for setting in SETTING_KEYS {
+            if setting.is_table {
+                let origins: Vec<&String> = match setting.name {
+                    "asset_targets" => self.asset_targets.keys().collect(),
+                    _ => self.release_indexes.keys().collect(),
+                };
+                for origin in origins {
+                    let key = format!("{}.{}", setting.name, origin);
+                    if let Ok(Some(value)) = self.get_key(&key) {
+                        result.push((key, value));
+                    }
+                }
+            } else if let Ok(Some(value)) = self.get_key(setting.name) {
+                if !(setting.name == "telemetry" && value == "false") {
+                    result.push((setting.name.to_owned(), value));
+                }
+            }
+        }
+        result
+    }
+
     pub fn parse(data: &str) -> Result<Self> {
-        let value = toml::from_str(data).map_err(ErrorKind::ParsingSettings)?;
+        let mut value = toml::from_str(data).map_err(ErrorKind::ParsingSettings)?;
+        migrate(&mut value)?;
         Self::from_toml(value, "")
     }
     pub fn stringify(self) -> String {
@@ -151,10 +770,32 @@ impl Settings {
             } else {
                 TelemetryMode::Off
             },
+            path_changes: Self::table_to_path_changes(&mut table, path)?,
+            registrations: Self::table_to_registrations(&mut table, path)?,
+            asset_targets: Self::table_to_asset_targets(&mut table, path)?,
+            release_indexes: Self::table_to_release_indexes(&mut table, path)?,
+            endpoints: Self::table_to_endpoints(&mut table, path)?,
+            default_target: get_opt_string(&mut table, "default_target", path)?,
+            self_delete_strategy: get_opt_string(&mut table, "self_delete_strategy", path)?,
+            proxy_auth: get_opt_string(&mut table, "proxy_auth", path)?,
+            proxy_link_mode: get_opt_string(&mut table, "proxy_link_mode", path)?,
+            trust_roots: Self::table_to_trust_roots(&mut table, path)?,
+            override_precedence: get_opt_string_array(&mut table, "override_precedence", path)?,
+            max_extracted_size: get_opt_positive(&mut table, "max_extracted_size", path)?,
+            max_extracted_files: get_opt_positive(&mut table, "max_extracted_files", path)?,
+            implicit_install_timeout: get_opt_positive(
+                &mut table,
+                "implicit_install_timeout",
+                path,
+            )?,
+            size_probe: get_opt_bool(&mut table, "size_probe", path)?,
+            peer_cache: get_opt_bool(&mut table, "peer_cache", path)?,
+            strip_patterns: get_opt_string_array(&mut table, "strip_patterns", path)?,
+            unknown: table,
         })
     }
     pub fn to_toml(self) -> toml::value::Table {
-        let mut result = toml::value::Table::new();
+        let mut result = self.unknown;
 
         result.insert("version".to_owned(), toml::Value::String(self.version));
 
@@ -168,9 +809,225 @@ impl Settings {
         let telemetry = self.telemetry == TelemetryMode::On;
         result.insert("telemetry".to_owned(), toml::Value::Boolean(telemetry));
 
+        // Written even if empty, as that means there is nothing to revert
+        if let Some(path_changes) = self.path_changes {
+            let path_changes = path_changes
+                .into_iter()
+                .map(|(k, v)| (k, toml::Value::String(v)))
+                .collect();
+            result.insert("path_changes".to_owned(), toml::Value::Table(path_changes));
+        }
+
+        if !self.registrations.is_empty() {
+            let registrations = self
+                .registrations
+                .into_iter()
+                .map(|(k, v)| (k, toml::Value::String(v)))
+                .collect();
+            result.insert(
+                "registrations".to_owned(),
+                toml::Value::Table(registrations),
+            );
+        }
+
+        if let Some(v) = self.default_target {
+            result.insert("default_target".to_owned(), toml::Value::String(v));
+        }
+
+        if let Some(v) = self.self_delete_strategy {
+            result.insert("self_delete_strategy".to_owned(), toml::Value::String(v));
+        }
+
+        for (k, v) in self.endpoints {
+            result.insert(k, toml::Value::String(v));
+        }
+
+        if let Some(v) = self.proxy_auth {
+            result.insert("proxy_auth".to_owned(), toml::Value::String(v));
+        }
+
+        if let Some(v) = self.proxy_link_mode {
+            result.insert("proxy_link_mode".to_owned(), toml::Value::String(v));
+        }
+
+        if let Some(v) = self.max_extracted_size {
+            result.insert(
+                "max_extracted_size".to_owned(),
+                toml::Value::Integer(v as i64),
+            );
+        }
+
+        if let Some(v) = self.max_extracted_files {
+            result.insert(
+                "max_extracted_files".to_owned(),
+                toml::Value::Integer(v as i64),
+            );
+        }
+
+        if let Some(v) = self.implicit_install_timeout {
+            result.insert(
+                "implicit_install_timeout".to_owned(),
+                toml::Value::Integer(v as i64),
+            );
+        }
+
+        if let Some(v) = self.size_probe {
+            result.insert("size_probe".to_owned(), toml::Value::Boolean(v));
+        }
+
+        if let Some(v) = self.peer_cache {
+            result.insert("peer_cache".to_owned(), toml::Value::Boolean(v));
+        }
+
+        if let Some(v) = self.strip_patterns {
+            let v = v.into_iter().map(toml::Value::String).collect();
+            result.insert("strip_patterns".to_owned(), toml::Value::Array(v));
+        }
+
+        if let Some(v) = self.override_precedence {
+            let v = v.into_iter().map(toml::Value::String).collect();
+            result.insert("override_precedence".to_owned(), toml::Value::Array(v));
+        }
+
+        if !self.trust_roots.is_empty() {
+            let trust_roots = self
+                .trust_roots
+                .into_iter()
+                .map(|(k, v)| (k, toml::Value::String(v)))
+                .collect();
+            result.insert("trust_roots".to_owned(), toml::Value::Table(trust_roots));
+        }
+
+        if !self.asset_targets.is_empty() {
+            let asset_targets = self
+                .asset_targets
+                .into_iter()
+                .map(|(k, v)| {
+                    let v = v.into_iter().map(toml::Value::String).collect();
+                    (k, toml::Value::Array(v))
+                })
+                .collect();
+            result.insert(
+                "asset_targets".to_owned(),
+                toml::Value::Table(asset_targets),
+            );
+        }
+
+        if !self.release_indexes.is_empty() {
+            let release_indexes = self
+                .release_indexes
+                .into_iter()
+                .map(|(k, v)| (k, toml::Value::String(v)))
+                .collect();
+            result.insert(
+                "release_index".to_owned(),
+                toml::Value::Table(release_indexes),
+            );
+        }
+
         result
     }
 
+    fn table_to_path_changes(
+        table: &mut toml::value::Table,
+        path: &str,
+    ) -> Result<Option<BTreeMap<String, String>>> {
+        if !table.contains_key("path_changes") {
+            return Ok(None);
+        }
+        let mut result = BTreeMap::new();
+        let changes_table = get_table(table, "path_changes", path)?;
+
+        for (k, v) in changes_table {
+            if let toml::Value::String(t) = v {
+                result.insert(k, t);
+            }
+        }
+
+        Ok(Some(result))
+    }
+
+    fn table_to_registrations(
+        table: &mut toml::value::Table,
+        path: &str,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut result = BTreeMap::new();
+        let registrations_table = get_table(table, "registrations", path)?;
+
+        for (k, v) in registrations_table {
+            if let toml::Value::String(description) = v {
+                result.insert(k, description);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn table_to_trust_roots(
+        table: &mut toml::value::Table,
+        path: &str,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut result = BTreeMap::new();
+        let roots_table = get_table(table, "trust_roots", path)?;
+
+        for (k, v) in roots_table {
+            if let toml::Value::String(key) = v {
+                result.insert(k, key);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn table_to_asset_targets(
+        table: &mut toml::value::Table,
+        path: &str,
+    ) -> Result<BTreeMap<String, Vec<String>>> {
+        let mut result = BTreeMap::new();
+        let targets_table = get_table(table, "asset_targets", path)?;
+
+        for (k, v) in targets_table {
+            if let toml::Value::Array(a) = v {
+                let targets = a
+                    .into_iter()
+                    .filter_map(|t| t.as_str().map(|t| t.to_owned()))
+                    .collect();
+                result.insert(k, targets);
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn table_to_endpoints(
+        table: &mut toml::value::Table,
+        path: &str,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut result = BTreeMap::new();
+        for endpoint in endpoints::ENDPOINTS {
+            if let Some(url) = get_opt_string(table, endpoint.setting, path)? {
+                result.insert(endpoint.setting.to_owned(), url);
+            }
+        }
+        Ok(result)
+    }
+
+    fn table_to_release_indexes(
+        table: &mut toml::value::Table,
+        path: &str,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut result = BTreeMap::new();
+        let indexes_table = get_table(table, "release_index", path)?;
+
+        for (k, v) in indexes_table {
+            if let toml::Value::String(url) = v {
+                result.insert(k, url);
+            }
+        }
+
+        Ok(result)
+    }
+
     fn table_to_overrides(
         table: &mut toml::value::Table,
         path: &str,
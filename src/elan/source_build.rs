@@ -0,0 +1,70 @@
+//! Building toolchains from source for platforms without binary releases,
+//! following the Lean 4 build instructions
+
+use crate::errors::*;
+use elan_utils::{endpoints, utils};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Errors with a list of the missing tools needed for a build, if any
+pub fn check_prerequisites() -> Result<()> {
+    if cfg!(windows) {
+        return Err("building Lean from source is not supported on Windows".into());
+    }
+    let mut missing = vec![];
+    for tool in &["git", "cmake", "make"] {
+        if !utils::has_cmd(tool) {
+            missing.push(format!("`{}`", tool));
+        }
+    }
+    if utils::find_cmd(&["c++", "clang++", "g++"]).is_none() {
+        missing.push("a C++ compiler".to_owned());
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorKind::MissingBuildPrerequisites(missing).into())
+    }
+}
+
+/// Fetches `rev`, a branch, tag, or full commit hash, of the GitHub repository
+/// `origin` into `work_dir` and builds it, returning the directory of the
+/// built toolchain
+pub fn build(origin: &str, rev: &str, work_dir: &Path) -> Result<PathBuf> {
+    let src_dir = work_dir.join("src");
+    utils::ensure_dir_exists("source", &src_dir, &|_| {})?;
+    let git = |args: &[&str]| {
+        utils::cmd_status("git", Command::new("git").args(args).current_dir(&src_dir))
+    };
+    git(&["init", "--quiet"])?;
+    git(&[
+        "fetch",
+        "--depth=1",
+        &format!("{}/{}", endpoints::GITHUB.url(), origin),
+        rev,
+    ])?;
+    git(&["checkout", "--quiet", "FETCH_HEAD"])?;
+
+    let build_dir = src_dir.join("build").join("release");
+    utils::ensure_dir_exists("build", &build_dir, &|_| {})?;
+    utils::cmd_status(
+        "cmake",
+        Command::new("cmake")
+            .arg(&src_dir)
+            .arg("-DCMAKE_BUILD_TYPE=Release")
+            .current_dir(&build_dir),
+    )?;
+    let jobs = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    utils::cmd_status(
+        "make",
+        Command::new("make")
+            .arg(format!("-j{}", jobs))
+            .current_dir(&build_dir),
+    )?;
+
+    let stage1 = build_dir.join("stage1");
+    utils::assert_is_directory(&stage1.join("bin"))?;
+    Ok(stage1)
+}
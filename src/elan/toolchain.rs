@@ -1,25 +1,44 @@
 use crate::config::Cfg;
 use crate::env_var;
 use crate::errors::*;
+use crate::history::{self, Entry, Operation};
 use crate::install::{self, InstallMethod};
 use crate::notifications::*;
-use elan_dist::dist::ToolchainDesc;
+pub use elan_dist::dist::is_commit_hash;
+use elan_dist::dist::{InstallPlan, ToolchainDesc, DEFAULT_ORIGIN};
 use elan_dist::download::DownloadCfg;
 use elan_dist::manifest::Component;
-use elan_utils::utils;
+use elan_dist::prefix::InstallPrefix;
+use elan_dist::target::Targets;
 use elan_utils::utils::fetch_url;
-use itertools::Itertools;
+use elan_utils::{endpoints, interrupt, utils};
+use filetime::FileTime;
 
 use regex::Regex;
 use serde_derive::Serialize;
+use std::collections::HashMap;
 use std::env;
 use std::env::consts::EXE_SUFFIX;
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
-const DEFAULT_ORIGIN: &str = "leanprover/lean4";
+/// Directory in `ELAN_HOME` with a file per toolchain whose modification time
+/// records when the toolchain was last run
+const USAGE_DIR: &str = "usage";
+/// Directory in `ELAN_HOME` with a marker file per toolchain protected from removal
+const FROZEN_DIR: &str = "frozen";
+/// Longest chain of `<origin>:lean-toolchain` references that is followed
+const MAX_TOOLCHAIN_FILE_DEPTH: usize = 8;
+
+/// Toolchain names in the `lean-toolchain` files fetched by this process,
+/// keyed by origin
+static TOOLCHAIN_FILES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+/// Granularity of last-use updates, so that not every run has to write
+const LAST_USED_RESOLUTION: Duration = Duration::from_secs(60 * 60);
 
 /// A fully resolved reference to a toolchain which may or may not exist
 pub struct Toolchain<'a> {
@@ -29,6 +48,30 @@ pub struct Toolchain<'a> {
     dist_handler: Box<dyn Fn(elan_dist::Notification<'_>) + 'a>,
 }
 
+/// Reasons an installed toolchain cannot be run, as found by
+/// `Toolchain::problem`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolchainProblem {
+    /// The directory a linked toolchain points to no longer exists
+    DanglingLink(PathBuf),
+    /// The toolchain has no `bin/lean`
+    MissingLean,
+    /// Its installation was interrupted and not recovered
+    InterruptedInstall,
+}
+
+impl std::fmt::Display for ToolchainProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolchainProblem::DanglingLink(target) => {
+                write!(f, "linked directory '{}' is missing", target.display())
+            }
+            ToolchainProblem::MissingLean => write!(f, "`bin/lean` is missing"),
+            ToolchainProblem::InterruptedInstall => write!(f, "installation was interrupted"),
+        }
+    }
+}
+
 /// Used by the `list_component` function
 pub struct ComponentStatus {
     pub component: Component,
@@ -40,76 +83,277 @@ pub struct ComponentStatus {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct UnresolvedToolchainDesc(pub ToolchainDesc);
 
+/// Spelled-out names of the channels, accepted wherever a channel is
+pub const CHANNEL_ALIASES: &[(&str, &str)] = &[
+    ("latest-stable-release", "stable"),
+    ("latest-beta-release", "beta"),
+    ("latest-nightly-release", "nightly"),
+];
+
+/// The channel a release belongs to, judging by its name
+pub fn release_channel(release: &str) -> Option<&'static str> {
+    if release.starts_with("nightly-") {
+        return Some("nightly");
+    }
+    semver::Version::parse(release.trim_start_matches('v'))
+        .ok()
+        .map(|v| if v.pre.is_empty() { "stable" } else { "beta" })
+}
+
+/// Translates `git+https://github.com/<owner>/<repo>#<revision>` into the
+/// equivalent `<owner>/<repo>:<revision>`
+fn parse_git_spec(spec: &str) -> Result<String> {
+    let unsupported = || Error::from(ErrorKind::UnsupportedGitSpec(spec.to_owned()));
+    let url = url::Url::parse(&spec["git+".len()..]).map_err(|_| unsupported())?;
+    if url.scheme() != "https" || url.host_str() != Some("github.com") {
+        return Err(unsupported());
+    }
+    let rev = url
+        .fragment()
+        .filter(|r| !r.is_empty())
+        .ok_or_else(unsupported)?;
+    let repo = url.path().trim_matches('/').trim_end_matches(".git");
+    if repo.split('/').count() != 2 {
+        return Err(unsupported());
+    }
+    Ok(format!("{}:{}", repo, rev))
+}
+
+/// Where the release tag found for `commit` by `find_release_for_commit` is
+/// remembered. Tags are not expected to move, so the tags of `origin` only
+/// need to be listed the first time a commit is resolved.
+fn commit_release_path(cfg: &Cfg, origin: &str, commit: &str) -> PathBuf {
+    cfg.metadata_cache_dir()
+        .join("commits")
+        .join(origin.replace('/', "--"))
+        .join(commit)
+}
+
+/// The release tag of `origin` found for `commit` before, if any
+fn cached_release_for_commit(cfg: &Cfg, origin: &str, commit: &str) -> Option<String> {
+    let release = utils::read_file("release", &commit_release_path(cfg, origin, commit)).ok()?;
+    Some(release.trim().to_owned()).filter(|r| !r.is_empty())
+}
+
+/// Finds the release tag of `origin` pointing at the commit `commit`
+fn find_release_for_commit(cfg: &Cfg, origin: &str, commit: &str) -> Result<String> {
+    if let Some(release) = cached_release_for_commit(cfg, origin, commit) {
+        return Ok(release);
+    }
+    let release = list_release_for_commit(origin, commit)?;
+    if !cfg.read_only {
+        let path = commit_release_path(cfg, origin, commit);
+        // Only saves looking it up again
+        let _ = utils::ensure_dir_exists("cache", path.parent().unwrap(), &|_| {})
+            .and_then(|_| utils::write_file("release", &path, &release));
+    }
+    Ok(release)
+}
+
+/// Looks for the release tag of `origin` pointing at `commit` in its list of
+/// tags
+fn list_release_for_commit(origin: &str, commit: &str) -> Result<String> {
+    // Give up eventually on repositories with a huge number of tags
+    for page in 1..=20 {
+        let endpoint = format!("repos/{}/tags?per_page=100&page={}", origin, page);
+        let tags: serde_json::Value = serde_json::from_str(&utils::fetch_github_api(&endpoint)?)
+            .chain_err(|| "failed to parse tag list")?;
+        let tags = match tags.as_array() {
+            Some(tags) if !tags.is_empty() => tags,
+            _ => break,
+        };
+        for tag in tags {
+            if let (Some(name), Some(sha)) = (tag["name"].as_str(), tag["commit"]["sha"].as_str()) {
+                if sha.starts_with(commit) {
+                    return Ok(name.to_owned());
+                }
+            }
+        }
+    }
+    Err(ErrorKind::NoReleaseForCommit(origin.to_owned(), commit.to_owned()).into())
+}
+
 pub fn lookup_unresolved_toolchain_desc(cfg: &Cfg, name: &str) -> Result<UnresolvedToolchainDesc> {
+    let git_name;
+    let name = if name.starts_with("git+") {
+        git_name = parse_git_spec(name)?;
+        &git_name
+    } else {
+        name
+    };
     let pattern = r"^(?:([a-zA-Z0-9-_]+[/][a-zA-Z0-9-_]+)[:])?([a-zA-Z0-9-.]+)$";
 
     let re = Regex::new(pattern).unwrap();
     if let Some(c) = re.captures(name) {
         let mut release = c.get(2).unwrap().as_str().to_owned();
-        let local_tc = Toolchain::from(
-            cfg,
-            &ToolchainDesc::Local {
-                name: release.clone(),
-            },
-        );
-        if local_tc.exists() && local_tc.is_custom() {
-            return Ok(UnresolvedToolchainDesc(ToolchainDesc::Local {
-                name: release,
-            }));
-        }
-        let mut origin = c
-            .get(1)
-            .map(|s| s.as_str())
-            .unwrap_or(DEFAULT_ORIGIN)
-            .to_owned();
-        if release.starts_with("nightly") && !origin.ends_with("-nightly") {
-            origin = format!("{}-nightly", origin);
-        }
-        let mut from_channel = None;
-        if release == "lean-toolchain"
-            || release == "stable"
-            || release == "beta"
-            || release == "nightly"
-        {
-            from_channel = Some(release.to_string());
+        let local_desc = ToolchainDesc::Local {
+            name: release.clone(),
+        };
+        // Names such as ".." must not be looked up as paths
+        if local_desc.validate().is_ok() {
+            let local_tc = Toolchain::from(cfg, &local_desc);
+            if local_tc.exists() && local_tc.is_custom() {
+                return Ok(UnresolvedToolchainDesc(local_desc));
+            }
+        }
+        if let Some((_, channel)) = CHANNEL_ALIASES.iter().find(|(alias, _)| *alias == release) {
+            release = channel.to_string();
         }
-        if release.starts_with(char::is_numeric) {
-            release = format!("v{}", release)
+        let desc = ToolchainDesc::from_release_spec(c.get(1).map(|s| s.as_str()), &release);
+        if let ToolchainDesc::Remote { ref origin, .. } = desc {
+            if origin.split('/').any(|part| part == release)
+                || ["lean4", "lean4-nightly"].contains(&&*release)
+            {
+                // e.g. `lean4` or `leanprover/lean4:lean4`
+                let origin = match c.get(1) {
+                    Some(_) => origin.clone(),
+                    None if release.starts_with("lean4") => format!("leanprover/{}", release),
+                    None => DEFAULT_ORIGIN.to_owned(),
+                };
+                let suggestions = release_suggestions(&origin, "");
+                return Err(ErrorKind::OriginWithoutRelease(name.to_owned(), suggestions).into());
+            }
         }
-        Ok(UnresolvedToolchainDesc(ToolchainDesc::Remote {
-            origin,
-            release,
-            from_channel,
-        }))
+        Ok(UnresolvedToolchainDesc(desc))
     } else {
         Err(ErrorKind::InvalidToolchainName(name.to_string()).into())
     }
 }
 
-fn find_latest_local_toolchain(cfg: &Cfg, channel: &str) -> Option<ToolchainDesc> {
+/// Suggests toolchain names for `origin` in place of the nonexistent
+/// `release`: indexed releases starting with `release`, or else the channels
+/// and the newest indexed release. Failing to query the index only results in
+/// fewer suggestions.
+fn release_suggestions(origin: &str, release: &str) -> Vec<String> {
+    let releases = utils::fetch_indexed_releases(origin).unwrap_or_default();
+    let prefix = format!("v{}", release.trim_start_matches('v'));
+    let matching = releases
+        .iter()
+        .filter(|r| !release.is_empty() && r.starts_with(&prefix))
+        .take(3)
+        .map(|r| format!("{}:{}", origin, r))
+        .collect::<Vec<_>>();
+    if !matching.is_empty() {
+        return matching;
+    }
+    let channels: &[&str] = if origin.ends_with("-nightly") {
+        &["nightly"]
+    } else {
+        &["stable", "nightly"]
+    };
+    let official = origin.trim_end_matches("-nightly") == DEFAULT_ORIGIN;
+    let channels = channels.iter().map(|c| match official {
+        true => c.to_string(),
+        false => format!("{}:{}", origin, c),
+    });
+    channels
+        .chain(releases.first().map(|r| format!("{}:{}", origin, r)))
+        .collect()
+}
+
+/// Finds the newest installed release of `origin` in `channel` (stable, beta,
+/// or nightly)
+fn find_latest_local_toolchain(cfg: &Cfg, origin: &str, channel: &str) -> Option<ToolchainDesc> {
     let toolchains = cfg.list_toolchains().ok()?;
     let toolchains = toolchains.into_iter().filter_map(|tc| match tc {
-        ToolchainDesc::Remote { release: ref r, .. } => Some((tc.to_owned(), r.to_string())),
+        ToolchainDesc::Remote {
+            origin: ref o,
+            release: ref r,
+            ..
+        } if o == origin => Some((tc.to_owned(), r.to_string())),
         _ => None,
     });
-    let toolchains: Vec<_> = match channel {
+    match channel {
+        // Nightly dates sort lexicographically
         "nightly" => toolchains
             .filter(|t| t.1.starts_with("nightly-"))
-            .sorted_by_key(|t| t.1.to_string())
-            .map(|t| t.0)
-            .collect(),
+            .max_by(|a, b| a.1.cmp(&b.1))
+            .map(|t| t.0),
         _ => toolchains
             .filter_map(|t| {
-                semver::Version::parse(t.1.trim_start_matches("v"))
+                semver::Version::parse(t.1.trim_start_matches('v'))
                     .ok()
                     .filter(|v| (channel == "stable") == v.pre.is_empty())
                     .map(|v| (t.0, v))
             })
-            .sorted_by_key(|t| t.1.to_string())
-            .map(|t| t.0)
-            .collect(),
+            .max_by(|a, b| a.1.cmp(&b.1))
+            .map(|t| t.0),
+    }
+}
+
+/// Resolves `channel` of `origin` to its latest release, which is looked up
+/// online unless `no_net` is set. If that is impossible and `use_cache` is
+/// set, the newest installed release of the channel is used instead.
+fn resolve_channel(
+    cfg: &Cfg,
+    origin: &str,
+    channel: &str,
+    from_channel: &str,
+    no_net: bool,
+    use_cache: bool,
+) -> Result<ToolchainDesc> {
+    let latest = if no_net {
+        Err(elan_utils::Error::from(format!(
+            "cannot resolve channel '{}' under `--no-net`",
+            channel
+        )))
+    } else {
+        utils::fetch_latest_release_tag(origin, channel, no_net, &|n| {
+            (cfg.notify_handler)(n.into())
+        })
+    };
+    let err = match latest {
+        Ok(release) => {
+            return Ok(ToolchainDesc::Remote {
+                origin: origin.to_owned(),
+                release,
+                from_channel: Some(from_channel.to_owned()),
+            })
+        }
+        Err(e) => e,
     };
-    toolchains.into_iter().last()
+    match find_latest_local_toolchain(cfg, origin, channel).filter(|_| use_cache) {
+        Some(tc) => {
+            if no_net {
+                (cfg.notify_handler)(Notification::UsingCachedReleaseOffline(&tc));
+            } else {
+                (cfg.notify_handler)(Notification::UsingExistingRelease(&tc));
+            }
+            Ok(tc)
+        }
+        None => Err(err.into()),
+    }
+}
+
+/// Fetches the toolchain name in the `lean-toolchain` file of the default
+/// branch of `origin`, at most once per process
+fn fetch_toolchain_file(origin: &str) -> Result<String> {
+    if let Some(name) = TOOLCHAIN_FILES
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|files| files.get(origin))
+    {
+        return Ok(name.clone());
+    }
+    let toolchain_url = format!(
+        "{}/{}/HEAD/lean-toolchain",
+        endpoints::GITHUB_RAW.url(),
+        origin
+    );
+    let name =
+        utils::parse_toolchain_file(fetch_url(&toolchain_url)?.as_bytes()).map_err(|reason| {
+            elan_utils::Error::from(elan_utils::ErrorKind::InvalidToolchainFile(
+                toolchain_url.clone(),
+                reason,
+            ))
+        })?;
+    TOOLCHAIN_FILES
+        .lock()
+        .unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(origin.to_owned(), name.clone());
+    Ok(name)
 }
 
 pub fn resolve_toolchain_desc_ext(
@@ -117,6 +361,18 @@ pub fn resolve_toolchain_desc_ext(
     unresolved_tc: &UnresolvedToolchainDesc,
     no_net: bool,
     use_cache: bool,
+) -> Result<ToolchainDesc> {
+    resolve_toolchain_desc_rec(cfg, unresolved_tc, no_net, use_cache, &mut vec![])
+}
+
+/// `resolve_toolchain_desc_ext`, where `origins` are the origins whose
+/// `lean-toolchain` files led to `unresolved_tc`
+fn resolve_toolchain_desc_rec(
+    cfg: &Cfg,
+    unresolved_tc: &UnresolvedToolchainDesc,
+    no_net: bool,
+    use_cache: bool,
+    origins: &mut Vec<String>,
 ) -> Result<ToolchainDesc> {
     if let ToolchainDesc::Remote {
         ref origin,
@@ -125,35 +381,53 @@ pub fn resolve_toolchain_desc_ext(
     } = unresolved_tc.0
     {
         if release == "lean-toolchain" {
-            let toolchain_url = format!(
-                "https://raw.githubusercontent.com/{}/HEAD/lean-toolchain",
-                origin
-            );
-            resolve_toolchain_desc_ext(
+            if no_net {
+                return Err(format!(
+                    "cannot resolve '{}:lean-toolchain' under `--no-net`",
+                    origin
+                )
+                .into());
+            }
+            let cycle = origins.contains(origin);
+            origins.push(origin.clone());
+            if cycle {
+                return Err(ErrorKind::ToolchainFileCycle(origins.clone()).into());
+            }
+            if origins.len() > MAX_TOOLCHAIN_FILE_DEPTH {
+                return Err(ErrorKind::ToolchainFileTooDeep(origins.clone()).into());
+            }
+            let toolchain_name = fetch_toolchain_file(origin)?;
+            resolve_toolchain_desc_rec(
                 cfg,
-                &lookup_unresolved_toolchain_desc(cfg, fetch_url(&toolchain_url)?.trim())?,
+                &lookup_unresolved_toolchain_desc(cfg, &toolchain_name)?,
                 no_net,
                 use_cache,
+                origins,
             )
         } else if release == "stable" || release == "beta" || release == "nightly" {
-            match utils::fetch_latest_release_tag(origin, no_net) {
-                Ok(release) => Ok(ToolchainDesc::Remote {
-                    origin: origin.clone(),
-                    release,
-                    from_channel: Some(channel.clone()),
-                }),
-                Err(e) => {
-                    if let (true, Some(tc)) = (use_cache, find_latest_local_toolchain(cfg, release))
-                    {
-                        if !no_net {
-                            (cfg.notify_handler)(Notification::UsingExistingRelease(&tc));
-                        }
-                        Ok(tc)
-                    } else {
-                        Err(e)?
-                    }
-                }
+            resolve_channel(cfg, origin, release, channel, no_net, use_cache)
+        } else if is_commit_hash(release) {
+            // a toolchain built from source at this commit
+            let built = ToolchainDesc::Remote {
+                origin: origin.clone(),
+                release: release.clone(),
+                from_channel: None,
+            };
+            if Toolchain::from(cfg, &built).exists() {
+                return Ok(built);
             }
+            let release = if no_net {
+                cached_release_for_commit(cfg, origin, release).ok_or_else(|| {
+                    format!("cannot resolve commit '{}' under `--no-net`", release)
+                })?
+            } else {
+                find_release_for_commit(cfg, origin, release)?
+            };
+            Ok(ToolchainDesc::Remote {
+                origin: origin.clone(),
+                release,
+                from_channel: Some(channel.clone()),
+            })
         } else {
             Ok(unresolved_tc.0.clone())
         }
@@ -177,16 +451,13 @@ pub fn read_unresolved_toolchain_desc_from_file(
     cfg: &Cfg,
     toolchain_file: &Path,
 ) -> Result<UnresolvedToolchainDesc> {
-    let s = utils::read_file("toolchain file", toolchain_file)?;
-    if let Some(s) = s.lines().next() {
-        let toolchain_name = s.trim();
-        lookup_unresolved_toolchain_desc(cfg, toolchain_name)
-    } else {
-        Err(Error::from(format!(
-            "empty toolchain file '{}'",
+    let toolchain_name = utils::read_toolchain_file(toolchain_file)?;
+    lookup_unresolved_toolchain_desc(cfg, &toolchain_name).chain_err(|| {
+        format!(
+            "invalid toolchain in toolchain file '{}'",
             toolchain_file.display()
-        )))
-    }
+        )
+    })
 }
 
 pub fn read_toolchain_desc_from_file(cfg: &Cfg, toolchain_file: &Path) -> Result<ToolchainDesc> {
@@ -196,13 +467,75 @@ pub fn read_toolchain_desc_from_file(cfg: &Cfg, toolchain_file: &Path) -> Result
     )
 }
 
+/// Describes a running process whose executable is inside `path`, if any
+#[cfg(target_os = "linux")]
+fn running_process_using(path: &Path) -> Option<String> {
+    let path = std::fs::canonicalize(path).ok()?;
+    std::fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find_map(|e| {
+            let pid: u32 = e.file_name().to_str()?.parse().ok()?;
+            let exe = std::fs::read_link(e.path().join("exe")).ok()?;
+            if exe.starts_with(&path) {
+                Some(format!(
+                    "a running process (PID {}, '{}')",
+                    pid,
+                    exe.display()
+                ))
+            } else {
+                None
+            }
+        })
+}
+
+/// Describes a running process that has an executable or library inside
+/// `path` loaded, if any. Windows does not let us open such files without
+/// sharing them, so we try that for each of them without changing anything.
+#[cfg(windows)]
+fn running_process_using(path: &Path) -> Option<String> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    let mut dirs = vec![path.join("bin"), path.join("lib")];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                dirs.push(path);
+                continue;
+            }
+            let is_binary = path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("exe") || ext.eq_ignore_ascii_case("dll")
+            });
+            if !is_binary {
+                continue;
+            }
+            // Neither truncates nor creates the file
+            let result = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .share_mode(0)
+                .open(&path);
+            if result.is_err_and(|e| e.raw_os_error() == Some(ERROR_SHARING_VIOLATION)) {
+                return Some(format!("a running process using '{}'", path.display()));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn running_process_using(_: &Path) -> Option<String> {
+    None
+}
+
 impl<'a> Toolchain<'a> {
     pub fn from(cfg: &'a Cfg, desc: &ToolchainDesc) -> Self {
-        //We need to replace ":" and "/" with "-" in the toolchain name in order to make a name which is a valid
-        //name for a directory.
-        let dir_name = desc.to_string().replace("/", "--").replace(":", "---");
-
-        let path = cfg.toolchains_dir.join(&dir_name[..]);
+        let path = cfg.toolchains_dir.join(desc.to_toolchain_dir());
 
         Toolchain {
             cfg,
@@ -231,35 +564,303 @@ impl<'a> Toolchain<'a> {
     }
     pub fn is_custom(&self) -> bool {
         assert!(self.exists());
-        self.is_symlink()
+        self.is_symlink() || self.path.join(install::WRAPPED_PREFIX_FILE).is_file()
+    }
+    /// Why the installed toolchain cannot be run, if it cannot
+    pub fn problem(&self) -> Option<ToolchainProblem> {
+        if !self.exists() {
+            return None;
+        }
+        if self.is_symlink() && !self.path.exists() {
+            let target = std::fs::read_link(&self.path).unwrap_or_default();
+            return Some(ToolchainProblem::DanglingLink(target));
+        }
+        if elan_dist::dist::has_interrupted_install(&self.path) {
+            return Some(ToolchainProblem::InterruptedInstall);
+        }
+        if !utils::is_file(self.binary_file("lean")) {
+            return Some(ToolchainProblem::MissingLean);
+        }
+        None
+    }
+    /// How to fix the toolchain when `problem` reports one
+    pub fn repair_hint(&self) -> String {
+        if self.is_custom() {
+            format!(
+                "remove it with `elan toolchain uninstall {0}` and, if needed, link it \
+                 again with `elan toolchain link {0} <path>`",
+                self.desc
+            )
+        } else {
+            format!(
+                "reinstall it with `elan toolchain uninstall {0}` followed by \
+                 `elan toolchain install {0}`",
+                self.desc
+            )
+        }
     }
     pub fn verify(&self) -> Result<()> {
         Ok(utils::assert_is_directory(&self.path)?)
     }
+    fn usage_file(&self) -> PathBuf {
+        self.cfg
+            .elan_dir
+            .join(USAGE_DIR)
+            .join(self.path.file_name().unwrap())
+    }
+    fn frozen_file(&self) -> PathBuf {
+        self.cfg
+            .elan_dir
+            .join(FROZEN_DIR)
+            .join(self.path.file_name().unwrap())
+    }
+    /// Whether the toolchain is protected from removal without `--force`
+    pub fn is_frozen(&self) -> bool {
+        self.frozen_file().is_file()
+    }
+    pub fn set_frozen(&self, frozen: bool) -> Result<()> {
+        self.cfg.check_toolchains_writable()?;
+        let path = self.frozen_file();
+        if frozen {
+            if !self.exists() {
+                return Err(format!("toolchain '{}' is not installed", self.desc).into());
+            }
+            utils::ensure_dir_exists("frozen", path.parent().unwrap(), &|n| {
+                (self.cfg.notify_handler)(n.into())
+            })?;
+            utils::write_file("frozen", &path, "")?;
+        } else if path.is_file() {
+            utils::remove_file("frozen", &path)?;
+        }
+        Ok(())
+    }
+    /// When the toolchain was last run, if known
+    pub fn last_used(&self) -> Option<SystemTime> {
+        std::fs::metadata(self.usage_file())
+            .and_then(|m| m.modified())
+            .ok()
+    }
+    fn record_use(&self) {
+        if self.cfg.read_only {
+            return;
+        }
+        let recent = self
+            .last_used()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age < LAST_USED_RESOLUTION);
+        if !recent {
+            // Not worth failing the actual command over
+            let path = self.usage_file();
+            let _ = std::fs::create_dir_all(path.parent().unwrap()).and_then(|_| {
+                if path.is_file() {
+                    filetime::set_file_mtime(&path, FileTime::now())
+                } else {
+                    std::fs::File::create(&path).map(|_| ())
+                }
+            });
+        }
+    }
+    /// Describes why removing this toolchain would likely break something,
+    /// if it would
+    pub fn in_use_reason(&self) -> Result<Option<String>> {
+        if !self.exists() {
+            return Ok(None);
+        }
+        // Resolution errors must not prevent uninstalling
+        if self.cfg.resolve_default().ok().flatten().as_ref() == Some(&self.desc) {
+            return Ok(Some("the default toolchain".to_owned()));
+        }
+        let cwd = utils::current_dir()?;
+        if let Ok(Some((unresolved, reason))) = self.cfg.find_override(&cwd) {
+            if resolve_toolchain_desc(self.cfg, &unresolved).ok().as_ref() == Some(&self.desc) {
+                return Ok(Some(format!(
+                    "the active toolchain in the current directory ({})",
+                    reason
+                )));
+            }
+        }
+        Ok(self.running_process().map(|p| format!("in use by {}", p)))
+    }
+
+    /// Describes a running process using this toolchain, if any
+    pub fn running_process(&self) -> Option<String> {
+        if !self.exists() || self.is_custom() {
+            return None;
+        }
+        running_process_using(&self.path)
+    }
+
+    /// Fails if the toolchain is frozen or in use, i.e. should only be
+    /// removed with `--force`
+    pub fn check_removable(&self) -> Result<()> {
+        if self.is_frozen() {
+            return Err(ErrorKind::ToolchainFrozen(self.desc.clone()).into());
+        }
+        if let Some(reason) = self.in_use_reason()? {
+            return Err(ErrorKind::ToolchainInUse(self.desc.clone(), reason).into());
+        }
+        Ok(())
+    }
+
+    /// The files and directories `remove` deletes, for reporting dry runs
+    pub fn removed_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        if self.exists() || self.is_symlink() {
+            paths.push(self.path.clone());
+        }
+        for file in [self.usage_file(), self.frozen_file()] {
+            if file.exists() {
+                paths.push(file);
+            }
+        }
+        paths
+    }
+
     pub fn remove(&self) -> Result<()> {
+        self.remove_as(Operation::Uninstall)
+    }
+
+    /// Removes the toolchain because `elan toolchain gc` found it unused,
+    /// which the history records as such
+    pub fn remove_unused(&self) -> Result<()> {
+        self.remove_as(Operation::Gc)
+    }
+
+    /// Removes the toolchain, recording `operation` as the reason in the
+    /// history
+    pub fn remove_as(&self, operation: Operation) -> Result<()> {
         if self.exists() || self.is_symlink() {
             (self.cfg.notify_handler)(Notification::UninstallingToolchain(&self.desc));
         } else {
             (self.cfg.notify_handler)(Notification::ToolchainNotInstalled(&self.desc));
             return Ok(());
         }
+        self.cfg.check_toolchains_writable()?;
         let result = install::uninstall(&self.path, &|n| (self.cfg.notify_handler)(n.into()));
         if !self.exists() {
+            let _ = std::fs::remove_file(self.usage_file());
+            let _ = std::fs::remove_file(self.frozen_file());
             (self.cfg.notify_handler)(Notification::UninstalledToolchain(&self.desc));
+            history::record(
+                self.cfg,
+                Entry {
+                    toolchain: Some(self.desc.to_string()),
+                    ..Entry::new(operation)
+                },
+            );
         }
         result
     }
+    /// The files and directories of the installation that match any of
+    /// `patterns`, see `utils::glob_match`, as `/`-separated paths relative
+    /// to it together with their sizes. Symbolic links are not followed, and
+    /// the files elan keeps about the installation never match.
+    pub fn strippable_paths(&self, patterns: &[String]) -> Result<Vec<(String, u64)>> {
+        if let Some(p) = patterns
+            .iter()
+            .find(|p| p.starts_with('/') || p.contains('\\') || p.split('/').any(|c| c == ".."))
+        {
+            return Err(ErrorKind::InvalidStripPattern(p.clone()).into());
+        }
+
+        fn matching(
+            dir: &Path,
+            rel: &str,
+            patterns: &[String],
+            found: &mut Vec<(String, u64)>,
+        ) -> Result<()> {
+            for entry in utils::read_dir("toolchain", dir)? {
+                let entry = entry.chain_err(|| format!("could not read '{}'", dir.display()))?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if rel.is_empty() && name.starts_with("elan-") {
+                    continue;
+                }
+                let rel = format!("{}{}", rel, name);
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                if patterns.iter().any(|p| utils::glob_match(p, &rel)) {
+                    let size = if is_dir {
+                        utils::dir_size(&entry.path())?
+                    } else {
+                        entry.metadata().map_or(0, |m| m.len())
+                    };
+                    found.push((rel, size));
+                } else if is_dir {
+                    matching(&entry.path(), &format!("{}/", rel), patterns, found)?;
+                }
+            }
+            Ok(())
+        }
+        let mut found = vec![];
+        matching(&self.path, "", patterns, &mut found)?;
+        found.sort();
+        Ok(found)
+    }
+    /// Removes the paths `strippable_paths` returns and records them in the
+    /// install manifest so that they are not mistaken for missing files,
+    /// returning the number of bytes freed
+    pub fn strip(&self, patterns: &[String]) -> Result<u64> {
+        if self.is_custom() {
+            return Err(ErrorKind::StrippingLinkedToolchain(self.desc.clone()).into());
+        }
+        self.cfg.check_toolchains_writable()?;
+        let found = self.strippable_paths(patterns)?;
+        let mut freed = 0;
+        for (rel, size) in &found {
+            let path = self.path.join(rel);
+            let is_dir = std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+            if is_dir {
+                utils::remove_dir("toolchain", &path, &|n| (self.cfg.notify_handler)(n.into()))?;
+            } else {
+                utils::remove_file("toolchain", &path)?;
+            }
+            freed += size;
+        }
+        let paths: Vec<String> = found.into_iter().map(|(rel, _)| rel).collect();
+        elan_dist::dist::record_stripped(&InstallPrefix::from(self.path.clone()), &paths)?;
+        Ok(freed)
+    }
+    /// Moves the installation, including its usage and frozen markers, to
+    /// the directory of `to`
+    pub fn rename(&self, to: &Toolchain<'_>) -> Result<()> {
+        self.cfg.check_toolchains_writable()?;
+        utils::rename_dir("toolchain", &self.path, &to.path)?;
+        for (from, to) in [
+            (self.usage_file(), to.usage_file()),
+            (self.frozen_file(), to.frozen_file()),
+        ] {
+            if from.is_file() {
+                utils::rename_file("toolchain", &from, &to)?;
+            }
+        }
+        Ok(())
+    }
     fn install(&self, install_method: InstallMethod<'_>) -> Result<()> {
-        let exists = self.exists();
-        if exists {
-            return Err(format!("'{}' is already installed", self.desc).into());
-        } else {
-            (self.cfg.notify_handler)(Notification::InstallingToolchain(&self.desc));
+        let reinstall = matches!(install_method, InstallMethod::Dist(.., true, _));
+        if self.exists() && !reinstall {
+            return Err(ErrorKind::ToolchainAlreadyInstalled(self.desc.clone()).into());
+        }
+        if self.cfg.read_only && reinstall && self.exists() {
+            return Err(ErrorKind::ReadOnly.into());
         }
+        if self.cfg.read_only {
+            return Err(ErrorKind::ToolchainNotInstalledReadOnly(self.desc.clone()).into());
+        }
+        self.cfg.check_toolchains_writable()?;
+        (self.cfg.notify_handler)(Notification::InstallingToolchain(&self.desc));
         (self.cfg.notify_handler)(Notification::ToolchainDirectory(&self.path, &self.desc));
+        // Let SIGINT and SIGTERM abort the installation cleanly instead of
+        // leaving partial downloads and staging directories behind
+        let _signals = interrupt::catch();
         install_method.run(&self.path, &|n| (self.cfg.notify_handler)(n.into()))?;
 
         (self.cfg.notify_handler)(Notification::InstalledToolchain(&self.desc));
+        history::record(
+            self.cfg,
+            Entry {
+                toolchain: Some(self.desc.to_string()),
+                ..Entry::new(Operation::Install)
+            },
+        );
 
         Ok(())
     }
@@ -268,11 +869,67 @@ impl<'a> Toolchain<'a> {
         if !self.exists() {
             self.install(install_method)
         } else {
-            Ok(())
+            self.check_provenance()
         }
     }
 
-    fn download_cfg(&self) -> DownloadCfg<'_> {
+    /// The release asset targets accepted for this toolchain, most preferred
+    /// first. An explicitly requested target takes precedence over
+    /// `asset_targets` for the toolchain's origin, which takes precedence over
+    /// `default_target`.
+    fn asset_targets(&self, target: Option<&str>) -> Result<Targets> {
+        if let Some(target) = target {
+            return Ok(Targets::custom(elan_dist::target::targets_for(target)));
+        }
+        let origin = match &self.desc {
+            ToolchainDesc::Remote { origin, .. } => Some(origin),
+            ToolchainDesc::Local { .. } => None,
+        };
+        self.cfg.settings_file.with(|s| {
+            if let Some(targets) = origin.and_then(|o| s.asset_targets.get(o)) {
+                Ok(Targets::custom(targets.clone()))
+            } else if let Some(target) = &s.default_target {
+                Ok(Targets::custom(elan_dist::target::targets_for(target)))
+            } else {
+                Ok(Targets::host())
+            }
+        })
+    }
+
+    /// Fails if the installation directory contains a different toolchain
+    pub fn check_provenance(&self) -> Result<()> {
+        let prefix = InstallPrefix::from(self.path.clone());
+        Ok(elan_dist::dist::check_provenance(&prefix, &self.desc)?)
+    }
+
+    /// The target recorded at installation, if any
+    pub fn installed_target(&self) -> Option<String> {
+        let prefix = InstallPrefix::from(self.path.clone());
+        elan_dist::dist::installed_target(&prefix).ok().flatten()
+    }
+
+    /// When the toolchain was installed, as a Unix timestamp in seconds, if
+    /// recorded at installation
+    pub fn installed_at(&self) -> Option<u64> {
+        let prefix = InstallPrefix::from(self.path.clone());
+        elan_dist::dist::installed_at(&prefix).ok().flatten()
+    }
+
+    /// The SHA-256 hash of the archive the toolchain was installed from, if
+    /// recorded at installation
+    pub fn installed_checksum(&self) -> Option<String> {
+        let prefix = InstallPrefix::from(self.path.clone());
+        elan_dist::dist::installed_checksum(&prefix).ok().flatten()
+    }
+
+    /// The size of the toolchain in bytes as recorded whenever elan changes
+    /// it, which avoids walking the toolchain directory
+    pub fn installed_size(&self) -> Option<u64> {
+        let prefix = InstallPrefix::from(self.path.clone());
+        elan_dist::dist::installed_size(&prefix).ok().flatten()
+    }
+
+    pub(crate) fn download_cfg(&self) -> DownloadCfg<'_> {
         DownloadCfg {
             temp_cfg: &self.cfg.temp_cfg,
             notify_handler: &*self.dist_handler,
@@ -280,11 +937,113 @@ impl<'a> Toolchain<'a> {
     }
 
     pub fn install_from_dist(&self) -> Result<()> {
-        self.install(InstallMethod::Dist(&self.desc, self.download_cfg()))
+        self.install_from_dist_ext(&[], true, None, false)
+    }
+
+    /// Installs the toolchain together with the given optional components.
+    /// Unless `allow_emulation` is set, builds for other architectures are
+    /// never installed in place of a missing native build. `target` overrides
+    /// the configured or host target. With `reinstall`, an existing
+    /// installation is replaced once the new one is complete, and kept if
+    /// installing fails.
+    pub fn install_from_dist_ext(
+        &self,
+        components: &[Component],
+        allow_emulation: bool,
+        target: Option<&str>,
+        reinstall: bool,
+    ) -> Result<()> {
+        if reinstall && self.exists() && self.is_custom() {
+            return Err(ErrorKind::ReinstallingLinkedToolchain(self.desc.clone()).into());
+        }
+        let mut targets = self.asset_targets(target)?;
+        if !allow_emulation {
+            targets = targets.without_emulation();
+        }
+        self.suggest_releases(self.install(InstallMethod::Dist(
+            &self.desc,
+            components,
+            &targets,
+            self.download_cfg(),
+            reinstall,
+            self.cfg.extraction_limits,
+        )))
+    }
+
+    /// Determines the archives `install_from_dist_ext` would download with
+    /// the same arguments, without downloading them
+    pub fn plan_install_from_dist(
+        &self,
+        components: &[Component],
+        allow_emulation: bool,
+        target: Option<&str>,
+    ) -> Result<InstallPlan> {
+        let mut targets = self.asset_targets(target)?;
+        if !allow_emulation {
+            targets = targets.without_emulation();
+        }
+        self.suggest_releases(
+            elan_dist::dist::plan_install_from_dist(
+                self.download_cfg(),
+                &self.desc,
+                &InstallPrefix::from(self.path.clone()),
+                components,
+                &targets,
+            )
+            .map_err(Error::from),
+        )
+    }
+
+    /// Points out similarly named releases if `result` failed because the
+    /// release does not exist
+    fn suggest_releases<T>(&self, result: Result<T>) -> Result<T> {
+        match (&result, &self.desc) {
+            (
+                Err(e),
+                ToolchainDesc::Remote {
+                    origin, release, ..
+                },
+            ) if matches!(
+                e.kind(),
+                ErrorKind::Dist(elan_dist::ErrorKind::NonexistentRelease(_))
+            ) =>
+            {
+                let suggestions = release_suggestions(origin, release);
+                result.chain_err(|| ErrorKind::ReleaseNotFound(self.desc.clone(), suggestions))
+            }
+            _ => result,
+        }
+    }
+
+    /// Installs the toolchain because one of its binaries is being run, which
+    /// may happen without any visible UI, e.g. for an editor. Aborts
+    /// downloads once the `implicit_install_timeout` setting has passed so
+    /// that a stalled network does not block the caller indefinitely.
+    pub fn install_implicitly(&self) -> Result<()> {
+        let timeout = self.cfg.implicit_install_timeout()?;
+        // Timeouts too large to represent never pass
+        let deadline = Instant::now().checked_add(timeout);
+        download::curl::set_deadline(deadline);
+        let result = self.install_from_dist();
+        download::curl::set_deadline(None);
+        if result.is_err() && deadline.is_some_and(|d| Instant::now() >= d) {
+            return result.chain_err(|| {
+                ErrorKind::ImplicitInstallTimedOut(self.desc.clone(), timeout.as_secs())
+            });
+        }
+        result
     }
 
     pub fn install_from_dist_if_not_installed(&self) -> Result<()> {
-        self.install_if_not_installed(InstallMethod::Dist(&self.desc, self.download_cfg()))
+        let targets = self.asset_targets(None)?;
+        self.install_if_not_installed(InstallMethod::Dist(
+            &self.desc,
+            &[],
+            &targets,
+            self.download_cfg(),
+            false,
+            self.cfg.extraction_limits,
+        ))
     }
 
     pub fn install_from_dir(&self, src: &Path, link: bool) -> Result<()> {
@@ -304,6 +1063,49 @@ impl<'a> Toolchain<'a> {
         Ok(())
     }
 
+    /// Builds the toolchain from its origin's sources at its release, which
+    /// may be any Git revision
+    #[cfg(feature = "build-from-source")]
+    pub fn install_from_source(&self) -> Result<()> {
+        let (origin, rev) = match self.desc {
+            ToolchainDesc::Remote {
+                ref origin,
+                ref release,
+                ..
+            } => (origin, release),
+            ToolchainDesc::Local { .. } => {
+                return Err(format!("cannot build local toolchain '{}'", self.desc).into())
+            }
+        };
+        if self.exists() {
+            return Err(format!("'{}' is already installed", self.desc).into());
+        }
+        // Git servers only hand out commits by their full hash
+        if is_commit_hash(rev) && rev.len() != 40 {
+            return Err(ErrorKind::AbbreviatedCommitHash(rev.clone()).into());
+        }
+        crate::source_build::check_prerequisites()?;
+        (self.cfg.notify_handler)(Notification::BuildingToolchain(&self.desc));
+        let work_dir = self.cfg.temp_cfg.new_directory()?;
+        let stage1 = crate::source_build::build(origin, rev, &work_dir)?;
+        self.install(InstallMethod::Copy(&stage1))
+    }
+
+    /// Registers the Lean installation in `prefix` as this toolchain without
+    /// exposing anything else installed there. Windows lacks unprivileged
+    /// file symlinks, so the whole prefix is linked there instead.
+    pub fn install_from_prefix(&self, prefix: &Path, tools: &[&str]) -> Result<()> {
+        let lean_path = prefix.join("bin").join(format!("lean{}", EXE_SUFFIX));
+        utils::assert_is_file(&lean_path)?;
+
+        let prefix = &utils::to_absolute(prefix)?;
+        if cfg!(windows) {
+            self.install(InstallMethod::Link(prefix))
+        } else {
+            self.install(InstallMethod::Wrap(prefix, tools))
+        }
+    }
+
     pub fn create_command<T: AsRef<OsStr>>(&self, binary: T) -> Result<Command> {
         self.install_from_dist_if_not_installed()?;
 
@@ -311,7 +1113,7 @@ impl<'a> Toolchain<'a> {
         let path = if utils::is_file(&bin_path) {
             &bin_path
         } else {
-            let recursion_count = env::var("LEAN_RECURSION_COUNT")
+            let recursion_count = env::var(env_var::LEAN_RECURSION_COUNT)
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(0);
@@ -332,16 +1134,27 @@ impl<'a> Toolchain<'a> {
             cmd = Command::new(path);
         };
         self.set_env(&mut cmd);
+        self.record_use();
         Ok(cmd)
     }
 
     fn set_env(&self, cmd: &mut Command) {
         self.set_path(cmd);
 
-        env_var::inc("LEAN_RECURSION_COUNT", cmd);
+        env_var::inc(env_var::LEAN_RECURSION_COUNT, cmd);
 
-        cmd.env("ELAN_TOOLCHAIN", self.name());
-        cmd.env("ELAN_HOME", &self.cfg.elan_dir);
+        cmd.env(env_var::ELAN_TOOLCHAIN, self.name());
+        cmd.env(env_var::ELAN_HOME, &self.cfg.elan_dir);
+        for (var, dir) in crate::artifact_cache::env_vars(self.cfg, &self.desc) {
+            cmd.env(var, dir);
+        }
+        // Only meant for the proxy invocation itself, not for any elan
+        // binaries the tool runs in turn
+        cmd.env_remove(env_var::ELAN_PROXY_NAME);
+        if self.cfg.read_only {
+            // Make nested proxy invocations read-only as well
+            cmd.env(env_var::ELAN_READ_ONLY, "1");
+        }
     }
 
     pub fn set_path(&self, cmd: &mut Command) {
@@ -350,7 +1163,9 @@ impl<'a> Toolchain<'a> {
         // proxy bins don't exist. We'll just be running whatever happens to
         // be on the PATH.
         let mut path_entries = vec![];
-        if let Ok(elan_home) = utils::elan_home() {
+        if let Some(system_home) = utils::elan_system_home() {
+            path_entries.push(system_home.join("bin"));
+        } else if let Ok(elan_home) = utils::elan_home() {
             path_entries.push(elan_home.join("bin").to_path_buf());
         }
 
@@ -379,11 +1194,92 @@ impl<'a> Toolchain<'a> {
         Ok(utils::open_browser(&self.doc_path(relative)?)?)
     }
 
+    fn prefix(&self) -> Result<InstallPrefix> {
+        if !self.exists() {
+            return Err(format!("toolchain '{}' is not installed", self.desc).into());
+        }
+        Ok(InstallPrefix::from(self.path.clone()))
+    }
+
+    /// Lists the installed components of this toolchain together with the
+    /// optional components published for its release
+    pub fn list_components(&self) -> Result<Vec<ComponentStatus>> {
+        let prefix = self.prefix()?;
+        let installed = elan_dist::dist::installed_components(&prefix)?;
+        let available = elan_dist::dist::available_components(
+            self.download_cfg(),
+            &self.desc,
+            &prefix,
+            &self.asset_targets(None)?,
+        )?;
+        let mut components = available
+            .iter()
+            .chain(installed.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+        components.sort();
+        components.dedup();
+        Ok(components
+            .into_iter()
+            .map(|component| ComponentStatus {
+                required: false,
+                installed: installed.contains(&component),
+                available: available.contains(&component),
+                component,
+            })
+            .collect())
+    }
+
+    pub fn add_component(&self, component: Component) -> Result<()> {
+        let prefix = self.prefix()?;
+        Ok(elan_dist::dist::install_component(
+            self.download_cfg(),
+            &self.desc,
+            &prefix,
+            &component,
+            &self.asset_targets(None)?,
+            self.cfg.extraction_limits,
+        )?)
+    }
+
+    /// Adds those of `components` that are not installed yet
+    pub fn add_missing_components(&self, components: &[Component]) -> Result<()> {
+        let installed = elan_dist::dist::installed_components(&self.prefix()?)?;
+        for component in components {
+            if !installed.contains(component) {
+                self.add_component(component.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_component(&self, component: Component) -> Result<()> {
+        let prefix = self.prefix()?;
+        Ok(elan_dist::dist::remove_component(
+            &prefix,
+            &component,
+            &*self.dist_handler,
+        )?)
+    }
+
     pub fn make_override(&self, path: &Path) -> Result<()> {
-        self.cfg.settings_file.with_mut(|s| {
+        let previous = self.cfg.settings_file.with_mut(|s| {
+            let previous = s.dir_override(path, self.cfg.notify_handler.as_ref());
             s.add_override(path, self.desc.clone(), self.cfg.notify_handler.as_ref());
-            Ok(())
-        })
+            Ok(previous)
+        })?;
+        if previous.as_ref() != Some(&self.desc) {
+            history::record(
+                self.cfg,
+                Entry {
+                    toolchain: Some(self.desc.to_string()),
+                    previous: previous.map(|p| p.to_string()),
+                    path: Some(path.display().to_string()),
+                    ..Entry::new(Operation::OverrideSet)
+                },
+            );
+        }
+        Ok(())
     }
 
     pub fn binary_file<T: AsRef<OsStr>>(&self, binary: T) -> PathBuf {
@@ -413,3 +1309,54 @@ impl<'a> Toolchain<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_toolchain_file_references() {
+        let home = tempfile::tempdir().unwrap();
+        env::set_var("ELAN_HOME", home.path());
+        let cfg = Cfg::from_env(Arc::new(|_| ())).unwrap();
+        // Pretend that these files have been fetched already
+        let mut references = vec![
+            ("a/x".to_owned(), "b/y:lean-toolchain".to_owned()),
+            ("b/y".to_owned(), "a/x:lean-toolchain".to_owned()),
+            ("c/z".to_owned(), "d/w:lean-toolchain".to_owned()),
+            ("d/w".to_owned(), "leanprover/lean4:v4.9.0".to_owned()),
+        ];
+        for i in 0..9 {
+            references.push((
+                format!("chain/r{}", i),
+                format!("chain/r{}:lean-toolchain", i + 1),
+            ));
+        }
+        *TOOLCHAIN_FILES.lock().unwrap() = Some(references.into_iter().collect());
+        let resolve = |name: &str| {
+            resolve_toolchain_desc_ext(
+                &cfg,
+                &lookup_unresolved_toolchain_desc(&cfg, name).unwrap(),
+                false,
+                false,
+            )
+        };
+
+        assert_eq!(
+            resolve("a/x:lean-toolchain").unwrap_err().to_string(),
+            "the `lean-toolchain` files of these repositories refer to each other in a cycle: \
+             a/x:lean-toolchain -> b/y:lean-toolchain -> a/x:lean-toolchain"
+        );
+        assert_eq!(
+            resolve("c/z:lean-toolchain").unwrap().to_string(),
+            "leanprover/lean4:v4.9.0"
+        );
+        let error = resolve("chain/r0:lean-toolchain").unwrap_err().to_string();
+        assert!(
+            error.starts_with("stopped following `lean-toolchain` files after 8 repositories"),
+            "{}",
+            error
+        );
+    }
+}
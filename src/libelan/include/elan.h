@@ -0,0 +1,38 @@
+/* C interface to elan; see src/libelan/src/lib.rs for details. */
+
+#ifndef ELAN_H
+#define ELAN_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Called with a JSON progress report that is only valid during the call. */
+typedef void (*elan_progress_callback)(const char *report, void *user_data);
+
+/* Returns the elan version; must not be freed. */
+const char *elan_version(void);
+
+/*
+ * All of the following functions take their parameters as a JSON object
+ * (or NULL for no parameters) and return a JSON string of the form
+ * {"result": ...} or {"error": {"code": ..., "message": ...}} that must be
+ * released with elan_string_free. `progress` may be NULL.
+ */
+
+/* {"toolchain": name} or {"dir": path} */
+char *elan_resolve(const char *params, elan_progress_callback progress, void *user_data);
+/* {"command": binary, "dir": path} */
+char *elan_which(const char *params, elan_progress_callback progress, void *user_data);
+/* {} */
+char *elan_list(const char *params, elan_progress_callback progress, void *user_data);
+/* {"toolchain": name} */
+char *elan_install(const char *params, elan_progress_callback progress, void *user_data);
+
+void elan_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ELAN_H */
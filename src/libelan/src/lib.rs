@@ -0,0 +1,265 @@
+//! C ABI for embedding elan in non-Rust tools such as editor extensions
+//!
+//! Each of `elan_resolve`, `elan_which`, `elan_list`, and `elan_install`
+//! takes its parameters as a JSON object, with the same fields as the
+//! corresponding `elan daemon` method, and returns a newly allocated JSON
+//! string that must be released with `elan_string_free`. The string is
+//! either `{"result": ...}` or `{"error": {"code": ..., "message": ...}}`,
+//! using the JSON-RPC error codes of the daemon. If `progress` is not null,
+//! it is called on the calling thread with each progress report of the
+//! daemon's `progress` notifications as a JSON string that is only valid
+//! during the call. The configuration is read from the environment on every
+//! call, just like for a new elan process. See `include/elan.h` for the C
+//! declarations.
+
+#![deny(rust_2018_idioms)]
+
+use elan_core::api::{dispatch, ProgressEncoder, RequestError, INTERNAL_ERROR, INVALID_PARAMS};
+use elan_core::{Cfg, Notification};
+use serde_json::{json, Value};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+pub type ProgressCallback = Option<unsafe extern "C" fn(*const c_char, *mut c_void)>;
+
+/// Delivers progress reports to a C callback, which callers expect to run on
+/// the thread that called into the library. Reports raised on other threads
+/// are queued and delivered by the calling thread.
+struct ProgressSink {
+    callback: unsafe extern "C" fn(*const c_char, *mut c_void),
+    user_data: *mut c_void,
+    thread: ThreadId,
+    sender: mpsc::Sender<Value>,
+    queue: Mutex<mpsc::Receiver<Value>>,
+}
+
+// SAFETY: `callback` and `user_data` are only used on `thread`; see `flush`
+unsafe impl Send for ProgressSink {}
+unsafe impl Sync for ProgressSink {}
+
+impl ProgressSink {
+    fn new(
+        callback: unsafe extern "C" fn(*const c_char, *mut c_void),
+        user_data: *mut c_void,
+    ) -> Self {
+        let (sender, queue) = mpsc::channel();
+        ProgressSink {
+            callback,
+            user_data,
+            thread: thread::current().id(),
+            sender,
+            queue: Mutex::new(queue),
+        }
+    }
+
+    fn report(&self, report: Value) {
+        // Cannot fail as `self` holds the receiver
+        let _ = self.sender.send(report);
+        self.flush();
+    }
+
+    /// Delivers the queued reports, unless called on another thread than the
+    /// calling one
+    fn flush(&self) {
+        if thread::current().id() != self.thread {
+            return;
+        }
+        for report in self.queue.lock().unwrap().try_iter() {
+            let report = into_c_string(&report);
+            unsafe {
+                (self.callback)(report, self.user_data);
+                elan_string_free(report);
+            }
+        }
+    }
+}
+
+static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Returns the elan version as a static string that must not be freed.
+#[no_mangle]
+pub extern "C" fn elan_version() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+/// Resolves a toolchain name (`toolchain`) or the toolchain active in a
+/// directory (`dir`, defaulting to the current directory).
+///
+/// # Safety
+///
+/// `params` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn elan_resolve(
+    params: *const c_char,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    call("resolve", params, progress, user_data)
+}
+
+/// Returns the path of binary `command` in the toolchain active in `dir`,
+/// installing the toolchain if necessary.
+///
+/// # Safety
+///
+/// `params` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn elan_which(
+    params: *const c_char,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    call("which", params, progress, user_data)
+}
+
+/// Lists the installed toolchains.
+///
+/// # Safety
+///
+/// `params` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn elan_list(
+    params: *const c_char,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    call("list", params, progress, user_data)
+}
+
+/// Installs toolchain `toolchain` unless it is already installed.
+///
+/// # Safety
+///
+/// `params` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn elan_install(
+    params: *const c_char,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    call("install", params, progress, user_data)
+}
+
+/// Releases a string returned by one of the functions above.
+///
+/// # Safety
+///
+/// `s` must be null or a string returned by this library that has not been
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn elan_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+unsafe fn call(
+    method: &str,
+    params: *const c_char,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> *mut c_char {
+    let params = if params.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(params).to_bytes())
+    };
+    // Unwinding into foreign code is undefined behavior
+    let response = panic::catch_unwind(AssertUnwindSafe(|| {
+        match run(method, params, progress, user_data) {
+            Ok(result) => json!({ "result": result }),
+            Err((code, message)) => json!({ "error": { "code": code, "message": message } }),
+        }
+    }))
+    .unwrap_or_else(|_| json!({ "error": { "code": INTERNAL_ERROR, "message": "elan panicked" } }));
+    into_c_string(&response)
+}
+
+fn run(
+    method: &str,
+    params: Option<&[u8]>,
+    progress: ProgressCallback,
+    user_data: *mut c_void,
+) -> Result<Value, (i64, String)> {
+    let params = match params {
+        Some(params) => serde_json::from_slice(params)
+            .map_err(|e| (INVALID_PARAMS, format!("invalid parameters: {}", e)))?,
+        None => json!({}),
+    };
+    let encoder = ProgressEncoder::default();
+    let sink = progress.map(|callback| Arc::new(ProgressSink::new(callback, user_data)));
+    let handler_sink = sink.clone();
+    let cfg = Cfg::from_env(Arc::new(move |n: Notification<'_>| {
+        if let (Some(sink), Some(report)) = (&handler_sink, encoder.encode(&n)) {
+            sink.report(report);
+        }
+    }))
+    .map_err(|e| RequestError::Elan(e).describe())?;
+    let result = dispatch(&cfg, method, &params).map_err(|e| e.describe());
+    if let Some(sink) = sink {
+        sink.flush();
+    }
+    result
+}
+
+fn into_c_string(value: &Value) -> *mut c_char {
+    // Serialized JSON escapes NUL characters, so this cannot fail
+    CString::new(value.to_string())
+        .expect("JSON contains NUL")
+        .into_raw()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn call_str(method: &str, params: &str) -> Value {
+        let params = CString::new(params).unwrap();
+        let response = call(method, params.as_ptr(), None, std::ptr::null_mut());
+        let value = serde_json::from_slice(CStr::from_ptr(response).to_bytes()).unwrap();
+        elan_string_free(response);
+        value
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        unsafe {
+            let response = call_str("list", "{");
+            assert_eq!(response["error"]["code"], INVALID_PARAMS);
+        }
+    }
+
+    /// Records each report together with the thread it was delivered on
+    unsafe extern "C" fn record(report: *const c_char, user_data: *mut c_void) {
+        let reports = &*(user_data as *const Mutex<Vec<(String, ThreadId)>>);
+        let report = CStr::from_ptr(report).to_str().unwrap().to_owned();
+        reports
+            .lock()
+            .unwrap()
+            .push((report, thread::current().id()));
+    }
+
+    #[test]
+    fn test_progress_sink() {
+        let reports: Mutex<Vec<(String, ThreadId)>> = Mutex::new(vec![]);
+        let sink = Arc::new(ProgressSink::new(
+            record,
+            &reports as *const _ as *mut c_void,
+        ));
+        let worker = sink.clone();
+        thread::spawn(move || worker.report(json!(1)))
+            .join()
+            .unwrap();
+        // Reports of other threads wait for the calling thread
+        assert!(reports.lock().unwrap().is_empty());
+        sink.report(json!(2));
+        let main = thread::current().id();
+        assert_eq!(
+            *reports.lock().unwrap(),
+            [("1".to_owned(), main), ("2".to_owned(), main)]
+        );
+    }
+}
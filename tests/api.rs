@@ -0,0 +1,147 @@
+//! Pins the output of `elan api`, which is a stable interface that scripts
+//! may rely on. Only linked toolchains and fake installations are used so
+//! that no network access is required.
+
+mod common;
+
+use common::Env;
+use serde_json::{json, Value};
+use std::env::consts::EXE_SUFFIX;
+use std::path::Path;
+
+fn str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn list() {
+    let env = Env::new();
+    assert_eq!(env.ok("list", json!({})), json!({ "toolchains": [] }));
+    env.link("b");
+    env.link("a");
+    assert_eq!(
+        env.ok("list", json!({})),
+        json!({ "toolchains": [
+            { "name": "a", "path": env.toolchain_path("a") },
+            { "name": "b", "path": env.toolchain_path("b") },
+        ] })
+    );
+}
+
+#[test]
+fn resolve_and_which() {
+    let env = Env::new();
+    env.link("a");
+    assert_eq!(
+        env.ok("resolve", json!({})),
+        json!({ "toolchain": null, "reason": null })
+    );
+    assert_eq!(
+        env.ok("resolve", json!({ "toolchain": "a" })),
+        json!({ "toolchain": "a", "reason": null })
+    );
+    env.run(&["default", "a"]);
+    assert_eq!(
+        env.ok("resolve", json!({})),
+        json!({ "toolchain": "a", "reason": "default toolchain" })
+    );
+
+    env.link("b");
+    let project = env.project("b");
+    assert_eq!(
+        env.ok("resolve", json!({ "dir": str(&project) })),
+        json!({
+            "toolchain": "b",
+            "reason": format!("overridden by '{}'", str(&project.join("lean-toolchain"))),
+        })
+    );
+    assert_eq!(
+        env.ok("which", json!({ "command": "lean", "dir": str(&project) })),
+        json!({ "path": env.path(&format!("home/.elan/toolchains/b/bin/lean{}", EXE_SUFFIX)) })
+    );
+}
+
+#[test]
+fn install_and_uninstall() {
+    let env = Env::new();
+    env.link("a");
+    assert_eq!(
+        env.ok("install", json!({ "toolchain": "a" })),
+        json!({ "toolchain": "a", "installed": false })
+    );
+
+    env.run(&["toolchain", "freeze", "a"]);
+    assert_eq!(
+        env.err("uninstall", json!({ "toolchain": "a" })),
+        json!({
+            "code": -32000,
+            "message": "toolchain 'a' is frozen; pass `--force` to remove it anyway \
+                        or unfreeze it with `elan toolchain unfreeze a`",
+        })
+    );
+    assert_eq!(
+        env.ok("uninstall", json!({ "toolchain": "a", "force": true })),
+        json!({ "toolchain": "a", "uninstalled": true })
+    );
+    assert_eq!(env.ok("list", json!({})), json!({ "toolchains": [] }));
+}
+
+#[test]
+fn gc() {
+    let env = Env::new();
+    env.link("a");
+    env.fake_install("leanprover--lean4---v4.0.0");
+    env.fake_install("leanprover--lean4---v4.1.0");
+    env.run(&["default", "a"]);
+    env.run(&["toolchain", "freeze", "leanprover/lean4:v4.1.0"]);
+    let expected = |deleted| {
+        json!({
+            "unused_toolchains": ["leanprover/lean4:v4.0.0"],
+            "frozen_toolchains": ["leanprover/lean4:v4.1.0"],
+            "used_toolchains": [{ "user": "default toolchain", "toolchain": "a" }],
+            "removed_paths": [env.toolchain_path("leanprover--lean4---v4.0.0")],
+            "deleted": deleted,
+        })
+    };
+    assert_eq!(env.ok("gc", json!({})), expected(false));
+    assert_eq!(env.ok("gc", json!({ "delete": true })), expected(true));
+    assert_eq!(
+        env.ok("list", json!({})),
+        json!({ "toolchains": [
+            { "name": "a", "path": env.toolchain_path("a") },
+            {
+                "name": "leanprover/lean4:v4.1.0",
+                "path": env.toolchain_path("leanprover--lean4---v4.1.0"),
+            },
+        ] })
+    );
+}
+
+#[test]
+fn errors() {
+    let env = Env::new();
+    assert_eq!(
+        env.err("which", json!({})),
+        json!({ "code": -32602, "message": "missing string parameter 'command'" })
+    );
+    assert_eq!(
+        env.err("gc", json!({ "delete": "yes" })),
+        json!({ "code": -32602, "message": "parameter 'delete' must be a boolean" })
+    );
+
+    let output = env
+        .elan(&["api", "--api-version", "2", "list"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        serde_json::from_slice::<Value>(&output.stdout).unwrap(),
+        json!({
+            "api_version": 1,
+            "error": {
+                "code": -32600,
+                "message": "unsupported API version '2'; this elan implements version 1",
+            },
+        })
+    );
+}
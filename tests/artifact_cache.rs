@@ -0,0 +1,201 @@
+//! Checks the artifact caches that projects declare in `elan-artifacts.toml`
+//! and `elan cache fetch` fetches.
+
+mod common;
+
+use common::Env;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+fn sha256(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Serves `files` from the directory `server` with an index listing them
+fn write_index(env: &Env, index: &str, files: &[(&str, &str)], hash: impl Fn(&str) -> String) {
+    let server = env.path("server");
+    fs::create_dir_all(&server).unwrap();
+    let entries: Vec<_> = files
+        .iter()
+        .map(|(name, contents)| {
+            fs::write(server.join(name), contents).unwrap();
+            serde_json::json!({ "name": name, "sha256": hash(contents) })
+        })
+        .collect();
+    fs::write(
+        server.join(index),
+        serde_json::json!({ "files": entries }).to_string(),
+    )
+    .unwrap();
+}
+
+fn declare(project: &Path, declarations: &str) {
+    fs::write(project.join("elan-artifacts.toml"), declarations).unwrap();
+}
+
+#[test]
+fn fetch_shares_files() {
+    let env = Env::new();
+    write_index(&env, "one.json", &[("a.ltar", "a")], sha256);
+    write_index(
+        &env,
+        "two.json",
+        &[("a.ltar", "a"), ("b.ltar", "b")],
+        sha256,
+    );
+    let project = env.project("leanprover/lean4:v4.9.0");
+    declare(
+        &project,
+        r#"
+        [[cache]]
+        name = "one"
+        index = "../server/one.json"
+
+        [[cache]]
+        name = "two"
+        index = "../server/two.json"
+        toolchains = ["leanprover/lean4:v4.9.0"]
+
+        [[cache]]
+        name = "other"
+        index = "../server/missing.json"
+        toolchains = ["leanprover/lean4:v4.0.0"]
+        "#,
+    );
+
+    let output = env
+        .elan(&["cache", "fetch", project.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("fetched 'one': 1 files, 1 downloaded"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("fetched 'two': 2 files, 1 downloaded"),
+        "{}",
+        stderr
+    );
+
+    let cache = env.path("home/.elan/artifact-cache");
+    assert_eq!(fs::read_to_string(cache.join("two/b.ltar")).unwrap(), "b");
+    assert!(same_file::is_same_file(cache.join("one/a.ltar"), cache.join("two/a.ltar")).unwrap());
+    assert_eq!(fs::read_dir(cache.join("objects")).unwrap().count(), 2);
+    assert!(!cache.join("other").exists());
+
+    let output = env
+        .elan(&["cache", "fetch", project.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("fetched 'two': 2 files, 0 downloaded")
+    );
+}
+
+#[test]
+fn fetch_rejects_checksum_mismatch() {
+    let env = Env::new();
+    write_index(&env, "index.json", &[("a.ltar", "a")], |_| sha256("b"));
+    let project = env.project("leanprover/lean4:v4.9.0");
+    declare(
+        &project,
+        "[[cache]]\nname = \"mathlib\"\nindex = \"../server/index.json\"\n",
+    );
+
+    let output = env
+        .elan(&["cache", "fetch", project.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("checksum failed"), "{}", stderr);
+    assert!(!env
+        .path("home/.elan/artifact-cache/mathlib/a.ltar")
+        .exists());
+}
+
+#[test]
+fn invalid_declarations() {
+    let env = Env::new();
+    let project = env.project("leanprover/lean4:v4.9.0");
+    for declarations in [
+        "[[cache]]\nname = \"objects\"\nindex = \"a.json\"\n",
+        "[[cache]]\nname = \"../a\"\nindex = \"a.json\"\n",
+        "[[cache]]\nname = \"a\"\nindex = \"a.json\"\nenv = \"LEAN_PATH\"\n",
+        "[[cache]]\nname = \"a\"\nindex = \"a.json\"\nenv = \"ELAN_HOME\"\n",
+        "[[cache]]\nname = \"a\"\nindex = \"a.json\"\n[[cache]]\nname = \"a\"\nindex = \"b.json\"\n",
+    ] {
+        declare(&project, declarations);
+        let output = env
+            .elan(&["cache", "fetch", project.to_str().unwrap()])
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{}", declarations);
+        assert!(
+            String::from_utf8_lossy(&output.stderr).contains("invalid artifact cache declaration"),
+            "{}",
+            declarations
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn env_vars_for_tools() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = Env::new();
+    env.link("a");
+    let lean = env.path("lean-a/bin/lean");
+    fs::write(&lean, "#!/bin/sh\nenv\n").unwrap();
+    fs::set_permissions(&lean, fs::Permissions::from_mode(0o755)).unwrap();
+    let project = env.project("a");
+    declare(
+        &project,
+        r#"
+        [[cache]]
+        name = "mathlib"
+        index = "https://example.org/mathlib.json"
+        env = "MATHLIB_CACHE_DIR"
+
+        [[cache]]
+        name = "other"
+        index = "https://example.org/other.json"
+        toolchains = ["b"]
+        env = "OTHER_CACHE_DIR"
+        "#,
+    );
+
+    let cache = env.path("home/.elan/artifact-cache");
+    let output = env
+        .elan(&["run", "a", "lean"])
+        .current_dir(&project)
+        .env_remove("MATHLIB_CACHE_DIR")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    assert!(stdout.contains(&format!("ELAN_ARTIFACT_CACHE={}\n", cache.display())));
+    assert!(stdout.contains(&format!(
+        "MATHLIB_CACHE_DIR={}\n",
+        cache.join("mathlib").display()
+    )));
+    assert!(!stdout.contains("OTHER_CACHE_DIR"));
+
+    // Set by the user, it is left alone
+    let output = env
+        .elan(&["run", "a", "lean"])
+        .current_dir(&project)
+        .env("MATHLIB_CACHE_DIR", "/custom")
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("MATHLIB_CACHE_DIR=/custom\n"));
+}
@@ -0,0 +1,89 @@
+//! Checks the cache archives of `elan cache` that CI workflows store between
+//! runs.
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+fn stdout(output: &std::process::Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn export_and_import() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.9.0");
+    let project = env.project("leanprover/lean4:v4.9.0");
+    let project = project.to_str().unwrap();
+
+    let key = stdout(&env.elan(&["cache", "key", project]).output().unwrap());
+    assert!(key.starts_with("elan-"), "{}", key);
+    // The key depends on the contents of `lean-toolchain` only
+    let other = env.path("other");
+    fs::create_dir_all(&other).unwrap();
+    fs::write(other.join("lean-toolchain"), "leanprover/lean4:v4.9.0").unwrap();
+    let other_key = stdout(
+        &env.elan(&["cache", "key", other.to_str().unwrap()])
+            .output()
+            .unwrap(),
+    );
+    assert_eq!(key, other_key);
+
+    let archive = env.path("toolchains.tar.zst");
+    let archive = archive.to_str().unwrap();
+    let exported = stdout(
+        &env.elan(&["cache", "export", archive, project])
+            .output()
+            .unwrap(),
+    );
+    assert_eq!(exported, key);
+
+    fs::remove_dir_all(env.toolchain_path("leanprover--lean4---v4.9.0")).unwrap();
+    let output = env.elan(&["cache", "import", archive]).output().unwrap();
+    stdout(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("restored"));
+    assert!(env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.9.0/bin")
+        .is_dir());
+
+    let output = env.elan(&["cache", "import", archive]).output().unwrap();
+    stdout(&output);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already installed"));
+}
+
+#[test]
+fn export_requires_installed_toolchains() {
+    let env = Env::new();
+    let project = env.project("leanprover/lean4:v4.9.0");
+    let archive = env.path("toolchains.tar.zst");
+    let output = env
+        .elan(&[
+            "cache",
+            "export",
+            archive.to_str().unwrap(),
+            project.to_str().unwrap(),
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(!archive.exists());
+}
+
+#[test]
+fn import_rejects_invalid_archives() {
+    let env = Env::new();
+    let archive = env.path("toolchains.tar.zst");
+    fs::write(&archive, "not an archive").unwrap();
+    let output = env
+        .elan(&["cache", "import", archive.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a valid elan cache archive"));
+}
@@ -0,0 +1,129 @@
+//! Checks that the command line accepts the flags, aliases, and trailing
+//! arguments it always has, and that its help stays reachable the same ways.
+
+mod common;
+
+use common::Env;
+use std::process::Output;
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn help() {
+    let env = Env::new();
+    let help = |args: &[&str]| stdout(&env.elan(args).output().unwrap());
+
+    let toolchain_help = help(&["toolchain", "--help"]);
+    assert!(toolchain_help.contains("link "), "{}", toolchain_help);
+    assert_eq!(help(&["help", "toolchain"]), toolchain_help);
+    assert_eq!(help(&["toolchain", "-h"]), toolchain_help);
+    // A missing subcommand shows the same help, though as an error
+    let output = env.elan(&["toolchain"]).output().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        toolchain_help.trim()
+    );
+
+    // The discussion sections of nested commands, as referred to by other
+    // help texts
+    let link_help = help(&["toolchain", "help", "link"]);
+    assert!(
+        link_help.contains("$ elan toolchain link master <path/to/lean/root>"),
+        "{}",
+        link_help
+    );
+    assert_eq!(help(&["help", "toolchain", "link"]), link_help);
+
+    let elan_help = help(&["--help"]);
+    for command in [
+        "show",
+        "toolchain",
+        "override",
+        "run",
+        "self",
+        "completions",
+    ] {
+        assert!(
+            elan_help.contains(&format!("\n  {} ", command)),
+            "{}",
+            elan_help
+        );
+    }
+    // Synonyms of `toolchain` subcommands stay hidden
+    assert!(!elan_help.contains("\n  install "), "{}", elan_help);
+    assert!(help(&["-V"]).starts_with("elan "));
+}
+
+#[test]
+fn aliases() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    assert!(stdout(&env.elan(&["toolchain", "list"]).output().unwrap()).contains("a"));
+
+    env.run(&["override", "add", "a"]);
+    assert!(stdout(&env.elan(&["override", "list"]).output().unwrap()).contains("a"));
+    env.run(&["override", "remove"]);
+    env.run(&["toolchain", "remove", "a"]);
+    env.run(&["uninstall", "b"]);
+    assert_eq!(
+        stdout(&env.elan(&["toolchain", "list"]).output().unwrap()).trim(),
+        "no installed toolchains"
+    );
+
+    for args in [
+        &["dump-state", "--offline"][..],
+        &["resolve", "--offline", "a"],
+        &["toolchain", "default-for", "--offline"],
+    ] {
+        let output = env.elan(args).output().unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!stderr.contains("unexpected argument"), "{}", stderr);
+    }
+}
+
+#[test]
+fn init_flags() {
+    let env = Env::new();
+    let help = stdout(&env.init(&["--help"]).output().unwrap());
+    for flag in [
+        "-y",
+        "--verbose",
+        "--quiet",
+        "--default-toolchain",
+        "--component",
+        "--no-modify-path",
+        "--proxy-auth",
+    ] {
+        assert!(help.contains(flag), "{}", help);
+    }
+
+    // Checked before anything is installed
+    let output = env
+        .init(&[
+            "-y",
+            "--no-modify-path",
+            "--default-toolchain",
+            "none",
+            "--components",
+            "a,b",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("`--component` requires a default toolchain"),
+        "{}",
+        stderr
+    );
+    assert!(!env.path("home/.elan/bin").exists());
+}
@@ -0,0 +1,140 @@
+//! Helpers for running elan in an isolated `ELAN_HOME`
+
+#![allow(dead_code)]
+
+use serde_json::Value;
+use std::env::consts::EXE_SUFFIX;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use tempfile::TempDir;
+
+pub struct Env {
+    dir: TempDir,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("bin")).unwrap();
+        fs::create_dir_all(dir.path().join("home")).unwrap();
+        // elan dispatches on the name of its executable
+        fs::copy(
+            env!("CARGO_BIN_EXE_elan-init"),
+            dir.path().join(format!("bin/elan{}", EXE_SUFFIX)),
+        )
+        .unwrap();
+        Env { dir }
+    }
+
+    /// `path` is separated by `/` on all platforms
+    pub fn path(&self, path: &str) -> PathBuf {
+        path.split('/')
+            .fold(self.dir.path().to_owned(), |dir, c| dir.join(c))
+    }
+
+    pub fn elan<S: AsRef<OsStr>>(&self, args: &[S]) -> Command {
+        self.command("elan", args)
+    }
+
+    /// Runs the installer, which the same executable becomes under the name
+    /// `elan-init`
+    pub fn init<S: AsRef<OsStr>>(&self, args: &[S]) -> Command {
+        let init = self.path(&format!("bin/elan-init{}", EXE_SUFFIX));
+        if !init.exists() {
+            fs::copy(env!("CARGO_BIN_EXE_elan-init"), init).unwrap();
+        }
+        self.command("elan-init", args)
+    }
+
+    /// Runs the proxy for `tool`, which the same executable becomes under the
+    /// tool's name
+    pub fn proxy<S: AsRef<OsStr>>(&self, tool: &str, args: &[S]) -> Command {
+        let proxy = self.path(&format!("bin/{}{}", tool, EXE_SUFFIX));
+        if !proxy.exists() {
+            fs::copy(env!("CARGO_BIN_EXE_elan-init"), proxy).unwrap();
+        }
+        self.command(tool, args)
+    }
+
+    fn command<S: AsRef<OsStr>>(&self, name: &str, args: &[S]) -> Command {
+        let mut cmd = Command::new(self.path(&format!("bin/{}{}", name, EXE_SUFFIX)));
+        cmd.args(args)
+            .current_dir(self.dir.path())
+            .env("HOME", self.path("home"))
+            .env("ELAN_HOME", self.path("home/.elan"))
+            .env_remove("ELAN_TOOLCHAIN")
+            .env_remove("ELAN_SYSTEM_HOME")
+            .env_remove("ELAN_CI")
+            .env_remove("GITHUB_ACTIONS")
+            // Tests may run in containers, where messages are off by default
+            .env("ELAN_QUIET", "0")
+            .env_remove("ELAN_DEFAULT_TOOLCHAIN");
+        cmd
+    }
+
+    pub fn run(&self, args: &[&str]) {
+        let output = self.elan(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    /// Returns whether the call succeeded and its result or error
+    pub fn api(&self, method: &str, params: Value) -> (bool, Value) {
+        let output = self
+            .elan(&["api", method, &params.to_string()])
+            .output()
+            .unwrap();
+        let mut response: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(response["api_version"], 1);
+        let success = output.status.success();
+        assert_eq!(success, response.get("result").is_some());
+        let value = response[if success { "result" } else { "error" }].take();
+        (success, value)
+    }
+
+    pub fn ok(&self, method: &str, params: Value) -> Value {
+        let (success, value) = self.api(method, params);
+        assert!(success, "{}", value);
+        value
+    }
+
+    pub fn err(&self, method: &str, params: Value) -> Value {
+        let (success, value) = self.api(method, params);
+        assert!(!success, "{}", value);
+        value
+    }
+
+    /// Links a fake toolchain containing only `bin/lean`
+    pub fn link(&self, name: &str) {
+        let dir = self.path(&format!("lean-{}", name));
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join(format!("bin/lean{}", EXE_SUFFIX)), "").unwrap();
+        self.run(&["toolchain", "link", name, dir.to_str().unwrap()]);
+    }
+
+    /// Creates the directory of a release toolchain without downloading it
+    pub fn fake_install(&self, dir_name: &str) {
+        let dir = self.path(&format!("home/.elan/toolchains/{}", dir_name));
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join(format!("bin/lean{}", EXE_SUFFIX)), "").unwrap();
+    }
+
+    pub fn toolchain_path(&self, name: &str) -> String {
+        self.path(&format!("home/.elan/toolchains/{}", name))
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    pub fn project(&self, toolchain: &str) -> PathBuf {
+        let dir = self.path("project");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lean-toolchain"), format!("{}\n", toolchain)).unwrap();
+        dir
+    }
+}
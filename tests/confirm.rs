@@ -0,0 +1,249 @@
+//! Checks that destructive commands only ask for confirmation where
+//! documented, and never when not run in a terminal
+
+mod common;
+
+use common::Env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const V0: &str = "leanprover--lean4---v4.0.0";
+const V1: &str = "leanprover--lean4---v4.1.0";
+
+fn installed(env: &Env, dir_name: &str) -> bool {
+    env.path(&format!("home/.elan/toolchains/{}", dir_name))
+        .is_dir()
+}
+
+/// Runs `cmd` with `input` on a stdin that is not a terminal
+fn unattended(mut cmd: Command, input: &str) -> Output {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Runs `cmd` in a pseudo-terminal created by `script`, answering `input` to
+/// any question. Both output streams end up in `stdout`.
+#[cfg(target_os = "linux")]
+fn in_terminal(cmd: Command, input: &str) -> Output {
+    let quote = |s: &std::ffi::OsStr| format!("'{}'", s.to_str().unwrap().replace('\'', r"'\''"));
+    let line = std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut script = Command::new("script");
+    script.args(["-qec", &line, "/dev/null"]);
+    if let Some(dir) = cmd.get_current_dir() {
+        script.current_dir(dir);
+    }
+    for (key, value) in cmd.get_envs() {
+        match value {
+            Some(value) => script.env(key, value),
+            None => script.env_remove(key),
+        };
+    }
+    unattended(script, input)
+}
+
+#[test]
+fn uninstall_unattended() {
+    let env = Env::new();
+    env.fake_install(V0);
+    env.fake_install(V1);
+    env.run(&["default", "leanprover/lean4:v4.0.0"]);
+
+    let output = unattended(
+        env.elan(&["toolchain", "uninstall", "leanprover/lean4:v4.1.0"]),
+        "",
+    );
+    assert!(output.status.success());
+    assert!(!installed(&env, V1));
+
+    let output = unattended(
+        env.elan(&["toolchain", "uninstall", "leanprover/lean4:v4.0.0"]),
+        "",
+    );
+    assert!(!output.status.success());
+    assert!(installed(&env, V0));
+    // Not run in a terminal, `--force` is enough even without `--yes`
+    let output = unattended(
+        env.elan(&[
+            "toolchain",
+            "uninstall",
+            "--force",
+            "leanprover/lean4:v4.0.0",
+        ]),
+        "n\n",
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("anyway?"), "{}", stderr);
+    assert!(!installed(&env, V0));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn uninstall_in_terminal() {
+    let env = Env::new();
+    env.fake_install(V0);
+    env.fake_install(V1);
+    env.run(&["default", "leanprover/lean4:v4.0.0"]);
+
+    // Uninstalling a toolchain nothing protects asks nothing, as it always did
+    let output = in_terminal(
+        env.elan(&["toolchain", "uninstall", "leanprover/lean4:v4.1.0"]),
+        "n\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(!stdout.contains('?'), "{}", stdout);
+    assert!(!installed(&env, V1));
+
+    let uninstall = |args: &[&str], input| {
+        let output = in_terminal(
+            env.elan(
+                &[
+                    &[
+                        "toolchain",
+                        "uninstall",
+                        "--force",
+                        "leanprover/lean4:v4.0.0",
+                    ],
+                    args,
+                ]
+                .concat(),
+            ),
+            input,
+        );
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let stdout = uninstall(&[], "n\n");
+    assert!(
+        stdout.contains(
+            "Uninstall toolchain 'leanprover/lean4:v4.0.0' (the default toolchain) anyway? (y/N)"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("aborting"), "{}", stdout);
+    assert!(installed(&env, V0));
+
+    let stdout = uninstall(&["--no-confirm"], "");
+    assert!(!stdout.contains("anyway?"), "{}", stdout);
+    assert!(!installed(&env, V0));
+
+    env.fake_install(V0);
+    uninstall(&["-y"], "");
+    assert!(!installed(&env, V0));
+}
+
+/// Serves a self-update to `version` whose `elan-init` only records that it
+/// was run
+#[cfg(unix)]
+fn serve_update(env: &Env, version: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let releases = env.path("api/repos/leanprover/elan/releases");
+    fs::create_dir_all(&releases).unwrap();
+    fs::write(
+        releases.join("latest"),
+        format!(r#"{{"tag_name":"v{}"}}"#, version),
+    )
+    .unwrap();
+    let init = env.path("elan-init");
+    fs::write(
+        &init,
+        format!(
+            "#!/bin/sh\ncase \"$1\" in\n--version) echo 'elan {}';;\n*) touch '{}';;\nesac\n",
+            version,
+            env.path("replaced").display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&init, fs::Permissions::from_mode(0o755)).unwrap();
+    let dir = env.path(&format!("update/v{}", version));
+    fs::create_dir_all(&dir).unwrap();
+    let archive =
+        fs::File::create(dir.join(format!("elan-{}.tar.gz", elan_dist::dist::host_triple())))
+            .unwrap();
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        archive,
+        flate2::Compression::default(),
+    ));
+    builder.append_path_with_name(&init, "elan-init").unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    fs::create_dir_all(env.path("home/.elan/bin")).unwrap();
+    fs::copy(env.path("bin/elan"), env.path("home/.elan/bin/elan")).unwrap();
+}
+
+#[cfg(unix)]
+fn self_update(env: &Env) -> Command {
+    let api = url::Url::from_directory_path(env.path("api")).unwrap();
+    let update = url::Url::from_directory_path(env.path("update")).unwrap();
+    let mut cmd = env.elan(&["self", "update"]);
+    cmd.env("ELAN_GITHUB_TOKEN", "token")
+        .env("ELAN_GITHUB_API_URL", api.as_str().trim_end_matches('/'))
+        .env("ELAN_UPDATE_ROOT", update.as_str().trim_end_matches('/'));
+    cmd
+}
+
+#[cfg(unix)]
+#[test]
+fn self_update_unattended() {
+    let env = Env::new();
+    serve_update(&env, "0.0.1");
+    let output = unattended(self_update(&env), "n\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains('?'), "{}", stderr);
+    assert!(env.path("replaced").exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn self_update_in_terminal() {
+    let env = Env::new();
+    serve_update(&env, "999.0.0");
+    // Updating to a newer version asks nothing, as it always did
+    let output = in_terminal(self_update(&env), "n\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(!stdout.contains('?'), "{}", stdout);
+    assert!(env.path("replaced").exists());
+
+    let env = Env::new();
+    serve_update(&env, "0.0.1");
+    let output = in_terminal(self_update(&env), "n\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains(&format!(
+            "Replace elan {} with the older version 0.0.1? (y/N)",
+            env!("CARGO_PKG_VERSION")
+        )),
+        "{}",
+        stdout
+    );
+    assert!(!env.path("replaced").exists());
+    assert!(!env.path("home/.elan/bin/elan-init").exists());
+
+    let mut cmd = self_update(&env);
+    cmd.arg("--yes");
+    let output = in_terminal(cmd, "");
+    assert!(output.status.success());
+    assert!(env.path("replaced").exists());
+}
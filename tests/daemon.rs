@@ -0,0 +1,62 @@
+//! Checks that `elan daemon --socket` serves several clients at once and
+//! shuts down on request.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::Env;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn request(stream: &UnixStream, request: Value) -> Value {
+    writeln!(&*stream, "{}", request).unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+#[test]
+fn concurrent_clients() {
+    let env = Env::new();
+    let socket = env.path("daemon.sock");
+    let mut daemon = env
+        .elan(&["daemon", "--socket"])
+        .arg(&socket)
+        .spawn()
+        .unwrap();
+    let connect = || {
+        let start = Instant::now();
+        loop {
+            match UnixStream::connect(&socket) {
+                Ok(stream) => return stream,
+                Err(e) if start.elapsed() > Duration::from_secs(10) => panic!("{}", e),
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    };
+
+    // An idle client does not hold up others
+    let idle = connect();
+    let client = connect();
+    client
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let response = request(
+        &client,
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "list" }),
+    );
+    assert_eq!(response["result"], json!({ "toolchains": [] }));
+
+    let response = request(
+        &client,
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+    );
+    assert_eq!(response["id"], 2);
+    assert!(daemon.wait().unwrap().success());
+    assert!(!socket.exists());
+    drop(idle);
+}
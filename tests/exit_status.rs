@@ -0,0 +1,49 @@
+//! Checks that `elan run` exits exactly like the tool it runs.
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[cfg(unix)]
+#[test]
+fn exit_status() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::ExitStatusExt;
+
+    let env = Env::new();
+    env.link("a");
+    let lean = env.path("lean-a/bin/lean");
+    fs::write(&lean, "#!/bin/sh\nexit 3\n").unwrap();
+    fs::set_permissions(&lean, fs::Permissions::from_mode(0o755)).unwrap();
+    let status = env.elan(&["run", "a", "lean"]).status().unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    fs::write(&lean, "#!/bin/sh\nkill -TERM $$\n").unwrap();
+    let status = env.elan(&["run", "a", "lean"]).status().unwrap();
+    assert_eq!(status.signal(), Some(libc::SIGTERM));
+}
+
+#[cfg(windows)]
+#[test]
+fn exit_status() {
+    let env = Env::new();
+    env.link("a");
+    let lean = env.path("lean-a/bin/lean.exe");
+    let cmd = std::path::Path::new(&std::env::var_os("SystemRoot").unwrap())
+        .join("System32")
+        .join("cmd.exe");
+    fs::copy(cmd, &lean).unwrap();
+    let status = env
+        .elan(&["run", "a", "lean", "/c", "exit 3"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    // NTSTATUS codes of crashes, here STATUS_ACCESS_VIOLATION, are kept
+    let status = env
+        .elan(&["run", "a", "lean", "/c", "exit -1073741819"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(0xC000_0005_u32 as i32));
+}
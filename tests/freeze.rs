@@ -0,0 +1,76 @@
+//! Checks that frozen toolchains are only removed when forced
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+const V0: &str = "leanprover--lean4---v4.0.0";
+const V1: &str = "leanprover--lean4---v4.1.0";
+
+fn installed(env: &Env, dir_name: &str) -> bool {
+    env.path(&format!("home/.elan/toolchains/{}", dir_name))
+        .is_dir()
+}
+
+#[test]
+fn uninstall() {
+    let env = Env::new();
+    env.fake_install(V0);
+    env.fake_install(V1);
+    env.run(&["toolchain", "freeze", "leanprover/lean4:v4.0.0"]);
+
+    let output = env
+        .elan(&["toolchain", "uninstall", "leanprover/lean4:v4.0.0", "-y"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is frozen"), "{}", stderr);
+    assert!(installed(&env, V0));
+
+    env.run(&["toolchain", "uninstall", "--all", "-y"]);
+    assert!(installed(&env, V0));
+    assert!(!installed(&env, V1));
+
+    env.run(&["toolchain", "uninstall", "--all", "--force", "-y"]);
+    assert!(!installed(&env, V0));
+    // The marker goes with the toolchain
+    assert!(!env.path(&format!("home/.elan/frozen/{}", V0)).exists());
+}
+
+#[test]
+fn gc() {
+    let env = Env::new();
+    env.fake_install(V0);
+    env.fake_install(V1);
+    env.run(&["toolchain", "freeze", "leanprover/lean4:v4.0.0"]);
+    env.run(&["toolchain", "gc", "--delete"]);
+    assert!(installed(&env, V0));
+    assert!(!installed(&env, V1));
+
+    env.run(&["toolchain", "unfreeze", "leanprover/lean4:v4.0.0"]);
+    env.run(&["toolchain", "gc", "--delete"]);
+    assert!(!installed(&env, V0));
+}
+
+#[test]
+fn obsolete_format() {
+    let env = Env::new();
+    // Installed under a channel name by an earlier version of elan
+    env.fake_install("stable");
+    env.fake_install("nightly");
+    fs::create_dir_all(env.path("home/.elan/frozen")).unwrap();
+    fs::write(env.path("home/.elan/frozen/stable"), "").unwrap();
+
+    let output = env
+        .init(&["-y", "--no-modify-path", "--default-toolchain", "none"])
+        .env("ELAN_GITHUB_URL", "http://127.0.0.1:9")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("keeping frozen toolchain"), "{}", stderr);
+    assert!(installed(&env, "stable"));
+    assert!(!installed(&env, "nightly"));
+}
@@ -0,0 +1,54 @@
+//! Checks of `elan toolchain gc` that do not need to download toolchains
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[test]
+fn legacy_roots() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.0.0");
+    env.fake_install("leanprover--lean4---v4.1.0");
+    let project = env.project("leanprover/lean4:v4.0.0");
+    // Written by an earlier version of elan
+    let legacy = env.path("home/.elan/known-projects");
+    fs::write(&legacy, format!("{}\n", project.display())).unwrap();
+
+    env.run(&["toolchain", "gc", "--delete", "-y"]);
+    assert!(env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.0.0")
+        .exists());
+    assert!(!env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.1.0")
+        .exists());
+    assert!(env.path("home/.elan/known-projects.toml").exists());
+    assert_eq!(
+        fs::read_to_string(&legacy).unwrap(),
+        format!("{}\n", project.display())
+    );
+
+    // Roots added later by an earlier version are still picked up
+    let other = env.path("other");
+    fs::create_dir_all(&other).unwrap();
+    fs::write(other.join("lean-toolchain"), "leanprover/lean4:v4.0.0\n").unwrap();
+    fs::write(
+        &legacy,
+        format!("{}\n{}\n", project.display(), other.display()),
+    )
+    .unwrap();
+    let output = env
+        .elan(&["toolchain", "gc", "--list-roots"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(other.to_str().unwrap()), "{}", stdout);
+
+    // Forgetting a root removes it from both files
+    env.run(&["toolchain", "gc", "--forget-root", other.to_str().unwrap()]);
+    assert_eq!(
+        fs::read_to_string(&legacy).unwrap(),
+        format!("{}\n", project.display())
+    );
+}
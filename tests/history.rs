@@ -0,0 +1,142 @@
+//! Checks of `elan history`
+
+mod common;
+
+use common::Env;
+use serde_json::{json, Value};
+
+fn history(env: &Env, args: &[&str]) -> Vec<Value> {
+    let output = env
+        .elan(&[&["history", "--json"], args].concat())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut entries: Vec<Value> = serde_json::from_slice(&output.stdout).unwrap();
+    for entry in &mut entries {
+        assert!(entry["time"].as_u64().unwrap() > 0);
+        entry.as_object_mut().unwrap().remove("time");
+        // Differs between runs
+        let invocation = entry.as_object_mut().unwrap().remove("invocation");
+        assert!(invocation.unwrap().as_str().is_some_and(|s| !s.is_empty()));
+    }
+    entries
+}
+
+#[test]
+fn operations() {
+    let env = Env::new();
+    assert_eq!(history(&env, &[]), Vec::<Value>::new());
+
+    env.link("a");
+    env.fake_install("leanprover--lean4---v4.0.0");
+    env.run(&["default", "a"]);
+    // Setting the same default again is no change
+    env.run(&["default", "a"]);
+    env.run(&[
+        "config",
+        "set",
+        "default_toolchain",
+        "leanprover/lean4:v4.0.0",
+    ]);
+    env.run(&["override", "set", "a"]);
+    env.run(&["override", "unset"]);
+    env.run(&["config", "unset", "default_toolchain"]);
+    env.run(&["toolchain", "gc", "--delete", "-y"]);
+    env.run(&["toolchain", "uninstall", "a", "-y"]);
+
+    // Overrides are set for the current directory
+    let dir = std::fs::canonicalize(env.path("")).unwrap();
+    let dir = dir.to_str().unwrap();
+    assert_eq!(
+        history(&env, &[]),
+        vec![
+            json!({
+                "operation": "install",
+                "toolchain": "a",
+                "command": format!("elan toolchain link a {}", env.path("lean-a").display()),
+                "undo": "elan toolchain uninstall a",
+            }),
+            json!({
+                "operation": "default",
+                "toolchain": "a",
+                "command": "elan default a",
+                "undo": "elan config unset default_toolchain",
+            }),
+            json!({
+                "operation": "default",
+                "toolchain": "leanprover/lean4:v4.0.0",
+                "previous": "a",
+                "command": "elan config set default_toolchain leanprover/lean4:v4.0.0",
+                "undo": "elan default a",
+            }),
+            json!({
+                "operation": "override-set",
+                "toolchain": "a",
+                "path": dir,
+                "command": "elan override set a",
+                "undo": format!("elan override unset --path '{}'", dir),
+            }),
+            json!({
+                "operation": "override-unset",
+                "previous": "a",
+                "path": dir,
+                "command": "elan override unset",
+                "undo": format!("cd '{}' && elan override set a", dir),
+            }),
+            json!({
+                "operation": "default",
+                "previous": "leanprover/lean4:v4.0.0",
+                "command": "elan config unset default_toolchain",
+                "undo": "elan default leanprover/lean4:v4.0.0",
+            }),
+            json!({
+                "operation": "gc",
+                "toolchain": "leanprover/lean4:v4.0.0",
+                "command": "elan toolchain gc --delete -y",
+                "undo": "elan toolchain install leanprover/lean4:v4.0.0",
+            }),
+            // A linked toolchain cannot be reinstalled
+            json!({
+                "operation": "uninstall",
+                "toolchain": "a",
+                "command": "elan toolchain uninstall a -y",
+                "undo": null,
+            }),
+        ]
+    );
+    assert_eq!(history(&env, &["-n", "1"]).len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn replaced_linked_toolchain() {
+    let env = Env::new();
+    // As linked by earlier versions of elan, which accepted release names
+    let dir = env.path("lean");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    let toolchains = env.path("home/.elan/toolchains");
+    std::fs::create_dir_all(&toolchains).unwrap();
+    std::os::unix::fs::symlink(&dir, toolchains.join("leanprover--lean4---v4.9.0")).unwrap();
+
+    // Replacing the link is not an uninstallation, even if downloading the
+    // release then fails
+    env.elan(&["toolchain", "install", "--force", "leanprover/lean4:v4.9.0"])
+        .env("ELAN_GITHUB_URL", "http://127.0.0.1:9")
+        .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+        .output()
+        .unwrap();
+    let entries = history(&env, &[]);
+    assert_eq!(
+        entries,
+        vec![json!({
+            "operation": "replace",
+            "toolchain": "leanprover/lean4:v4.9.0",
+            "command": "elan toolchain install --force leanprover/lean4:v4.9.0",
+            "undo": null,
+        })]
+    );
+}
@@ -0,0 +1,53 @@
+//! Checks that installations triggered by running a missing toolchain give
+//! up on a stalled network
+
+mod common;
+
+use common::Env;
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+/// Returns the URL of a proxy that accepts connections but never answers
+fn stalled_proxy() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_secs(60));
+                drop(stream);
+            });
+        }
+    });
+    url
+}
+
+#[test]
+fn stalled() {
+    let env = Env::new();
+    let proxy = stalled_proxy();
+    env.run(&["config", "set", "implicit_install_timeout", "2"]);
+    let output = env
+        .elan(&["run", "--install", "leanprover/lean4:v4.9.0", "lean"])
+        .env("https_proxy", &proxy)
+        .env("http_proxy", &proxy)
+        .env_remove("no_proxy")
+        .env_remove("NO_PROXY")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains(
+            "installation of toolchain 'leanprover/lean4:v4.9.0' did not finish within 2 seconds; \
+             install it manually with `elan toolchain install leanprover/lean4:v4.9.0`"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(!env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.9.0")
+        .exists());
+}
@@ -0,0 +1,284 @@
+//! Checks of `elan-init` that do not install any toolchain, so that no
+//! network access is required
+
+mod common;
+
+use common::Env;
+use serde_json::{json, Value};
+
+fn init_error(env: &Env, args: &[&str], toolchain_env: Option<&str>) -> String {
+    let mut cmd = env.init(args);
+    if let Some(toolchain) = toolchain_env {
+        cmd.env("ELAN_DEFAULT_TOOLCHAIN", toolchain);
+    }
+    let output = cmd.output().unwrap();
+    assert!(!output.status.success());
+    assert!(!env.path("home/.elan").exists());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn default_toolchain_suggestions() {
+    let env = Env::new();
+    for (spec, suggestion) in [
+        ("stabel", "`stable`"),
+        ("Nightly", "`nightly`"),
+        ("4.9", "`v4.9.0`"),
+        ("v4.9", "`v4.9.0`"),
+        ("leanprover/lean4:beat", "`leanprover/lean4:beta`"),
+    ] {
+        let stderr = init_error(&env, &["-y", "--default-toolchain", spec], None);
+        assert!(
+            stderr.contains(&format!(
+                "'{}' is not a valid default toolchain (did you mean {}?)",
+                spec, suggestion
+            )),
+            "{}",
+            stderr
+        );
+    }
+    let stderr = init_error(&env, &["-y", "--default-toolchain", "foo"], None);
+    assert!(
+        stderr.contains("'foo' is not a valid default toolchain;"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn default_toolchain_from_env() {
+    let env = Env::new();
+    let stderr = init_error(&env, &["-y"], Some("latest-stabel-release"));
+    assert!(
+        stderr.contains("(did you mean `latest-stable-release`?)"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn quiet_install() {
+    let env = Env::new();
+    let output = env
+        .init(&[
+            "--quiet",
+            "--no-modify-path",
+            "--default-toolchain",
+            "v4.9.0",
+        ])
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(output.stderr.is_empty());
+    assert_eq!(
+        serde_json::from_slice::<Value>(&output.stdout).unwrap(),
+        json!({
+            "elan_home": env.path("home/.elan"),
+            "elan_path": env.path(&format!("home/.elan/bin/elan{}", std::env::consts::EXE_SUFFIX)),
+            "default_toolchain": "leanprover/lean4:v4.9.0",
+            "path_modified": false,
+            "components": [],
+        })
+    );
+}
+
+#[test]
+fn uninstall_dry_run() {
+    let env = Env::new();
+    let status = env
+        .init(&["--quiet", "--no-modify-path", "--default-toolchain", "none"])
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = env
+        .elan(&["self", "uninstall", "--dry-run", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let plan: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let removed = plan["removed_paths"].as_array().unwrap();
+    let elan_home = env.path("home/.elan");
+    let elan_path = elan_home.join(format!("bin/elan{}", std::env::consts::EXE_SUFFIX));
+    assert!(removed.contains(&json!(elan_path)));
+    assert_eq!(removed.last(), Some(&json!(elan_home)));
+    assert_eq!(plan["edited_files"], json!([]));
+    // Nothing was actually removed
+    assert!(elan_path.exists());
+}
+
+#[test]
+fn uninstall_dry_run_registrations() {
+    let env = Env::new();
+    let status = env
+        .init(&["--quiet", "--no-modify-path", "--default-toolchain", "none"])
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    // As recorded by a feature registering a file association
+    let settings = env.path("home/.elan/settings.toml");
+    let mut toml = std::fs::read_to_string(&settings).unwrap();
+    toml.push_str(
+        "\n[registrations]\n'Software\\Classes\\.lean' = \"the .lean file association\"\n",
+    );
+    std::fs::write(&settings, toml).unwrap();
+    let output = env
+        .elan(&["self", "uninstall", "--dry-run", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let plan: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let keys = plan["removed_registry_keys"].as_array().unwrap();
+    assert!(keys.contains(&json!(r"HKEY_CURRENT_USER\Software\Classes\.lean")));
+}
+
+#[test]
+fn proxy_link_mode() {
+    let env = Env::new();
+    let init = |args: &[&str]| {
+        let status = env
+            .init(args)
+            .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+            .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    init(&["--quiet", "--no-modify-path", "--default-toolchain", "none"]);
+    let bin = env.path("home/.elan/bin");
+    let elan = bin.join(format!("elan{}", std::env::consts::EXE_SUFFIX));
+    let lean = bin.join(format!("lean{}", std::env::consts::EXE_SUFFIX));
+    assert!(same_file::is_same_file(&elan, &lean).unwrap());
+
+    env.run(&["config", "set", "proxy_link_mode", "copy"]);
+    init(&["-y", "--repair", "--no-modify-path"]);
+    assert!(!same_file::is_same_file(&elan, &lean).unwrap());
+    assert_eq!(std::fs::read(&elan).unwrap(), std::fs::read(&lean).unwrap());
+
+    env.run(&["config", "set", "proxy_link_mode", "hardlink"]);
+    init(&["-y", "--repair", "--no-modify-path"]);
+    assert!(same_file::is_same_file(&elan, &lean).unwrap());
+}
+
+#[test]
+fn reinstall_replaces_elan() {
+    let env = Env::new();
+    let init = |args: &[&str]| {
+        let output = env
+            .init(args)
+            .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+            .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stderr).unwrap()
+    };
+    init(&["--quiet", "--no-modify-path", "--default-toolchain", "none"]);
+    env.link("a");
+    env.run(&["default", "a"]);
+    // Pretend that an older version is installed
+    let elan = env.path(&format!(
+        "home/.elan/bin/elan{}",
+        std::env::consts::EXE_SUFFIX
+    ));
+    let new = std::fs::read(&elan).unwrap();
+    std::fs::write(&elan, "old elan").unwrap();
+
+    let stderr = init(&["-y", "--no-modify-path"]);
+    assert!(stderr.contains("already installed"), "{}", stderr);
+    assert!(stderr.contains("with this version of elan"), "{}", stderr);
+    assert_eq!(std::fs::read(&elan).unwrap(), new);
+    let output = env
+        .elan(&["config", "get", "default_toolchain"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "a");
+}
+
+#[test]
+fn heal_proxies() {
+    let env = Env::new();
+    let status = env
+        .init(&["--quiet", "--no-modify-path", "--default-toolchain", "none"])
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let lake = env.path(&format!(
+        "home/.elan/bin/lake{}",
+        std::env::consts::EXE_SUFFIX
+    ));
+    std::fs::remove_file(&lake).unwrap();
+
+    let output = env.elan(&["toolchain", "list"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(lake.exists());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("restored missing or outdated tools: lake"),
+        "{}",
+        stderr
+    );
+
+    let output = env.elan(&["self", "repair-proxies"]).output().unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("all tools are already linked to elan"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn which_installation() {
+    let env = Env::new();
+    let status = env
+        .init(&["--quiet", "--no-modify-path", "--default-toolchain", "none"])
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .env("ELAN_INIT_SKIP_SUDO_CHECK", "yes")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let installed = env.path(&format!(
+        "home/.elan/bin/elan{}",
+        std::env::consts::EXE_SUFFIX
+    ));
+    let output = env
+        .elan(&["self", "which-installation", "--json"])
+        .env("ELAN_UPDATE_ROOT", "https://example.invalid/elan")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["elan_home"], json!(env.path("home/.elan")));
+    assert_eq!(report["in_elan_home"], json!(false));
+    assert_eq!(report["install_method"], json!("elan-init"));
+    assert_eq!(report["update_root"], json!("https://example.invalid/elan"));
+    assert_eq!(report["update_root_source"], json!("environment"));
+
+    let output = std::process::Command::new(&installed)
+        .args(["self", "which-installation", "--json"])
+        .env("HOME", env.path("home"))
+        .env("ELAN_HOME", env.path("home/.elan"))
+        .env_remove("ELAN_SYSTEM_HOME")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["in_elan_home"], json!(true));
+}
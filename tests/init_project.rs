@@ -0,0 +1,80 @@
+//! Checks that `elan init-project` sets up projects with Lake of the chosen
+//! toolchain.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::Env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+/// Links the toolchain `a` with a `lake` that records its arguments and
+/// working directory and then runs `script`
+fn link_with_lake(env: &Env, script: &str) {
+    env.link("a");
+    let lake = env.path("lean-a/bin/lake");
+    let log = env.path("lake.log");
+    fs::write(
+        &lake,
+        format!(
+            "#!/bin/sh\necho \"$PWD $* $ELAN_TOOLCHAIN\" >> '{}'\n{}\n",
+            log.display(),
+            script
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&lake, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn init_project() {
+    let env = Env::new();
+    link_with_lake(&env, "if [ \"$1\" = new ]; then mkdir \"$2\"; fi");
+    let root = env.path("");
+    let root = root.to_str().unwrap().trim_end_matches('/');
+
+    let output = env
+        .elan(&["init-project", "exe", "hello", "--toolchain", "a"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(env.path("hello/lean-toolchain")).unwrap(),
+        "a\n"
+    );
+
+    fs::create_dir_all(env.path("existing")).unwrap();
+    let output = env
+        .elan(&["init-project", "lib", "existing", "--toolchain", "a"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(env.path("lake.log")).unwrap(),
+        format!(
+            "{0} new hello exe a\n{0}/existing init existing lib a\n",
+            root
+        )
+    );
+    assert!(env.path("existing/lean-toolchain").is_file());
+}
+
+#[test]
+fn init_project_lake_fails() {
+    let env = Env::new();
+    link_with_lake(&env, "exit 1");
+    fs::create_dir_all(env.path("project")).unwrap();
+    let output = env
+        .elan(&["init-project", "std", "project", "--toolchain", "a"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("`lake init project std` failed; the project was not set up"));
+    assert!(!env.path("project/lean-toolchain").exists());
+}
@@ -0,0 +1,683 @@
+//! Checks how `elan toolchain install` treats toolchains that are already
+//! installed, what it reports in a dry run, how it fails over between
+//! mirrors, that it prefers IPFS sources with the `ipfs` feature, how it
+//! shares archives through the peer cache, what `--minimize` removes, that
+//! implicit installations accept any timeout, that requested components are
+//! added to installed toolchains, that the installation time, checksum, and
+//! size are recorded, and that builds from source need full commit hashes.
+
+mod common;
+
+use common::Env;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+#[test]
+fn already_installed() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.9.0");
+
+    let output = env
+        .elan(&["toolchain", "install", "leanprover/lean4:v4.9.0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'leanprover/lean4:v4.9.0' is already installed; pass `--reinstall`"),
+        "{}",
+        stderr
+    );
+
+    // Linked toolchains cannot be reinstalled from a release
+    env.link("a");
+    let output = env
+        .elan(&["toolchain", "install", "--reinstall", "a"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("'a' is a linked toolchain"), "{}", stderr);
+}
+
+#[cfg(unix)]
+#[test]
+fn linked_release_name() {
+    let env = Env::new();
+    env.link("a");
+    fs::create_dir_all(env.path("home/.elan/toolchains")).unwrap();
+    std::os::unix::fs::symlink(
+        env.path("lean-a"),
+        env.path("home/.elan/toolchains/leanprover--lean4---v4.9.0"),
+    )
+    .unwrap();
+
+    let output = env
+        .elan(&["toolchain", "install", "leanprover/lean4:v4.9.0"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'leanprover/lean4:v4.9.0' is a linked toolchain; skipping"),
+        "{}",
+        stderr
+    );
+
+    // `--force` replaces the link, leaving the linked directory alone
+    let output = env
+        .elan(&["toolchain", "install", "--force", "leanprover/lean4:v4.9.0"])
+        .env("ELAN_GITHUB_URL", "http://127.0.0.1:9")
+        .env("ELAN_GITHUB_API_URL", "http://127.0.0.1:9")
+        .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.9.0")
+        .symlink_metadata()
+        .is_err());
+    assert!(env.path("lean-a/bin").is_dir());
+}
+
+#[test]
+fn dry_run() {
+    let env = Env::new();
+    // A mirror of the GitHub release page and its archive
+    let releases = env.path("mirror/leanprover/lean4/releases");
+    fs::create_dir_all(releases.join("expanded_assets")).unwrap();
+    fs::create_dir_all(releases.join("download/v4.9.0")).unwrap();
+    let assets = [
+        "linux",
+        "linux_aarch64",
+        "darwin",
+        "darwin_aarch64",
+        "windows",
+    ]
+    .iter()
+    .map(|target| {
+        let ext = if *target == "windows" {
+            "zip"
+        } else {
+            "tar.zst"
+        };
+        let name = format!("lean-4.9.0-{}.{}", target, ext);
+        fs::write(releases.join("download/v4.9.0").join(&name), [0; 1234]).unwrap();
+        format!(
+            r#"<a href="/leanprover/lean4/releases/download/v4.9.0/{}">"#,
+            name
+        )
+    })
+    .collect::<String>();
+    fs::write(releases.join("expanded_assets/v4.9.0"), assets).unwrap();
+    let mirror = Url::from_directory_path(env.path("mirror")).unwrap();
+
+    let dry_run = || {
+        let output = env
+            .elan(&[
+                "toolchain",
+                "install",
+                "--dry-run",
+                "leanprover/lean4:v4.9.0",
+            ])
+            .env("ELAN_GITHUB_URL", mirror.as_str())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let stdout = dry_run();
+    assert!(
+        stdout.contains(&format!(
+            "asset: {}leanprover/lean4/releases/download/v4.9.0/lean-4.9.0-",
+            mirror
+        )),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("size: 1 KiB (1234 bytes)"), "{}", stdout);
+    assert!(
+        stdout.contains(&format!(
+            "destination: {}",
+            env.toolchain_path("leanprover--lean4---v4.9.0")
+        )),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("installed: no"), "{}", stdout);
+    assert!(!env.path("home/.elan/toolchains").exists());
+
+    env.fake_install("leanprover--lean4---v4.9.0");
+    assert!(dry_run().contains("installed: yes"));
+}
+
+/// Serves the files below `root` over HTTP, except for release archives,
+/// which always fail with 503
+fn serve_release_pages(root: PathBuf) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+                request += &line;
+            }
+            let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+            let file = root.join(path.trim_start_matches('/'));
+            let (status, body) = if path.contains("/releases/download/") {
+                ("503 Service Unavailable", vec![])
+            } else if file.is_file() {
+                ("200 OK", fs::read(file).unwrap())
+            } else {
+                ("404 Not Found", vec![])
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+    url
+}
+
+/// Sets up a mirror of the release pages of `leanprover/lean4:v4.9.0` below
+/// `mirror` and a toolchain archive, returning the archive file names of the
+/// release and the archive
+fn release_mirror(env: &Env) -> (Vec<String>, PathBuf) {
+    let targets = [
+        "linux",
+        "linux_aarch64",
+        "darwin",
+        "darwin_aarch64",
+        "windows",
+    ];
+    let names: Vec<_> = targets
+        .iter()
+        .map(|target| format!("lean-4.9.0-{}.tar.gz", target))
+        .collect();
+
+    let releases = env.path("mirror/leanprover/lean4/releases");
+    fs::create_dir_all(releases.join("expanded_assets")).unwrap();
+    let page = names
+        .iter()
+        .map(|name| {
+            format!(
+                r#"<a href="/leanprover/lean4/releases/download/v4.9.0/{}">"#,
+                name
+            )
+        })
+        .collect::<String>();
+    fs::write(releases.join("expanded_assets/v4.9.0"), page).unwrap();
+    fs::create_dir_all(env.path("mirror/leanprover/elan/releases")).unwrap();
+    fs::write(
+        env.path("mirror/leanprover/elan/releases/latest"),
+        "/leanprover/elan/releases/tag/v99.0.0",
+    )
+    .unwrap();
+
+    let archive = env.path("lean.tar.gz");
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        fs::File::create(&archive).unwrap(),
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(0);
+    header.set_mode(0o755);
+    tar.append_data(&mut header, "lean-4.9.0/bin/lean", &[][..])
+        .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+    (names, archive)
+}
+
+/// Writes a release index listing `urls(name)` as the alternative URLs of
+/// each archive of `leanprover/lean4:v4.9.0`, returning its URL
+fn release_index(env: &Env, names: &[String], urls: impl Fn(&str) -> Vec<String>) -> Url {
+    let assets: serde_json::Map<_, _> = names
+        .iter()
+        .map(|name| (name.clone(), serde_json::json!(urls(name))))
+        .collect();
+    let index = serde_json::json!({ "stable": [{ "name": "v4.9.0", "assets": assets }] });
+    fs::write(env.path("index.json"), index.to_string()).unwrap();
+    Url::from_file_path(env.path("index.json")).unwrap()
+}
+
+#[test]
+fn mirror_failover() {
+    let env = Env::new();
+    let (names, archive) = release_mirror(&env);
+    // Only available at the alternative URL listed in the release index;
+    // IPFS URLs are skipped without the `ipfs` feature
+    let archive_url = Url::from_file_path(&archive).unwrap();
+    let index_url = release_index(&env, &names, |name| {
+        vec![format!("ipfs://bafytest/{}", name), archive_url.to_string()]
+    });
+
+    // The first mirror cannot be reached
+    let server = serve_release_pages(env.path("mirror"));
+    let output = env
+        .elan(&["toolchain", "install", "leanprover/lean4:v4.9.0"])
+        .env("ELAN_GITHUB_URL", format!("http://127.0.0.1:9,{}", server))
+        .env("ELAN_IPFS_GATEWAY", "http://127.0.0.1:9")
+        .env(
+            "ELAN_RELEASE_INDEX",
+            format!("leanprover/lean4={}", index_url),
+        )
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains(&format!(
+            "failed to download 'http://127.0.0.1:9/leanprover/lean4/releases/expanded_assets/v4.9.0'; \
+             trying '{}/leanprover/lean4/releases/expanded_assets/v4.9.0' instead",
+            server
+        )),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(&format!("trying '{}' instead", archive_url)),
+        "{}",
+        stderr
+    );
+    assert!(env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.9.0/bin/lean")
+        .is_file());
+}
+
+#[test]
+fn peer_cache() {
+    let env = Env::new();
+    let (names, archive) = release_mirror(&env);
+    let download = env.path("mirror/leanprover/lean4/releases/download/v4.9.0");
+    fs::create_dir_all(&download).unwrap();
+    for name in &names {
+        fs::copy(&archive, download.join(name)).unwrap();
+    }
+    let mirror = Url::from_directory_path(env.path("mirror")).unwrap();
+    let install = || {
+        let output = env
+            .elan(&["toolchain", "install", "leanprover/lean4:v4.9.0"])
+            .env("ELAN_GITHUB_URL", mirror.as_str())
+            .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert!(output.status.success(), "{}", stderr);
+        stderr
+    };
+
+    env.run(&["config", "set", "peer_cache", "true"]);
+    install();
+    let cache = env.path("home/.elan/cache/downloads/leanprover/lean4/v4.9.0");
+    let cached: Vec<_> = fs::read_dir(&cache)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    assert_eq!(cached.len(), 1, "{:?}", cached);
+    assert!(names.contains(&cached[0]));
+
+    // Reinstalled from the cache once the mirror is gone
+    env.run(&["toolchain", "uninstall", "leanprover/lean4:v4.9.0"]);
+    fs::remove_dir_all(&download).unwrap();
+    let stderr = install();
+    assert!(stderr.contains("using cached archive"), "{}", stderr);
+
+    // Served to peers
+    let mut server = env
+        .elan(&["cache", "serve"])
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(server.stderr.take().unwrap());
+    let port = loop {
+        let mut line = String::new();
+        assert_ne!(stderr.read_line(&mut line).unwrap(), 0);
+        if let Some((_, rest)) = line.split_once("on port ") {
+            break rest.split(';').next().unwrap().to_owned();
+        }
+    };
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: peer\r\n\r\n", path).unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).unwrap();
+        response
+    };
+    let response = get(&format!("/leanprover/lean4/v4.9.0/{}", cached[0]));
+    assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+    assert!(response.ends_with(&fs::read(&archive).unwrap()));
+    let response = get("/../settings.toml");
+    assert!(response.starts_with(b"HTTP/1.1 404 Not Found"));
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    // Damaged archives are downloaded again
+    fs::create_dir_all(&download).unwrap();
+    for name in &names {
+        fs::copy(&archive, download.join(name)).unwrap();
+    }
+    env.run(&["toolchain", "uninstall", "leanprover/lean4:v4.9.0"]);
+    fs::write(cache.join(&cached[0]), "damaged").unwrap();
+    let stderr = install();
+    assert!(
+        stderr.contains("bad checksum for cached download"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        fs::read(cache.join(&cached[0])).unwrap(),
+        fs::read(&archive).unwrap()
+    );
+
+    // Including ones without a recorded hash, which fail to unpack
+    env.run(&["toolchain", "uninstall", "leanprover/lean4:v4.9.0"]);
+    fs::write(cache.join(&cached[0]), "damaged").unwrap();
+    fs::remove_file(cache.join(format!(".{}.sha256", cached[0]))).unwrap();
+    let stderr = install();
+    assert!(
+        stderr.contains("could not unpack cached archive"),
+        "{}",
+        stderr
+    );
+    assert_eq!(
+        fs::read(cache.join(&cached[0])).unwrap(),
+        fs::read(&archive).unwrap()
+    );
+}
+
+#[test]
+fn implicit_install_timeout() {
+    let env = Env::new();
+    let (names, archive) = release_mirror(&env);
+    let download = env.path("mirror/leanprover/lean4/releases/download/v4.9.0");
+    fs::create_dir_all(&download).unwrap();
+    for name in &names {
+        fs::copy(&archive, download.join(name)).unwrap();
+    }
+    let mirror = Url::from_directory_path(env.path("mirror")).unwrap();
+
+    env.run(&[
+        "config",
+        "set",
+        "implicit_install_timeout",
+        &i64::MAX.to_string(),
+    ]);
+    let output = env
+        .elan(&["run", "--install", "leanprover/lean4:v4.9.0", "lean"])
+        .env("ELAN_GITHUB_URL", mirror.as_str())
+        .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(
+        env.path("home/.elan/toolchains/leanprover--lean4---v4.9.0/bin/lean")
+            .exists(),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn components_of_installed_toolchain() {
+    let env = Env::new();
+    let (mut names, archive) = release_mirror(&env);
+    let download = env.path("mirror/leanprover/lean4/releases/download/v4.9.0");
+    fs::create_dir_all(&download).unwrap();
+    for name in &names {
+        fs::copy(&archive, download.join(name)).unwrap();
+    }
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        fs::File::create(download.join("lean-4.9.0-docs.tar.gz")).unwrap(),
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(0);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, "lean-4.9.0/share/doc/index.html", &[][..])
+        .unwrap();
+    tar.into_inner().unwrap().finish().unwrap();
+    names.push("lean-4.9.0-docs.tar.gz".to_owned());
+    let page = names
+        .iter()
+        .map(|name| {
+            format!(
+                r#"<a href="/leanprover/lean4/releases/download/v4.9.0/{}">"#,
+                name
+            )
+        })
+        .collect::<String>();
+    fs::write(
+        env.path("mirror/leanprover/lean4/releases/expanded_assets/v4.9.0"),
+        page,
+    )
+    .unwrap();
+    let mirror = Url::from_directory_path(env.path("mirror")).unwrap();
+    let install = |args: &[&str]| {
+        env.elan(&[&["toolchain", "install", "leanprover/lean4:v4.9.0"], args].concat())
+            .env("ELAN_GITHUB_URL", mirror.as_str())
+            .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+            .output()
+            .unwrap()
+    };
+
+    let output = install(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let docs = env.path("home/.elan/toolchains/leanprover--lean4---v4.9.0/share/doc/index.html");
+    assert!(!docs.exists());
+
+    // Components missing from an installed toolchain are added to it
+    for _ in 0..2 {
+        let output = install(&["--component", "docs"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(docs.is_file());
+    }
+    let output = install(&[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn metadata_recorded() {
+    let env = Env::new();
+    let (names, archive) = release_mirror(&env);
+    let download = env.path("mirror/leanprover/lean4/releases/download/v4.9.0");
+    fs::create_dir_all(&download).unwrap();
+    for name in &names {
+        fs::copy(&archive, download.join(name)).unwrap();
+    }
+    let mirror = Url::from_directory_path(env.path("mirror")).unwrap();
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    };
+    let before = now();
+    let output = env
+        .elan(&["toolchain", "install", "leanprover/lean4:v4.9.0"])
+        .env("ELAN_GITHUB_URL", mirror.as_str())
+        .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let after = now();
+
+    let output = env.elan(&["dump-state", "--no-net"]).output().unwrap();
+    assert!(output.status.success());
+    let state: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let installed = &state["toolchains"]["installed"][0];
+    let installed_at = installed["installed_at"].as_u64().unwrap();
+    assert!((before..=after).contains(&installed_at), "{}", installed);
+    let checksum: String = Sha256::digest(&fs::read(&archive).unwrap())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(installed["checksum"], checksum.as_str());
+    assert!(installed["size"].as_u64().unwrap() > 0, "{}", installed);
+}
+
+#[cfg(feature = "build-from-source")]
+#[test]
+fn build_abbreviated_commit() {
+    let env = Env::new();
+    let output = env
+        .elan(&[
+            "toolchain",
+            "install",
+            "--build",
+            "leanprover/lean4:abc1234",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("full 40-character hash"), "{}", stderr);
+}
+
+#[cfg(feature = "ipfs")]
+#[test]
+fn ipfs_source() {
+    let env = Env::new();
+    let (names, archive) = release_mirror(&env);
+    // A gateway serving the archives below the content identifier listed in
+    // the release index
+    let gateway = env.path("gateway/ipfs/bafytest");
+    fs::create_dir_all(&gateway).unwrap();
+    for name in &names {
+        fs::copy(&archive, gateway.join(name)).unwrap();
+    }
+    let index_url = release_index(&env, &names, |name| {
+        vec![format!("ipfs://bafytest/{}", name)]
+    });
+
+    // Release archives cannot be downloaded from the GitHub mirror
+    let server = serve_release_pages(env.path("mirror"));
+    let output = env
+        .elan(&["toolchain", "install", "leanprover/lean4:v4.9.0"])
+        .env("ELAN_GITHUB_URL", &server)
+        .env(
+            "ELAN_IPFS_GATEWAY",
+            Url::from_directory_path(env.path("gateway"))
+                .unwrap()
+                .as_str(),
+        )
+        .env(
+            "ELAN_RELEASE_INDEX",
+            format!("leanprover/lean4={}", index_url),
+        )
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("failed to download"), "{}", stderr);
+    assert!(env
+        .path("home/.elan/toolchains/leanprover--lean4---v4.9.0/bin/lean")
+        .is_file());
+}
+
+#[test]
+fn minimize() {
+    let env = Env::new();
+    let (names, _) = release_mirror(&env);
+    let archive = env.path("lean-full.tar.gz");
+    let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+        fs::File::create(&archive).unwrap(),
+        flate2::Compression::default(),
+    ));
+    for path in [
+        "bin/lean",
+        "lib/lean/Init.olean",
+        "lib/lean/Init.ilean",
+        "share/doc/README.md",
+        "src/lean/Init.lean",
+        "src/lake/tests/test.sh",
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o755);
+        tar.append_data(&mut header, format!("lean-4.9.0/{}", path), &b"data"[..])
+            .unwrap();
+    }
+    tar.into_inner().unwrap().finish().unwrap();
+    let download = env.path("mirror/leanprover/lean4/releases/download/v4.9.0");
+    fs::create_dir_all(&download).unwrap();
+    for name in &names {
+        fs::copy(&archive, download.join(name)).unwrap();
+    }
+    let mirror = Url::from_directory_path(env.path("mirror")).unwrap();
+    let install = |quiet: &str| {
+        env.elan(&[
+            "toolchain",
+            "install",
+            "--reinstall",
+            "--minimize",
+            "leanprover/lean4:v4.9.0",
+        ])
+        .env("ELAN_GITHUB_URL", mirror.as_str())
+        .env("ELAN_RELEASE_INDEX", "leanprover/lean4=none")
+        .env("ELAN_QUIET", quiet)
+        .output()
+        .unwrap()
+    };
+    let toolchain = env.path("home/.elan/toolchains/leanprover--lean4---v4.9.0");
+
+    let output = install("0");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("minimized 'leanprover/lean4:v4.9.0', freeing "),
+        "{}",
+        stderr
+    );
+    assert!(toolchain.join("bin/lean").is_file());
+    assert!(toolchain.join("lib/lean/Init.ilean").is_file());
+    assert!(toolchain.join("share").is_dir());
+    assert!(!toolchain.join("share/doc").exists());
+    assert!(!toolchain.join("src").exists());
+
+    // Messages are suppressed as in containers
+    env.run(&["config", "set", "strip_patterns", "**/*.ilean"]);
+    let output = install("1");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("info:"), "{}", stderr);
+    assert!(!toolchain.join("lib/lean/Init.ilean").exists());
+    assert!(toolchain.join("src/lean/Init.lean").is_file());
+
+    env.run(&["config", "set", "strip_patterns", "../settings.toml"]);
+    let output = install("0");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid pattern"));
+}
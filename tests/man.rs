@@ -0,0 +1,60 @@
+//! Checks the man pages of elan's commands.
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[test]
+fn generate() {
+    let env = Env::new();
+    let dir = env.path("man");
+    env.run(&["man", "--generate", dir.to_str().unwrap()]);
+
+    let page = fs::read_to_string(dir.join("elan-toolchain-install.1")).unwrap();
+    assert!(page.starts_with(".TH \"ELAN\\-TOOLCHAIN\\-INSTALL\" \"1\""));
+    assert!(page.contains("elan\\-toolchain\\-install \\- Install a given toolchain\n"));
+    assert!(page.contains(".TP\n\\fB\\-\\-dry\\-run\\fR\n"), "{}", page);
+    assert!(page.contains("\\fBelan\\-toolchain\\fR(1)"), "{}", page);
+
+    let page = fs::read_to_string(dir.join("elan.1")).unwrap();
+    assert!(page.contains("see \\fBelan\\-show\\fR(1)"), "{}", page);
+    assert!(page.contains(".SH DISCUSSION\n"), "{}", page);
+    assert!(dir.join("elan-self-trust-root-add.1").is_file());
+    assert!(!dir.join("elan-help.1").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn view() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = Env::new();
+    // Stands in for `man`, printing the page it is given
+    let man = env.path("bin/man");
+    fs::write(&man, "#!/bin/sh\ncat \"$1\"\n").unwrap();
+    fs::set_permissions(&man, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:/usr/bin:/bin", env.path("bin").display());
+
+    for args in [
+        &["man", "toolchain", "install"][..],
+        &["man", "elan-toolchain-install"],
+    ] {
+        let output = env.elan(args).env("PATH", &path).output().unwrap();
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout)
+            .starts_with(".TH \"ELAN\\-TOOLCHAIN\\-INSTALL\""));
+    }
+
+    // Pages of other tools come from the toolchain
+    env.link("a");
+    fs::create_dir_all(env.path("lean-a/share/man/man1")).unwrap();
+    fs::write(env.path("lean-a/share/man/man1/lean.1"), ".TH LEAN 1\n").unwrap();
+    let output = env
+        .elan(&["man", "lean", "--toolchain", "a"])
+        .env("PATH", &path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b".TH LEAN 1\n");
+}
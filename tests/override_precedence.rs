@@ -0,0 +1,87 @@
+//! Checks of the `override_precedence` setting
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+/// The `lean` run in `project`, and the warnings
+fn active_toolchain(env: &Env, project: &std::path::Path) -> (String, String) {
+    let output = env
+        .elan(&["which", "lean"])
+        .current_dir(project)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn order() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    env.run(&["default", "a"]);
+    let project = env.project("b");
+
+    let (active, _) = active_toolchain(&env, &project);
+    assert!(active.contains("toolchains/b/"), "{}", active);
+
+    env.run(&["config", "set", "override_precedence", "default"]);
+    let (active, _) = active_toolchain(&env, &project);
+    assert!(active.contains("toolchains/a/"), "{}", active);
+
+    env.run(&[
+        "config",
+        "set",
+        "override_precedence",
+        "toolchain-file,default",
+    ]);
+    let (active, _) = active_toolchain(&env, &project);
+    assert!(active.contains("toolchains/b/"), "{}", active);
+
+    // The environment variable is ignored unless listed
+    let output = env
+        .elan(&["which", "lean"])
+        .current_dir(&project)
+        .env("ELAN_TOOLCHAIN", "a")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("toolchains/b/"), "{}", stdout);
+}
+
+#[test]
+fn unknown_sources() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    env.run(&["default", "a"]);
+    let project = env.project("b");
+
+    let output = env
+        .elan(&["config", "set", "override_precedence", "future,default"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    // e.g. written by a newer version of elan
+    let settings = env.path("home/.elan/settings.toml");
+    let toml = fs::read_to_string(&settings).unwrap();
+    // Top-level keys go before the tables
+    fs::write(
+        &settings,
+        format!("override_precedence = [\"future\", \"default\"]\n{}", toml),
+    )
+    .unwrap();
+    let (active, stderr) = active_toolchain(&env, &project);
+    assert!(active.contains("toolchains/a/"), "{}", active);
+    assert!(
+        stderr.contains("ignoring unknown source 'future'"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,74 @@
+//! Checks that `elan self uninstall` reverts exactly the PATH changes that
+//! `elan-init` made
+
+#![cfg(unix)]
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[test]
+fn reverted_after_shell_change() {
+    let env = Env::new();
+    let zdotdir = env.path("zdotdir");
+    fs::create_dir_all(&zdotdir).unwrap();
+    fs::write(zdotdir.join(".zprofile"), "setopt extended_glob\n").unwrap();
+    let status = env
+        .init(&["-y", "--default-toolchain", "none"])
+        .env("SHELL", "/bin/zsh")
+        .env("ZDOTDIR", &zdotdir)
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let export = "/.elan/bin:$PATH\"";
+    for rc in [env.path("home/.profile"), zdotdir.join(".zprofile")] {
+        let contents = fs::read_to_string(&rc).unwrap();
+        assert!(contents.contains(export), "{}: {}", rc.display(), contents);
+    }
+
+    // Uninstalled from another shell, where `.zprofile` would not be guessed
+    let output = env
+        .elan(&["self", "uninstall", "-y"])
+        .env("SHELL", "/bin/bash")
+        .env_remove("ZDOTDIR")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!fs::read_to_string(env.path("home/.profile"))
+        .unwrap()
+        .contains(export));
+    assert_eq!(
+        fs::read_to_string(zdotdir.join(".zprofile")).unwrap(),
+        "setopt extended_glob\n"
+    );
+}
+
+#[test]
+fn preexisting_line_kept() {
+    let env = Env::new();
+    let profile = env.path("home/.profile");
+    let contents = "umask 022\nexport PATH=\"$HOME/.elan/bin:$PATH\"\n";
+    fs::write(&profile, contents).unwrap();
+    let status = env
+        .init(&["-y", "--default-toolchain", "none"])
+        .env("SHELL", "/bin/bash")
+        .env("ELAN_INIT_SKIP_PATH_CHECK", "yes")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(&profile).unwrap(), contents);
+
+    let status = env
+        .elan(&["self", "uninstall", "-y"])
+        .env("SHELL", "/bin/bash")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(fs::read_to_string(&profile).unwrap(), contents);
+}
@@ -0,0 +1,29 @@
+//! Checks how proxies treat `+toolchain` arguments they cannot use.
+
+mod common;
+
+use common::Env;
+
+#[test]
+fn toolchain_suggestions() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.9.0");
+    // Linked toolchains named like releases are suggested only once
+    env.link("v4.9.0");
+    env.link("u4.9.1");
+
+    let output = env
+        .proxy("lean", &["+v4.9.1", "--version"])
+        .env("ELAN_GITHUB_URL", "http://127.0.0.1:9")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "toolchain '+v4.9.1' could not be resolved (did you mean `+v4.9.0` or `+u4.9.1`?)"
+        ),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,20 @@
+//! Checks that elan cleans up after installations that were interrupted or
+//! killed.
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[test]
+fn stale_lock_files_are_removed() {
+    let env = Env::new();
+    let toolchains = env.path("home/.elan/toolchains");
+    fs::create_dir_all(&toolchains).unwrap();
+    // Left behind by a process killed while installing the toolchain
+    let lock = toolchains.join("leanprover--lean4---v4.9.0.lock");
+    fs::write(&lock, "4194304\n").unwrap();
+
+    env.run(&["toolchain", "list"]);
+    assert!(!lock.exists());
+}
@@ -0,0 +1,381 @@
+//! Checks that channels resolve to installed releases without network access.
+
+mod common;
+
+use common::Env;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[test]
+fn channels_offline() {
+    let env = Env::new();
+    for dir_name in [
+        "leanprover--lean4---v4.9.0",
+        "leanprover--lean4---v4.10.0",
+        "leanprover--lean4---v4.11.0-rc1",
+        "leanprover--lean4-nightly---nightly-2024-12-31",
+        "leanprover--lean4-nightly---nightly-2025-01-03",
+        "other--lean4---v4.12.0",
+    ] {
+        env.fake_install(dir_name);
+    }
+    let output = env
+        .elan(&[
+            "resolve",
+            "--offline",
+            "stable",
+            "beta",
+            "nightly",
+            "other/lean4:nightly",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let results = output
+        .stdout
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_slice::<Value>(l).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        [
+            json!({ "input": "stable", "installed": true, "resolved": "leanprover/lean4:v4.10.0" }),
+            json!({ "input": "beta", "installed": true, "resolved": "leanprover/lean4:v4.11.0-rc1" }),
+            json!({
+                "input": "nightly",
+                "installed": true,
+                "resolved": "leanprover/lean4-nightly:nightly-2025-01-03",
+            }),
+            json!({
+                "input": "other/lean4:nightly",
+                "error": "cannot resolve channel 'nightly' under `--no-net`",
+            }),
+        ]
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "using cached 'leanprover/lean4-nightly:nightly-2025-01-03'; latest unknown (offline)"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn origin_without_release() {
+    let env = Env::new();
+    let output = env
+        .elan(&["resolve", "--offline", "lean4", "leanprover/lean4:lean4"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 2);
+    for line in stdout.lines() {
+        let result: Value = serde_json::from_str(line).unwrap();
+        let error = result["error"].as_str().unwrap();
+        // The suggestions depend on whether the release index is reachable
+        assert!(
+            error.starts_with(&format!(
+                "'{}' names a repository but no release; use e.g. `stable`",
+                result["input"].as_str().unwrap()
+            )),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn release_index_per_origin() {
+    let env = Env::new();
+    let index = env.path("index.json");
+    std::fs::write(&index, r#"{"stable": [{"name": "v4.9.0"}]}"#).unwrap();
+    let url = url::Url::from_file_path(&index).unwrap();
+    env.run(&["config", "set", "release_index.other/lean4", url.as_str()]);
+    let output = env
+        .elan(&["resolve", "other/lean4:stable"])
+        .output()
+        .unwrap();
+    let result: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["resolved"], "other/lean4:v4.9.0");
+
+    std::fs::write(
+        &index,
+        r#"{"schema_version": 2, "stable": [{"name": "v5.0.0"}]}"#,
+    )
+    .unwrap();
+    let output = env
+        .elan(&["resolve", "leanprover/lean4:stable"])
+        .env("ELAN_RELEASE_INDEX", format!("leanprover/lean4={}", url))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("it uses schema version 2, but only versions up to 1 are supported"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn sparse_release_index() {
+    let env = Env::new();
+    let index = env.path("index/index.json");
+    std::fs::create_dir_all(env.path("index/releases")).unwrap();
+    std::fs::write(&index, r#"{"stable": [{"name": "v4.9.0"}]}"#).unwrap();
+    std::fs::write(
+        env.path("index/releases/stable.json"),
+        r#"{"name": "v4.10.0"}"#,
+    )
+    .unwrap();
+    let url = url::Url::from_file_path(&index).unwrap();
+    env.run(&["config", "set", "release_index.other/lean4", url.as_str()]);
+    let resolve = || {
+        let output = env
+            .elan(&["resolve", "other/lean4:stable"])
+            .output()
+            .unwrap();
+        let result: Value = serde_json::from_slice(&output.stdout).unwrap();
+        result["resolved"].clone()
+    };
+    // The entry is preferred over the full index, which may lag behind
+    assert_eq!(resolve(), "other/lean4:v4.10.0");
+
+    std::fs::remove_file(env.path("index/releases/stable.json")).unwrap();
+    assert_eq!(resolve(), "other/lean4:v4.9.0");
+}
+
+/// Serves a release index with an `ETag` on a local port, answering
+/// conditional requests for the current tag with 304; returns the index URL
+/// and the log of requested paths and response statuses
+fn serve_release_index(index: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/index.json", listener.local_addr().unwrap());
+    let log = Arc::new(Mutex::new(vec![]));
+    let server_log = log.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                    break;
+                }
+                request += &line;
+            }
+            let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+            let (status, body) = if path != "/index.json" {
+                ("404 Not Found", "")
+            } else if request
+                .to_ascii_lowercase()
+                .contains("if-none-match: \"v1\"")
+            {
+                ("304 Not Modified", "")
+            } else {
+                ("200 OK", index)
+            };
+            server_log
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", path, status));
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {}\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+        }
+    });
+    (url, log)
+}
+
+#[test]
+fn conditional_release_index_requests() {
+    let env = Env::new();
+    let (url, log) = serve_release_index(r#"{"stable": [{"name": "v4.9.0"}]}"#);
+    env.run(&["config", "set", "release_index.other/lean4", &url]);
+    for _ in 0..2 {
+        let output = env
+            .elan(&["resolve", "other/lean4:stable"])
+            .env("NO_PROXY", "127.0.0.1")
+            .output()
+            .unwrap();
+        let result: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(result["resolved"], "other/lean4:v4.9.0");
+    }
+    let log = log.lock().unwrap().clone();
+    assert_eq!(
+        log.iter()
+            .filter(|l| l.starts_with("/index.json"))
+            .collect::<Vec<_>>(),
+        ["/index.json 200 OK", "/index.json 304 Not Modified"],
+        "{:?}",
+        log
+    );
+}
+
+#[test]
+fn mirrored_endpoints() {
+    let env = Env::new();
+    let raw = env.path("raw");
+    std::fs::create_dir_all(raw.join("other/lean4/HEAD")).unwrap();
+    std::fs::write(
+        raw.join("other/lean4/HEAD/lean-toolchain"),
+        "leanprover/lean4:v4.9.0\n",
+    )
+    .unwrap();
+    let url = url::Url::from_directory_path(&raw).unwrap();
+    env.run(&["config", "set", "github_raw_url", url.as_str()]);
+    let output = env
+        .elan(&["resolve", "other/lean4:lean-toolchain"])
+        .output()
+        .unwrap();
+    let result: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["resolved"], "leanprover/lean4:v4.9.0", "{}", result);
+
+    // The update root follows the GitHub mirror unless set itself
+    env.run(&["config", "set", "github_url", "https://mirror.invalid/"]);
+    let output = env
+        .elan(&["self", "which-installation", "--json"])
+        .env_remove("ELAN_UPDATE_ROOT")
+        .output()
+        .unwrap();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report["update_root"],
+        "https://mirror.invalid/leanprover/elan/releases/download"
+    );
+    assert_eq!(report["update_root_source"], "default");
+}
+
+#[test]
+fn commits_and_git_specs() {
+    let env = Env::new();
+    let sha = "abcdef0123456789abcdef0123456789abcdef01";
+    let api = env.path("api");
+    std::fs::create_dir_all(api.join("repos/leanprover/lean4")).unwrap();
+    std::fs::write(
+        api.join("repos/leanprover/lean4/tags"),
+        json!([
+            { "name": "v4.10.0", "commit": { "sha": "1".repeat(40) } },
+            { "name": "v4.9.0", "commit": { "sha": sha } },
+        ])
+        .to_string(),
+    )
+    .unwrap();
+    let url = url::Url::from_directory_path(&api).unwrap();
+    env.run(&["config", "set", "github_api_url", url.as_str()]);
+    let resolve = |args: &[&str]| {
+        let output = env.elan(&[&["resolve"], args].concat()).output().unwrap();
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|l| {
+                let result: Value = serde_json::from_str(l).unwrap();
+                result.get("resolved").unwrap_or(&result["error"]).clone()
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        resolve(&[
+            "leanprover/lean4:abcdef0",
+            &format!("git+https://github.com/leanprover/lean4#{}", sha),
+            "git+https://github.com/leanprover/lean4.git#v4.9.0",
+        ]),
+        ["leanprover/lean4:v4.9.0"; 3]
+    );
+    let errors = resolve(&[
+        "git+https://gitlab.com/leanprover/lean4#v4.9.0",
+        "git+https://github.com/leanprover/lean4",
+        "git+https://github.com/leanprover#v4.9.0",
+    ]);
+    for error in errors {
+        assert!(
+            error
+                .as_str()
+                .unwrap()
+                .starts_with("unsupported toolchain specification"),
+            "{}",
+            error
+        );
+    }
+
+    // Commits resolved before are remembered
+    std::fs::remove_dir_all(&api).unwrap();
+    assert_eq!(
+        resolve(&["--offline", "leanprover/lean4:abcdef0"]),
+        ["leanprover/lean4:v4.9.0"]
+    );
+    assert_eq!(
+        resolve(&["--offline", "leanprover/lean4:abcdef1"]),
+        ["cannot resolve commit 'abcdef1' under `--no-net`"]
+    );
+}
+
+#[test]
+fn latest_release_sources() {
+    let env = Env::new();
+    for repo in ["other/lean4", "leanprover/lean4"] {
+        let releases = env.path(&format!("api/repos/{}/releases", repo));
+        std::fs::create_dir_all(&releases).unwrap();
+        std::fs::write(
+            releases.join("latest"),
+            json!({ "tag_name": "v4.9.0" }).to_string(),
+        )
+        .unwrap();
+    }
+    let api = url::Url::from_directory_path(env.path("api")).unwrap();
+    env.run(&["config", "set", "github_api_url", api.as_str()]);
+    let pages = env.path("github/other/lean4/releases");
+    std::fs::create_dir_all(&pages).unwrap();
+    std::fs::write(
+        pages.join("latest"),
+        r#"<a href="/other/lean4/releases/tag/v4.8.0">"#,
+    )
+    .unwrap();
+    let github = url::Url::from_directory_path(env.path("github")).unwrap();
+    env.run(&["config", "set", "github_url", github.as_str()]);
+    let index = env.path("index.json");
+    std::fs::write(
+        &index,
+        json!({
+            "stable": [{ "name": "v4.9.0" }],
+            "beta": [{ "name": "v4.10.0-rc1" }],
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let index = url::Url::from_file_path(&index).unwrap();
+
+    let resolve = |name: &str, token: &str| {
+        let output = env
+            .elan(&["resolve", name])
+            .env("ELAN_GITHUB_TOKEN", token)
+            .env("ELAN_RELEASE_INDEX", format!("leanprover/lean4={}", index))
+            .output()
+            .unwrap();
+        let result: Value = serde_json::from_slice(&output.stdout).unwrap();
+        result["resolved"].clone()
+    };
+    // The GitHub API is only used with a token
+    assert_eq!(resolve("other/lean4:stable", "token"), "other/lean4:v4.9.0");
+    assert_eq!(resolve("other/lean4:stable", ""), "other/lean4:v4.8.0");
+    // `beta` is the release GitHub marks as latest, not the newest prerelease
+    assert_eq!(resolve("other/lean4:beta", "token"), "other/lean4:v4.9.0");
+    assert_eq!(
+        resolve("leanprover/lean4:beta", "token"),
+        "leanprover/lean4:v4.9.0"
+    );
+    assert_eq!(
+        resolve("leanprover/lean4:stable", ""),
+        "leanprover/lean4:v4.9.0"
+    );
+}
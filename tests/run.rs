@@ -0,0 +1,97 @@
+//! Checks that `elan run` passes arguments on exactly as given.
+
+#![cfg(unix)]
+
+mod common;
+
+use common::Env;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+
+#[test]
+fn run_preserves_argv() {
+    let env = Env::new();
+    env.link("a");
+    // Prints each argument followed by a NUL byte
+    let lean = env.path("lean-a/bin/lean");
+    fs::write(&lean, "#!/bin/sh\nprintf '%s\\0' \"$@\"\n").unwrap();
+    fs::set_permissions(&lean, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let check = |args: &[&[u8]], expected: &[&[u8]]| {
+        let args = args
+            .iter()
+            .map(|a| OsStr::from_bytes(a))
+            .collect::<Vec<_>>();
+        let output = env.elan(&args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let mut printed = output.stdout.split(|b| *b == 0).collect::<Vec<_>>();
+        assert_eq!(printed.pop(), Some(&b""[..]));
+        assert_eq!(printed, expected);
+    };
+
+    check(
+        &[b"run", b"a", b"lean", b"--version", b"-x"],
+        &[b"--version", b"-x"],
+    );
+    check(
+        &[b"run", b"a", b"--", b"lean", b"--", b"-y"],
+        &[b"--", b"-y"],
+    );
+    check(
+        &[b"run", b"--install", b"a", b"lean", b"--install"],
+        &[b"--install"],
+    );
+    // Flags elan itself knows are the tool's after its name
+    check(
+        &[b"run", b"a", b"lean", b"-v", b"--help", b"-V"],
+        &[b"-v", b"--help", b"-V"],
+    );
+    check(&[b"run", b"a", b"lean", b"\xff", b""], &[b"\xff", b""]);
+}
+
+#[test]
+fn run_script_without_shebang() {
+    let env = Env::new();
+    env.link("a");
+    let lean = env.path("lean-a/bin/lean");
+    fs::write(&lean, "echo \"script $1\"\n").unwrap();
+    fs::set_permissions(&lean, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = env.elan(&["run", "a", "lean", "x"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"script x\n");
+
+    fs::write(&lean, "#!/nonexistent/sh -e\n").unwrap();
+    let output = env.elan(&["run", "a", "lean"]).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("interpreter '/nonexistent/sh' named in the first line"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn run_records_use() {
+    let env = Env::new();
+    env.link("a");
+    let lean = env.path("lean-a/bin/lean");
+    fs::write(&lean, "#!/bin/sh\n").unwrap();
+    fs::set_permissions(&lean, fs::Permissions::from_mode(0o755)).unwrap();
+    env.run(&["run", "a", "lean"]);
+    let usage = env.path("home/.elan/usage/a");
+    assert!(usage.is_file());
+
+    // Uses long enough ago are updated
+    let old = filetime::FileTime::from_unix_time(0, 0);
+    filetime::set_file_mtime(&usage, old).unwrap();
+    env.run(&["run", "a", "lean"]);
+    let modified = filetime::FileTime::from_last_modification_time(&fs::metadata(&usage).unwrap());
+    assert!(modified > old);
+}
@@ -0,0 +1,65 @@
+//! Checks that commands typed from rustup habit work or point to elan's
+//! counterpart.
+
+mod common;
+
+use common::Env;
+use std::path::Path;
+
+#[test]
+fn aliases() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    env.run(&["toolchain", "rm", "a"]);
+    assert!(!Path::new(&env.toolchain_path("a")).exists());
+
+    // Linked toolchains are skipped rather than installed over
+    env.run(&["toolchain", "add", "b"]);
+    assert!(Path::new(&env.toolchain_path("b")).exists());
+}
+
+#[test]
+fn unsupported_verbs() {
+    let env = Env::new();
+    for (args, hint) in [
+        (
+            &["target", "add", "x86_64-apple-darwin"][..],
+            "--target <triple>",
+        ),
+        (&["update"], "elan self update"),
+        (&["set", "profile", "minimal"], "elan config set"),
+    ] {
+        let output = env.elan(args).output().unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!("elan has no `{}` command", args[0])),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains(hint), "{}", stderr);
+    }
+
+    // They are not advertised
+    let output = env.elan(&["--help"]).output().unwrap();
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("target"));
+}
+
+/// rustup's `--no-self-update` and other flags of commands elan lacks lead to
+/// the hint for the command instead of an error about the flag
+#[test]
+fn unsupported_verb_flags() {
+    let env = Env::new();
+    let output = env
+        .elan(&["update", "--no-self-update", "stable"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("elan has no `update` command"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,126 @@
+//! Checks that equivalent toolchain names are stored in one normal form.
+
+mod common;
+
+use common::Env;
+use serde_json::json;
+use std::fs;
+
+#[test]
+fn normalize_settings() {
+    let env = Env::new();
+    let project = env.path("project");
+    fs::create_dir_all(&project).unwrap();
+    let project = project.to_str().unwrap();
+    fs::create_dir_all(env.path("home/.elan")).unwrap();
+    fs::write(
+        env.path("home/.elan/settings.toml"),
+        format!(
+            "default_toolchain = \"4.9.0\"\nversion = \"12\"\n\n[overrides]\n\
+             \"{0}\" = \"v4.9.0\"\n\"{0}/\" = \"leanprover/lean4:v4.8.0\"\n",
+            project
+        ),
+    )
+    .unwrap();
+
+    env.run(&["self", "normalize-settings"]);
+    let settings = fs::read_to_string(env.path("home/.elan/settings.toml")).unwrap();
+    assert!(
+        settings.contains("default_toolchain = \"leanprover/lean4:v4.9.0\""),
+        "{}",
+        settings
+    );
+    assert!(
+        settings.contains(&format!("\"{}\" = \"leanprover/lean4:v4.9.0\"", project)),
+        "{}",
+        settings
+    );
+    assert!(!settings.contains("v4.8.0"), "{}", settings);
+
+    let output = env.elan(&["self", "normalize-settings"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("settings are already normalized"));
+}
+
+#[test]
+fn default_is_normalized() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.9.0");
+    env.run(&["default", "4.9.0"]);
+    let settings = fs::read_to_string(env.path("home/.elan/settings.toml")).unwrap();
+    assert!(
+        settings.contains("default_toolchain = \"leanprover/lean4:v4.9.0\""),
+        "{}",
+        settings
+    );
+}
+
+#[test]
+fn merge_origin_case_duplicates() {
+    let env = Env::new();
+    env.fake_install("LeanProver--lean4---v4.8.0");
+    env.fake_install("LeanProver--lean4---v4.9.0");
+    env.fake_install("leanprover--lean4---v4.9.0");
+    env.run(&["self", "normalize-settings"]);
+    assert_eq!(
+        env.ok("list", json!({}))["toolchains"],
+        json!([
+            {
+                "name": "leanprover/lean4:v4.8.0",
+                "path": env.toolchain_path("leanprover--lean4---v4.8.0"),
+            },
+            {
+                "name": "leanprover/lean4:v4.9.0",
+                "path": env.toolchain_path("leanprover--lean4---v4.9.0"),
+            },
+        ])
+    );
+}
+
+#[test]
+fn rename_linked_toolchain() {
+    let env = Env::new();
+    env.link("a");
+    env.run(&["default", "a"]);
+    env.run(&["override", "set", "a"]);
+    env.run(&["toolchain", "rename", "a", "b"]);
+    assert_eq!(
+        env.ok("list", json!({}))["toolchains"],
+        json!([{ "name": "b", "path": env.toolchain_path("b") }])
+    );
+    let settings = fs::read_to_string(env.path("home/.elan/settings.toml")).unwrap();
+    assert!(
+        settings.contains("default_toolchain = \"b\""),
+        "{}",
+        settings
+    );
+    assert!(!settings.contains("\"a\""), "{}", settings);
+
+    env.fake_install("leanprover--lean4---v4.9.0");
+    let output = env
+        .elan(&["toolchain", "rename", "v4.9.0", "c"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not a linked toolchain"));
+}
+
+#[test]
+fn clean_dangling_overrides() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    env.run(&["override", "set", "a"]);
+    env.run(&["toolchain", "uninstall", "--force", "a"]);
+    let output = env.elan(&["override", "list"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("a (linked toolchain missing)"));
+
+    env.run(&["override", "clean", "--reassign", "b"]);
+    let settings = fs::read_to_string(env.path("home/.elan/settings.toml")).unwrap();
+    assert!(settings.contains("= \"b\""), "{}", settings);
+
+    env.run(&["toolchain", "uninstall", "--force", "b"]);
+    env.run(&["override", "clean"]);
+    let output = env.elan(&["override", "list"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "no overrides\n");
+}
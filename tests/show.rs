@@ -0,0 +1,95 @@
+//! Checks that `elan show` and `elan toolchain list` point out toolchains that
+//! cannot be run.
+
+mod common;
+
+use common::Env;
+use std::env::consts::EXE_SUFFIX;
+use std::fs;
+
+#[test]
+fn broken_toolchains() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    fs::remove_dir_all(env.path("lean-a")).unwrap();
+    env.fake_install("leanprover--lean4---v4.9.0");
+    fs::remove_file(env.path(&format!(
+        "home/.elan/toolchains/leanprover--lean4---v4.9.0/bin/lean{}",
+        EXE_SUFFIX
+    )))
+    .unwrap();
+
+    let output = env.elan(&["toolchain", "list"]).output().unwrap();
+    assert!(output.status.success());
+    // The plain list is read by scripts and stays unchanged
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "a\nb\nleanprover/lean4:v4.9.0\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("toolchain 'a' is broken"), "{}", stderr);
+    assert!(
+        stderr.contains("toolchain 'leanprover/lean4:v4.9.0' is broken: `bin/lean` is missing"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("toolchain 'b'"), "{}", stderr);
+
+    env.run(&["default", "a"]);
+    let output = env.elan(&["show"]).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("a (default) (broken)\nb\n"), "{}", stdout);
+    assert!(stdout.contains("(broken: linked directory"), "{}", stdout);
+}
+
+#[test]
+fn unrecognized_toolchain_dirs() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.9.0");
+    // e.g. left behind by a backup tool
+    fs::create_dir_all(env.path("home/.elan/toolchains/leanprover--lean4---v4.9.0 (copy)"))
+        .unwrap();
+
+    let output = env.elan(&["toolchain", "list"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "leanprover/lean4:v4.9.0\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("v4.9.0 (copy)"), "{}", stderr);
+}
+
+#[test]
+fn status_without_subcommand() {
+    let env = Env::new();
+    let output = env.elan::<&str>(&[]).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("elan "), "{}", stdout);
+    assert!(stdout.contains("default toolchain: none\n"), "{}", stdout);
+    assert!(stdout.contains("active toolchain:  none\n"), "{}", stdout);
+    assert!(stdout.contains("are not in PATH"), "{}", stdout);
+    assert!(stdout.contains("elan default stable"), "{}", stdout);
+
+    env.link("a");
+    env.run(&["default", "a"]);
+    let project = env.project("leanprover/lean4:v4.9.0");
+    let output = env
+        .elan::<&str>(&[])
+        .current_dir(&project)
+        .env("PATH", env.path("home/.elan/bin"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("default toolchain: a\n"), "{}", stdout);
+    assert!(
+        stdout.contains("active toolchain:  leanprover/lean4:v4.9.0 (overridden by"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("PATH:              set up\n"), "{}", stdout);
+    assert!(stdout.contains("lake build"), "{}", stdout);
+}
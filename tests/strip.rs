@@ -0,0 +1,63 @@
+//! Checks what `elan toolchain strip` removes from installed toolchains and
+//! that it records it.
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+#[test]
+fn strip() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.9.0");
+    let toolchain = env.path("home/.elan/toolchains/leanprover--lean4---v4.9.0");
+    for path in ["share/doc/README.md", "src/lean/Init.lean", "lib/libInit.a"] {
+        let path = toolchain.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "data").unwrap();
+    }
+    let strip = |args: &[&str]| {
+        let mut cmd = env.elan(&["toolchain", "strip", "leanprover/lean4:v4.9.0"]);
+        cmd.args(args).output().unwrap()
+    };
+
+    let output = strip(&["--dry-run"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("  share/doc ("), "{}", stdout);
+    assert!(stdout.contains("  src ("), "{}", stdout);
+    assert!(!stdout.contains("lib"), "{}", stdout);
+    assert!(toolchain.join("src").is_dir());
+
+    let output = strip(&[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("stripped 'leanprover/lean4:v4.9.0'"),
+        "{}",
+        stderr
+    );
+    assert!(!toolchain.join("share/doc").exists());
+    assert!(!toolchain.join("src").exists());
+    assert!(toolchain.join("bin/lean").is_file());
+    assert!(toolchain.join("lib/libInit.a").is_file());
+
+    let output = strip(&["--pattern", "**/*.a"]);
+    assert!(output.status.success());
+    assert!(!toolchain.join("lib/libInit.a").exists());
+    let manifest = fs::read_to_string(toolchain.join("elan-install.toml")).unwrap();
+    let manifest: toml::Value = toml::from_str(&manifest).unwrap();
+    assert_eq!(
+        manifest["stripped"],
+        toml::Value::try_from(["share/doc", "src", "lib/libInit.a"]).unwrap()
+    );
+
+    let output = strip(&["--pattern", "../settings.toml"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid pattern"));
+
+    env.link("a");
+    let output = env.elan(&["toolchain", "strip", "a"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'a' is a linked toolchain"));
+}
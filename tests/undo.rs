@@ -0,0 +1,129 @@
+//! Checks of `elan undo` that do not need to download toolchains
+
+mod common;
+
+use common::Env;
+use std::fs;
+
+fn undo(env: &Env, args: &[&str]) -> String {
+    let output = env.elan(&[&["undo"], args].concat()).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn get(env: &Env, args: &[&str]) -> String {
+    let output = env.elan(args).output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+#[test]
+fn settings() {
+    let env = Env::new();
+    env.link("a");
+    env.link("b");
+    env.run(&["default", "a"]);
+    env.run(&["override", "set", "b"]);
+    env.run(&["override", "unset"]);
+    env.run(&["default", "b"]);
+
+    let stdout = undo(&env, &["--dry-run"]);
+    assert!(
+        stdout.contains("- restore the default toolchain 'a'"),
+        "{}",
+        stdout
+    );
+    assert_eq!(get(&env, &["config", "get", "default_toolchain"]), "b");
+
+    undo(&env, &[]);
+    assert_eq!(get(&env, &["config", "get", "default_toolchain"]), "a");
+    let stdout = undo(&env, &[]);
+    assert!(stdout.contains("- restore the override for"), "{}", stdout);
+    assert!(get(&env, &["override", "list"]).contains("b"));
+    undo(&env, &[]);
+    assert_eq!(get(&env, &["override", "list"]), "no overrides");
+    undo(&env, &[]);
+    let output = env
+        .elan(&["config", "get", "default_toolchain"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let output = env.elan(&["undo"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("nothing to undo"));
+
+    let history = get(&env, &["history"]);
+    assert_eq!(history.matches("undone by `elan undo`").count(), 4);
+    assert!(history.contains("undid `elan default b`"), "{}", history);
+}
+
+#[test]
+fn toolchains() {
+    let env = Env::new();
+    env.link("a");
+    env.fake_install("leanprover--lean4---v4.0.0");
+    env.fake_install("leanprover--lean4---v4.1.0");
+    env.run(&["toolchain", "uninstall", "a", "-y"]);
+    env.run(&["toolchain", "gc", "--delete", "-y"]);
+
+    // Both toolchains deleted by the same command are restored together
+    let stdout = undo(&env, &["--dry-run"]);
+    for toolchain in ["v4.0.0", "v4.1.0"] {
+        assert!(
+            stdout.contains(&format!(
+                "- reinstall 'leanprover/lean4:{}' by downloading it again",
+                toolchain
+            )),
+            "{}",
+            stdout
+        );
+    }
+    assert!(stdout.contains("are not restored"), "{}", stdout);
+
+    env.run(&["config", "set", "peer_cache", "true"]);
+    let cached = env.path("home/.elan/cache/downloads/leanprover/lean4/v4.1.0");
+    fs::create_dir_all(&cached).unwrap();
+    fs::write(cached.join("lean.tar.zst"), "").unwrap();
+    let stdout = undo(&env, &["--dry-run"]);
+    assert!(
+        stdout.contains("- reinstall 'leanprover/lean4:v4.1.0' from the download cache"),
+        "{}",
+        stdout
+    );
+
+    // Pretend that the gc run has been undone
+    env.fake_install("leanprover--lean4---v4.0.0");
+    env.fake_install("leanprover--lean4---v4.1.0");
+    let stdout = undo(&env, &[]);
+    assert!(
+        stdout.contains("- 'leanprover/lean4:v4.0.0' is installed already"),
+        "{}",
+        stdout
+    );
+
+    let stdout = undo(&env, &[]);
+    assert!(
+        stdout.contains("- cannot restore the linked toolchain 'a'"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn identical_commands() {
+    let env = Env::new();
+    env.fake_install("leanprover--lean4---v4.0.0");
+    env.run(&["toolchain", "gc", "--delete", "-y"]);
+    env.fake_install("leanprover--lean4---v4.1.0");
+    env.run(&["toolchain", "gc", "--delete", "-y"]);
+
+    // Only the toolchain deleted by the second run is restored
+    let stdout = undo(&env, &["--dry-run"]);
+    assert!(stdout.contains("'leanprover/lean4:v4.1.0'"), "{}", stdout);
+    assert!(!stdout.contains("'leanprover/lean4:v4.0.0'"), "{}", stdout);
+}